@@ -1,14 +1,125 @@
-use super::api_client::{ApiClient, AuthMethod};
-use super::base::{ConfigKey, ProviderDef, ProviderMetadata};
-use super::openai_compatible::OpenAiCompatibleProvider;
+use super::base::{
+    ConfigKey, MessageStream, Provider, ProviderDef, ProviderMetadata, ProviderUsage, Usage,
+};
+use super::errors::ProviderError;
+use crate::conversation::message::Message;
 use crate::model::ModelConfig;
 use anyhow::Result;
+use async_trait::async_trait;
+use audit::{AuditLog, AuditRecord};
+use flavor::UpstreamFlavor;
 use futures::future::BoxFuture;
-use serde::Deserialize;
+use rmcp::model::Tool;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use token::TokenManager;
+use tokio::sync::OnceCell;
+use tracing::Instrument;
+use transport::{shared_http_client, ClientSettings, TimeoutSettings, Transport};
+use uaa::{AuthMethod, UaaClientConfig, UaaTokens};
+use usage::UsageLedger;
+use wire::WireFormat;
+
+mod affinity;
+mod attribution;
+mod audio;
+mod audit;
+mod balance;
+mod batch;
+mod benchmark;
+mod best_of;
+mod binding_error;
+mod binding_parser;
+mod breaker;
+mod capabilities;
+mod classify;
+pub mod client;
+mod compact;
+mod completion;
+mod configure;
+mod context;
+mod credential_source;
+mod credhub;
+mod debug_http;
+mod deprecation;
+mod disk_cache;
+mod dlp;
+mod doctor;
+mod egress;
+mod embeddings;
+mod endpoint;
+mod estimate;
+mod etag;
+mod failover;
+mod fault;
+mod flavor;
+mod headers;
+mod lead_worker;
+mod limits;
+mod metrics;
+mod model_detail;
+mod parallel;
+mod params;
+mod platform_prompt;
+mod poll;
+mod preflight;
+mod profile;
+mod prompt_cache;
+mod reasoning;
+mod registrar;
+mod replay;
+mod residency;
+mod response_cache;
+mod revision;
+mod select;
+mod service_bindings;
+mod session;
+mod sse;
+mod stop_tokens;
+mod stream;
+mod structured;
+mod telemetry;
+mod tiered;
+mod timing;
+mod token;
+mod tokens;
+mod tool_args;
+mod tool_budget;
+mod tools;
+mod trace;
+mod transport;
+mod truncate;
+mod uaa;
+mod usage;
+mod usage_export;
+mod vision;
+mod warmup;
+mod wire;
+
+pub use batch::TanzuBatchResult;
+pub use benchmark::{BenchmarkCaseResult, ModelBenchmark, TanzuBenchmarkReport};
+pub use binding_error::BindingParseError;
+pub use binding_parser::{register_binding_parser, BindingParser};
+pub use capabilities::{Support, TanzuCapabilities, TanzuModelCapabilities, TanzuModelInfo};
+pub use client::TanzuClient;
+pub use configure::{advertised_chat_models, save_default_model};
+pub use deprecation::Deprecation;
+pub use dlp::{content_filter_fn, ContentFilter, RegexFilter};
+pub use doctor::{run as run_diagnostics, CheckStatus, DoctorCheck, DoctorReport};
+pub use metrics::{snapshot as metrics_snapshot, MetricsSnapshot};
+pub use model_detail::ModelDetail;
+pub use preflight::PreflightError;
+pub use session::TanzuSessionMetadata;
+pub use usage::{ModelUsage, TanzuUsageReport};
 
 const TANZU_PROVIDER_NAME: &str = "tanzu_ai";
 const TANZU_DEFAULT_MODEL: &str = "openai/gpt-oss-120b";
+const TANZU_DEFAULT_DISCOVERY_TTL_SECS: u64 = 300;
+/// Discovery is a small metadata call; the client itself only bounds connecting
+const TANZU_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(30);
 const TANZU_DOC_URL: &str =
     "https://techdocs.broadcom.com/us/en/vmware-tanzu/platform/ai-services/10-3/ai/index.html";
 
@@ -21,9 +132,25 @@ struct TanzuCredentials {
     api_key: String,
     /// Config URL for model discovery
     config_url: Option<String>,
+    /// Bearer token for the config URL when it differs from `api_key`
+    /// (`TANZU_AI_CONFIG_API_KEY`)
+    config_api_key: Option<String>,
     /// Model name (for single-model bindings; used in model discovery)
-    #[allow(dead_code)]
     model_name: Option<String>,
+    /// Service instance name from VCAP_SERVICES, when known
+    binding_name: Option<String>,
+    /// Service instance GUID from VCAP_SERVICES, when known
+    binding_guid: Option<String>,
+    /// Region the binding's foundation runs in (`region` in the binding), when declared
+    region: Option<String>,
+    /// Alternate names that resolve to `model_name` (single-model bindings)
+    model_aliases: Vec<String>,
+    /// API shape spoken by the endpoint (`wire_format` in the binding)
+    wire_format: WireFormat,
+    /// How requests are authenticated
+    auth: AuthMethod,
+    /// API behind the OpenAI-compatible paths
+    flavor: UpstreamFlavor,
 }
 
 /// Response from the config URL endpoint
@@ -35,20 +162,114 @@ struct ConfigResponse {
 }
 
 /// A model advertised by the config endpoint
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AdvertisedModel {
     name: String,
     #[serde(default)]
     capabilities: Vec<String>,
+    /// Context window in tokens, when the config endpoint advertises it
+    #[serde(
+        default,
+        alias = "contextLength",
+        alias = "context_length",
+        alias = "maxContextLength"
+    )]
+    context_length: Option<usize>,
+    /// Set when the platform is retiring the model
+    #[serde(
+        default,
+        rename = "deprecated",
+        alias = "deprecation",
+        deserialize_with = "deprecation::deserialize"
+    )]
+    deprecation: Option<deprecation::Deprecation>,
+    /// Version of the weights being served, for pinning and reproducibility
+    #[serde(
+        default,
+        alias = "version",
+        alias = "digest",
+        alias = "modelRevision",
+        alias = "modelVersion"
+    )]
+    revision: Option<String>,
+}
+
+/// A parsed binding together with the transport used to reach it
+#[derive(Clone)]
+struct TanzuBinding {
+    credentials: TanzuCredentials,
+    transport: Transport,
+    /// Chat and completion models served by this binding, used for request routing
+    models: Arc<RwLock<Vec<String>>>,
+    /// Discovery documents kept for conditional GETs
+    etags: Arc<etag::ETagCache>,
+}
+
+pub struct TanzuAIServicesProvider {
+    /// Bindings, discovery and request dispatch
+    client: TanzuClient,
+    model: ModelConfig,
+    /// Binding index and model used for embeddings, selected on first use
+    embedding_model: OnceCell<(usize, String)>,
+    /// Binding index and model used for transcription, selected on first use
+    transcription_model: OnceCell<(usize, String)>,
+    /// Models tried in order when the requested one is not served (`TANZU_AI_FALLBACK_MODELS`)
+    fallback_models: Vec<String>,
+    /// Token usage per session and model
+    usage: Arc<UsageLedger>,
+    /// Native structured output support learned by probing models that don't advertise it
+    structured_output: Mutex<HashMap<String, bool>>,
+    /// Tool-call support learned by probing models of bindings without a config URL
+    tool_support: Mutex<HashMap<String, bool>>,
+    /// Advertised context length per model, looked up once per model
+    context_lengths: Mutex<HashMap<String, Option<usize>>>,
+    /// Background config URL refresh (`TANZU_AI_CONFIG_POLL_SECS`)
+    poller: Option<tokio::task::JoinHandle<()>>,
+    /// Per-request audit log (`TANZU_AI_AUDIT_LOG`)
+    audit: Option<Arc<AuditLog>>,
+    /// Request parameters merged in per model (`TANZU_AI_MODEL_PARAMS`)
+    model_params: params::ModelParams,
+    /// Configured parallel tool call settings per model (`TANZU_AI_PARALLEL_TOOL_CALLS`)
+    parallel_tools: parallel::ParallelToolCalls,
+    /// Last model listing and when it was taken, reused while discovery is fresh
+    model_list: Mutex<Option<(Instant, Vec<String>)>>,
+    /// Summarize older turns instead of failing on context overflow (`TANZU_AI_AUTO_COMPACT`)
+    auto_compact: bool,
+    /// Fast/strong model routing (`TANZU_AI_TIERED_ROUTING`)
+    tiered: Option<tiered::TieredRouting>,
+    /// Fast model for tiered routing, selected on first use
+    fast_model: OnceCell<String>,
+    /// `TANZU_AI_LEAD_MODEL`/`TANZU_AI_WORKER_MODEL`, resolved and validated
+    lead_worker: Option<lead_worker::LeadWorker>,
+    /// Revision the configured model is pinned to (`TANZU_AI_MODEL_REVISION`)
+    pinned_revision: Option<String>,
+    /// Resumed sessions pinned to the binding and model they ran on
+    sessions: session::SessionPins,
+    /// Filters run over outgoing message text (`TANZU_AI_CONTENT_FILTERS`)
+    content_filters: dlp::ContentFilters,
+    /// Reasoning effort for models that advertise reasoning (`TANZU_AI_REASONING_EFFORT`)
+    reasoning_effort: Option<String>,
+    /// Candidate completions per request and how one is chosen (`TANZU_AI_CANDIDATES`)
+    best_of: best_of::BestOf,
+    /// Replies kept for identical temperature-0 requests (`TANZU_AI_RESPONSE_CACHE`)
+    response_cache: Option<response_cache::ResponseCache>,
+    /// Platform context appended to system prompts (`TANZU_AI_PLATFORM_PROMPT`)
+    platform_prompt: Option<platform_prompt::PlatformPrompt>,
 }
 
-pub struct TanzuAIServicesProvider;
+impl Drop for TanzuAIServicesProvider {
+    fn drop(&mut self) {
+        if let Some(poller) = &self.poller {
+            poller.abort();
+        }
+    }
+}
 
 impl ProviderDef for TanzuAIServicesProvider {
-    type Provider = OpenAiCompatibleProvider;
+    type Provider = Self;
 
     fn metadata() -> ProviderMetadata {
-        ProviderMetadata::new(
+        let mut metadata = ProviderMetadata::new(
             TANZU_PROVIDER_NAME,
             "Tanzu AI Services",
             "LLM access via VMware Tanzu Platform AI Services (OpenAI-compatible)",
@@ -59,410 +280,6323 @@ impl ProviderDef for TanzuAIServicesProvider {
                 ConfigKey::new("TANZU_AI_API_KEY", true, true, None),
                 ConfigKey::new("TANZU_AI_ENDPOINT", true, false, None),
                 ConfigKey::new("TANZU_AI_CONFIG_URL", false, false, None),
+                ConfigKey::new("TANZU_AI_CONFIG_API_KEY", false, true, None),
                 ConfigKey::new("TANZU_AI_MODEL_NAME", false, false, None),
+                ConfigKey::new("TANZU_AI_LEAD_MODEL", false, false, None),
+                ConfigKey::new("TANZU_AI_WORKER_MODEL", false, false, None),
             ],
         )
-        .with_unlisted_models()
+        .with_unlisted_models();
+
+        // Models already discovered in this process, with their advertised context lengths
+        for model in capabilities::discovered_models() {
+            if !model.supports("CHAT") && !model.supports("TOOLS") {
+                continue;
+            }
+            let info = model.model_info();
+            match metadata
+                .known_models
+                .iter_mut()
+                .find(|m| m.name == info.name)
+            {
+                Some(known) => *known = info,
+                None => metadata.known_models.push(info),
+            }
+        }
+        metadata
     }
 
-    fn from_env(model: ModelConfig) -> BoxFuture<'static, Result<OpenAiCompatibleProvider>> {
+    fn from_env(mut model: ModelConfig) -> BoxFuture<'static, Result<Self>> {
         Box::pin(async move {
-            let creds = resolve_credentials()?;
+            egress::check_other_providers()?;
+            let client = TanzuClient::from_env().await?;
 
-            // The OpenAI-compatible base URL is {endpoint_base}/openai
-            let host = format!("{}/openai", creds.endpoint_base.trim_end_matches('/'));
+            let actual = client.resolve_model_name(&model.model_name);
+            if actual != model.model_name {
+                tracing::debug!("Resolved model alias {} to {}", model.model_name, actual);
+                model.model_name = actual;
+            }
 
-            let api_client = ApiClient::new(host, AuthMethod::BearerToken(creds.api_key))?;
+            let fallback_models = crate::config::Config::global()
+                .get_param::<String>("TANZU_AI_FALLBACK_MODELS")
+                .map(|list| parse_model_list(&list))
+                .unwrap_or_default();
 
-            Ok(OpenAiCompatibleProvider::new(
-                TANZU_PROVIDER_NAME.to_string(),
-                api_client,
+            let mut provider = Self {
+                client,
                 model,
-                String::new(), // no extra prefix; paths are relative to host
-            ))
+                embedding_model: OnceCell::new(),
+                transcription_model: OnceCell::new(),
+                fallback_models,
+                usage: Arc::new(UsageLedger::from_config()),
+                structured_output: Mutex::new(HashMap::new()),
+                tool_support: Mutex::new(HashMap::new()),
+                context_lengths: Mutex::new(HashMap::new()),
+                poller: None,
+                audit: AuditLog::from_config().map(Arc::new),
+                model_params: params::ModelParams::from_config(),
+                parallel_tools: parallel::ParallelToolCalls::from_config(),
+                model_list: Mutex::new(None),
+                auto_compact: compact::enabled(),
+                tiered: tiered::TieredRouting::from_config(),
+                fast_model: OnceCell::new(),
+                lead_worker: None,
+                pinned_revision: revision::pinned(),
+                sessions: Default::default(),
+                content_filters: dlp::ContentFilters::from_config()?,
+                reasoning_effort: reasoning::configured_effort(),
+                best_of: best_of::BestOf::from_config(),
+                response_cache: response_cache::ResponseCache::from_config(),
+                platform_prompt: platform_prompt::PlatformPrompt::from_config(),
+            };
+
+            if !model_configured() {
+                if let Some(selected) = provider.select_default_model().await {
+                    if selected != provider.model.model_name {
+                        tracing::info!(
+                            "No model configured, selected {} from the bound plan",
+                            selected
+                        );
+                        provider.model.model_name = selected;
+                    }
+                }
+            }
+
+            // An explicitly configured limit wins over what the plan advertises
+            if provider.model.context_limit.is_none() {
+                let model_name = provider.model.model_name.clone();
+                if let Some(limit) = provider.context_length_for(&model_name).await {
+                    provider.model = provider.model.clone().with_context_limit(Some(limit));
+                }
+            }
+
+            if let Some(pair) = lead_worker::LeadWorker::from_config(&provider.model.model_name) {
+                let pair = pair.resolved(|m| provider.client.resolve_model_name(m));
+                match provider.fetch_supported_models().await {
+                    Ok(models) => pair.validate(&models)?,
+                    Err(e) => tracing::warn!(
+                        "Could not check the lead and worker models against the plan: {}",
+                        e
+                    ),
+                }
+                provider.lead_worker = Some(pair);
+            }
+
+            let model_name = provider.model.model_name.clone();
+            let advertised = provider.client.advertised_model(&model_name).await;
+            if let Some(deprecation) = advertised.as_ref().and_then(|m| m.deprecation.as_ref()) {
+                deprecation::warn_once(&model_name, deprecation);
+            }
+            if let Some(pinned) = &provider.pinned_revision {
+                let served = advertised.as_ref().and_then(|m| m.revision.as_deref());
+                revision::check(&model_name, pinned, served);
+            }
+
+            let poll_secs: Option<u64> = crate::config::Config::global()
+                .get_param("TANZU_AI_CONFIG_POLL_SECS")
+                .ok()
+                .filter(|secs| *secs > 0);
+            if let Some(secs) = poll_secs {
+                provider.start_polling(Duration::from_secs(secs));
+            }
+            registrar::start();
+            provider.prefetch_models();
+
+            let preflight: bool = crate::config::Config::global()
+                .get_param("TANZU_AI_PREFLIGHT")
+                .unwrap_or(false);
+            // Replayed fixtures stand in for the endpoint, which may be unreachable
+            if preflight && !replay::is_replaying() {
+                provider.verify_connection().await?;
+            }
+
+            Ok(provider)
         })
     }
 }
 
-/// Resolve credentials from environment variables or VCAP_SERVICES.
-///
-/// Priority:
-/// 1. Explicit env vars (TANZU_AI_ENDPOINT + TANZU_AI_API_KEY)
-/// 2. VCAP_SERVICES auto-detection
-fn resolve_credentials() -> Result<TanzuCredentials> {
-    let config = crate::config::Config::global();
+impl TanzuBinding {
+    /// Set up the transport for a binding without contacting the endpoint.
+    fn build(credentials: TanzuCredentials, http: reqwest::Client) -> Self {
+        let endpoint_base = credentials.endpoint_base.clone();
+        let binding_name = credentials.binding_name.clone();
+        let tokens = Arc::new(TokenManager::new(credentials.api_key.clone(), move || {
+            let endpoint_base = endpoint_base.clone();
+            let binding_name = binding_name.clone();
+            async move { reresolve_api_key(&endpoint_base, binding_name.as_deref()).await }
+        }));
 
-    // Try explicit configuration first
-    let endpoint: Result<String, _> = config.get_param("TANZU_AI_ENDPOINT");
-    let api_key: Result<String, _> = config.get_secret("TANZU_AI_API_KEY");
+        let mut transport = Transport::new(http.clone(), &credentials.endpoint_base, tokens)
+            .with_flavor(credentials.flavor.clone());
+        if let AuthMethod::ClientCredentials(client) = &credentials.auth {
+            transport = transport.with_uaa(UaaTokens::new(client.clone(), http));
+        }
+        let models = credentials.model_name.iter().cloned().collect();
 
-    if let (Ok(endpoint), Ok(api_key)) = (endpoint, api_key) {
-        let config_url: Option<String> = config.get_param("TANZU_AI_CONFIG_URL").ok();
-        let model_name: Option<String> = config.get_param("TANZU_AI_MODEL_NAME").ok();
+        Self {
+            credentials,
+            transport,
+            models: Arc::new(RwLock::new(models)),
+            etags: Arc::new(etag::ETagCache::default()),
+        }
+    }
 
-        return Ok(TanzuCredentials {
-            endpoint_base: endpoint,
-            api_key,
-            config_url,
-            model_name,
-        });
+    /// Models this binding currently routes.
+    fn models(&self) -> Vec<String> {
+        self.models.read().unwrap().clone()
+    }
+
+    fn set_models(&self, models: Vec<String>) {
+        *self.models.write().unwrap() = models;
+    }
+
+    fn serves(&self, model_name: &str) -> bool {
+        self.models.read().unwrap().iter().any(|m| m == model_name)
     }
 
-    // Try VCAP_SERVICES
-    if let Ok(vcap) = std::env::var("VCAP_SERVICES") {
-        if let Some(creds) = parse_vcap_services(&vcap) {
-            return Ok(creds);
+    /// Set up the transport and, if requested, discover the models it serves for routing.
+    async fn new(
+        credentials: TanzuCredentials,
+        http: reqwest::Client,
+        timeouts: TimeoutSettings,
+        discover: bool,
+    ) -> Result<Self> {
+        let mut binding = Self::build(credentials, http);
+        binding.transport = binding.transport.with_timeouts(timeouts);
+        if discover {
+            match binding.discover_served_models().await {
+                Ok(models) if !models.is_empty() => binding.set_models(models),
+                Ok(_) => {}
+                Err(e) => tracing::warn!(
+                    "Tanzu AI model discovery failed for {}: {}",
+                    binding.credentials.endpoint_base,
+                    e
+                ),
+            }
         }
+        Ok(binding)
     }
 
-    anyhow::bail!(
-        "Tanzu AI Services credentials not found. Set TANZU_AI_ENDPOINT and TANZU_AI_API_KEY, \
-         or run on Cloud Foundry with a bound genai service instance."
-    )
-}
+    /// Discover the models advertised by this binding, served from the cache when fresh.
+    async fn discover(&self) -> Result<Vec<AdvertisedModel>> {
+        let key = self.credentials.discovery_key();
+        let ttl = discovery_ttl();
+        if let Some(models) = DISCOVERY_CACHE.get(&key, ttl) {
+            return Ok(models);
+        }
+        let disk = disk_cache::DiskCache::from_config();
+        if let Some(models) = disk.as_ref().and_then(|d| d.load(&self.credentials, ttl)) {
+            DISCOVERY_CACHE.insert(key, models.clone());
+            return Ok(models);
+        }
 
-/// Parse credentials from the VCAP_SERVICES environment variable.
-///
-/// Looks for `genai` service bindings and supports both single-model
-/// and multi-model credential formats.
-fn parse_vcap_services(vcap_json: &str) -> Option<TanzuCredentials> {
-    let vcap: Value = serde_json::from_str(vcap_json).ok()?;
-    let genai_bindings = vcap.get("genai")?.as_array()?;
-
-    // Check for a specific binding name override
-    let binding_name = std::env::var("TANZU_AI_BINDING_NAME").ok();
-
-    let binding = if let Some(ref name) = binding_name {
-        genai_bindings.iter().find(|b| {
-            b.get("name")
-                .and_then(|n| n.as_str())
-                .map(|n| n == name.as_str())
-                .unwrap_or(false)
-        })?
-    } else {
-        genai_bindings.first()?
-    };
+        let models = discover_models(
+            self.transport.http(),
+            &self.credentials,
+            &self.transport.bearer_token().await?,
+            &self.etags,
+        )
+        .await?;
+        DISCOVERY_CACHE.insert(key, models.clone());
+        if let Some(disk) = disk {
+            let (credentials, models) = (self.credentials.clone(), models.clone());
+            tokio::task::spawn_blocking(move || disk.store(&credentials, &models));
+        }
+        Ok(models)
+    }
 
-    let creds = binding.get("credentials")?;
-    parse_binding_credentials(creds)
+    /// Discover the models this binding can serve conversations with: chat models and
+    /// COMPLETION-only models, which go to the completions endpoint.
+    async fn discover_served_models(&self) -> Result<Vec<String>> {
+        Ok(filter_served_models(&self.discover().await?))
+    }
 }
 
-/// Parse credentials from a single binding's credentials object.
-///
-/// Handles both formats:
-/// - Multi-model: only `endpoint` block present
-/// - Single-model: top-level `api_base`, `model_name`, and optionally `endpoint`
-fn parse_binding_credentials(creds: &Value) -> Option<TanzuCredentials> {
-    // Try multi-model format first (recommended): only endpoint block
-    if let Some(endpoint) = creds.get("endpoint") {
-        let endpoint_base = endpoint.get("api_base")?.as_str()?.to_string();
-        let api_key = endpoint.get("api_key")?.as_str()?.to_string();
-        let config_url = endpoint
-            .get("config_url")
-            .and_then(|v| v.as_str())
-            .map(String::from);
+impl TanzuAIServicesProvider {
+    /// Check that every binding is reachable, accepts its API key, and that the
+    /// configured model is served by one of them.
+    ///
+    /// Runs on startup with `TANZU_AI_PREFLIGHT=true`.
+    pub async fn verify_connection(&self) -> Result<(), PreflightError> {
+        let available = self.client.verify().await?;
 
-        // If model_name exists at top level, this is single-model format with endpoint block
-        let model_name = creds
-            .get("model_name")
-            .and_then(|v| v.as_str())
-            .map(String::from);
+        // Some proxies return an empty listing; only flag the model when we know what is offered
+        let model = &self.model.model_name;
+        if !available.is_empty() && !available.contains(model) {
+            return Err(PreflightError::UnknownModel {
+                model: model.clone(),
+                available,
+            });
+        }
+        Ok(())
+    }
 
-        return Some(TanzuCredentials {
-            endpoint_base,
-            api_key,
-            config_url,
-            model_name,
-        });
+    /// A telemetry span for `operation` on the binding `model_name` routes to.
+    fn telemetry_span(&self, operation: &'static str, model_name: &str) -> tracing::Span {
+        let binding = route_binding(
+            &self.client.bindings,
+            &self.client.resolve_model_name(model_name),
+        );
+        telemetry::span(
+            operation,
+            Some(model_name),
+            &binding.credentials.endpoint_base,
+            binding.credentials.binding_name.as_deref(),
+        )
     }
 
-    // Fall back to single-model format (deprecated): top-level api_base with /openai suffix
-    let api_base = creds.get("api_base")?.as_str()?;
-    let api_key = creds.get("api_key")?.as_str()?.to_string();
-    let model_name = creds
-        .get("model_name")
-        .and_then(|v| v.as_str())
-        .map(String::from);
+    /// Warm the discovery cache in the background, so the first model listing does not
+    /// wait on the config URL. Disabled with `TANZU_AI_PREFETCH_MODELS=false`.
+    fn prefetch_models(&self) {
+        let enabled: bool = crate::config::Config::global()
+            .get_param("TANZU_AI_PREFETCH_MODELS")
+            .unwrap_or(true);
+        if !enabled {
+            return;
+        }
+        for binding in self.client.bindings.iter().cloned() {
+            tokio::spawn(async move {
+                if let Err(e) = binding.discover().await {
+                    tracing::debug!(
+                        "Prefetching models from {} failed: {}",
+                        binding.credentials.endpoint_base,
+                        e
+                    );
+                }
+            });
+        }
+    }
 
-    Some(TanzuCredentials {
-        endpoint_base: strip_openai_suffix(api_base),
-        api_key,
-        config_url: None,
-        model_name,
-    })
-}
+    /// Refresh advertised models in the background at `interval`.
+    fn start_polling(&mut self, interval: Duration) {
+        let targets = self
+            .client
+            .bindings
+            .iter()
+            .map(|b| poll::PollTarget {
+                credentials: b.credentials.clone(),
+                transport: b.transport.clone(),
+                models: b.models.clone(),
+                etags: b.etags.clone(),
+            })
+            .collect();
+        tracing::debug!("Polling Tanzu AI config every {:?}", interval);
+        self.poller = Some(poll::spawn(
+            targets,
+            self.model.model_name.clone(),
+            interval,
+        ));
+    }
 
-/// Strip the `/openai` suffix from a single-model format `api_base`.
-fn strip_openai_suffix(api_base: &str) -> String {
-    api_base
-        .trim_end_matches('/')
-        .trim_end_matches("/openai")
-        .to_string()
-}
+    /// Choose a chat model when none is configured.
+    ///
+    /// A binding that names its model is used as-is; otherwise the best advertised
+    /// chat model across bindings is picked (see [`select::select_chat_model`]).
+    async fn select_default_model(&self) -> Option<String> {
+        if let Some(name) = self
+            .client
+            .bindings
+            .iter()
+            .find_map(|b| b.credentials.model_name.clone())
+        {
+            return Some(name);
+        }
 
-/// Discover available models from the config URL endpoint.
-///
-/// The config URL returns metadata including advertised models with their capabilities.
-/// Falls back to the OpenAI `/v1/models` endpoint if the config URL is unavailable.
-#[allow(dead_code)]
-async fn discover_models(creds: &TanzuCredentials) -> Result<Vec<AdvertisedModel>> {
-    let client = reqwest::Client::new();
+        let mut advertised = Vec::new();
+        for binding in &self.client.bindings {
+            match binding.discover().await {
+                Ok(models) => advertised.extend(models),
+                Err(e) => tracing::debug!(
+                    "Model discovery failed for {}: {}",
+                    binding.credentials.endpoint_base,
+                    e
+                ),
+            }
+        }
+        let preferences = crate::config::Config::global()
+            .get_param::<String>("TANZU_AI_MODEL_PREFERENCE")
+            .map(|list| parse_model_list(&list))
+            .unwrap_or_default();
+        select::select_chat_model(&advertised, &preferences)
+    }
 
-    // Try config URL first for rich metadata
-    if let Some(config_url) = &creds.config_url {
-        let response = client
-            .get(config_url)
-            .bearer_auth(&creds.api_key)
-            .send()
-            .await;
+    /// Context length advertised for a model by the binding that serves it.
+    ///
+    /// Remembered once discovery has answered for the model, so completions don't look it
+    /// up again; a failed discovery is retried on the next request.
+    async fn context_length_for(&self, model_name: &str) -> Option<usize> {
+        if let Some(known) = self.context_lengths.lock().unwrap().get(model_name) {
+            return *known;
+        }
+        let context_length = self
+            .client
+            .advertised_model(model_name)
+            .await?
+            .context_length;
+        self.context_lengths
+            .lock()
+            .unwrap()
+            .insert(model_name.to_string(), context_length);
+        context_length
+    }
 
-        if let Ok(resp) = response {
-            if resp.status().is_success() {
-                if let Ok(config) = resp.json::<ConfigResponse>().await {
-                    if !config.advertised_models.is_empty() {
-                        return Ok(config.advertised_models);
+    /// Build the chat payload with the model's parameter profile applied, truncating
+    /// oversized tool results, rejecting prompts that still exceed the context length
+    /// or the request size limit, and fitting `max_tokens` into the rest of the window.
+    async fn build_request(
+        &self,
+        format: WireFormat,
+        model_config: &ModelConfig,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+        stream: bool,
+    ) -> Result<Value, ProviderError> {
+        let model_name = &model_config.model_name;
+        let parallel = if tools.is_empty() || format != WireFormat::OpenAi {
+            None
+        } else {
+            self.parallel_tool_calls(model_name).await
+        };
+        let effort = match format {
+            WireFormat::OpenAi => self.reasoning_effort(model_name).await,
+            _ => None,
+        };
+        let cache_salt = match self.client.advertised_model(model_name).await {
+            Some(m)
+                if format == WireFormat::OpenAi
+                    && m.has_capability(prompt_cache::PROMPT_CACHE_CAPABILITY) =>
+            {
+                let binding = self.client.binding_for_model(model_name).await;
+                Some(prompt_cache::salt(&binding.credentials.endpoint_base))
+            }
+            _ => None,
+        };
+        let system = match &self.platform_prompt {
+            Some(prompt) => {
+                let advertised = self.client.advertised_model(model_name).await;
+                let model = platform_prompt::ModelContext {
+                    name: model_name.clone(),
+                    capabilities: advertised
+                        .as_ref()
+                        .map(|m| m.capabilities.clone())
+                        .unwrap_or_default(),
+                    context_length: advertised.and_then(|m| m.context_length),
+                };
+                prompt.apply(system, &model)
+            }
+            None => system.to_string(),
+        };
+        let build = |messages: &[Message]| -> Result<Value, ProviderError> {
+            let mut payload =
+                format.create_request(model_config, &system, messages, tools, stream)?;
+            // A model parameter profile may still set its own effort
+            if let Some(effort) = effort {
+                payload["reasoning_effort"] = Value::from(effort);
+            }
+            if format == WireFormat::OpenAi {
+                stop_tokens::apply_defaults(&mut payload, model_name);
+            }
+            self.model_params.apply(&mut payload, model_name);
+            if let Some(enabled) = parallel {
+                parallel::apply(&mut payload, enabled);
+            }
+            if let Some(salt) = &cache_salt {
+                prompt_cache::apply(&mut payload, salt);
+            }
+            Ok(payload)
+        };
+
+        let mut payload = build(messages)?;
+        if let Some(limit) = self.context_length_for(model_name).await {
+            if truncate::enabled() {
+                let estimated = context::estimate_prompt_tokens(&payload);
+                if let Some(fitted) = truncate::fit_tool_results(messages, estimated, limit) {
+                    payload = build(&fitted)?;
+                }
+            }
+            context::check_context_length(&payload, model_name, limit)?;
+            context::clamp_max_tokens(&mut payload, model_name, limit);
+        }
+        self.content_filters.apply(&mut payload, model_name)?;
+        if let Some(max_bytes) = context::max_request_bytes() {
+            context::check_request_size(&payload, model_name, max_bytes)?;
+        }
+        Ok(payload)
+    }
+
+    /// The model a request for `model_name` goes to once lead/worker or tiered routing
+    /// has been applied.
+    async fn tiered_model(
+        &self,
+        model_name: &str,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> String {
+        let own_model = self.client.resolve_model_name(&self.model.model_name);
+        if self.client.resolve_model_name(model_name) != own_model {
+            return model_name.to_string();
+        }
+        if let Some(pair) = &self.lead_worker {
+            return pair.model_for(lead_worker::Phase::of(messages)).to_string();
+        }
+        let Some(tiers) = &self.tiered else {
+            return model_name.to_string();
+        };
+        let strong = || tiers.strong_model.clone().unwrap_or(own_model.clone());
+        match tiers.classify(system, messages, !tools.is_empty()) {
+            tiered::Tier::Strong => strong(),
+            tiered::Tier::Fast => match self.fast_model(tiers).await {
+                Some(fast) => {
+                    tracing::debug!("Routing short request to fast model {}", fast);
+                    fast.clone()
+                }
+                None => strong(),
+            },
+        }
+    }
+
+    /// The configured fast model, else the smallest chat model the bindings advertise.
+    async fn fast_model(&self, tiers: &tiered::TieredRouting) -> Option<&String> {
+        self.fast_model
+            .get_or_try_init(|| async {
+                if let Some(model) = &tiers.fast_model {
+                    return Ok(model.clone());
+                }
+                let mut advertised = Vec::new();
+                for binding in &self.client.bindings {
+                    match binding.discover().await {
+                        Ok(models) => advertised.extend(models),
+                        Err(e) => tracing::debug!(
+                            "Model discovery failed for {}: {}",
+                            binding.credentials.endpoint_base,
+                            e
+                        ),
                     }
                 }
+                // Nothing is remembered when discovery finds no fast model, so it is retried
+                select::select_fast_model(&advertised).ok_or(())
+            })
+            .await
+            .ok()
+    }
+
+    /// Whether `model_name` may return several tool calls in one reply, when configured
+    /// or advertised.
+    async fn parallel_tool_calls(&self, model_name: &str) -> Option<bool> {
+        if let Some(enabled) = self.parallel_tools.setting(model_name) {
+            return Some(enabled);
+        }
+        self.client
+            .advertised_model(model_name)
+            .await
+            .filter(|m| m.has_capability(parallel::PARALLEL_TOOLS_CAPABILITY))
+            .map(|_| true)
+    }
+
+    /// The configured reasoning effort when `model_name` advertises reasoning.
+    async fn reasoning_effort(&self, model_name: &str) -> Option<&str> {
+        let effort = self.reasoning_effort.as_deref()?;
+        self.client
+            .advertised_model(model_name)
+            .await
+            .filter(|m| m.has_capability(reasoning::REASONING_CAPABILITY))
+            .map(|_| effort)
+    }
+
+    /// Reject images for models whose advertised capabilities lack VISION.
+    ///
+    /// Models without advertised capabilities are given the benefit of the doubt.
+    async fn check_vision(
+        &self,
+        model_name: &str,
+        messages: &[Message],
+    ) -> Result<(), ProviderError> {
+        if !vision::contains_images(messages) {
+            return Ok(());
+        }
+        match self.client.advertised_model(model_name).await {
+            Some(m)
+                if !m.capabilities.is_empty() && !m.has_capability(vision::VISION_CAPABILITY) =>
+            {
+                Err(vision::unsupported(model_name))
             }
+            _ => Ok(()),
         }
     }
 
-    // Fall back to OpenAI /v1/models endpoint
-    let models_url = format!(
-        "{}/openai/v1/models",
-        creds.endpoint_base.trim_end_matches('/')
-    );
-    let response = client
-        .get(&models_url)
-        .bearer_auth(&creds.api_key)
-        .send()
-        .await?;
+    /// Complete a conversation and parse the reply as JSON conforming to `schema`.
+    ///
+    /// The request walks the fallback chain like any completion. Each model is sent the
+    /// schema as `response_format` when it advertises structured output; models that
+    /// advertise capabilities without it get prompt-based JSON. For models without
+    /// advertised capabilities native support is probed, and only a rejection naming
+    /// `response_format` is remembered.
+    pub async fn complete_structured(
+        &self,
+        session_id: Option<&str>,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Value, ProviderUsage), ProviderError> {
+        let (message, usage) = self
+            .complete_once(session_id, &self.model, system, messages, &[], Some(schema))
+            .await?;
+        let value = structured::extract_json(&message.as_concat_text())?;
+        Ok((value, usage))
+    }
 
-    let json: Value = response.json().await?;
-    let models = json
-        .get("data")
-        .and_then(|d| d.as_array())
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|m| {
-                    Some(AdvertisedModel {
-                        name: m.get("id")?.as_str()?.to_string(),
-                        capabilities: vec!["CHAT".to_string()],
-                    })
-                })
-                .collect()
+    /// Whether `model_name` takes `response_format`, as remembered or advertised; `None`
+    /// until a model without advertised capabilities has been probed.
+    async fn native_structured_output(&self, model_name: &str) -> Option<bool> {
+        if let Some(known) = self
+            .structured_output
+            .lock()
+            .unwrap()
+            .get(model_name)
+            .copied()
+        {
+            return Some(known);
+        }
+        let advertised = self
+            .client
+            .advertised_model(model_name)
+            .await
+            .filter(|m| !m.capabilities.is_empty())?;
+        let native = structured::STRUCTURED_OUTPUT_CAPABILITIES
+            .iter()
+            .any(|c| advertised.has_capability(c));
+        self.structured_output
+            .lock()
+            .unwrap()
+            .insert(model_name.to_string(), native);
+        Some(native)
+    }
+
+    /// Whether `model_name` can return native tool calls.
+    ///
+    /// Bindings with a config URL are trusted. For the others a small completion offering
+    /// a dummy tool is sent once per model and the conclusive outcome remembered.
+    async fn supports_tools(&self, model_name: &str) -> bool {
+        if let Some(known) = self.tool_support.lock().unwrap().get(model_name).copied() {
+            return known;
+        }
+        if self.client.completion_only(model_name).await {
+            return false;
+        }
+        let binding = self.client.binding_for_model(model_name).await;
+        if binding.credentials.config_url.is_some() {
+            return true;
+        }
+
+        let mut model_config = self.model.clone();
+        model_config.model_name = model_name.to_string();
+        let format = binding.credentials.wire_format;
+        let outcome = async {
+            let payload = format.create_request(
+                &model_config,
+                "",
+                &tools::probe_messages(),
+                &[tools::probe_tool()],
+                false,
+            )?;
+            let response = binding
+                .transport
+                .chat_completion(None, format, &payload)
+                .await?;
+            Ok(format.parse_response(&response)?.0)
+        }
+        .await;
+
+        match tools::probe_outcome(&outcome) {
+            Some(supported) => {
+                if !supported {
+                    tracing::info!(
+                        "{} does not support tool calls, using text-only tool emulation",
+                        model_name
+                    );
+                }
+                self.tool_support
+                    .lock()
+                    .unwrap()
+                    .insert(model_name.to_string(), supported);
+                supported
+            }
+            None => true,
+        }
+    }
+
+    /// The advertised revision of `model_name`, looked up before a request so that
+    /// discovery is not triggered once it has been sent.
+    async fn advertised_revision(&self, model_name: &str) -> Option<String> {
+        self.client
+            .advertised_model(model_name)
+            .await
+            .and_then(|m| m.revision)
+    }
+
+    /// Check a served revision against the pin for the configured model and record it
+    /// in the usage report.
+    fn note_revision(&self, model_name: &str, served: Option<&str>) {
+        if let Some(pinned) = self
+            .pinned_revision
+            .as_deref()
+            .filter(|_| model_name == self.model.model_name)
+        {
+            revision::check(model_name, pinned, served);
+        }
+        if let Some(served) = served {
+            self.usage.record_revision(model_name, served);
+        }
+    }
+
+    /// Start an audit record for a chat request, when auditing is enabled.
+    fn audit_record(
+        &self,
+        binding: &TanzuBinding,
+        model_name: &str,
+        payload: &Value,
+        started: Instant,
+    ) -> Option<AuditRecord> {
+        let log = self.audit.as_ref()?;
+        let mut record = AuditRecord::new(
+            model_name,
+            &binding.credentials.endpoint_base,
+            started.elapsed(),
+        );
+        if log.include_content() {
+            record.request = payload.get("messages").cloned();
+        }
+        Some(record)
+    }
+
+    fn write_audit(&self, binding: &TanzuBinding, record: AuditRecord) {
+        if let Some(log) = &self.audit {
+            log.record(record, &binding.transport.api_key());
+        }
+    }
+
+    /// Complete each prompt as its own single-turn conversation, with up to
+    /// `TANZU_AI_BATCH_CONCURRENCY` requests in flight.
+    ///
+    /// Every prompt gets a result; one failure does not stop the rest of the batch.
+    pub async fn complete_batch(
+        &self,
+        session_id: Option<&str>,
+        system: &str,
+        prompts: &[String],
+    ) -> TanzuBatchResult {
+        batch::run(prompts, batch::concurrency(), |prompt| async move {
+            let messages = [Message::user().with_text(prompt)];
+            self.complete_with_model(session_id, &self.model, system, &messages, &[])
+                .await
         })
-        .unwrap_or_default();
+        .await
+    }
+
+    /// Run the benchmark suite on every chat model of the plan, one model at a time.
+    pub async fn benchmark(&self) -> Result<TanzuBenchmarkReport, ProviderError> {
+        let models = self.fetch_supported_models().await?;
+        let mut report = TanzuBenchmarkReport::default();
+        for model in models {
+            let mut cases = Vec::with_capacity(benchmark::SUITE.len());
+            for case in &benchmark::SUITE {
+                cases.push(self.benchmark_case(&model, case).await);
+            }
+            report.models.push(ModelBenchmark { model, cases });
+        }
+        Ok(report)
+    }
+
+    async fn benchmark_case(
+        &self,
+        model_name: &str,
+        case: &benchmark::BenchmarkCase,
+    ) -> BenchmarkCaseResult {
+        if case.uses_tools() && !self.supports_tools(model_name).await {
+            return BenchmarkCaseResult::not_run(
+                case,
+                "the model does not support tool calls".to_string(),
+            );
+        }
+        let mut model_config = self.model.clone();
+        model_config.model_name = model_name.to_string();
+        let (index, binding) = self.client.dispatch_binding(model_name).await;
+        let format = self.client.wire_format_for(binding, model_name).await;
+        let messages = [Message::user().with_text(case.prompt)];
+        let payload = match self
+            .build_request(
+                format,
+                &model_config,
+                benchmark::SYSTEM_PROMPT,
+                &messages,
+                &case.tools(),
+                true,
+            )
+            .await
+        {
+            Ok(payload) => payload,
+            Err(e) => return BenchmarkCaseResult::not_run(case, e.to_string()),
+        };
+        let started = Instant::now();
+        match self.client.chat_stream(None, index, format, &payload).await {
+            Ok(stream) => benchmark::run_case(case, stream, started).await,
+            Err(e) => BenchmarkCaseResult::not_run(case, e.to_string()),
+        }
+    }
+
+    /// Prompt tokens of a request to the provider's model, counted for its tokenizer
+    /// family (llama, qwen, mistral and gpt-oss split text differently).
+    pub async fn count_tokens(&self, system: &str, messages: &[Message], tools: &[Tool]) -> usize {
+        let model_name = self.client.resolve_model_name(&self.model.model_name);
+        tokens::count_prompt_tokens(&model_name, system, messages, tools).await
+    }
+
+    /// Tokens consumed through this provider, by Goose session and model.
+    pub fn usage_report(&self) -> TanzuUsageReport {
+        self.usage.report()
+    }
+
+    /// Post usage still queued for `TANZU_AI_USAGE_WEBHOOK`, for embedding code shutting
+    /// down. Usage left unsent when the last provider is dropped is spilled instead.
+    pub async fn flush_usage(&self) {
+        self.usage.flush().await;
+    }
+
+    /// Tanzu-specific context of a session, which session export stores under
+    /// [`TanzuSessionMetadata::EXTENSION_KEY`].
+    pub async fn session_metadata(&self, session_id: &str) -> TanzuSessionMetadata {
+        let (binding, model) = match self.sessions.get(Some(session_id)) {
+            Some(pin) => (&self.client.bindings[pin.binding], pin.model),
+            None => {
+                let model = self.client.resolve_model_name(&self.model.model_name);
+                (self.client.binding_for_model(&model).await, model)
+            }
+        };
+        let report = self.usage.report();
+        let revision = match report.revisions.get(&model) {
+            Some(served) => Some(served.clone()),
+            None => self.advertised_revision(&model).await,
+        };
+        TanzuSessionMetadata {
+            endpoint: binding.credentials.endpoint_base.clone(),
+            binding_name: binding.credentials.binding_name.clone(),
+            binding_guid: binding.credentials.binding_guid.clone(),
+            model,
+            revision,
+            usage: report.session_total(session_id),
+        }
+    }
+
+    /// Reconnect an imported session to the binding and model recorded in its
+    /// metadata, carrying over its usage.
+    ///
+    /// Returns false, leaving the session to normal routing, when this app is no
+    /// longer bound to the recorded service instance.
+    pub async fn restore_session(&self, session_id: &str, metadata: &TanzuSessionMetadata) -> bool {
+        let bindings = self.client.bindings.iter().map(|b| {
+            (
+                b.credentials.binding_guid.as_deref(),
+                b.credentials.binding_name.as_deref(),
+                b.credentials.endpoint_base.as_str(),
+            )
+        });
+        let Some(index) = session::find_binding(metadata, bindings) else {
+            tracing::warn!(
+                "Session {} ran on Tanzu binding {}, which this app is no longer bound to",
+                session_id,
+                metadata
+                    .binding_name
+                    .as_deref()
+                    .unwrap_or(&metadata.endpoint)
+            );
+            return false;
+        };
+        if let Some(recorded) = &metadata.revision {
+            if let Some(served) = self
+                .advertised_revision(&metadata.model)
+                .await
+                .filter(|served| !revision::matches(recorded, served))
+            {
+                tracing::warn!(
+                    "Session {} used {} at revision {}, which is now served at revision {}",
+                    session_id,
+                    metadata.model,
+                    recorded,
+                    served
+                );
+            }
+        }
+        self.usage
+            .restore_session(session_id, &metadata.model, metadata.usage);
+        self.sessions.insert(
+            session_id,
+            session::SessionPin {
+                binding: index,
+                model: metadata.model.clone(),
+            },
+        );
+        true
+    }
+
+    /// The model to request for a session, and the session's pin when it applies.
+    ///
+    /// A restored session keeps the model it ran on in place of the configured one.
+    async fn session_model(
+        &self,
+        session_id: Option<&str>,
+        model_name: &str,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> (String, Option<session::SessionPin>) {
+        match self
+            .sessions
+            .get(session_id)
+            .filter(|_| model_name == self.model.model_name)
+        {
+            Some(pin) => (pin.model.clone(), Some(pin)),
+            None => (
+                self.tiered_model(model_name, system, messages, tools).await,
+                None,
+            ),
+        }
+    }
+
+    /// The binding to send `model_name` to: the pinned one for a restored session,
+    /// otherwise whichever the dispatcher picks.
+    async fn session_binding(
+        &self,
+        pin: Option<&session::SessionPin>,
+        model_name: &str,
+    ) -> (usize, &TanzuBinding) {
+        match pin.filter(|pin| pin.model == model_name) {
+            Some(pin) => (pin.binding, &self.client.bindings[pin.binding]),
+            None => self.client.dispatch_binding(model_name).await,
+        }
+    }
+
+    /// Run `filter` over the message text of every chat request, after the filters
+    /// configured with `TANZU_AI_CONTENT_FILTERS`.
+    pub fn with_content_filter(mut self, filter: Arc<dyn ContentFilter>) -> Self {
+        self.content_filters.push(filter);
+        self
+    }
+
+    /// Drop cached discovery results for this provider's bindings so the next
+    /// model listing queries the config URL again.
+    pub fn invalidate_model_cache(&self) {
+        *self.model_list.lock().unwrap() = None;
+        self.context_lengths.lock().unwrap().clear();
+        self.client.invalidate_discovery();
+    }
+
+    /// Every model the bindings advertise, embedding models included, with its
+    /// capabilities and context length.
+    pub async fn fetch_model_info(&self) -> Result<Vec<TanzuModelInfo>, ProviderError> {
+        self.client.models().await
+    }
+
+    /// What the provider supports for each configured model, from advertised
+    /// capabilities and earlier requests, without sending any probes.
+    pub async fn capabilities(&self) -> TanzuCapabilities {
+        let mut names = self.model_chain(&self.model.model_name);
+        if let Some(pair) = &self.lead_worker {
+            for name in [&pair.lead, &pair.worker] {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        let mut models = Vec::with_capacity(names.len());
+        for name in names {
+            models.push(self.model_capabilities(name).await);
+        }
+
+        let mut embeddings = crate::config::Config::global()
+            .get_param::<String>("TANZU_AI_EMBEDDING_MODEL")
+            .is_ok();
+        for binding in &self.client.bindings {
+            if embeddings {
+                break;
+            }
+            embeddings = binding
+                .discover()
+                .await
+                .is_ok_and(|advertised| !filter_embedding_models(&advertised).is_empty());
+        }
+        TanzuCapabilities { embeddings, models }
+    }
+
+    async fn model_capabilities(&self, model_name: String) -> TanzuModelCapabilities {
+        let binding = self.client.binding_for_model(&model_name).await;
+        let format = self.client.wire_format_for(binding, &model_name).await;
+        let advertised = self
+            .client
+            .advertised_model(&model_name)
+            .await
+            .filter(|m| !m.capabilities.is_empty());
+        let advertises = |capability: &str| match &advertised {
+            Some(m) => Support::from(m.has_capability(capability)),
+            None => Support::Unknown,
+        };
+
+        let known_tools = self.tool_support.lock().unwrap().get(&model_name).copied();
+        let tools = match known_tools {
+            Some(known) => Support::from(known),
+            None if self.client.completion_only(&model_name).await => Support::No,
+            None if binding.credentials.config_url.is_some() => Support::Yes,
+            None => Support::Unknown,
+        };
+        let known_structured = self
+            .structured_output
+            .lock()
+            .unwrap()
+            .get(&model_name)
+            .copied();
+        let structured_output = match known_structured {
+            _ if format != WireFormat::OpenAi => Support::No,
+            Some(known) => Support::from(known),
+            None => match &advertised {
+                Some(m) => Support::from(
+                    structured::STRUCTURED_OUTPUT_CAPABILITIES
+                        .iter()
+                        .any(|c| m.has_capability(c)),
+                ),
+                None => Support::Unknown,
+            },
+        };
+        let prompt_caching = match advertises(prompt_cache::PROMPT_CACHE_CAPABILITY) {
+            Support::Yes if format == WireFormat::OpenAi => Support::Yes,
+            _ => Support::No,
+        };
+
+        TanzuModelCapabilities {
+            streaming: true,
+            tools,
+            vision: advertises(vision::VISION_CAPABILITY),
+            structured_output,
+            prompt_caching,
+            context_length: self.context_length_for(&model_name).await,
+            model: model_name,
+        }
+    }
+
+    /// Names of the connection profiles defined in the environment and config.
+    pub fn profiles() -> Vec<String> {
+        profile::names()
+    }
+
+    /// The profile in use, if any.
+    pub fn active_profile() -> Option<String> {
+        profile::active()
+    }
+
+    /// A provider for the same model connected through profile `name`, which stays
+    /// selected for providers created afterwards in this process. The selection is
+    /// left unchanged if the profile cannot be used.
+    pub async fn switch_profile(&self, name: &str) -> Result<Self> {
+        let previous = profile::select(Some(name));
+        let switched = <Self as ProviderDef>::from_env(self.model.clone()).await;
+        if switched.is_err() {
+            profile::select(previous.as_deref());
+        }
+        switched
+    }
+
+    /// List the plan's chat models, bypassing every cache.
+    ///
+    /// Documents the endpoint reports as unchanged are still not transferred again.
+    pub async fn refresh_supported_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.invalidate_model_cache();
+        self.fetch_supported_models().await
+    }
+
+    /// The requested model followed by the configured fallbacks, aliases resolved.
+    fn model_chain(&self, model_name: &str) -> Vec<String> {
+        let mut chain = vec![self.client.resolve_model_name(model_name)];
+        for fallback in &self.fallback_models {
+            let fallback = self.client.resolve_model_name(fallback);
+            if !chain.contains(&fallback) {
+                chain.push(fallback);
+            }
+        }
+        chain
+    }
+}
+
+impl TanzuAIServicesProvider {
+    /// Select the embedding model and the binding that serves it.
+    ///
+    /// `TANZU_AI_EMBEDDING_MODEL` pins a model name; otherwise the first
+    /// EMBEDDING-capable model advertised by any binding is used.
+    async fn embedding_target(&self) -> Result<&(usize, String), ProviderError> {
+        self.embedding_model
+            .get_or_try_init(|| async {
+                let configured: Option<String> = crate::config::Config::global()
+                    .get_param("TANZU_AI_EMBEDDING_MODEL")
+                    .ok();
+
+                for (index, binding) in self.client.bindings.iter().enumerate() {
+                    let advertised =
+                        match binding.discover().await {
+                            Ok(advertised) => advertised,
+                            Err(e) => {
+                                tracing::warn!(
+                                    "Tanzu AI model discovery failed for {}: {}",
+                                    binding.credentials.endpoint_base,
+                                    e
+                                );
+                                continue;
+                            }
+                        };
+                    let candidates = filter_embedding_models(&advertised);
+                    let found = match &configured {
+                        Some(name) => candidates.into_iter().find(|m| m == name),
+                        None => candidates.into_iter().next(),
+                    };
+                    if let Some(model) = found {
+                        return Ok((index, model));
+                    }
+                }
+
+                // A pinned model that discovery doesn't know about is sent to the default binding
+                configured.map(|name| (0, name)).ok_or_else(|| {
+                    ProviderError::ExecutionError(
+                        "No EMBEDDING-capable model is advertised by the bound Tanzu AI Services plan. \
+                         Set TANZU_AI_EMBEDDING_MODEL to choose one explicitly."
+                            .to_string(),
+                    )
+                })
+            })
+            .await
+    }
+}
+
+impl TanzuAIServicesProvider {
+    /// Transcribe a recording with the plan's AUDIO-capable model.
+    ///
+    /// `file_name` tells the endpoint the audio format; `language` is an optional
+    /// ISO-639-1 hint.
+    pub async fn transcribe(
+        &self,
+        audio: &[u8],
+        file_name: &str,
+        language: Option<&str>,
+    ) -> Result<String, ProviderError> {
+        let (index, model) = self.transcription_target().await?;
+        let binding = &self.client.bindings[*index];
+        audio::transcribe(&binding.transport, model, audio, file_name, language).await
+    }
+
+    /// Select the transcription model and the binding that serves it.
+    ///
+    /// `TANZU_AI_TRANSCRIPTION_MODEL` pins a model name; otherwise the first
+    /// AUDIO-capable model advertised by any binding is used.
+    async fn transcription_target(&self) -> Result<&(usize, String), ProviderError> {
+        self.transcription_model
+            .get_or_try_init(|| async {
+                let configured: Option<String> = crate::config::Config::global()
+                    .get_param("TANZU_AI_TRANSCRIPTION_MODEL")
+                    .ok();
+
+                for (index, binding) in self.client.bindings.iter().enumerate() {
+                    let advertised = match binding.discover().await {
+                        Ok(advertised) => advertised,
+                        Err(e) => {
+                            tracing::warn!(
+                                "Tanzu AI model discovery failed for {}: {}",
+                                binding.credentials.endpoint_base,
+                                e
+                            );
+                            continue;
+                        }
+                    };
+                    let found = advertised
+                        .into_iter()
+                        .filter(|m| m.has_capability("AUDIO"))
+                        .map(|m| m.name)
+                        .find(|name| configured.as_ref().is_none_or(|c| c == name));
+                    if let Some(model) = found {
+                        return Ok((index, model));
+                    }
+                }
+
+                configured.map(|name| (0, name)).ok_or_else(|| {
+                    ProviderError::ExecutionError(
+                        "No AUDIO-capable model is advertised by the bound Tanzu AI Services plan. \
+                         Set TANZU_AI_TRANSCRIPTION_MODEL to choose one explicitly."
+                            .to_string(),
+                    )
+                })
+            })
+            .await
+    }
+}
+
+/// Whether the user picked a model through `TANZU_AI_MODEL_NAME` or `GOOSE_MODEL`.
+fn model_configured() -> bool {
+    let config = crate::config::Config::global();
+    ["TANZU_AI_MODEL_NAME", "GOOSE_MODEL"]
+        .iter()
+        .any(|key| config.get_param::<String>(key).is_ok())
+}
+
+/// Find the deployed model name for an alias declared by any binding.
+fn resolve_model_alias<'a>(
+    credentials: impl IntoIterator<Item = &'a TanzuCredentials>,
+    alias: &str,
+) -> Option<&'a str> {
+    credentials
+        .into_iter()
+        .find(|c| c.model_aliases.iter().any(|a| a == alias))
+        .and_then(|c| c.model_name.as_deref())
+}
+
+/// Route a model name to the binding that advertises it.
+fn route_binding<'a>(bindings: &'a [TanzuBinding], model_name: &str) -> &'a TanzuBinding {
+    bindings
+        .iter()
+        .find(|b| b.serves(model_name))
+        .unwrap_or(&bindings[0])
+}
+
+impl TanzuAIServicesProvider {
+    /// One completion attempt over the model chain, without compaction.
+    async fn complete_once(
+        &self,
+        session_id: Option<&str>,
+        model_config: &ModelConfig,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+        schema: Option<&Value>,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let reduced =
+            tool_budget::max_tools().and_then(|max| tool_budget::reduce(tools, messages, max));
+        let tools = reduced.as_deref().unwrap_or(tools);
+        let (requested, pin) = self
+            .session_model(
+                session_id,
+                &model_config.model_name,
+                system,
+                messages,
+                tools,
+            )
+            .await;
+        let chain = self.model_chain(&requested);
+        let mut last_error = None;
+
+        let mut next = 0;
+        while let Some(model_name) = chain.get(next) {
+            next += 1;
+            let mut model_config = model_config.clone();
+            model_config.model_name = model_name.clone();
+
+            let (index, binding) = self.session_binding(pin.as_ref(), model_name).await;
+            tracing::debug!(
+                "Routing {} to Tanzu binding {}",
+                model_name,
+                binding
+                    .credentials
+                    .binding_name
+                    .as_deref()
+                    .unwrap_or(&binding.credentials.endpoint_base)
+            );
+
+            let emulate = !tools.is_empty() && !self.supports_tools(model_name).await;
+            let (system, messages, tools) = if emulate {
+                (
+                    tools::emulation_instructions(system, tools),
+                    tools::flatten_tool_messages(messages),
+                    &[][..],
+                )
+            } else {
+                (system.to_string(), messages.to_vec(), tools)
+            };
+
+            let format = self.client.wire_format_for(binding, model_name).await;
+            let known_structured = match schema {
+                Some(_) => self.native_structured_output(model_name).await,
+                None => None,
+            };
+            // Models without advertised capabilities are probed with `response_format`
+            let native = schema.is_some()
+                && format == WireFormat::OpenAi
+                && known_structured.unwrap_or(true);
+            let system = match schema {
+                Some(schema) if !native => structured::schema_instructions(&system, schema),
+                _ => system,
+            };
+            let mut payload = self
+                .build_request(format, &model_config, &system, &messages, tools, false)
+                .await?;
+            if let Some(schema) = schema.filter(|_| native) {
+                payload["response_format"] = structured::response_format(schema);
+            }
+            if format == WireFormat::OpenAi {
+                self.best_of.apply(&mut payload);
+            }
+            self.check_vision(model_name, &messages).await?;
+            let revision = self.advertised_revision(model_name).await;
+            let endpoint = &binding.credentials.endpoint_base;
+            let cache = self
+                .response_cache
+                .as_ref()
+                .filter(|_| response_cache::ResponseCache::cacheable(&payload));
+            let cached = cache.and_then(|cache| cache.get(endpoint, &payload));
+            let hit = cached.is_some();
+            let started = Instant::now();
+            let response = match cached {
+                Some(cached) => {
+                    tracing::debug!(
+                        "Reusing the cached reply to an identical {} request",
+                        model_name
+                    );
+                    Ok(cached)
+                }
+                None => {
+                    self.client
+                        .chat_completion(session_id, index, format, &payload)
+                        .await
+                }
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    if let Some(record) = self.audit_record(binding, model_name, &payload, started)
+                    {
+                        self.write_audit(binding, record.with_error(e.error()));
+                    }
+                    if native && known_structured.is_none() && structured::is_unsupported(e.error())
+                    {
+                        tracing::info!(
+                            "{} rejected response_format, falling back to prompt-based JSON: {}",
+                            model_name,
+                            e
+                        );
+                        self.structured_output
+                            .lock()
+                            .unwrap()
+                            .insert(model_name.clone(), false);
+                        // Ask the same model again with the schema in the prompt
+                        next -= 1;
+                        continue;
+                    }
+                    if !e.is_model_unavailable() {
+                        return Err(e.into());
+                    }
+                    tracing::warn!(
+                        "{} is not available, trying the next fallback: {}",
+                        model_name,
+                        e
+                    );
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            if native && known_structured.is_none() {
+                self.structured_output
+                    .lock()
+                    .unwrap()
+                    .insert(model_name.clone(), true);
+            }
+            if *model_name != chain[0] {
+                tracing::info!(
+                    "Request for {} served by fallback model {}",
+                    chain[0],
+                    model_name
+                );
+            }
+
+            let (mut message, usage) = match best_of::candidates(&response) {
+                Some(candidates) if format == WireFormat::OpenAi => {
+                    self.select_candidate(session_id, model_name, &messages, &payload, candidates)
+                        .await?
+                }
+                _ => format.parse_response(&response)?,
+            };
+            if let Some(cache) = cache.filter(|_| !hit) {
+                let (cache, endpoint) = (cache.clone(), endpoint.clone());
+                let (payload, response) = (payload.clone(), response.clone());
+                tokio::task::spawn_blocking(move || cache.put(&endpoint, &payload, &response));
+            }
+            if let Some(sanitizer) = stop_tokens::Sanitizer::for_request(model_name, &payload) {
+                message = sanitizer.clean_message(message);
+            }
+            if emulate {
+                message = tools::parse_tool_calls(message);
+            }
+            if !tools.is_empty() && self.parallel_tool_calls(model_name).await == Some(false) {
+                message = parallel::first_tool_call_only(message);
+            }
+            let served_by = response
+                .get("model")
+                .and_then(Value::as_str)
+                .unwrap_or(model_name);
+            // A cached reply cost nothing and sent no request to audit
+            if hit {
+                return Ok((
+                    message,
+                    ProviderUsage::new(served_by.to_string(), Usage::default()),
+                ));
+            }
+            self.usage.record(session_id, served_by, &usage);
+            if let Some(cached) = prompt_cache::cached_tokens(&response) {
+                self.usage.record_cache_hits(session_id, served_by, cached);
+            }
+            self.note_revision(model_name, revision.as_deref());
+            if let Some(record) = self.audit_record(binding, served_by, &payload, started) {
+                self.write_audit(binding, record.with_usage(&usage).with_revision(revision));
+            }
+            return Ok((message, ProviderUsage::new(served_by.to_string(), usage)));
+        }
+
+        Err(last_error.expect("model chain is never empty").into())
+    }
+
+    /// Parse the candidates of a reply and return the one `TANZU_AI_CANDIDATE_SELECTION`
+    /// picks.
+    async fn select_candidate(
+        &self,
+        session_id: Option<&str>,
+        model_name: &str,
+        messages: &[Message],
+        payload: &Value,
+        candidates: Vec<Value>,
+    ) -> Result<(Message, Usage), ProviderError> {
+        let format = WireFormat::OpenAi;
+        // Every candidate carries the usage of the whole response
+        let usage = format.usage(&candidates[0]);
+        let sanitizer = stop_tokens::Sanitizer::for_request(model_name, payload);
+        let mut replies = Vec::with_capacity(candidates.len());
+        for candidate in &candidates {
+            let (message, _) = format.parse_response(candidate)?;
+            replies.push(match &sanitizer {
+                Some(sanitizer) => sanitizer.clean_message(message),
+                None => message,
+            });
+        }
+
+        let selected = match &self.best_of.selection {
+            best_of::Selection::First => 0,
+            best_of::Selection::Longest => best_of::longest(&replies),
+            best_of::Selection::Judge(judge) => {
+                let judge = judge.as_deref().unwrap_or(model_name);
+                match self
+                    .judge_candidates(session_id, judge, messages, &replies)
+                    .await
+                {
+                    Ok(selected) => selected,
+                    Err(e) => {
+                        tracing::warn!(
+                            "Judging candidates with {} failed, keeping the first: {}",
+                            judge,
+                            e
+                        );
+                        0
+                    }
+                }
+            }
+        };
+        tracing::debug!(
+            "Selected candidate {} of {} from {}",
+            selected + 1,
+            replies.len(),
+            model_name
+        );
+        Ok((replies.swap_remove(selected), usage))
+    }
+
+    /// Ask `judge` which of `candidates` best answers `messages`.
+    async fn judge_candidates(
+        &self,
+        session_id: Option<&str>,
+        judge: &str,
+        messages: &[Message],
+        candidates: &[Message],
+    ) -> Result<usize, ProviderError> {
+        let mut model_config = self.model.clone();
+        model_config.model_name = judge.to_string();
+        let (index, binding) = self.client.dispatch_binding(judge).await;
+        let format = self.client.wire_format_for(binding, judge).await;
+        let prompt = [Message::user().with_text(best_of::judge_prompt(messages, candidates))];
+        let mut payload = self
+            .build_request(
+                format,
+                &model_config,
+                best_of::JUDGE_SYSTEM_PROMPT,
+                &prompt,
+                &[],
+                false,
+            )
+            .await?;
+        if let Some(payload) = payload.as_object_mut() {
+            payload.remove("n");
+        }
+        let response = self
+            .client
+            .chat_completion(session_id, index, format, &payload)
+            .await?;
+        let (answer, usage) = format.parse_response(&response)?;
+        self.usage.record(session_id, judge, &usage);
+        let answer = answer.as_concat_text();
+        best_of::parse_verdict(&answer, candidates.len()).ok_or_else(|| {
+            ProviderError::ExecutionError(format!(
+                "the judge's answer names no candidate: {}",
+                answer
+            ))
+        })
+    }
+
+    /// One streaming attempt over the model chain, without compaction.
+    async fn stream_once(
+        &self,
+        session_id: &str,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<MessageStream, ProviderError> {
+        let reduced =
+            tool_budget::max_tools().and_then(|max| tool_budget::reduce(tools, messages, max));
+        let tools = reduced.as_deref().unwrap_or(tools);
+        let (requested, pin) = self
+            .session_model(
+                Some(session_id),
+                &self.model.model_name,
+                system,
+                messages,
+                tools,
+            )
+            .await;
+        let chain = self.model_chain(&requested);
+        let emulated = !tools.is_empty() && !self.supports_tools(&chain[0]).await;
+        if emulated || self.best_of.candidates.is_some() {
+            // Emulated tool calls are parsed from the whole reply and candidates are
+            // compared whole, so neither is streamed
+            let (message, usage) = self
+                .complete_with_model(Some(session_id), &self.model, system, messages, tools)
+                .await?;
+            return Ok(Box::pin(futures::stream::once(async move {
+                Ok((Some(message), Some(usage)))
+            })));
+        }
+        let mut last_error = None;
+
+        for model_name in &chain {
+            let mut model_config = self.model.clone();
+            model_config.model_name = model_name.clone();
+
+            let (index, binding) = self.session_binding(pin.as_ref(), model_name).await;
+            let format = self.client.wire_format_for(binding, model_name).await;
+            let payload = self
+                .build_request(format, &model_config, system, messages, tools, true)
+                .await?;
+            self.check_vision(model_name, messages).await?;
+            let revision = self.advertised_revision(model_name).await;
+            let started = Instant::now();
+            match self
+                .client
+                .chat_stream(Some(session_id), index, format, &payload)
+                .await
+            {
+                Err(e) => {
+                    if let Some(record) = self.audit_record(binding, model_name, &payload, started)
+                    {
+                        self.write_audit(binding, record.with_error(e.error()));
+                    }
+                    if !e.is_model_unavailable() {
+                        return Err(e.into());
+                    }
+                    tracing::warn!(
+                        "{} is not available, trying the next fallback: {}",
+                        model_name,
+                        e
+                    );
+                    last_error = Some(e);
+                }
+                Ok(stream) => {
+                    if *model_name != chain[0] {
+                        tracing::info!(
+                            "Stream for {} served by fallback model {}",
+                            chain[0],
+                            model_name
+                        );
+                    }
+                    self.note_revision(model_name, revision.as_deref());
+                    let record = self
+                        .audit_record(binding, model_name, &payload, started)
+                        .map(|record| record.with_revision(revision));
+                    let stream = match stop_tokens::Sanitizer::for_request(model_name, &payload) {
+                        Some(sanitizer) => stop_tokens::sanitize_stream(stream, sanitizer),
+                        None => stream,
+                    };
+                    let stream = estimate::fill_missing_usage(stream, payload, model_name.clone());
+                    let mut stream = timing::timed_stream(
+                        stream,
+                        started,
+                        model_name.clone(),
+                        self.usage.clone(),
+                        session_id.to_string(),
+                    );
+                    if !tools.is_empty()
+                        && self.parallel_tool_calls(model_name).await == Some(false)
+                    {
+                        stream = parallel::serialize_stream(stream);
+                    }
+                    let stream = match (&self.audit, record) {
+                        (Some(log), Some(record)) => audit::audit_stream(
+                            stream,
+                            log.clone(),
+                            record,
+                            binding.transport.api_key(),
+                            started,
+                        ),
+                        _ => stream,
+                    };
+                    return Ok(record_stream_usage(stream, self.usage.clone(), session_id));
+                }
+            }
+        }
+
+        Err(last_error.expect("model chain is never empty").into())
+    }
+
+    /// Replace the older half of a conversation with a summary of it.
+    ///
+    /// Older turns too long for one summarization request are summarized in chunks
+    /// whose summaries are then summarized together. `error` is returned when there is
+    /// nothing older to summarize.
+    async fn compact_messages(
+        &self,
+        session_id: Option<&str>,
+        model_config: &ModelConfig,
+        messages: &[Message],
+        error: String,
+    ) -> Result<Vec<Message>, ProviderError> {
+        let Some((older, recent)) = compact::split(messages) else {
+            return Err(ProviderError::ContextLengthExceeded(error));
+        };
+        let model_name = self.client.resolve_model_name(&model_config.model_name);
+        let context_length = match self.context_length_for(&model_name).await {
+            Some(length) => length,
+            None => model_config.context_limit(),
+        };
+        tracing::info!(
+            "Conversation exceeds {}'s context ({}); summarizing {} older messages",
+            model_name,
+            error,
+            older.len()
+        );
+
+        let mut summaries = Vec::new();
+        for chunk in compact::transcript_chunks(older, context_length) {
+            summaries.push(self.summarize(session_id, model_config, &chunk).await?);
+        }
+        let summary = if summaries.len() == 1 {
+            summaries.remove(0)
+        } else {
+            self.summarize(session_id, model_config, &summaries.join("\n\n"))
+                .await?
+        };
+        Ok(compact::compacted(&summary, recent))
+    }
+
+    async fn summarize(
+        &self,
+        session_id: Option<&str>,
+        model_config: &ModelConfig,
+        transcript: &str,
+    ) -> Result<String, ProviderError> {
+        let (message, _) = self
+            .complete_once(
+                session_id,
+                model_config,
+                compact::SUMMARY_PROMPT,
+                &[compact::summary_request(transcript)],
+                &[],
+                None,
+            )
+            .await?;
+        Ok(message.as_concat_text())
+    }
+}
+
+#[async_trait]
+impl Provider for TanzuAIServicesProvider {
+    fn get_name(&self) -> &str {
+        TANZU_PROVIDER_NAME
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+
+    async fn complete_with_model(
+        &self,
+        session_id: Option<&str>,
+        model_config: &ModelConfig,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let span = self.telemetry_span("chat", &model_config.model_name);
+        let result = async {
+            let (messages, reply) = match self
+                .complete_once(session_id, model_config, system, messages, tools, None)
+                .await
+            {
+                Err(ProviderError::ContextLengthExceeded(e)) if self.auto_compact => {
+                    let messages = self
+                        .compact_messages(session_id, model_config, messages, e)
+                        .await?;
+                    let reply = self
+                        .complete_once(session_id, model_config, system, &messages, tools, None)
+                        .await?;
+                    (messages, reply)
+                }
+                other => (messages.to_vec(), other?),
+            };
+            if tools.is_empty() || !tool_args::enabled() {
+                return Ok(reply);
+            }
+            let invalid = tool_args::invalid_calls(&reply.0, tools);
+            if invalid.is_empty() {
+                return Ok(reply);
+            }
+            for call in &invalid {
+                tracing::warn!(
+                    "{} called {} with invalid arguments, asking it to retry: {}",
+                    model_config.model_name,
+                    call.tool,
+                    call.errors.join("; ")
+                );
+            }
+            let messages = tool_args::repair_messages(&messages, &invalid);
+            self.complete_once(session_id, model_config, system, &messages, tools, None)
+                .await
+        }
+        .instrument(span.clone())
+        .await;
+        if let Ok((_, usage)) = &result {
+            telemetry::record_usage(&span, usage);
+        }
+        telemetry::record_outcome(&span, &result);
+        result
+    }
+
+    async fn fetch_supported_models(&self) -> Result<Vec<String>, ProviderError> {
+        if let Some((listed_at, models)) = &*self.model_list.lock().unwrap() {
+            if listed_at.elapsed() < discovery_ttl() {
+                return Ok(models.clone());
+            }
+        }
+
+        let mut models: Vec<String> = Vec::new();
+        let mut last_error = None;
+
+        for binding in &self.client.bindings {
+            let span = telemetry::span(
+                "list_models",
+                None,
+                &binding.credentials.endpoint_base,
+                binding.credentials.binding_name.as_deref(),
+            );
+            let discovered = binding
+                .discover_served_models()
+                .instrument(span.clone())
+                .await;
+            telemetry::record_discovery(&span, &discovered);
+            match discovered {
+                Ok(discovered) => {
+                    for model in discovered {
+                        let model = self.client.resolve_model_name(&model);
+                        if !models.contains(&model) {
+                            models.push(model);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Tanzu AI model discovery failed for {}: {}",
+                        binding.credentials.endpoint_base,
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if models.is_empty() => Err(ProviderError::RequestFailed(format!(
+                "Failed to list Tanzu AI Services models: {}",
+                e
+            ))),
+            // A partial listing is not cached, so the next one retries the failed bindings
+            Some(_) => Ok(models),
+            None => {
+                *self.model_list.lock().unwrap() = Some((Instant::now(), models.clone()));
+                Ok(models)
+            }
+        }
+    }
+
+    fn supports_embeddings(&self) -> bool {
+        true
+    }
+
+    async fn create_embeddings(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let (index, model) = self.embedding_target().await?;
+        let binding = &self.client.bindings[*index];
+        embeddings::create_embeddings(&binding.transport, model, texts).await
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    async fn stream(
+        &self,
+        session_id: &str,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<MessageStream, ProviderError> {
+        let span = self.telemetry_span("chat_stream", &self.model.model_name);
+        let result = async {
+            match self.stream_once(session_id, system, messages, tools).await {
+                Err(ProviderError::ContextLengthExceeded(e)) if self.auto_compact => {
+                    let messages = self
+                        .compact_messages(Some(session_id), &self.model, messages, e)
+                        .await?;
+                    self.stream_once(session_id, system, &messages, tools).await
+                }
+                other => other,
+            }
+        }
+        .instrument(span.clone())
+        .await;
+        telemetry::record_outcome(&span, &result);
+        let result = result.map(|stream| telemetry::instrument_stream(stream, span));
+        if tools.is_empty() || !tool_args::enabled() {
+            return result;
+        }
+        result.map(|stream| tool_args::warn_invalid(stream, tools.to_vec()))
+    }
+}
+
+/// Record the usage chunk of a stream in the ledger as it passes through.
+fn record_stream_usage(
+    stream: MessageStream,
+    ledger: Arc<UsageLedger>,
+    session_id: &str,
+) -> MessageStream {
+    use futures::StreamExt;
+    let session_id = session_id.to_string();
+    Box::pin(stream.inspect(move |item| {
+        if let Ok((_, Some(usage))) = item {
+            ledger.record(Some(&session_id), &usage.model, &usage.usage);
+        }
+    }))
+}
+
+/// Resolve credentials from the first [`credential_source`] that has any.
+///
+/// The result is restricted to `TANZU_AI_REQUIRED_REGION`; see [`residency`].
+async fn resolve_credentials() -> Result<Vec<TanzuCredentials>> {
+    let mut found = credential_source::resolve(&credential_source::default_sources()).await?;
+    let config_api_key: Option<String> = crate::config::Config::global()
+        .get_secret("TANZU_AI_CONFIG_API_KEY")
+        .ok();
+    if let Some(key) = config_api_key.filter(|key| !key.is_empty()) {
+        for credentials in &mut found {
+            credentials.config_api_key = Some(key.clone());
+        }
+    }
+    residency::restrict(found, residency::required_region().as_deref())
+}
+
+/// Re-resolve credentials and return the current API key for a binding.
+///
+/// Used by the token manager to pick up rotated keys before the old JWT expires.
+async fn reresolve_api_key(endpoint_base: &str, binding_name: Option<&str>) -> Result<String> {
+    resolve_credentials()
+        .await?
+        .into_iter()
+        .find(|c| c.endpoint_base == endpoint_base && c.binding_name.as_deref() == binding_name)
+        .map(|c| c.api_key)
+        .ok_or_else(|| anyhow::anyhow!("binding for {} is no longer available", endpoint_base))
+}
+
+/// Criteria for choosing among several `genai` bindings.
+///
+/// Each criterion that is set must match; unset criteria match everything.
+#[derive(Debug, Default)]
+struct BindingSelector {
+    /// `TANZU_AI_BINDING_NAME`: binding `name` or `instance_name`
+    name: Option<String>,
+    /// `TANZU_AI_BINDING_TAG`: one of the service `tags` (case-insensitive)
+    tag: Option<String>,
+    /// `TANZU_AI_BINDING_PLAN`: the service `plan`
+    plan: Option<String>,
+}
+
+impl BindingSelector {
+    fn from_env() -> Self {
+        Self {
+            name: std::env::var("TANZU_AI_BINDING_NAME").ok(),
+            tag: std::env::var("TANZU_AI_BINDING_TAG").ok(),
+            plan: std::env::var("TANZU_AI_BINDING_PLAN").ok(),
+        }
+    }
+
+    fn matches(&self, binding: &Value) -> bool {
+        let field = |key: &str| binding.get(key).and_then(|v| v.as_str());
+
+        let name_matches = self
+            .name
+            .as_deref()
+            .is_none_or(|name| field("name") == Some(name) || field("instance_name") == Some(name));
+        let tag_matches = self.tag.as_deref().is_none_or(|tag| {
+            binding
+                .get("tags")
+                .and_then(|t| t.as_array())
+                .is_some_and(|tags| {
+                    tags.iter()
+                        .filter_map(|t| t.as_str())
+                        .any(|t| t.eq_ignore_ascii_case(tag))
+                })
+        });
+        let plan_matches = self
+            .plan
+            .as_deref()
+            .is_none_or(|plan| field("plan") == Some(plan));
+
+        name_matches && tag_matches && plan_matches
+    }
+}
+
+/// Tags that mark a service as a GenAI binding, whatever its label.
+const GENAI_TAGS: [&str; 2] = ["genai", "llm"];
+
+/// Why a `VCAP_SERVICES` entry is taken for a GenAI binding, strongest first. Matches
+/// are ordered by it, so the default route is a marketplace binding when there is one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum GenaiMatch {
+    /// Labelled `genai`, the current broker's service
+    Label,
+    /// Labelled `genai-service`, as 0.x brokers registered it
+    LegacyLabel,
+    /// Labelled `genai-*`, as brokers that register one service per plan do
+    LabelPrefix,
+    /// Recognised by a registered [`BindingParser`]
+    Parser,
+    /// Tagged `genai` or `llm` under any other label, e.g. a user-provided service
+    Tagged,
+}
+
+impl GenaiMatch {
+    /// How the entry at `label` with `binding` matches, if it does.
+    fn classify(label: &str, binding: &Value) -> Option<Self> {
+        let label = label.to_ascii_lowercase();
+        if label == "genai" {
+            return Some(Self::Label);
+        }
+        if label == "genai-service" {
+            return Some(Self::LegacyLabel);
+        }
+        if label.starts_with("genai-") || label.starts_with("genai_") {
+            return Some(Self::LabelPrefix);
+        }
+        let tagged = binding
+            .get("tags")
+            .and_then(Value::as_array)
+            .is_some_and(|tags| {
+                tags.iter()
+                    .filter_map(Value::as_str)
+                    .any(|t| GENAI_TAGS.iter().any(|genai| t.eq_ignore_ascii_case(genai)))
+            });
+        tagged.then_some(Self::Tagged)
+    }
+
+    /// Whether the entry is certainly meant as a GenAI binding, so that credentials
+    /// that do not parse are an error rather than a sign it is something else.
+    fn is_certain(self) -> bool {
+        matches!(self, Self::Label | Self::LegacyLabel | Self::Parser)
+    }
+}
+
+/// Every GenAI binding in `VCAP_SERVICES` with its path (e.g. `genai[0]`) and how it
+/// matched, in [`GenaiMatch`] order.
+///
+/// Broker versions have registered the service as `genai`, `genai-service` and one
+/// `genai-<plan>` label per plan, and developers often recreate a plan's credentials
+/// with `cf create-user-provided-service` and a `genai` or `llm` tag rather than
+/// binding the marketplace service. `genai-*` and tagged entries are only used when
+/// their credentials parse as one of the binding formats.
+fn genai_bindings(vcap: &Value) -> Vec<(String, GenaiMatch, &Value)> {
+    let mut found = Vec::new();
+    for (label, entries) in vcap.as_object().into_iter().flatten() {
+        for (index, binding) in entries.as_array().into_iter().flatten().enumerate() {
+            if let Some(kind) = GenaiMatch::classify(label, binding) {
+                found.push((format!("{}[{}]", label, index), kind, binding));
+            }
+        }
+    }
+    found.sort_by_key(|(_, kind, _)| *kind);
+    found
+}
+
+/// Parse credentials from the VCAP_SERVICES environment variable.
+///
+/// Looks for GenAI service bindings under any of the broker's labels and tagged
+/// services (see [`genai_bindings`]) and supports both single-model and multi-model
+/// credential formats. Bindings are filtered by the `TANZU_AI_BINDING_*` selectors; see
+/// [`parse_vcap_services_with`].
+fn parse_vcap_services(vcap_json: &str) -> Result<Vec<TanzuCredentials>, BindingParseError> {
+    parse_vcap_services_with(vcap_json, &BindingSelector::from_env())
+}
+
+/// Parse every GenAI binding accepted by `selector`, including the entries a registered
+/// [`BindingParser`] recognises.
+///
+/// Matches are ordered by how they matched (see [`GenaiMatch`]), then binding name,
+/// then instance GUID, so the default route does not depend on the order Cloud
+/// Foundry happens to emit. Malformed `genai`, `genai-service` and custom bindings are
+/// skipped with a warning; the first one is returned as the error when no binding
+/// parses.
+fn parse_vcap_services_with(
+    vcap_json: &str,
+    selector: &BindingSelector,
+) -> Result<Vec<TanzuCredentials>, BindingParseError> {
+    let vcap =
+        serde_json::from_str::<Value>(vcap_json).map_err(|e| BindingParseError::InvalidJson {
+            path: "VCAP_SERVICES".to_string(),
+            reason: e.to_string(),
+        })?;
+
+    let mut genai = genai_bindings(&vcap);
+    // Registered parsers get tagged entries, whose tags may not mean the GenAI broker
+    let skip: Vec<String> = genai
+        .iter()
+        .filter(|(_, kind, _)| *kind != GenaiMatch::Tagged)
+        .map(|(path, _, _)| path.clone())
+        .collect();
+    let custom = binding_parser::custom_bindings(&vcap, &skip);
+    genai.retain(|(path, _, _)| !custom.iter().any(|(custom, _)| custom == path));
+    let mut matched: Vec<(String, GenaiMatch, &Value)> = genai
+        .into_iter()
+        .chain(
+            custom
+                .iter()
+                .map(|(path, b)| (path.clone(), GenaiMatch::Parser, b)),
+        )
+        .filter(|(_, _, b)| selector.matches(b))
+        .collect();
+    let sort_key = |b: &Value| {
+        let field = |key: &str| {
+            b.get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        (field("name"), field("instance_guid"))
+    };
+    matched.sort_by_key(|(_, kind, b)| (*kind, sort_key(b)));
+
+    let mut bindings = Vec::new();
+    let mut first_error = None;
+    for (path, kind, b) in matched {
+        match parse_vcap_binding(&path, b) {
+            Ok(creds) => bindings.push(creds),
+            // A tagged or `genai-*` service that does not parse is not a GenAI binding
+            Err(_) if !kind.is_certain() => {}
+            Err(e) => {
+                tracing::warn!(
+                    "Skipping genai binding {}: {}",
+                    b.get("name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or("<unnamed>"),
+                    e
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if bindings.is_empty() => Err(e),
+        _ => Ok(bindings),
+    }
+}
+
+/// Parse one `VCAP_SERVICES` entry found at `path`.
+fn parse_vcap_binding(path: &str, binding: &Value) -> Result<TanzuCredentials, BindingParseError> {
+    let credentials_path = binding_error::field_path(path, "credentials");
+    let credentials = binding_error::required(binding, path, "credentials")?;
+    if credhub::is_reference(credentials) {
+        return Err(BindingParseError::UnresolvedCredHub {
+            path: credentials_path,
+        });
+    }
+    let mut creds = parse_binding_credentials(credentials, &credentials_path)?;
+    creds.binding_name = binding
+        .get("name")
+        .and_then(|n| n.as_str())
+        .map(String::from);
+    creds.binding_guid = binding
+        .get("instance_guid")
+        .and_then(|g| g.as_str())
+        .map(String::from);
+    Ok(creds)
+}
+
+/// Parse the output of `cf service-key`.
+///
+/// Accepts the credentials object itself or the newer `{"credentials": {...}}` wrapper,
+/// and skips the "Getting key ..." banner if the output was saved verbatim.
+fn parse_service_key(contents: &str) -> Result<TanzuCredentials, BindingParseError> {
+    let invalid = |reason: String| BindingParseError::InvalidJson {
+        path: "service key".to_string(),
+        reason,
+    };
+    let start = contents
+        .find('{')
+        .ok_or_else(|| invalid("no JSON object found".to_string()))?;
+    let key: Value =
+        serde_json::from_str(&contents[start..]).map_err(|e| invalid(e.to_string()))?;
+    match key.get("credentials") {
+        Some(credentials) => parse_binding_credentials(credentials, "credentials"),
+        None => parse_binding_credentials(&key, ""),
+    }
+}
+
+/// Parse credentials from a single binding's credentials object, found at `path`,
+/// naming the offending field on failure.
+///
+/// Handles both formats:
+/// - Multi-model: only `endpoint` block present
+/// - Single-model: top-level `api_base`, `model_name`, and optionally `endpoint`
+fn parse_binding_credentials(
+    creds: &Value,
+    path: &str,
+) -> Result<TanzuCredentials, BindingParseError> {
+    use binding_error::{field_path, optional_str, required_str};
+
+    if !creds.is_object() {
+        return Err(BindingParseError::WrongType {
+            path: path.to_string(),
+            expected: "an object",
+        });
+    }
+    let region = optional_str(creds, path, "region")?.map(String::from);
+
+    // Try multi-model format first (recommended): only endpoint block
+    if let Some(endpoint) = creds.get("endpoint").filter(|e| !e.is_null()) {
+        let endpoint_path = field_path(path, "endpoint");
+        if !endpoint.is_object() {
+            return Err(BindingParseError::WrongType {
+                path: endpoint_path,
+                expected: "an object",
+            });
+        }
+        let endpoint_base = normalize_api_base(required_str(endpoint, &endpoint_path, "api_base")?);
+        let api_key = required_str(endpoint, &endpoint_path, "api_key")?.to_string();
+        let config_url = optional_str(endpoint, &endpoint_path, "config_url")?.map(String::from);
+
+        // If model_name exists at top level, this is single-model format with endpoint block
+        let model_name = optional_str(creds, path, "model_name")?.map(String::from);
+
+        return Ok(TanzuCredentials {
+            flavor: UpstreamFlavor::detect(Some(creds), &endpoint_base),
+            endpoint_base,
+            api_key,
+            config_url,
+            config_api_key: None,
+            model_name,
+            binding_name: None,
+            binding_guid: None,
+            region,
+            model_aliases: parse_model_aliases(creds),
+            wire_format: parse_wire_format(creds),
+            auth: AuthMethod::ApiKey,
+        });
+    }
+
+    // Fall back to single-model format (deprecated): top-level api_base with /openai suffix
+    if creds.get("api_base").is_none() {
+        return Err(BindingParseError::Missing {
+            path: field_path(path, "endpoint"),
+        });
+    }
+    let api_base = required_str(creds, path, "api_base")?;
+    let api_key = required_str(creds, path, "api_key")?.to_string();
+    let model_name = optional_str(creds, path, "model_name")?.map(String::from);
+
+    let endpoint_base = normalize_api_base(api_base);
+    Ok(TanzuCredentials {
+        flavor: UpstreamFlavor::detect(Some(creds), &endpoint_base),
+        endpoint_base,
+        api_key,
+        config_url: None,
+        config_api_key: None,
+        model_name,
+        binding_name: None,
+        binding_guid: None,
+        region,
+        model_aliases: parse_model_aliases(creds),
+        wire_format: parse_wire_format(creds),
+        auth: AuthMethod::ApiKey,
+    })
+}
+
+/// Parse `model_aliases`, which brokers emit as `null`, a list, or a comma-separated string.
+fn parse_model_aliases(creds: &Value) -> Vec<String> {
+    match creds.get("model_aliases") {
+        Some(Value::Array(aliases)) => aliases
+            .iter()
+            .filter_map(|a| a.as_str())
+            .map(String::from)
+            .collect(),
+        Some(Value::String(aliases)) => parse_model_list(aliases),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_wire_format(creds: &Value) -> WireFormat {
+    WireFormat::parse(creds.get("wire_format").and_then(|v| v.as_str()))
+}
+
+/// Split a comma-separated list of model names.
+fn parse_model_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Normalise an `api_base` for either endpoint layout, stripping any `/openai` suffix.
+fn normalize_api_base(api_base: &str) -> String {
+    endpoint::Endpoint::parse(api_base).base
+}
+
+impl TanzuCredentials {
+    /// Cache key for discovery results: the config URL, or the models endpoint without one.
+    fn discovery_key(&self) -> String {
+        self.config_url.clone().unwrap_or_else(|| {
+            format!(
+                "{}/openai/v1/models",
+                self.endpoint_base.trim_end_matches('/')
+            )
+        })
+    }
+}
+
+/// Process-wide cache of discovery results, shared by all provider instances.
+static DISCOVERY_CACHE: LazyLock<DiscoveryCache> = LazyLock::new(DiscoveryCache::default);
+
+/// In-memory discovery results keyed by config URL.
+#[derive(Default)]
+struct DiscoveryCache {
+    entries: Mutex<HashMap<String, (Instant, Vec<AdvertisedModel>)>>,
+}
+
+impl DiscoveryCache {
+    fn get(&self, key: &str, ttl: Duration) -> Option<Vec<AdvertisedModel>> {
+        let entries = self.entries.lock().unwrap();
+        let (fetched_at, models) = entries.get(key)?;
+        (fetched_at.elapsed() < ttl).then(|| models.clone())
+    }
+
+    fn insert(&self, key: String, models: Vec<AdvertisedModel>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), models));
+    }
+
+    fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /// Every cached model, fresh or not.
+    fn models(&self) -> Vec<AdvertisedModel> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .flat_map(|(_, models)| models.iter().cloned())
+            .collect()
+    }
+}
+
+/// How long discovery results stay fresh (`TANZU_AI_DISCOVERY_TTL_SECS`; 0 disables caching).
+fn discovery_ttl() -> Duration {
+    let secs: u64 = crate::config::Config::global()
+        .get_param("TANZU_AI_DISCOVERY_TTL_SECS")
+        .unwrap_or(TANZU_DEFAULT_DISCOVERY_TTL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Discover available models from the config URL endpoint.
+///
+/// The config URL returns metadata including advertised models with their capabilities.
+/// Falls back to the OpenAI `/v1/models` endpoint if the config URL is unavailable.
+async fn discover_models(
+    client: &reqwest::Client,
+    creds: &TanzuCredentials,
+    api_key: &str,
+    etags: &etag::ETagCache,
+) -> Result<Vec<AdvertisedModel>> {
+    // Try config URL first for rich metadata
+    if let Some(config_url) = &creds.config_url {
+        let path = reqwest::Url::parse(config_url)
+            .map(|url| url.path().to_string())
+            .unwrap_or_else(|_| config_url.clone());
+        let key = creds.config_api_key.as_deref().unwrap_or(api_key);
+        match fetch_discovery(client, &creds.flavor, config_url, &path, key, etags).await {
+            Ok(json) => {
+                if let Ok(config) = serde_json::from_value::<ConfigResponse>(json) {
+                    if !config.advertised_models.is_empty() {
+                        return Ok(config.advertised_models);
+                    }
+                }
+            }
+            Err(e) => match config_auth_failure(&e) {
+                Some(status) => tracing::warn!(
+                    "{}; listing models from the models endpoint instead",
+                    config_auth_message(config_url, status, creds.config_api_key.is_some())
+                ),
+                None => tracing::debug!("Config URL {} unavailable: {}", config_url, e),
+            },
+        }
+    }
+
+    // Azure cannot list deployments; the binding names the one it serves
+    if creds.flavor.is_azure() {
+        let models = creds.model_name.iter().map(|name| AdvertisedModel {
+            name: name.clone(),
+            capabilities: vec!["CHAT".to_string()],
+            context_length: None,
+            deprecation: None,
+            revision: None,
+        });
+        return Ok(models.collect());
+    }
+
+    // Fall back to OpenAI /v1/models endpoint
+    let models_url = format!(
+        "{}/openai/v1/models",
+        creds.endpoint_base.trim_end_matches('/')
+    );
+    let json = fetch_discovery(
+        client,
+        &creds.flavor,
+        &models_url,
+        "openai/v1/models",
+        api_key,
+        etags,
+    )
+    .await?;
+    let models = json
+        .get("data")
+        .and_then(|d| d.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|m| {
+                    Some(AdvertisedModel {
+                        name: m.get("id")?.as_str()?.to_string(),
+                        capabilities: vec!["CHAT".to_string()],
+                        context_length: None,
+                        deprecation: None,
+                        revision: None,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(models)
+}
+
+/// The status of a config URL fetch that failed because the key was rejected.
+fn config_auth_failure(error: &anyhow::Error) -> Option<reqwest::StatusCode> {
+    let status = error.downcast_ref::<reqwest::Error>()?.status()?;
+    matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    )
+    .then_some(status)
+}
+
+/// Explain a rejected config URL key and which setting to change.
+fn config_auth_message(
+    config_url: &str,
+    status: reqwest::StatusCode,
+    separate_key: bool,
+) -> String {
+    if separate_key {
+        format!(
+            "{} rejected TANZU_AI_CONFIG_API_KEY ({}); check that it is valid for the config endpoint",
+            config_url, status
+        )
+    } else {
+        format!(
+            "{} rejected the inference API key ({}); set TANZU_AI_CONFIG_API_KEY if the config endpoint uses a separate credential",
+            config_url, status
+        )
+    }
+}
+
+/// GET a discovery document, or the recorded one when replaying fixtures.
+///
+/// Sends `If-None-Match` when the document was served with an entity tag before and
+/// reuses it on `304 Not Modified`. `api_key` goes in the header `flavor` authenticates
+/// with.
+async fn fetch_discovery(
+    client: &reqwest::Client,
+    flavor: &UpstreamFlavor,
+    url: &str,
+    fixture_path: &str,
+    api_key: &str,
+    etags: &etag::ETagCache,
+) -> Result<Value> {
+    let fixtures = replay::Fixtures::from_config();
+    if let Some(replayed) = fixtures
+        .as_ref()
+        .and_then(|f| f.replay("GET", fixture_path, None))
+    {
+        return Ok(replayed?.into_json()?);
+    }
+    let mut request = flavor
+        .authorize(trace::inject(client.get(url)), api_key)
+        .timeout(TANZU_DISCOVERY_TIMEOUT);
+    if let Some(etag) = etags.etag(url) {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        if let Some(json) = etags.document(url) {
+            tracing::debug!("{} not modified, reusing the cached document", url);
+            return Ok(json);
+        }
+    }
+    let response = response.error_for_status()?;
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let json: Value = response.json().await?;
+    match etag {
+        Some(etag) => etags.store(url, etag, json.clone()),
+        None => etags.remove(url),
+    }
+    if let Some(fixtures) = &fixtures {
+        let body = replay::FixtureBody::Json(json.clone());
+        fixtures.record("GET", fixture_path, None, body, api_key);
+    }
+    Ok(json)
+}
+
+impl AdvertisedModel {
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(capability))
+    }
+}
+
+/// Filter models to only those with chat or tool capabilities.
+fn filter_chat_models(models: &[AdvertisedModel]) -> Vec<String> {
+    models
+        .iter()
+        .filter(|m| {
+            m.capabilities
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case("chat") || c.eq_ignore_ascii_case("tools"))
+        })
+        .map(|m| m.name.clone())
+        .collect()
+}
+
+/// Filter models to chat models and COMPLETION-only models, which are served through
+/// the completions endpoint.
+fn filter_served_models(models: &[AdvertisedModel]) -> Vec<String> {
+    models
+        .iter()
+        .filter(|m| {
+            ["chat", "tools", completion::COMPLETION_CAPABILITY]
+                .iter()
+                .any(|c| m.has_capability(c))
+        })
+        .map(|m| m.name.clone())
+        .collect()
+}
+
+/// Filter models to only those with embedding capability.
+fn filter_embedding_models(models: &[AdvertisedModel]) -> Vec<String> {
+    models
+        .iter()
+        .filter(|m| {
+            m.capabilities
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case("embedding"))
+        })
+        .map(|m| m.name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{
+        body_partial_json, body_string_contains, header, header_exists, method, path, query_param,
+    };
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_provider(credentials: Vec<TanzuCredentials>) -> TanzuAIServicesProvider {
+        let model = ModelConfig::new_or_fail(TANZU_DEFAULT_MODEL);
+        let bindings: Vec<TanzuBinding> = credentials
+            .into_iter()
+            .map(|c| TanzuBinding::build(c, reqwest::Client::new()))
+            .collect();
+        TanzuAIServicesProvider {
+            client: TanzuClient::from_bindings(bindings, balance::BalanceMode::Primary),
+            model,
+            embedding_model: OnceCell::new(),
+            transcription_model: OnceCell::new(),
+            fallback_models: Vec::new(),
+            usage: Arc::new(UsageLedger::default()),
+            structured_output: Mutex::new(HashMap::new()),
+            tool_support: Mutex::new(HashMap::new()),
+            context_lengths: Mutex::new(HashMap::new()),
+            poller: None,
+            audit: None,
+            model_params: params::ModelParams::default(),
+            parallel_tools: parallel::ParallelToolCalls::default(),
+            model_list: Mutex::new(None),
+            auto_compact: false,
+            tiered: None,
+            fast_model: OnceCell::new(),
+            lead_worker: None,
+            pinned_revision: None,
+            sessions: Default::default(),
+            content_filters: Default::default(),
+            reasoning_effort: None,
+            best_of: Default::default(),
+            response_cache: None,
+            platform_prompt: None,
+        }
+    }
+
+    // --- Credential Parsing Tests ---
+
+    #[test]
+    fn test_parse_single_model_credentials() {
+        let json = serde_json::json!({
+            "api_base": "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7/openai",
+            "api_key": "eyJhbGciOiJIUzI1NiJ9.test",
+            "endpoint": {
+                "api_base": "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7",
+                "api_key": "eyJhbGciOiJIUzI1NiJ9.test",
+                "config_url": "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7/config/v1/endpoint",
+                "name": "tanzu-gpt-oss-120b-v1025-eaf66e7"
+            },
+            "model_aliases": null,
+            "model_capabilities": ["chat", "tools"],
+            "model_name": "openai/gpt-oss-120b",
+            "wire_format": "openai"
+        });
+
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
+        assert_eq!(
+            creds.endpoint_base,
+            "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7"
+        );
+        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.test");
+        assert_eq!(creds.model_name, Some("openai/gpt-oss-120b".to_string()));
+        assert!(creds.config_url.is_some());
+        assert_eq!(
+            creds.config_url.unwrap(),
+            "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7/config/v1/endpoint"
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_model_credentials() {
+        let json = serde_json::json!({
+            "endpoint": {
+                "api_base": "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a",
+                "api_key": "eyJhbGciOiJIUzI1NiJ9.multi",
+                "config_url": "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a/config/v1/endpoint",
+                "name": "tanzu-all-models-1a56b7a"
+            }
+        });
+
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
+        assert_eq!(
+            creds.endpoint_base,
+            "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a"
+        );
+        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.multi");
+        assert_eq!(creds.model_name, None);
+        assert!(creds.config_url.is_some());
+    }
+
+    #[test]
+    fn test_parse_deprecated_single_model_no_endpoint() {
+        let json = serde_json::json!({
+            "api_base": "https://genai-proxy.sys.example.com/some-guid/openai",
+            "api_key": "eyJhbGciOiJIUzI1NiJ9.deprecated",
+            "model_name": "llama3:8b",
+            "model_capabilities": ["chat"],
+            "wire_format": "openai"
+        });
+
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
+        assert_eq!(
+            creds.endpoint_base,
+            "https://genai-proxy.sys.example.com/some-guid"
+        );
+        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.deprecated");
+        assert_eq!(creds.model_name, Some("llama3:8b".to_string()));
+        assert!(creds.config_url.is_none());
+    }
+
+    #[test]
+    fn test_parse_model_aliases() {
+        let json = serde_json::json!({
+            "api_base": "https://proxy.example.com/guid/openai",
+            "api_key": "key",
+            "model_name": "openai/gpt-oss-120b",
+            "model_aliases": ["gpt-4", "gpt-4o"]
+        });
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
+        assert_eq!(creds.model_aliases, vec!["gpt-4", "gpt-4o"]);
+
+        let json = serde_json::json!({
+            "endpoint": {"api_base": "https://proxy.example.com/guid", "api_key": "key"},
+            "model_name": "llama3:8b",
+            "model_aliases": "llama3, llama"
+        });
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
+        assert_eq!(creds.model_aliases, vec!["llama3", "llama"]);
+
+        let json = serde_json::json!({
+            "endpoint": {"api_base": "https://proxy.example.com/guid", "api_key": "key"},
+            "model_aliases": null
+        });
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
+        assert!(creds.model_aliases.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_model_alias() {
+        let mut aliased = test_credentials("https://proxy.example.com/guid", None);
+        aliased.model_name = Some("openai/gpt-oss-120b".to_string());
+        aliased.model_aliases = vec!["gpt-4".to_string()];
+        let credentials = vec![
+            test_credentials("https://proxy.example.com/other", None),
+            aliased,
+        ];
+
+        assert_eq!(
+            resolve_model_alias(&credentials, "gpt-4"),
+            Some("openai/gpt-oss-120b")
+        );
+        assert_eq!(resolve_model_alias(&credentials, "llama3.2:1b"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_supported_models_maps_aliases() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/alias-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {"id": "gpt-4", "object": "model"},
+                    {"id": "openai/gpt-oss-120b", "object": "model"}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut credentials = test_credentials(&format!("{}/alias-plan", mock_server.uri()), None);
+        credentials.model_name = Some("openai/gpt-oss-120b".to_string());
+        credentials.model_aliases = vec!["gpt-4".to_string()];
+        let provider = test_provider(vec![credentials]);
+
+        let models = provider.fetch_supported_models().await.unwrap();
+        assert_eq!(models, vec!["openai/gpt-oss-120b"]);
+    }
+
+    // --- URL Construction Tests ---
+
+    #[test]
+    fn test_normalize_api_base() {
+        assert_eq!(
+            normalize_api_base("https://proxy.example.com/guid/openai"),
+            "https://proxy.example.com/guid"
+        );
+        assert_eq!(
+            normalize_api_base("https://proxy.example.com/guid/openai/"),
+            "https://proxy.example.com/guid"
+        );
+        assert_eq!(
+            normalize_api_base("https://proxy.example.com/guid"),
+            "https://proxy.example.com/guid"
+        );
+        assert_eq!(
+            normalize_api_base("https://proxy.example.com/guid/openai/v1"),
+            "https://proxy.example.com/guid"
+        );
+    }
+
+    #[test]
+    fn test_subdomain_style_binding() {
+        let creds = parse_binding_credentials(
+            &serde_json::json!({
+                "endpoint": {
+                    "api_base": "https://3f9a2c.genai.sys.example.com/",
+                    "api_key": "key",
+                }
+            }),
+            "credentials",
+        )
+        .unwrap();
+        assert_eq!(creds.endpoint_base, "https://3f9a2c.genai.sys.example.com");
+        assert_eq!(
+            creds.discovery_key(),
+            "https://3f9a2c.genai.sys.example.com/openai/v1/models"
+        );
+
+        let creds = parse_binding_credentials(
+            &serde_json::json!({
+                "api_base": "https://3f9a2c.genai.sys.example.com/openai",
+                "api_key": "key",
+                "model_name": "llama3.2:1b",
+            }),
+            "credentials",
+        )
+        .unwrap();
+        assert_eq!(creds.endpoint_base, "https://3f9a2c.genai.sys.example.com");
+    }
+
+    #[test]
+    fn test_openai_base_url_construction() {
+        let endpoint_base = "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a";
+        let host = format!("{}/openai", endpoint_base.trim_end_matches('/'));
+        assert_eq!(
+            host,
+            "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a/openai"
+        );
+    }
+
+    // --- VCAP_SERVICES Parsing Tests ---
+
+    #[test]
+    fn test_parse_vcap_services_multi_model() {
+        let vcap = serde_json::json!({
+            "genai": [{
+                "binding_guid": "162e78b4-408b-4bdd-8df3-0ae1e4d6d13b",
+                "binding_name": null,
+                "credentials": {
+                    "endpoint": {
+                        "api_base": "https://genai-proxy.sys.example.com/all-models-9afff1f",
+                        "api_key": "eyJhbGciOiJIUzI1NiJ9.vcap",
+                        "config_url": "https://genai-proxy.sys.example.com/all-models-9afff1f/config/v1/endpoint",
+                        "name": "all-models-9afff1f"
+                    }
+                },
+                "instance_guid": "5008a1ec-c406-4ee8-8f9d-56c723af2f1f",
+                "instance_name": "all-models",
+                "label": "genai",
+                "name": "all-models",
+                "plan": "all-models",
+                "tags": ["genai", "llm"]
+            }]
+        });
+
+        let bindings = parse_vcap_services(&vcap.to_string()).unwrap();
+        assert_eq!(bindings.len(), 1);
+        let creds = &bindings[0];
+        assert_eq!(
+            creds.endpoint_base,
+            "https://genai-proxy.sys.example.com/all-models-9afff1f"
+        );
+        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.vcap");
+        assert!(creds.config_url.is_some());
+        assert_eq!(creds.model_name, None);
+        assert_eq!(creds.binding_name, Some("all-models".to_string()));
+    }
+
+    #[test]
+    fn test_parse_vcap_services_multiple_bindings() {
+        let vcap = serde_json::json!({
+            "genai": [
+                {
+                    "name": "chat",
+                    "credentials": {
+                        "endpoint": {
+                            "api_base": "https://genai-proxy.sys.example.com/chat-plan",
+                            "api_key": "key-chat",
+                            "config_url": "https://genai-proxy.sys.example.com/chat-plan/config/v1/endpoint"
+                        }
+                    }
+                },
+                {
+                    "name": "broken",
+                    "credentials": {}
+                },
+                {
+                    "name": "embeddings",
+                    "credentials": {
+                        "api_base": "https://genai-proxy.sys.example.com/embed-plan/openai",
+                        "api_key": "key-embed",
+                        "model_name": "mxbai-embed-large"
+                    }
+                }
+            ]
+        });
+
+        let bindings = parse_vcap_services(&vcap.to_string()).unwrap();
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings[0].binding_name, Some("chat".to_string()));
+        assert_eq!(bindings[0].api_key, "key-chat");
+        assert_eq!(bindings[1].binding_name, Some("embeddings".to_string()));
+        assert_eq!(
+            bindings[1].endpoint_base,
+            "https://genai-proxy.sys.example.com/embed-plan"
+        );
+    }
+
+    fn selector_test_vcap() -> String {
+        let binding = |name: &str, plan: &str, tags: &[&str], guid: &str| {
+            serde_json::json!({
+                "name": name,
+                "instance_name": format!("{}-instance", name),
+                "instance_guid": guid,
+                "label": "genai",
+                "plan": plan,
+                "tags": tags,
+                "credentials": {
+                    "endpoint": {
+                        "api_base": format!("https://proxy.example.com/{}", name),
+                        "api_key": "key"
+                    }
+                }
+            })
+        };
+        serde_json::json!({
+            "genai": [
+                binding("zeta", "all-models", &["genai", "llm"], "guid-3"),
+                binding("alpha", "embeddings", &["genai"], "guid-1"),
+                binding("mid", "all-models", &["GenAI", "llm"], "guid-2")
+            ]
+        })
+        .to_string()
+    }
+
+    fn binding_names(bindings: &[TanzuCredentials]) -> Vec<&str> {
+        bindings
+            .iter()
+            .filter_map(|b| b.binding_name.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn test_binding_selection_is_ordered_by_name() {
+        let bindings =
+            parse_vcap_services_with(&selector_test_vcap(), &BindingSelector::default()).unwrap();
+        assert_eq!(binding_names(&bindings), vec!["alpha", "mid", "zeta"]);
+    }
+
+    #[test]
+    fn test_binding_selection_by_tag_and_plan() {
+        let vcap = selector_test_vcap();
+
+        let by_tag = BindingSelector {
+            tag: Some("llm".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            binding_names(&parse_vcap_services_with(&vcap, &by_tag).unwrap()),
+            vec!["mid", "zeta"]
+        );
+
+        let by_plan = BindingSelector {
+            plan: Some("embeddings".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            binding_names(&parse_vcap_services_with(&vcap, &by_plan).unwrap()),
+            vec!["alpha"]
+        );
+
+        let combined = BindingSelector {
+            tag: Some("genai".to_string()),
+            plan: Some("all-models".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            binding_names(&parse_vcap_services_with(&vcap, &combined).unwrap()),
+            vec!["mid", "zeta"]
+        );
+    }
+
+    #[test]
+    fn test_binding_selection_by_instance_name() {
+        let selector = BindingSelector {
+            name: Some("zeta-instance".to_string()),
+            ..Default::default()
+        };
+        let bindings = parse_vcap_services_with(&selector_test_vcap(), &selector).unwrap();
+        assert_eq!(binding_names(&bindings), vec!["zeta"]);
+    }
+
+    #[test]
+    fn test_parse_vcap_services_no_genai() {
+        let vcap = serde_json::json!({
+            "mysql": [{
+                "credentials": {"uri": "mysql://localhost"}
+            }]
+        });
+
+        assert!(parse_vcap_services(&vcap.to_string()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_vcap_services_empty_genai() {
+        let vcap = serde_json::json!({
+            "genai": []
+        });
+
+        assert!(parse_vcap_services(&vcap.to_string()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_vcap_services_invalid_json() {
+        assert!(matches!(
+            parse_vcap_services("not json"),
+            Err(BindingParseError::InvalidJson { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_vcap_services_reports_malformed_binding() {
+        let vcap = serde_json::json!({
+            "genai": [
+                {
+                    "name": "genai-broken",
+                    "credentials": {
+                        "endpoint": {"api_base": "https://genai-proxy.sys.example.com/plan"}
+                    }
+                },
+                {
+                    "name": "genai-legacy",
+                    "credentials": {"api_base": 42, "api_key": "jwt"}
+                }
+            ]
+        });
+        let err =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap_err();
+        assert_eq!(err.path(), "genai[0].credentials.endpoint.api_key");
+        assert_eq!(
+            err.to_string(),
+            "genai[0].credentials.endpoint.api_key missing"
+        );
+
+        // A usable binding is enough; the broken one is skipped
+        let mut vcap = vcap;
+        vcap["genai"][0]["credentials"]["endpoint"]["api_key"] = "jwt".into();
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(binding_names(&bindings), vec!["genai-broken"]);
+
+        vcap["genai"][0]["credentials"] = serde_json::json!({"credhub-ref": "/c/genai/abc"});
+        let err =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "genai[0].credentials is an unresolved CredHub reference"
+        );
+    }
+
+    #[test]
+    fn test_parse_vcap_services_skips_unresolved_credhub_refs() {
+        let vcap = serde_json::json!({
+            "genai": [
+                {
+                    "name": "genai-secure",
+                    "credentials": {"credhub-ref": "/c/genai/genai-secure/abc/credentials"}
+                },
+                {
+                    "name": "genai-plain",
+                    "credentials": {
+                        "endpoint": {
+                            "api_base": "https://genai-proxy.sys.example.com/plan",
+                            "api_key": "jwt"
+                        }
+                    }
+                }
+            ]
+        });
+
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].binding_name.as_deref(), Some("genai-plain"));
+    }
+
+    #[test]
+    fn test_parse_vcap_services_user_provided() {
+        let vcap = serde_json::json!({
+            "user-provided": [
+                {
+                    "name": "my-llm",
+                    "label": "user-provided",
+                    "tags": ["LLM"],
+                    "credentials": {
+                        "api_base": "https://genai-proxy.sys.example.com/plan/openai",
+                        "api_key": "jwt",
+                        "model_name": "llama3.2:3b"
+                    }
+                },
+                {
+                    "name": "my-genai-notes",
+                    "label": "user-provided",
+                    "tags": ["genai"],
+                    "credentials": {"url": "https://notes.example.com"}
+                },
+                {
+                    "name": "my-db",
+                    "label": "user-provided",
+                    "tags": ["postgres"],
+                    "credentials": {
+                        "endpoint": {"api_base": "https://db.example.com", "api_key": "pw"}
+                    }
+                }
+            ]
+        });
+
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].binding_name.as_deref(), Some("my-llm"));
+        assert_eq!(
+            bindings[0].endpoint_base,
+            "https://genai-proxy.sys.example.com/plan"
+        );
+        assert_eq!(bindings[0].model_name.as_deref(), Some("llama3.2:3b"));
+    }
+
+    #[test]
+    fn test_parse_vcap_services_legacy_broker_label() {
+        // 0.x brokers registered the service as `genai-service` with single-model credentials
+        let mut vcap = serde_json::json!({
+            "genai-service": [{
+                "name": "llama-legacy",
+                "label": "genai-service",
+                "plan": "llama3",
+                "tags": [],
+                "credentials": {
+                    "api_base": "https://genai-proxy.sys.example.com/llama3-1f2e/openai",
+                    "api_key": "jwt",
+                    "model_name": "llama3"
+                }
+            }]
+        });
+
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(binding_names(&bindings), vec!["llama-legacy"]);
+        assert_eq!(
+            bindings[0].endpoint_base,
+            "https://genai-proxy.sys.example.com/llama3-1f2e"
+        );
+        assert_eq!(bindings[0].model_name.as_deref(), Some("llama3"));
+
+        vcap["genai-service"][0]["credentials"] = serde_json::json!({"api_key": "jwt"});
+        let err =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap_err();
+        assert_eq!(err.path(), "genai-service[0].credentials.endpoint");
+    }
+
+    #[test]
+    fn test_parse_vcap_services_per_plan_broker_labels() {
+        // Brokers that register one service per plan label it `genai-<plan>`
+        let vcap = serde_json::json!({
+            "genai-chat": [{
+                "name": "chat",
+                "label": "genai-chat",
+                "credentials": {
+                    "endpoint": {
+                        "api_base": "https://genai-proxy.sys.example.com/chat-9c1d",
+                        "api_key": "jwt-chat",
+                        "config_url": "https://genai-proxy.sys.example.com/chat-9c1d/config/v1/endpoint"
+                    }
+                }
+            }],
+            "GenAI_Embeddings": [{
+                "name": "embeddings",
+                "credentials": {
+                    "endpoint": {
+                        "api_base": "https://genai-proxy.sys.example.com/embed-4b7a",
+                        "api_key": "jwt-embed"
+                    }
+                }
+            }],
+            "genai-dashboard": [{
+                "name": "dashboard",
+                "credentials": {"url": "https://genai-dashboard.sys.example.com"}
+            }]
+        });
+
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(binding_names(&bindings), vec!["chat", "embeddings"]);
+        assert_eq!(bindings[1].api_key, "jwt-embed");
+    }
+
+    #[test]
+    fn test_parse_vcap_services_match_priority() {
+        let binding = |name: &str, plan: &str| {
+            serde_json::json!({
+                "name": name,
+                "tags": ["genai", "llm"],
+                "credentials": {
+                    "endpoint": {
+                        "api_base": format!("https://genai-proxy.sys.example.com/{}", plan),
+                        "api_key": "jwt"
+                    }
+                }
+            })
+        };
+        let vcap = serde_json::json!({
+            "ai-gateway": [binding("a-gateway", "gateway")],
+            "user-provided": [binding("b-cups", "cups")],
+            "genai-llama": [binding("c-plan", "llama")],
+            "genai-service": [binding("d-legacy", "legacy")],
+            "genai": [binding("z-marketplace", "all-models"), binding("y-marketplace", "chat")]
+        });
+
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(
+            binding_names(&bindings),
+            vec![
+                "y-marketplace",
+                "z-marketplace",
+                "d-legacy",
+                "c-plan",
+                "a-gateway",
+                "b-cups"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_vcap_services_custom_binding_parser() {
+        struct Wrapped;
+
+        impl BindingParser for Wrapped {
+            fn name(&self) -> &str {
+                "wrapped"
+            }
+
+            fn extract(&self, label: &str, binding: &Value) -> Option<Value> {
+                (label == "wrapped-genai").then(|| binding["credentials"]["genai"].clone())
+            }
+        }
+
+        register_binding_parser(Wrapped);
+        let mut vcap = serde_json::json!({
+            "wrapped-genai": [
+                {
+                    "name": "wrapped-chat",
+                    "credentials": {
+                        "broker": "acme",
+                        "genai": {
+                            "endpoint": {
+                                "api_base": "https://genai-proxy.sys.example.com/wrapped",
+                                "api_key": "jwt"
+                            }
+                        }
+                    }
+                },
+                {"name": "wrapped-broken", "credentials": {"genai": {"endpoint": {}}}}
+            ]
+        });
+
+        let bindings =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap();
+        assert_eq!(binding_names(&bindings), vec!["wrapped-chat"]);
+        assert_eq!(
+            bindings[0].endpoint_base,
+            "https://genai-proxy.sys.example.com/wrapped"
+        );
+
+        vcap["wrapped-genai"].as_array_mut().unwrap().remove(0);
+        let err =
+            parse_vcap_services_with(&vcap.to_string(), &BindingSelector::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "wrapped-genai[0].credentials.endpoint.api_base missing"
+        );
+    }
+
+    // --- Binding Routing Tests ---
+
+    fn test_credentials(endpoint_base: &str, config_url: Option<String>) -> TanzuCredentials {
+        TanzuCredentials {
+            endpoint_base: endpoint_base.to_string(),
+            api_key: "test-jwt-token".to_string(),
+            config_url,
+            config_api_key: None,
+            model_name: None,
+            binding_name: None,
+            binding_guid: None,
+            region: None,
+            model_aliases: Vec::new(),
+            wire_format: WireFormat::OpenAi,
+            auth: AuthMethod::ApiKey,
+            flavor: UpstreamFlavor::Tanzu,
+        }
+    }
+
+    fn test_binding(endpoint_base: &str, models: &[&str]) -> TanzuBinding {
+        let binding = TanzuBinding::build(
+            test_credentials(endpoint_base, None),
+            reqwest::Client::new(),
+        );
+        binding.set_models(models.iter().map(|m| m.to_string()).collect());
+        binding
+    }
+
+    #[test]
+    fn test_route_binding_by_model() {
+        let bindings = vec![
+            test_binding("https://proxy.example.com/chat", &["llama3.2:1b"]),
+            test_binding("https://proxy.example.com/big", &["openai/gpt-oss-120b"]),
+        ];
+
+        let routed = route_binding(&bindings, "openai/gpt-oss-120b");
+        assert_eq!(
+            routed.credentials.endpoint_base,
+            "https://proxy.example.com/big"
+        );
+        let routed = route_binding(&bindings, "llama3.2:1b");
+        assert_eq!(
+            routed.credentials.endpoint_base,
+            "https://proxy.example.com/chat"
+        );
+    }
+
+    #[test]
+    fn test_route_binding_unknown_model_uses_first() {
+        let bindings = vec![
+            test_binding("https://proxy.example.com/chat", &["llama3.2:1b"]),
+            test_binding("https://proxy.example.com/big", &[]),
+        ];
+
+        let routed = route_binding(&bindings, "not-advertised");
+        assert_eq!(
+            routed.credentials.endpoint_base,
+            "https://proxy.example.com/chat"
+        );
+    }
+
+    // --- Model Discovery Tests ---
+
+    #[test]
+    fn test_filter_chat_models() {
+        let models = vec![
+            AdvertisedModel {
+                name: "llama3.2:1b".to_string(),
+                capabilities: vec!["CHAT".to_string(), "TOOLS".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+            AdvertisedModel {
+                name: "mxbai-embed-large".to_string(),
+                capabilities: vec!["EMBEDDING".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+            AdvertisedModel {
+                name: "qwen3-30b".to_string(),
+                capabilities: vec!["chat".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+        ];
+
+        let chat_models = filter_chat_models(&models);
+        assert_eq!(chat_models.len(), 2);
+        assert!(chat_models.contains(&"llama3.2:1b".to_string()));
+        assert!(chat_models.contains(&"qwen3-30b".to_string()));
+        assert!(!chat_models.contains(&"mxbai-embed-large".to_string()));
+    }
+
+    #[test]
+    fn test_filter_chat_models_excludes_completion_only() {
+        let models = vec![
+            AdvertisedModel {
+                name: "gpt-3.5-turbo-instruct".to_string(),
+                capabilities: vec!["COMPLETION".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+            AdvertisedModel {
+                name: "qwen3-30b".to_string(),
+                capabilities: vec!["TOOLS".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+        ];
+
+        assert_eq!(filter_chat_models(&models), vec!["qwen3-30b"]);
+        assert_eq!(
+            filter_served_models(&models),
+            vec!["gpt-3.5-turbo-instruct", "qwen3-30b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_supported_models_from_config_url() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/config-plan/config/v1/endpoint"))
+            .and(header("Authorization", "Bearer test-jwt-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "plan",
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT", "TOOLS"]},
+                    {"name": "mxbai-embed-large", "capabilities": ["EMBEDDING"]},
+                    {"name": "qwen3-30b", "capabilities": ["CHAT"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/config-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let models = provider.fetch_supported_models().await.unwrap();
+        assert_eq!(models, vec!["llama3.2:1b", "qwen3-30b"]);
+    }
+
+    #[tokio::test]
+    async fn test_config_url_uses_separate_key() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/split-auth/config/v1/endpoint"))
+            .and(header("Authorization", "Bearer config-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [{"name": "qwen3-30b", "capabilities": ["CHAT"]}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/split-auth", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let mut creds = test_credentials(&endpoint_base, Some(config_url.clone()));
+        creds.config_api_key = Some("config-key".to_string());
+        let models = discover_models(
+            &reqwest::Client::new(),
+            &creds,
+            &creds.api_key,
+            &etag::ETagCache::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(models[0].name, "qwen3-30b");
+
+        assert_eq!(
+            config_auth_message(&config_url, reqwest::StatusCode::UNAUTHORIZED, false),
+            format!(
+                "{} rejected the inference API key (401 Unauthorized); set TANZU_AI_CONFIG_API_KEY \
+                 if the config endpoint uses a separate credential",
+                config_url
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_azure_config_url_uses_api_key_header() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/azure-plan/config/v1/endpoint"))
+            .and(header("api-key", "test-jwt-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [{"name": "gpt-4o", "capabilities": ["CHAT"]}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/azure-plan", mock_server.uri());
+        let mut creds = test_credentials(
+            &endpoint_base,
+            Some(format!("{}/config/v1/endpoint", endpoint_base)),
+        );
+        creds.flavor = UpstreamFlavor::AzureOpenAi {
+            api_version: flavor::DEFAULT_AZURE_API_VERSION.to_string(),
+        };
+        let models = discover_models(
+            &reqwest::Client::new(),
+            &creds,
+            &creds.api_key,
+            &etag::ETagCache::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(models[0].name, "gpt-4o");
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert!(requests[0].headers.get("authorization").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_fills_discovery_cache() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/prefetch-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [{"name": "llama3.2:1b", "capabilities": ["CHAT"]}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/prefetch-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let creds = test_credentials(&endpoint_base, Some(config_url));
+        let key = creds.discovery_key();
+        let provider = test_provider(vec![creds]);
+
+        provider.prefetch_models();
+        let started = Instant::now();
+        while DISCOVERY_CACHE.get(&key, discovery_ttl()).is_none() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Served from the cache without another request
+        let models = provider.fetch_supported_models().await.unwrap();
+        assert_eq!(models, vec!["llama3.2:1b"]);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_report() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/caps-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {
+                        "name": TANZU_DEFAULT_MODEL,
+                        "capabilities": ["CHAT", "TOOLS", "VISION", "PROMPT_CACHE"],
+                        "contextLength": 131072
+                    },
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT"]},
+                    {"name": "mxbai-embed-large", "capabilities": ["EMBEDDING"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/caps-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let mut provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+        provider.fallback_models = vec!["llama3.2:1b".to_string()];
+        provider
+            .tool_support
+            .lock()
+            .unwrap()
+            .insert("llama3.2:1b".to_string(), false);
+
+        let report = provider.capabilities().await;
+        assert!(report.embeddings);
+        assert_eq!(
+            report.models,
+            vec![
+                TanzuModelCapabilities {
+                    model: TANZU_DEFAULT_MODEL.to_string(),
+                    streaming: true,
+                    tools: Support::Yes,
+                    vision: Support::Yes,
+                    structured_output: Support::No,
+                    prompt_caching: Support::Yes,
+                    context_length: Some(131072),
+                },
+                TanzuModelCapabilities {
+                    model: "llama3.2:1b".to_string(),
+                    streaming: true,
+                    tools: Support::No,
+                    vision: Support::No,
+                    structured_output: Support::No,
+                    prompt_caching: Support::No,
+                    context_length: None,
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&report.models[1]).unwrap()["vision"],
+            "no"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_only_model_uses_completions_endpoint() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/legacy-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "gpt-3.5-turbo-instruct", "capabilities": ["COMPLETION"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/legacy-plan/openai/v1/completions"))
+            .and(body_string_contains("User: hi\\n\\nAssistant:"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "gpt-3.5-turbo-instruct",
+                "choices": [{"index": 0, "text": " Hello!", "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/legacy-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+        let model_config = ModelConfig::new_or_fail("gpt-3.5-turbo-instruct");
+        let (message, usage) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "Hello!");
+        assert_eq!(usage.usage.total_tokens, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_completion_only_model_routed_to_serving_binding() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/chat-only-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "openai/gpt-oss-120b", "capabilities": ["CHAT", "TOOLS"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/instruct-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "gpt-3.5-turbo-instruct", "capabilities": ["COMPLETION"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/instruct-plan/openai/v1/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "gpt-3.5-turbo-instruct",
+                "choices": [{"index": 0, "text": " Hello!", "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let credentials = ["chat-only-plan", "instruct-plan"].map(|plan| {
+            let endpoint_base = format!("{}/{}", mock_server.uri(), plan);
+            let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+            test_credentials(&endpoint_base, Some(config_url))
+        });
+        let provider = test_provider(credentials.to_vec());
+        let model_config = ModelConfig::new_or_fail("gpt-3.5-turbo-instruct");
+        for _ in 0..2 {
+            let (message, _) = provider
+                .complete_with_model(
+                    None,
+                    &model_config,
+                    "",
+                    &[Message::user().with_text("hi")],
+                    &[],
+                )
+                .await
+                .unwrap();
+            assert_eq!(message.as_concat_text(), "Hello!");
+        }
+
+        // Found once, the model stays routed to the binding that serves it
+        assert!(provider.client.bindings[1].serves("gpt-3.5-turbo-instruct"));
+        let models = provider.fetch_supported_models().await.unwrap();
+        assert!(models.contains(&"gpt-3.5-turbo-instruct".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_supported_models_falls_back_to_openai_models() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/fallback-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/fallback-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": "list",
+                "data": [
+                    {"id": "openai/gpt-oss-120b", "object": "model"},
+                    {"id": "llama3.2:1b", "object": "model"}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/fallback-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let models = provider.fetch_supported_models().await.unwrap();
+        assert_eq!(models, vec!["openai/gpt-oss-120b", "llama3.2:1b"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_supported_models_all_bindings_fail() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/unauthorized-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/unauthorized-plan", mock_server.uri());
+        let provider = test_provider(vec![test_credentials(&endpoint_base, None)]);
+
+        let result = provider.fetch_supported_models().await;
+        assert!(matches!(result, Err(ProviderError::RequestFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_embeddings_uses_advertised_embedding_model() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/embedding-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT", "TOOLS"]},
+                    {"name": "mxbai-embed-large", "capabilities": ["EMBEDDING"]}
+                ]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/embedding-plan/openai/v1/embeddings"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "mxbai-embed-large"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"index": 0, "embedding": [0.5, 0.25]}]
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/embedding-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        assert!(provider.supports_embeddings());
+        for _ in 0..2 {
+            let embeddings = provider
+                .create_embeddings(vec!["hello".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(embeddings, vec![vec![0.5, 0.25]]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_uses_advertised_audio_model() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/audio-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT"]},
+                    {"name": "whisper-large-v3", "capabilities": ["AUDIO"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/audio-plan/openai/v1/audio/transcriptions"))
+            .and(body_string_contains("whisper-large-v3"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"text": "hello"})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/audio-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let text = provider
+            .transcribe(b"RIFF", "memo.wav", None)
+            .await
+            .unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn test_filter_embedding_models() {
+        let models = vec![
+            AdvertisedModel {
+                name: "llama3.2:1b".to_string(),
+                capabilities: vec!["CHAT".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+            AdvertisedModel {
+                name: "nomic-embed-text".to_string(),
+                capabilities: vec!["embedding".to_string()],
+                context_length: None,
+                deprecation: None,
+                revision: None,
+            },
+        ];
+
+        assert_eq!(filter_embedding_models(&models), vec!["nomic-embed-text"]);
+    }
+
+    #[test]
+    fn test_discovery_cache_ttl() {
+        let cache = DiscoveryCache::default();
+        let models = vec![AdvertisedModel {
+            name: "llama3.2:1b".to_string(),
+            capabilities: vec!["CHAT".to_string()],
+            context_length: None,
+            deprecation: None,
+            revision: None,
+        }];
+        cache.insert("https://proxy.example.com/plan/config".to_string(), models);
+
+        let hit = cache.get(
+            "https://proxy.example.com/plan/config",
+            Duration::from_secs(60),
+        );
+        assert_eq!(hit.unwrap()[0].name, "llama3.2:1b");
+        assert!(cache
+            .get("https://proxy.example.com/plan/config", Duration::ZERO)
+            .is_none());
+
+        cache.invalidate("https://proxy.example.com/plan/config");
+        assert!(cache
+            .get(
+                "https://proxy.example.com/plan/config",
+                Duration::from_secs(60)
+            )
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_discovery_is_cached_until_invalidated() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/cached-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [{"name": "llama3.2:1b", "capabilities": ["CHAT"]}]
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/cached-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        provider.fetch_supported_models().await.unwrap();
+        provider.fetch_supported_models().await.unwrap();
+        provider.invalidate_model_cache();
+        let models = provider.fetch_supported_models().await.unwrap();
+        assert_eq!(models, vec!["llama3.2:1b"]);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_supported_models_revalidates_with_etag() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/etag-plan/config/v1/endpoint"))
+            .and(header("If-None-Match", "\"plan-v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/etag-plan/config/v1/endpoint"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"plan-v1\"")
+                    .set_body_json(serde_json::json!({
+                        "advertisedModels": [{"name": "qwen3-30b", "capabilities": ["CHAT"]}]
+                    })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/etag-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        assert_eq!(
+            provider.fetch_supported_models().await.unwrap(),
+            vec!["qwen3-30b"]
+        );
+        // Served from the instance cache without a request
+        provider.fetch_supported_models().await.unwrap();
+        // Forced refresh revalidates and reuses the unchanged document
+        assert_eq!(
+            provider.refresh_supported_models().await.unwrap(),
+            vec!["qwen3-30b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_connection_unknown_model() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/preflight-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": "llama3.2:1b"}, {"id": "qwen3-30b"}]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/preflight-plan", mock_server.uri());
+        let provider = test_provider(vec![test_credentials(&endpoint_base, None)]);
+
+        match provider.verify_connection().await {
+            Err(PreflightError::UnknownModel { model, available }) => {
+                assert_eq!(model, TANZU_DEFAULT_MODEL);
+                assert_eq!(available, vec!["llama3.2:1b", "qwen3-30b"]);
+            }
+            other => panic!("Expected UnknownModel, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_connection_ok() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/preflight-ok-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": TANZU_DEFAULT_MODEL}]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/preflight-ok-plan", mock_server.uri());
+        let provider = test_provider(vec![test_credentials(&endpoint_base, None)]);
+
+        assert!(provider.verify_connection().await.is_ok());
+    }
+
+    // --- Completion Tests ---
+
+    #[tokio::test]
+    async fn test_complete_routes_to_binding_chat_endpoint() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/routed-plan/openai/v1/chat/completions"))
+            .and(header("Authorization", "Bearer test-jwt-token"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "llama3.2:1b"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "model": "llama3.2:1b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "routed"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 4, "completion_tokens": 1, "total_tokens": 5}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![
+            test_credentials(&format!("{}/default-plan", mock_server.uri()), None),
+            test_credentials(&format!("{}/routed-plan", mock_server.uri()), None),
+        ]);
+        provider.client.bindings[1].set_models(vec!["llama3.2:1b".to_string()]);
+
+        let model_config = ModelConfig::new_or_fail("llama3.2:1b");
+        let (message, usage) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "routed");
+        assert_eq!(usage.model, "llama3.2:1b");
+        assert_eq!(usage.usage.total_tokens, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_restored_session_reconnects_to_recorded_binding() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/plan-b/openai/v1/chat/completions"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "llama3.2:1b"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "resumed"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 4, "completion_tokens": 1, "total_tokens": 5}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let bound = |plan: &str, guid: &str| TanzuCredentials {
+            binding_guid: Some(guid.to_string()),
+            ..test_credentials(&format!("{}/{}", mock_server.uri(), plan), None)
+        };
+        let provider = test_provider(vec![bound("plan-a", "guid-1"), bound("plan-b", "guid-2")]);
+
+        // Exported from an app where the instance was bound under another route
+        let exported = TanzuSessionMetadata {
+            endpoint: "https://genai.old.example.com/plan-b".to_string(),
+            binding_guid: Some("guid-2".to_string()),
+            model: "llama3.2:1b".to_string(),
+            usage: ModelUsage {
+                requests: 3,
+                prompt_tokens: 30,
+                completion_tokens: 6,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(provider.restore_session("resumed", &exported).await);
+
+        let (message, usage) = provider
+            .complete_with_model(
+                Some("resumed"),
+                &provider.model,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "resumed");
+        assert_eq!(usage.model, "llama3.2:1b");
+
+        let metadata = provider.session_metadata("resumed").await;
+        assert_eq!(metadata.binding_guid.as_deref(), Some("guid-2"));
+        assert_eq!(metadata.endpoint, format!("{}/plan-b", mock_server.uri()));
+        assert_eq!(metadata.model, "llama3.2:1b");
+        assert_eq!(metadata.usage.requests, 4);
+        assert_eq!(metadata.usage.prompt_tokens, 34);
+
+        let unknown = TanzuSessionMetadata {
+            endpoint: "https://genai.example.com/gone".to_string(),
+            binding_guid: Some("guid-9".to_string()),
+            ..exported
+        };
+        assert!(!provider.restore_session("other", &unknown).await);
+    }
+
+    #[tokio::test]
+    async fn test_content_filters_redact_before_sending() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "messages": [
+                    {"role": "system", "content": "system"},
+                    {"role": "user", "content": "deploy to [REDACTED:codename] now"}
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "openai/gpt-oss-120b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "ok"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/plan", mock_server.uri()),
+            None,
+        )])
+        .with_content_filter(Arc::new(
+            RegexFilter::new("codename", r"(?i)project\s+falcon").unwrap(),
+        ));
+
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &provider.model,
+                "system",
+                &[Message::user().with_text("deploy to Project Falcon now")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_platform_context_appended_to_system_prompt() {
+        let mock_server = MockServer::start().await;
+        let expected = format!("system\n\nServing {} for release-bot.", TANZU_DEFAULT_MODEL);
+
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "messages": [{"role": "system", "content": expected}]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": TANZU_DEFAULT_MODEL,
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "ok"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/plan", mock_server.uri()),
+            None,
+        )]);
+        let app = attribution::Attribution {
+            app: Some("release-bot".to_string()),
+            ..Default::default()
+        };
+        provider.platform_prompt = Some(platform_prompt::PlatformPrompt::new(
+            "Serving {model} for {app}.\nIn space {space}.",
+            app,
+            None,
+        ));
+
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &provider.model,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_complete_model_override_discovers_binding() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/override-big/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "openai/gpt-oss-120b", "capabilities": ["CHAT", "TOOLS"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/override-big/openai/v1/chat/completions"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "openai/gpt-oss-120b"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "openai/gpt-oss-120b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "lead"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let big = format!("{}/override-big", mock_server.uri());
+        let provider = test_provider(vec![
+            test_credentials(&format!("{}/override-small", mock_server.uri()), None),
+            test_credentials(&big, Some(format!("{}/config/v1/endpoint", big))),
+        ]);
+        provider.client.bindings[0].set_models(vec!["llama3.2:1b".to_string()]);
+
+        // The provider's own model stays on the first binding; the override goes to the
+        // binding that advertises it
+        let model_config = ModelConfig::new_or_fail("openai/gpt-oss-120b");
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "lead");
+        assert!(provider.client.bindings[1].serves("openai/gpt-oss-120b"));
+    }
+
+    #[tokio::test]
+    async fn test_complete_times_out_after_total_deadline() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/slow-plan/openai/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_delay(Duration::from_secs(2))
+                    .set_body_json(serde_json::json!({"choices": []})),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/slow-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.client.bindings[0].transport = provider.client.bindings[0]
+            .transport
+            .clone()
+            .with_timeouts(TimeoutSettings {
+                total: Duration::from_millis(200),
+                ..Default::default()
+            });
+
+        let model_config = provider.get_model_config();
+        let started = Instant::now();
+        let result = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_complete_retries_server_errors() {
+        std::env::set_var("GOOSE_PROVIDER_SKIP_BACKOFF", "true");
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/retry-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(502).set_body_json(serde_json::json!({
+                "error": {"message": "Bad Gateway", "type": "server_error"}
+            })))
+            .expect(4) // 1 initial + 3 retries
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/retry-plan", mock_server.uri()),
+            None,
+        )]);
+        let model_config = provider.get_model_config();
+        let result = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await;
+
+        assert!(matches!(result, Err(ProviderError::ServerError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_retries_reuse_idempotency_key() {
+        std::env::set_var("GOOSE_PROVIDER_SKIP_BACKOFF", "true");
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/idempotent-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(504))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/idempotent-plan/openai/v1/chat/completions"))
+            .and(header_exists("X-Idempotency-Key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "once"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/idempotent-plan", mock_server.uri()),
+            None,
+        )]);
+        let model_config = provider.get_model_config();
+        for _ in 0..2 {
+            provider
+                .complete_with_model(
+                    None,
+                    &model_config,
+                    "system",
+                    &[Message::user().with_text("hi")],
+                    &[],
+                )
+                .await
+                .unwrap();
+        }
+
+        let keys: Vec<String> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.method.as_str() == "POST")
+            .map(|r| r.headers["x-idempotency-key"].to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(keys.len(), 3);
+        // The retry repeats the first request's key; the next request gets its own
+        assert_eq!(keys[0], keys[1]);
+        assert_ne!(keys[1], keys[2]);
+    }
+
+    #[tokio::test]
+    async fn test_requests_carry_cost_attribution_headers() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/billed-plan/openai/v1/chat/completions"))
+            .and(header("X-Tanzu-Org", "platform-eng"))
+            .and(header("X-Tanzu-Space", "dev"))
+            .and(header("X-Tanzu-App", "goose-agent"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "billed"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let settings = ClientSettings {
+            attribution: attribution::Attribution {
+                org: Some("platform-eng".to_string()),
+                space: Some("dev".to_string()),
+                app: Some("goose-agent".to_string()),
+            },
+            ..Default::default()
+        };
+        let mut provider = test_provider(Vec::new());
+        provider.client = TanzuClient::from_bindings(
+            vec![TanzuBinding::build(
+                test_credentials(&format!("{}/billed-plan", mock_server.uri()), None),
+                transport::build_http_client(&settings).unwrap(),
+            )],
+            balance::BalanceMode::Primary,
+        );
+
+        let model_config = provider.get_model_config();
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "billed");
+    }
+
+    #[tokio::test]
+    async fn test_requests_carry_extra_headers() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/waf-plan/openai/v1/chat/completions"))
+            .and(header("X-Client-Id", "goose-desktop"))
+            .and(header_exists("Authorization"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "allowed"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let settings = ClientSettings {
+            extra_headers: headers::ExtraHeaders::from_json(r#"{"X-Client-Id": "goose-desktop"}"#),
+            ..Default::default()
+        };
+        let mut provider = test_provider(Vec::new());
+        provider.client = TanzuClient::from_bindings(
+            vec![TanzuBinding::build(
+                test_credentials(&format!("{}/waf-plan", mock_server.uri()), None),
+                transport::build_http_client(&settings).unwrap(),
+            )],
+            balance::BalanceMode::Primary,
+        );
+
+        let model_config = provider.get_model_config();
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "allowed");
+
+        let reserved = ClientSettings {
+            extra_headers: headers::ExtraHeaders::from_json(r#"{"Authorization": "Bearer x"}"#),
+            ..Default::default()
+        };
+        assert!(transport::build_http_client(&reserved).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_requests_stick_to_replica() {
+        let mock_server = MockServer::start().await;
+        let reply = serde_json::json!({
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "ok"},
+                "finish_reason": "stop"
+            }]
+        });
+        Mock::given(method("POST"))
+            .and(path("/sticky-plan/openai/v1/chat/completions"))
+            .and(header("X-Session-Affinity", "replica-2"))
+            .and(header("Cookie", "JSESSIONID=abc; __VCAP_ID__=f00d"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(reply.clone()))
+            .expect(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/sticky-plan/openai/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("X-Session-Affinity", "replica-2")
+                    .append_header("Set-Cookie", "JSESSIONID=abc; Path=/")
+                    .append_header("Set-Cookie", "__VCAP_ID__=f00d; Path=/; HttpOnly")
+                    .set_body_json(reply),
+            )
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(Vec::new());
+        provider.client = TanzuClient::from_bindings(
+            vec![TanzuBinding::build(
+                test_credentials(&format!("{}/sticky-plan", mock_server.uri()), None),
+                reqwest::Client::new(),
+            )],
+            balance::BalanceMode::Primary,
+        );
+
+        let model_config = provider.get_model_config();
+        let messages = [Message::user().with_text("hi")];
+        // The second request of session a is pinned; session b starts unpinned
+        for session_id in ["a", "a", "b"] {
+            provider
+                .complete_with_model(Some(session_id), &model_config, "system", &messages, &[])
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_azure_upstream_uses_deployment_paths_and_api_key() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt-4o/chat/completions"))
+            .and(query_param("api-version", "2025-01-01-preview"))
+            .and(header("api-key", "test-jwt-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "ok"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let binding = serde_json::json!({
+            "api_base": format!("{}/openai", mock_server.uri()),
+            "api_key": "test-jwt-token",
+            "api_version": "2025-01-01-preview",
+            "model_name": "gpt-4o"
+        });
+        let credentials = parse_binding_credentials(&binding, "credentials").unwrap();
+        assert!(credentials.flavor.is_azure());
+
+        let mut provider = test_provider(Vec::new());
+        provider.client = TanzuClient::from_bindings(
+            vec![TanzuBinding::build(credentials, reqwest::Client::new())],
+            balance::BalanceMode::Primary,
+        );
+        let mut model_config = provider.get_model_config();
+        model_config.model_name = "gpt-4o".to_string();
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_strict_egress_refuses_redirects_off_endpoint() {
+        let mock_server = MockServer::start().await;
+        let endpoint = format!("{}/strict-plan", mock_server.uri());
+        Mock::given(method("POST"))
+            .and(path("/strict-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(307).insert_header(
+                "Location",
+                format!("{}/elsewhere/v1/chat/completions", mock_server.uri()),
+            ))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/elsewhere/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let http = transport::build_http_client(&ClientSettings {
+            egress: Some(egress::EgressPolicy::new([endpoint.as_str()])),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut provider = test_provider(Vec::new());
+        provider.client = TanzuClient::from_bindings(
+            vec![TanzuBinding::build(test_credentials(&endpoint, None), http)],
+            balance::BalanceMode::Primary,
+        );
+
+        let model_config = provider.get_model_config();
+        let err = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("redirect"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_complete_quota_exceeded_fails_fast() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/quota-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(serde_json::json!({
+                "error": {"message": "Monthly token quota exhausted", "type": "quota_exceeded"}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/quota-plan", mock_server.uri()),
+            None,
+        )]);
+        let model_config = provider.get_model_config();
+        let err = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, ProviderError::QuotaExceeded(_)));
+        assert!(err.to_string().contains("Monthly token quota exhausted"));
+    }
+
+    #[tokio::test]
+    async fn test_traceparent_sent_and_request_id_reported() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/trace-plan/openai/v1/chat/completions"))
+            .and(header_exists("traceparent"))
+            .respond_with(
+                ResponseTemplate::new(401)
+                    .insert_header("X-Vcap-Request-Id", "5e1c-4a7f")
+                    .set_body_json(serde_json::json!({"error": {"message": "invalid token"}})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        std::env::set_var("TANZU_AI_TRACE_PROPAGATION", "true");
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/trace-plan", mock_server.uri()),
+            None,
+        )]);
+        let model_config = provider.get_model_config();
+        let err = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap_err();
+        std::env::remove_var("TANZU_AI_TRACE_PROPAGATION");
+
+        assert!(err.to_string().contains("X-Vcap-Request-Id: 5e1c-4a7f"));
+        let requests = mock_server.received_requests().await.unwrap();
+        let traceparent = requests[0].headers.get("traceparent").unwrap();
+        assert!(trace::TraceParent::parse(traceparent.to_str().unwrap()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_uaa_token_exchanged_again_on_401() {
+        let mock_server = MockServer::start().await;
+
+        for token in ["revoked-token", "fresh-token"] {
+            Mock::given(method("POST"))
+                .and(path("/uaa/oauth/token"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "access_token": token,
+                    "expires_in": 3600
+                })))
+                .up_to_n_times(1)
+                .mount(&mock_server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path("/uaa-plan/openai/v1/chat/completions"))
+            .and(header("Authorization", "Bearer revoked-token"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/uaa-plan/openai/v1/chat/completions"))
+            .and(header("Authorization", "Bearer fresh-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "authorized"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut credentials = test_credentials(&format!("{}/uaa-plan", mock_server.uri()), None);
+        credentials.api_key = String::new();
+        credentials.auth = AuthMethod::ClientCredentials(UaaClientConfig {
+            token_url: format!("{}/uaa/oauth/token", mock_server.uri()),
+            client_id: "goose".to_string(),
+            client_secret: "secret".to_string(),
+        });
+        let provider = test_provider(vec![credentials]);
+        let model_config = provider.get_model_config();
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "authorized");
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_on_repeated_server_errors() {
+        std::env::set_var("GOOSE_PROVIDER_SKIP_BACKOFF", "true");
+        let mock_server = MockServer::start().await;
+
+        // Default threshold is 5: the first request uses 4 attempts, the second trips
+        // the breaker on its first attempt, the third never reaches the proxy.
+        Mock::given(method("POST"))
+            .and(path("/breaker-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(5)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/breaker-plan", mock_server.uri()),
+            None,
+        )]);
+        let model_config = provider.get_model_config();
+        let messages = [Message::user().with_text("hi")];
+
+        let mut errors = Vec::new();
+        for _ in 0..3 {
+            errors.push(
+                provider
+                    .complete_with_model(None, &model_config, "system", &messages, &[])
+                    .await
+                    .unwrap_err(),
+            );
+        }
+
+        assert!(matches!(errors[0], ProviderError::ServerError(_)));
+        assert!(errors[2].to_string().contains("failing repeatedly"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_decodes_sse() {
+        let mock_server = MockServer::start().await;
+
+        let sse_body = [
+            "data: {\"model\":\"openai/gpt-oss-120b\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"model\":\"openai/gpt-oss-120b\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\" Tanzu\"},\"finish_reason\":\"stop\"}],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2,\"total_tokens\":5}}\n\n",
+            "data: [DONE]\n\n",
+        ]
+        .join("");
+        Mock::given(method("POST"))
+            .and(path("/stream-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(sse_body, "text/event-stream"))
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/stream-plan", mock_server.uri()),
+            None,
+        )]);
+        let mut stream = provider
+            .stream("session", "system", &[Message::user().with_text("hi")], &[])
+            .await
+            .unwrap();
+
+        use futures::StreamExt;
+        let mut text = String::new();
+        let mut final_usage = None;
+        while let Some(chunk) = stream.next().await {
+            let (message, usage) = chunk.unwrap();
+            if let Some(message) = message {
+                text.push_str(&message.as_concat_text());
+            }
+            final_usage = usage.or(final_usage);
+        }
+
+        assert_eq!(text, "Hello Tanzu");
+        assert_eq!(final_usage.unwrap().usage.total_tokens, Some(5));
+
+        let report = provider.usage_report();
+        let usage = report.sessions["session"]["openai/gpt-oss-120b"];
+        assert_eq!(
+            (usage.requests, usage.prompt_tokens, usage.completion_tokens),
+            (1, 3, 2)
+        );
+        assert_eq!((usage.timed_streams, usage.streamed_output_tokens), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_stream_surfaces_reasoning_as_thinking() {
+        let mock_server = MockServer::start().await;
+
+        let sse_body = [
+            "data: {\"model\":\"openai/gpt-oss-120b\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"reasoning_content\":\"User greets.\"}}]}\n\n",
+            "data: {\"model\":\"openai/gpt-oss-120b\",\"choices\":[{\"index\":0,\"delta\":{\"reasoning_content\":\" Reply briefly.\"}}]}\n\n",
+            "data: {\"model\":\"openai/gpt-oss-120b\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ]
+        .join("");
+        Mock::given(method("POST"))
+            .and(path("/reasoning-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(sse_body, "text/event-stream"))
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/reasoning-plan", mock_server.uri()),
+            None,
+        )]);
+        let mut stream = provider
+            .stream("session", "system", &[Message::user().with_text("hi")], &[])
+            .await
+            .unwrap();
+
+        use crate::conversation::message::MessageContent;
+        use futures::StreamExt;
+        let mut thinking = String::new();
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            let Some(message) = chunk.unwrap().0 else {
+                continue;
+            };
+            for content in &message.content {
+                match content {
+                    MessageContent::Thinking(t) => thinking.push_str(&t.thinking),
+                    MessageContent::Text(t) => text.push_str(&t.text),
+                    _ => {}
+                }
+            }
+        }
+
+        assert_eq!(thinking, "User greets. Reply briefly.");
+        assert_eq!(text, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_stream_waits_for_cold_start_with_progress() {
+        std::env::set_var("GOOSE_PROVIDER_SKIP_BACKOFF", "true");
+        let mock_server = MockServer::start().await;
+
+        // More 503s than the generic retries would sit through
+        Mock::given(method("POST"))
+            .and(path("/warming-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(503).set_body_json(serde_json::json!({
+                "error": {"message": "Model is starting", "code": "model-warming"}
+            })))
+            .up_to_n_times(5)
+            .mount(&mock_server)
+            .await;
+        let sse_body = [
+            "data: {\"model\":\"openai/gpt-oss-120b\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Ready\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ]
+        .join("");
+        Mock::given(method("POST"))
+            .and(path("/warming-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(sse_body, "text/event-stream"))
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/warming-plan", mock_server.uri()),
+            None,
+        )]);
+        let mut stream = provider
+            .stream("session", "system", &[Message::user().with_text("hi")], &[])
+            .await
+            .unwrap();
+
+        use crate::conversation::message::MessageContent;
+        use futures::StreamExt;
+        let mut progress = Vec::new();
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            let Some(message) = chunk.unwrap().0 else {
+                continue;
+            };
+            for content in &message.content {
+                match content {
+                    MessageContent::Thinking(t) => progress.push(t.thinking.clone()),
+                    MessageContent::Text(t) => text.push_str(&t.text),
+                    _ => {}
+                }
+            }
+        }
+
+        assert_eq!(progress.len(), 5);
+        assert!(progress[0].contains("warming up"));
+        assert_eq!(text, "Ready");
+    }
+
+    #[tokio::test]
+    async fn test_complete_walks_fallback_chain() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/fallback-chain-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"model": "qwen3-30b"})))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "error": {"message": "The model `qwen3-30b` does not exist."}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/fallback-chain-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "llama3.2:1b"}),
+            ))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": {"message": "Model llama3.2:1b is not available"}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/fallback-chain-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "openai/gpt-oss-120b"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "openai/gpt-oss-120b",
+                "choices": [{"message": {"role": "assistant", "content": "served"}}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/fallback-chain-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.fallback_models = parse_model_list("llama3.2:1b, qwen3-30b, openai/gpt-oss-120b");
+
+        let (message, usage) = provider
+            .complete_with_model(
+                None,
+                &ModelConfig::new_or_fail("qwen3-30b"),
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "served");
+        assert_eq!(usage.model, "openai/gpt-oss-120b");
+        // Usage is booked against the model that served the request
+        let report = provider.usage_report();
+        assert_eq!(report.by_model()["openai/gpt-oss-120b"].requests, 1);
+        assert!(!report.by_model().contains_key("qwen3-30b"));
+    }
+
+    #[tokio::test]
+    async fn test_complete_fallback_exhausted_returns_last_error() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/exhausted-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(
+                serde_json::json!({"error": {"message": "model not found in plan"}}),
+            ))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/exhausted-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.fallback_models = vec!["llama3.2:1b".to_string()];
+
+        let err = provider
+            .complete_with_model(
+                None,
+                &ModelConfig::new_or_fail("qwen3-30b"),
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("Model unavailable on Tanzu AI Services"));
+    }
+
+    #[test]
+    fn test_parse_vcap_wire_format() {
+        let vcap = r#"{
+            "genai": [{
+                "name": "claude-binding",
+                "credentials": {
+                    "endpoint": {
+                        "api_base": "https://genai-proxy.example.com/claude-plan",
+                        "api_key": "jwt"
+                    },
+                    "model_name": "claude-sonnet",
+                    "wire_format": "anthropic"
+                }
+            }, {
+                "name": "gpt-binding",
+                "credentials": {
+                    "endpoint": {
+                        "api_base": "https://genai-proxy.example.com/gpt-plan",
+                        "api_key": "jwt"
+                    }
+                }
+            }]
+        }"#;
+
+        let creds = parse_vcap_services(vcap).unwrap();
+        assert_eq!(creds[0].wire_format, WireFormat::Anthropic);
+        assert_eq!(creds[1].wire_format, WireFormat::OpenAi);
+    }
+
+    #[tokio::test]
+    async fn test_complete_anthropic_wire_format() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/anthropic-plan/anthropic/v1/messages"))
+            .and(header("anthropic-version", "2023-06-01"))
+            .and(header("authorization", "Bearer test-jwt-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet",
+                "content": [{"type": "text", "text": "Hello from Claude"}],
+                "usage": {"input_tokens": 9, "output_tokens": 4}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut creds = test_credentials(&format!("{}/anthropic-plan", mock_server.uri()), None);
+        creds.wire_format = WireFormat::Anthropic;
+        let provider = test_provider(vec![creds]);
+
+        let (message, usage) = provider
+            .complete_with_model(
+                None,
+                &ModelConfig::new_or_fail("claude-sonnet"),
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "Hello from Claude");
+        assert_eq!(usage.model, "claude-sonnet");
+        assert_eq!(usage.usage.input_tokens, Some(9));
+    }
+
+    #[tokio::test]
+    async fn test_complete_batch_aggregates_usage() {
+        let mock_server = MockServer::start().await;
+
+        for item in ["alpha", "beta"] {
+            Mock::given(method("POST"))
+                .and(path("/batch-plan/openai/v1/chat/completions"))
+                .and(body_string_contains(item))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "model": "openai/gpt-oss-120b",
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": item.to_uppercase()},
+                        "finish_reason": "stop"
+                    }],
+                    "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+                })))
+                .mount(&mock_server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path("/batch-plan/openai/v1/chat/completions"))
+            .and(body_string_contains("gamma"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": {"message": "bad request"}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/batch-plan", mock_server.uri()),
+            None,
+        )]);
+        let prompts = vec!["alpha".to_string(), "gamma".to_string(), "beta".to_string()];
+        let batch = provider
+            .complete_batch(Some("batch"), "Uppercase the input", &prompts)
+            .await;
+
+        assert_eq!(batch.succeeded(), 2);
+        assert_eq!(
+            batch.results[0].as_ref().unwrap().0.as_concat_text(),
+            "ALPHA"
+        );
+        assert!(batch.results[1].is_err());
+        assert_eq!(
+            batch.results[2].as_ref().unwrap().0.as_concat_text(),
+            "BETA"
+        );
+        assert_eq!(batch.usage.total_tokens, Some(14));
+        assert_eq!(
+            provider.usage_report().sessions["batch"]["openai/gpt-oss-120b"].requests,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_across_bindings_of_same_plan() {
+        let mock_server = MockServer::start().await;
+        for plan in ["plan-a", "plan-b"] {
+            Mock::given(method("POST"))
+                .and(path(format!("/{}/openai/v1/chat/completions", plan)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": plan},
+                        "finish_reason": "stop"
+                    }]
+                })))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let credentials = ["plan-a", "plan-b"]
+            .iter()
+            .map(|plan| TanzuCredentials {
+                model_name: Some(TANZU_DEFAULT_MODEL.to_string()),
+                ..test_credentials(&format!("{}/{}", mock_server.uri(), plan), None)
+            })
+            .collect();
+        let mut provider = test_provider(credentials);
+        provider.client.balancer = balance::Balancer::new(balance::BalanceMode::RoundRobin, 2);
+        let model_config = provider.get_model_config();
+
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let (message, _) = provider
+                .complete_with_model(
+                    None,
+                    &model_config,
+                    "system",
+                    &[Message::user().with_text("hi")],
+                    &[],
+                )
+                .await
+                .unwrap();
+            replies.push(message.as_concat_text());
+        }
+        assert_eq!(replies, ["plan-a", "plan-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_emulates_tools_when_probe_fails() {
+        let mock_server = MockServer::start().await;
+
+        // The probe offers a dummy tool and the model answers in text
+        Mock::given(method("POST"))
+            .and(path("/legacy-plan/openai/v1/chat/completions"))
+            .and(body_string_contains("tanzu_probe"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "ok"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/legacy-plan/openai/v1/chat/completions"))
+            .and(body_string_contains("get_weather"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": "{\"tool_calls\": [{\"name\": \"get_weather\", \"arguments\": {\"city\": \"SF\"}}]}"
+                    },
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 30, "completion_tokens": 12, "total_tokens": 42}
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/legacy-plan", mock_server.uri()),
+            None,
+        )]);
+        let tool = Tool::new(
+            "get_weather",
+            "Look up the weather",
+            Arc::new(
+                serde_json::json!({"type": "object", "properties": {"city": {"type": "string"}}})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+            ),
+        );
+        let messages = [Message::user().with_text("Weather in SF?")];
+
+        for _ in 0..2 {
+            let (message, _) = provider
+                .complete_with_model(
+                    None,
+                    &provider.model,
+                    "system",
+                    &messages,
+                    std::slice::from_ref(&tool),
+                )
+                .await
+                .unwrap();
+            assert!(message.content.iter().any(|c| matches!(
+                c,
+                crate::conversation::message::MessageContent::ToolRequest(_)
+            )));
+        }
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let chat: Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+        assert!(chat.get("tools").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_model_params_merged_into_request() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/params-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "openai/gpt-oss-120b",
+                "reasoning_effort": "high",
+                "temperature": 0.3
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "tuned"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/params-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.model_params = params::ModelParams::parse(&serde_json::json!({
+            "openai/gpt-oss-*": {"reasoning_effort": "high", "temperature": 0.3}
+        }));
+        let model_config = provider.get_model_config();
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "tuned");
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_effort_only_sent_to_reasoning_models() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/effort-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "openai/gpt-oss-120b", "capabilities": ["CHAT", "REASONING"]},
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/effort-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "ok"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let base = format!("{}/effort-plan", mock_server.uri());
+        let mut provider = test_provider(vec![test_credentials(
+            &base,
+            Some(format!("{}/config/v1/endpoint", base)),
+        )]);
+        provider.reasoning_effort = Some("low".to_string());
+
+        for model in ["openai/gpt-oss-120b", "llama3.2:1b"] {
+            provider
+                .complete_with_model(
+                    None,
+                    &ModelConfig::new_or_fail(model),
+                    "system",
+                    &[Message::user().with_text("hi")],
+                    &[],
+                )
+                .await
+                .unwrap();
+        }
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let chats: Vec<Value> = requests
+            .iter()
+            .filter(|r| r.method.as_str() == "POST")
+            .map(|r| serde_json::from_slice(&r.body).unwrap())
+            .collect();
+        assert_eq!(chats[0]["reasoning_effort"], "low");
+        assert!(chats[1].get("reasoning_effort").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_best_of_candidates_selected_by_judge() {
+        let mock_server = MockServer::start().await;
+        let reply = |content: &str| serde_json::json!({"role": "assistant", "content": content});
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"model": "llama3.2:1b", "n": 2})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "choices": [
+                    {"index": 0, "message": reply("A long but wrong answer: Lyon."), "finish_reason": "stop"},
+                    {"index": 1, "message": reply("Paris."), "finish_reason": "stop"}
+                ],
+                "usage": {"prompt_tokens": 10, "completion_tokens": 12, "total_tokens": 22}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "judge-model"}),
+            ))
+            .and(body_string_contains("Candidate 2:"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{"index": 0, "message": reply("2"), "finish_reason": "stop"}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/plan", mock_server.uri()),
+            None,
+        )]);
+        provider.best_of = best_of::BestOf {
+            candidates: Some(2),
+            selection: best_of::Selection::Judge(Some("judge-model".to_string())),
+        };
+        let (message, usage) = provider
+            .complete_with_model(
+                None,
+                &ModelConfig::new_or_fail("llama3.2:1b"),
+                "system",
+                &[Message::user().with_text("What is the capital of France?")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "Paris.");
+        assert_eq!(usage.usage.output_tokens, Some(12));
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let chats: Vec<Value> = requests
+            .iter()
+            .filter(|r| r.method.as_str() == "POST")
+            .map(|r| serde_json::from_slice(&r.body).unwrap())
+            .collect();
+        assert_eq!(chats[1]["model"], "judge-model");
+        assert!(chats[1].get("n").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tool_calls_disabled_keeps_first_call() {
+        let mock_server = MockServer::start().await;
+        let call = |id: &str, city: &str| {
+            serde_json::json!({
+                "id": id,
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "arguments": serde_json::json!({"city": city}).to_string()
+                }
+            })
+        };
+        Mock::given(method("POST"))
+            .and(path("/serial-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [call("call_1", "SF"), call("call_2", "NYC")]
+                    },
+                    "finish_reason": "tool_calls"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/serial-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.parallel_tools =
+            parallel::ParallelToolCalls::parse(&serde_json::json!({"openai/gpt-oss-*": false}));
+        provider
+            .tool_support
+            .lock()
+            .unwrap()
+            .insert(TANZU_DEFAULT_MODEL.to_string(), true);
+        let tool = Tool::new(
+            "get_weather",
+            "Look up the weather",
+            Arc::new(
+                serde_json::json!({"type": "object", "properties": {"city": {"type": "string"}}})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+            ),
+        );
+
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &provider.model,
+                "system",
+                &[Message::user().with_text("Weather in SF and NYC?")],
+                std::slice::from_ref(&tool),
+            )
+            .await
+            .unwrap();
+
+        let calls: Vec<&str> = message
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                crate::conversation::message::MessageContent::ToolRequest(r) => Some(r.id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, vec!["call_1"]);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let chat: Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+        assert!(chat.get("tools").is_some());
+        assert!(chat.get("parallel_tool_calls").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_replies_are_cached() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/cached-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "4"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 12, "completion_tokens": 1, "total_tokens": 13}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/cached-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.response_cache = Some(response_cache::ResponseCache::new(
+            dir.path().to_path_buf(),
+            Duration::from_secs(60),
+            10,
+        ));
+        let messages = [Message::user().with_text("What is 2 + 2?")];
+        let deterministic = ModelConfig::new_or_fail("llama3.2:1b").with_temperature(Some(0.0));
+        let sampled = ModelConfig::new_or_fail("llama3.2:1b").with_temperature(Some(0.7));
+
+        provider
+            .complete_with_model(None, &deterministic, "system", &messages, &[])
+            .await
+            .unwrap();
+        // The reply is written in the background
+        for _ in 0..100 {
+            let mut entries = std::fs::read_dir(dir.path()).unwrap();
+            if entries.any(|e| e.unwrap().path().extension().is_some_and(|x| x == "json")) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let (message, usage) = provider
+            .complete_with_model(None, &deterministic, "system", &messages, &[])
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "4");
+        // Nothing was spent on the cached reply
+        assert_eq!(usage.usage.input_tokens, None);
+        assert_eq!(usage.usage.output_tokens, None);
+        assert_eq!(usage.usage.total_tokens, None);
+
+        for _ in 0..2 {
+            provider
+                .complete_with_model(None, &sampled, "system", &messages, &[])
+                .await
+                .unwrap();
+        }
+
+        let posts = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.method.as_str() == "POST")
+            .count();
+        assert_eq!(posts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_tool_arguments_are_retried_once() {
+        let mock_server = MockServer::start().await;
+        let reply = |arguments: Value| {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": {"name": "get_weather", "arguments": arguments.to_string()}
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }]
+            }))
+        };
+        Mock::given(method("POST"))
+            .and(path("/args-plan/openai/v1/chat/completions"))
+            .respond_with(reply(serde_json::json!({"town": "Oslo"})))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/args-plan/openai/v1/chat/completions"))
+            .respond_with(reply(serde_json::json!({"city": "Oslo"})))
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/args-plan", mock_server.uri()),
+            None,
+        )]);
+        provider
+            .tool_support
+            .lock()
+            .unwrap()
+            .insert(TANZU_DEFAULT_MODEL.to_string(), true);
+        let tool = Tool::new(
+            "get_weather",
+            "Look up the weather",
+            Arc::new(
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                })
+                .as_object()
+                .cloned()
+                .unwrap(),
+            ),
+        );
+
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &provider.model,
+                "system",
+                &[Message::user().with_text("Weather in Oslo?")],
+                std::slice::from_ref(&tool),
+            )
+            .await
+            .unwrap();
+        assert!(tool_args::invalid_calls(&message, std::slice::from_ref(&tool)).is_empty());
+
+        let chats: Vec<String> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.method.as_str() == "POST")
+            .map(|r| String::from_utf8_lossy(&r.body).into_owned())
+            .collect();
+        assert_eq!(chats.len(), 2);
+        assert!(chats[1].contains("arguments.city is required"));
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_native() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/json-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "openai/gpt-oss-120b", "capabilities": ["CHAT", "STRUCTURED_OUTPUT"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/json-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "response_format": {"type": "json_schema"}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "openai/gpt-oss-120b",
+                "choices": [{"message": {"role": "assistant", "content": "{\"city\": \"Paris\"}"}}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/json-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let schema =
+            serde_json::json!({"type": "object", "properties": {"city": {"type": "string"}}});
+        let (value, _) = provider
+            .complete_structured(
+                None,
+                "system",
+                &[Message::user().with_text("capital?")],
+                &schema,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(value, serde_json::json!({"city": "Paris"}));
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_degrades_to_prompt() {
+        let mock_server = MockServer::start().await;
+
+        // No capabilities advertised: the probe is rejected, then the prompt-based path runs
+        Mock::given(method("POST"))
+            .and(path("/json-probe-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "response_format": {"type": "json_schema"}
+            })))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": {"message": "response_format is not supported"}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/json-probe-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "openai/gpt-oss-120b",
+                "choices": [{"message": {"role": "assistant", "content": "```json\n{\"city\": \"Paris\"}\n```"}}]
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/json-probe-plan", mock_server.uri()),
+            None,
+        )]);
+
+        let schema = serde_json::json!({"type": "object"});
+        for _ in 0..2 {
+            let (value, _) = provider
+                .complete_structured(
+                    None,
+                    "system",
+                    &[Message::user().with_text("capital?")],
+                    &schema,
+                )
+                .await
+                .unwrap();
+            assert_eq!(value, serde_json::json!({"city": "Paris"}));
+        }
+        assert_eq!(
+            provider
+                .structured_output
+                .lock()
+                .unwrap()
+                .get(TANZU_DEFAULT_MODEL),
+            Some(&false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_bad_request_not_remembered() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/json-bad-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": {"message": "max_tokens must be positive"}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let provider = test_provider(vec![test_credentials(
+            &format!("{}/json-bad-plan", mock_server.uri()),
+            None,
+        )]);
+
+        let result = provider
+            .complete_structured(
+                None,
+                "system",
+                &[Message::user().with_text("capital?")],
+                &serde_json::json!({"type": "object"}),
+            )
+            .await;
+
+        assert!(result.is_err());
+        // An unrelated bad request says nothing about response_format support
+        assert!(provider.structured_output.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured_walks_fallback_chain() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/json-fallback-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(
+                serde_json::json!({"model": TANZU_DEFAULT_MODEL}),
+            ))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "error": {"message": "model not found in plan"}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/json-fallback-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "response_format": {"type": "json_schema"}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "choices": [{"message": {"role": "assistant", "content": "{\"city\": \"Paris\"}"}}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
 
-    Ok(models)
-}
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/json-fallback-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.fallback_models = vec!["llama3.2:1b".to_string()];
 
-/// Filter models to only those with chat or tool capabilities.
-#[allow(dead_code)]
-fn filter_chat_models(models: &[AdvertisedModel]) -> Vec<String> {
-    models
-        .iter()
-        .filter(|m| {
-            m.capabilities.iter().any(|c| {
-                c.eq_ignore_ascii_case("chat")
-                    || c.eq_ignore_ascii_case("tools")
-                    || c.eq_ignore_ascii_case("completion")
-            })
-        })
-        .map(|m| m.name.clone())
-        .collect()
-}
+        let (value, usage) = provider
+            .complete_structured(
+                None,
+                "system",
+                &[Message::user().with_text("capital?")],
+                &serde_json::json!({"type": "object"}),
+            )
+            .await
+            .unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(value, serde_json::json!({"city": "Paris"}));
+        assert_eq!(usage.model, "llama3.2:1b");
+    }
 
-    // --- Credential Parsing Tests ---
+    #[tokio::test]
+    async fn test_prompt_cache_hints_and_hits() {
+        let mock_server = MockServer::start().await;
 
-    #[test]
-    fn test_parse_single_model_credentials() {
-        let json = serde_json::json!({
-            "api_base": "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7/openai",
-            "api_key": "eyJhbGciOiJIUzI1NiJ9.test",
-            "endpoint": {
-                "api_base": "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7",
-                "api_key": "eyJhbGciOiJIUzI1NiJ9.test",
-                "config_url": "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7/config/v1/endpoint",
-                "name": "tanzu-gpt-oss-120b-v1025-eaf66e7"
-            },
-            "model_aliases": null,
-            "model_capabilities": ["chat", "tools"],
-            "model_name": "openai/gpt-oss-120b",
-            "wire_format": "openai"
-        });
+        Mock::given(method("GET"))
+            .and(path("/cache-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "qwen3-30b", "capabilities": ["CHAT", "TOOLS", "PROMPT_CACHE"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/cache-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "messages": [{
+                    "role": "system",
+                    "content": [{"type": "text", "cache_control": {"type": "ephemeral"}}]
+                }]
+            })))
+            .and(|request: &wiremock::Request| {
+                serde_json::from_slice::<Value>(&request.body)
+                    .is_ok_and(|body| body["cache_salt"].is_string())
+            })
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("X-Cached-Prompt-Tokens", "96")
+                    .set_body_json(serde_json::json!({
+                        "model": "qwen3-30b",
+                        "choices": [{"message": {"role": "assistant", "content": "cached"}}],
+                        "usage": {"prompt_tokens": 128, "completion_tokens": 2, "total_tokens": 130}
+                    })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
 
-        let creds = parse_binding_credentials(&json).unwrap();
-        assert_eq!(
-            creds.endpoint_base,
-            "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7"
-        );
-        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.test");
-        assert_eq!(creds.model_name, Some("openai/gpt-oss-120b".to_string()));
-        assert!(creds.config_url.is_some());
+        let endpoint_base = format!("{}/cache-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+        let (message, _) = provider
+            .complete_with_model(
+                Some("cache"),
+                &ModelConfig::new_or_fail("qwen3-30b"),
+                "You are goose.",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "cached");
         assert_eq!(
-            creds.config_url.unwrap(),
-            "https://genai-proxy.sys.example.com/tanzu-gpt-oss-120b-v1025-eaf66e7/config/v1/endpoint"
+            provider.usage_report().sessions["cache"]["qwen3-30b"].cached_prompt_tokens,
+            96
         );
     }
 
+    #[tokio::test]
+    async fn test_complete_rejects_images_without_vision() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/text-only-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT", "TOOLS"]},
+                    {"name": "llava:7b", "capabilities": ["CHAT", "VISION"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/text-only-plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"model": "llava:7b"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llava:7b",
+                "choices": [{"message": {"role": "assistant", "content": "A cat"}}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/text-only-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+        let messages = [Message::user()
+            .with_text("What is this?")
+            .with_image("iVBORw0KGgo=", "image/png")];
+
+        let err = provider
+            .complete_with_model(
+                None,
+                &ModelConfig::new_or_fail("llama3.2:1b"),
+                "system",
+                &messages,
+                &[],
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("VISION"));
+
+        let (message, _) = provider
+            .complete_with_model(
+                None,
+                &ModelConfig::new_or_fail("llava:7b"),
+                "system",
+                &messages,
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "A cat");
+    }
+
     #[test]
-    fn test_parse_multi_model_credentials() {
-        let json = serde_json::json!({
-            "endpoint": {
-                "api_base": "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a",
-                "api_key": "eyJhbGciOiJIUzI1NiJ9.multi",
-                "config_url": "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a/config/v1/endpoint",
-                "name": "tanzu-all-models-1a56b7a"
-            }
-        });
+    fn test_parse_service_key_multi_model() {
+        let contents = r#"Getting key goose-key for service instance genai as developer...
+
+{
+  "credentials": {
+    "endpoint": {
+      "api_base": "https://genai-proxy.sys.example.com/tanzu-all-models-abc",
+      "api_key": "eyJhbGciOiJIUzI1NiJ9.service-key",
+      "config_url": "https://genai-proxy.sys.example.com/tanzu-all-models-abc/config/v1/endpoint"
+    }
+  }
+}"#;
 
-        let creds = parse_binding_credentials(&json).unwrap();
+        let creds = parse_service_key(contents).unwrap();
         assert_eq!(
             creds.endpoint_base,
-            "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a"
+            "https://genai-proxy.sys.example.com/tanzu-all-models-abc"
         );
-        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.multi");
-        assert_eq!(creds.model_name, None);
+        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.service-key");
         assert!(creds.config_url.is_some());
+        assert!(creds.model_name.is_none());
     }
 
     #[test]
-    fn test_parse_deprecated_single_model_no_endpoint() {
-        let json = serde_json::json!({
-            "api_base": "https://genai-proxy.sys.example.com/some-guid/openai",
-            "api_key": "eyJhbGciOiJIUzI1NiJ9.deprecated",
-            "model_name": "llama3:8b",
-            "model_capabilities": ["chat"],
+    fn test_parse_service_key_single_model() {
+        let contents = r#"{
+            "api_base": "https://genai-proxy.sys.example.com/tanzu-llama-abc/openai",
+            "api_key": "jwt",
+            "model_name": "llama3.2:1b",
+            "model_capabilities": ["chat", "tools"],
             "wire_format": "openai"
-        });
+        }"#;
 
-        let creds = parse_binding_credentials(&json).unwrap();
+        let creds = parse_service_key(contents).unwrap();
         assert_eq!(
             creds.endpoint_base,
-            "https://genai-proxy.sys.example.com/some-guid"
+            "https://genai-proxy.sys.example.com/tanzu-llama-abc"
         );
-        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.deprecated");
-        assert_eq!(creds.model_name, Some("llama3:8b".to_string()));
-        assert!(creds.config_url.is_none());
-    }
-
-    // --- URL Construction Tests ---
+        assert_eq!(creds.model_name.as_deref(), Some("llama3.2:1b"));
 
-    #[test]
-    fn test_strip_openai_suffix() {
-        assert_eq!(
-            strip_openai_suffix("https://proxy.example.com/guid/openai"),
-            "https://proxy.example.com/guid"
-        );
+        assert!(parse_service_key("No service key found").is_err());
         assert_eq!(
-            strip_openai_suffix("https://proxy.example.com/guid/openai/"),
-            "https://proxy.example.com/guid"
+            parse_service_key(r#"{"uri": "postgres://db"}"#)
+                .unwrap_err()
+                .to_string(),
+            "endpoint missing"
         );
+    }
+
+    #[tokio::test]
+    async fn test_select_default_model_from_config_url() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/select-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT", "TOOLS"]},
+                    {"name": "qwen3-30b", "capabilities": ["CHAT", "TOOLS"]},
+                    {"name": "nomic-embed-text", "capabilities": ["EMBEDDING"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/select-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
         assert_eq!(
-            strip_openai_suffix("https://proxy.example.com/guid"),
-            "https://proxy.example.com/guid"
+            provider.select_default_model().await.as_deref(),
+            Some("qwen3-30b")
         );
     }
 
-    #[test]
-    fn test_openai_base_url_construction() {
-        let endpoint_base = "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a";
-        let host = format!("{}/openai", endpoint_base.trim_end_matches('/'));
+    #[tokio::test]
+    async fn test_select_default_model_uses_single_model_binding() {
+        let mut creds = test_credentials("https://genai.example.com/llama-plan", None);
+        creds.model_name = Some("llama3.2:1b".to_string());
+        let provider = test_provider(vec![creds]);
+
         assert_eq!(
-            host,
-            "https://genai-proxy.sys.example.com/tanzu-all-models-1a56b7a/openai"
+            provider.select_default_model().await.as_deref(),
+            Some("llama3.2:1b")
         );
     }
 
-    // --- VCAP_SERVICES Parsing Tests ---
+    #[tokio::test]
+    async fn test_polling_updates_routing_models() {
+        let mock_server = MockServer::start().await;
 
-    #[test]
-    fn test_parse_vcap_services_multi_model() {
-        let vcap = serde_json::json!({
-            "genai": [{
-                "binding_guid": "162e78b4-408b-4bdd-8df3-0ae1e4d6d13b",
-                "binding_name": null,
-                "credentials": {
-                    "endpoint": {
-                        "api_base": "https://genai-proxy.sys.example.com/all-models-9afff1f",
-                        "api_key": "eyJhbGciOiJIUzI1NiJ9.vcap",
-                        "config_url": "https://genai-proxy.sys.example.com/all-models-9afff1f/config/v1/endpoint",
-                        "name": "all-models-9afff1f"
-                    }
-                },
-                "instance_guid": "5008a1ec-c406-4ee8-8f9d-56c723af2f1f",
-                "instance_name": "all-models",
-                "label": "genai",
-                "name": "all-models",
-                "plan": "all-models",
-                "tags": ["genai", "llm"]
-            }]
-        });
+        Mock::given(method("GET"))
+            .and(path("/poll-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "qwen3-30b", "capabilities": ["CHAT", "TOOLS"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/poll-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let mut provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+        provider.client.bindings[0].set_models(vec!["llama3.2:1b".to_string()]);
+
+        provider.start_polling(Duration::from_millis(20));
+        for _ in 0..50 {
+            if provider.client.bindings[0].serves("qwen3-30b") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
 
-        let creds = parse_vcap_services(&vcap.to_string()).unwrap();
         assert_eq!(
-            creds.endpoint_base,
-            "https://genai-proxy.sys.example.com/all-models-9afff1f"
+            provider.client.bindings[0].models(),
+            vec!["qwen3-30b".to_string()]
         );
-        assert_eq!(creds.api_key, "eyJhbGciOiJIUzI1NiJ9.vcap");
-        assert!(creds.config_url.is_some());
-        assert_eq!(creds.model_name, None);
     }
 
     #[test]
-    fn test_parse_vcap_services_no_genai() {
-        let vcap = serde_json::json!({
-            "mysql": [{
-                "credentials": {"uri": "mysql://localhost"}
-            }]
+    fn test_parse_config_response_context_length() {
+        let json = r#"{
+            "advertisedModels": [
+                {"name": "llama3.2:1b", "capabilities": ["CHAT"], "contextLength": 8192},
+                {"name": "qwen3-30b", "capabilities": ["CHAT"], "context_length": 32768},
+                {"name": "mxbai-embed-large", "capabilities": ["EMBEDDING"]}
+            ]
+        }"#;
+
+        let config: ConfigResponse = serde_json::from_str(json).unwrap();
+        let lengths: Vec<_> = config
+            .advertised_models
+            .iter()
+            .map(|m| m.context_length)
+            .collect();
+        assert_eq!(lengths, vec![Some(8192), Some(32768), None]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_rejects_prompt_over_context_length() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/small-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT"], "contextLength": 64}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/small-plan/openai/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/small-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let model_config = ModelConfig::new_or_fail("llama3.2:1b");
+        let result = provider
+            .complete_with_model(
+                None,
+                &model_config,
+                "system",
+                &[Message::user().with_text("word ".repeat(200))],
+                &[],
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tiered_routing_sends_short_requests_to_fast_model() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/tiered-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "openai/gpt-oss-120b", "capabilities": ["CHAT", "TOOLS"]},
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT", "TOOLS"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        for model in ["llama3.2:1b", "openai/gpt-oss-120b"] {
+            Mock::given(method("POST"))
+                .and(path("/tiered-plan/openai/v1/chat/completions"))
+                .and(body_partial_json(serde_json::json!({"model": model})))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "model": model,
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "ok"},
+                        "finish_reason": "stop"
+                    }]
+                })))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/tiered-plan", mock_server.uri()),
+            Some(format!(
+                "{}/tiered-plan/config/v1/endpoint",
+                mock_server.uri()
+            )),
+        )]);
+        provider.tiered = Some(tiered::TieredRouting {
+            fast_model: None,
+            strong_model: None,
+            fast_max_tokens: 100,
         });
+        let tool = Tool::new(
+            "get_weather",
+            "Look up the weather",
+            Arc::new(
+                serde_json::json!({"type": "object"})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+            ),
+        );
+        let messages = [Message::user().with_text("Title for this chat?")];
 
-        assert!(parse_vcap_services(&vcap.to_string()).is_none());
+        let (_, usage) = provider
+            .complete_with_model(None, &provider.model, "system", &messages, &[])
+            .await
+            .unwrap();
+        assert_eq!(usage.model, "llama3.2:1b");
+
+        let (_, usage) = provider
+            .complete_with_model(
+                None,
+                &provider.model,
+                "system",
+                &messages,
+                std::slice::from_ref(&tool),
+            )
+            .await
+            .unwrap();
+        assert_eq!(usage.model, "openai/gpt-oss-120b");
     }
 
-    #[test]
-    fn test_parse_vcap_services_empty_genai() {
-        let vcap = serde_json::json!({
-            "genai": []
+    #[tokio::test]
+    async fn test_lead_plans_and_worker_executes() {
+        let mock_server = MockServer::start().await;
+        for model in ["openai/gpt-oss-120b", "llama3.2:1b"] {
+            Mock::given(method("POST"))
+                .and(path("/lead-plan/openai/v1/chat/completions"))
+                .and(body_partial_json(serde_json::json!({"model": model})))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "model": model,
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "ok"},
+                        "finish_reason": "stop"
+                    }]
+                })))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut provider = test_provider(vec![test_credentials(
+            &format!("{}/lead-plan", mock_server.uri()),
+            None,
+        )]);
+        provider.lead_worker = Some(lead_worker::LeadWorker {
+            lead: "openai/gpt-oss-120b".to_string(),
+            worker: "llama3.2:1b".to_string(),
         });
+        let prompt = Message::user().with_text("Fix the failing test");
+        let (_, usage) = provider
+            .complete_with_model(
+                None,
+                &provider.model,
+                "system",
+                std::slice::from_ref(&prompt),
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(usage.model, "openai/gpt-oss-120b");
 
-        assert!(parse_vcap_services(&vcap.to_string()).is_none());
+        let messages = [
+            prompt,
+            Message::assistant().with_tool_request(
+                "call_1",
+                Ok(rmcp::model::CallToolRequestParam {
+                    name: "shell".into(),
+                    arguments: None,
+                }),
+            ),
+            Message::user().with_tool_response("call_1", Ok(vec![])),
+        ];
+        let (_, usage) = provider
+            .complete_with_model(None, &provider.model, "system", &messages, &[])
+            .await
+            .unwrap();
+        assert_eq!(usage.model, "llama3.2:1b");
     }
 
-    #[test]
-    fn test_parse_vcap_services_invalid_json() {
-        assert!(parse_vcap_services("not json").is_none());
+    #[tokio::test]
+    async fn test_auto_compact_summarizes_older_turns() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/compact-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT"], "contextLength": 200}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        let reply = |text: &str| {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": "stop"
+                }]
+            }))
+        };
+        // Three chunk summaries, then one summary of the summaries
+        Mock::given(method("POST"))
+            .and(path("/compact-plan/openai/v1/chat/completions"))
+            .and(body_string_contains("You condense conversations"))
+            .respond_with(reply("The user deployed the app to staging."))
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/compact-plan/openai/v1/chat/completions"))
+            .and(body_string_contains("Summary of the earlier conversation"))
+            .respond_with(reply("Done."))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/compact-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let mut provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+        provider.auto_compact = true;
+
+        let long = "log line ".repeat(35);
+        let messages = [
+            Message::user().with_text(&long),
+            Message::assistant().with_text(&long),
+            Message::user().with_text(&long),
+            Message::assistant().with_text("ok"),
+            Message::user().with_text("and now?"),
+            Message::assistant().with_text("checking"),
+        ];
+        let model_config = ModelConfig::new_or_fail("llama3.2:1b");
+        let (message, _) = provider
+            .complete_with_model(None, &model_config, "system", &messages, &[])
+            .await
+            .unwrap();
+
+        assert_eq!(message.as_concat_text(), "Done.");
     }
 
-    // --- Model Discovery Tests ---
+    #[tokio::test]
+    async fn test_complete_truncates_oversized_tool_result() {
+        let mock_server = MockServer::start().await;
 
-    #[test]
-    fn test_filter_chat_models() {
-        let models = vec![
-            AdvertisedModel {
-                name: "llama3.2:1b".to_string(),
-                capabilities: vec!["CHAT".to_string(), "TOOLS".to_string()],
-            },
-            AdvertisedModel {
-                name: "mxbai-embed-large".to_string(),
-                capabilities: vec!["EMBEDDING".to_string()],
-            },
-            AdvertisedModel {
-                name: "qwen3-30b".to_string(),
-                capabilities: vec!["chat".to_string()],
-            },
+        Mock::given(method("GET"))
+            .and(path("/truncate-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT", "TOOLS"], "contextLength": 4096}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/truncate-plan/openai/v1/chat/completions"))
+            .and(body_string_contains("bytes of tool output omitted"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "The log is long."},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/truncate-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let log = "GET /healthz 200\n".repeat(3000);
+        let messages = [
+            Message::user().with_text("summarize the log"),
+            Message::assistant().with_tool_request(
+                "call_1",
+                Ok(rmcp::model::CallToolRequestParam {
+                    name: "read_log".into(),
+                    arguments: None,
+                }),
+            ),
+            Message::user().with_tool_response("call_1", Ok(vec![rmcp::model::Content::text(log)])),
         ];
+        let model_config = ModelConfig::new_or_fail("llama3.2:1b");
+        let (message, _) = provider
+            .complete_with_model(None, &model_config, "system", &messages, &[])
+            .await
+            .unwrap();
 
-        let chat_models = filter_chat_models(&models);
-        assert_eq!(chat_models.len(), 2);
-        assert!(chat_models.contains(&"llama3.2:1b".to_string()));
-        assert!(chat_models.contains(&"qwen3-30b".to_string()));
-        assert!(!chat_models.contains(&"mxbai-embed-large".to_string()));
+        assert_eq!(message.as_concat_text(), "The log is long.");
+    }
+
+    #[tokio::test]
+    async fn test_model_info_lists_capabilities() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/capability-plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "capability-test-vl", "capabilities": ["CHAT", "TOOLS", "VISION"], "contextLength": 65536},
+                    {"name": "capability-test-embed", "capabilities": ["EMBEDDING"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint_base = format!("{}/capability-plan", mock_server.uri());
+        let config_url = format!("{}/config/v1/endpoint", endpoint_base);
+        let provider = test_provider(vec![test_credentials(&endpoint_base, Some(config_url))]);
+
+        let models = provider.fetch_model_info().await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert!(models[0].supports("VISION"));
+        assert!(models[1].supports("EMBEDDING"));
+
+        let meta = TanzuAIServicesProvider::metadata();
+        let known = meta
+            .known_models
+            .iter()
+            .find(|m| m.name == "capability-test-vl")
+            .unwrap();
+        assert_eq!(known.context_limit, 65536);
+        assert!(!meta
+            .known_models
+            .iter()
+            .any(|m| m.name == "capability-test-embed"));
     }
 
     #[test]
@@ -484,6 +6618,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_config_response_deprecations() {
+        let json = r#"{
+            "advertisedModels": [
+                {"name": "llama3.1:8b", "capabilities": ["CHAT"], "deprecated": {"replacedBy": "llama3.3:70b"}},
+                {"name": "qwen2.5:7b", "capabilities": ["CHAT"], "deprecated": true},
+                {"name": "llama3.3:70b", "capabilities": ["CHAT", "TOOLS"], "deprecated": false}
+            ]
+        }"#;
+
+        let config: ConfigResponse = serde_json::from_str(json).unwrap();
+        let deprecations: Vec<_> = config
+            .advertised_models
+            .iter()
+            .map(|m| m.deprecation.clone())
+            .collect();
+        assert_eq!(
+            deprecations[0].as_ref().unwrap().replacement.as_deref(),
+            Some("llama3.3:70b")
+        );
+        assert_eq!(deprecations[1], Some(Deprecation::default()));
+        assert_eq!(deprecations[2], None);
+        assert!(TanzuModelInfo::from(config.advertised_models[0].clone()).is_deprecated());
+    }
+
+    #[test]
+    fn test_parse_config_response_revisions() {
+        let json = r#"{
+            "advertisedModels": [
+                {"name": "llama3.3:70b", "capabilities": ["CHAT"], "revision": "2025-06"},
+                {"name": "qwen3-30b", "capabilities": ["CHAT"], "digest": "sha256:9f86d081"},
+                {"name": "llama3.2:1b", "capabilities": ["CHAT"]}
+            ]
+        }"#;
+
+        let config: ConfigResponse = serde_json::from_str(json).unwrap();
+        let revisions: Vec<_> = config
+            .advertised_models
+            .iter()
+            .map(|m| m.revision.as_deref())
+            .collect();
+        assert_eq!(
+            revisions,
+            vec![Some("2025-06"), Some("sha256:9f86d081"), None]
+        );
+    }
+
     // --- Format Detection Tests ---
 
     #[test]
@@ -503,7 +6684,7 @@ mod tests {
             "wire_format": "openai"
         });
 
-        let creds = parse_binding_credentials(&json).unwrap();
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
         // Should prefer endpoint.api_base and have model_name
         assert_eq!(creds.endpoint_base, "https://proxy.example.com/guid");
         assert_eq!(creds.model_name, Some("openai/gpt-oss-120b".to_string()));
@@ -520,7 +6701,7 @@ mod tests {
             }
         });
 
-        let creds = parse_binding_credentials(&json).unwrap();
+        let creds = parse_binding_credentials(&json, "credentials").unwrap();
         assert_eq!(creds.endpoint_base, "https://proxy.example.com/plan");
         assert_eq!(creds.model_name, None);
     }
@@ -557,5 +6738,13 @@ mod tests {
             .find(|k| k.name == "TANZU_AI_CONFIG_URL")
             .unwrap();
         assert!(!config_url.required);
+
+        let config_api_key = meta
+            .config_keys
+            .iter()
+            .find(|k| k.name == "TANZU_AI_CONFIG_API_KEY")
+            .unwrap();
+        assert!(!config_api_key.required);
+        assert!(config_api_key.secret);
     }
 }
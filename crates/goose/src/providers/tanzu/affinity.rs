@@ -0,0 +1,204 @@
+//! Sticky routing of a Goose session to one model replica.
+//!
+//! A plan whose model runs on several replicas benefits from sending a session's
+//! requests to the replica that already holds its prefix in the KV cache. The gorouter
+//! pins clients that replay its `__VCAP_ID__` cookie (set next to a `JSESSIONID`), and the
+//! GenAI proxy pins those that echo its `X-Session-Affinity` header. Both are captured
+//! from each chat response and replayed on the session's next request. Tokens are kept
+//! per Goose session, never shared across sessions. `TANZU_AI_SESSION_AFFINITY=false`
+//! turns this off.
+
+use reqwest::header::{HeaderMap, COOKIE, SET_COOKIE};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Instant;
+
+/// Header the GenAI proxy routes sticky requests by.
+pub const AFFINITY_HEADER: &str = "X-Session-Affinity";
+/// Cookies the gorouter routes sticky requests by.
+const AFFINITY_COOKIES: [&str; 2] = ["JSESSIONID", "__VCAP_ID__"];
+/// Sessions remembered per endpoint before the least recently used is forgotten.
+const MAX_SESSIONS: usize = 1024;
+
+static AFFINITIES: LazyLock<Mutex<HashMap<String, Arc<SessionAffinity>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The shared affinity store for an endpoint base.
+pub fn for_endpoint(endpoint_base: &str) -> Arc<SessionAffinity> {
+    AFFINITIES
+        .lock()
+        .unwrap()
+        .entry(endpoint_base.to_string())
+        .or_insert_with(|| {
+            let enabled = crate::config::Config::global()
+                .get_param("TANZU_AI_SESSION_AFFINITY")
+                .unwrap_or(true);
+            Arc::new(SessionAffinity::new(enabled))
+        })
+        .clone()
+}
+
+/// What a session replays to reach its replica.
+#[derive(Debug, Default, Clone)]
+struct Route {
+    header: Option<String>,
+    cookies: Vec<(String, String)>,
+    used: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct SessionAffinity {
+    enabled: bool,
+    sessions: Mutex<HashMap<String, Route>>,
+}
+
+impl SessionAffinity {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Headers routing `session_id` to its replica, empty until one has answered.
+    pub fn headers(&self, session_id: Option<&str>) -> Vec<(&'static str, String)> {
+        let Some(session_id) = session_id.filter(|_| self.enabled) else {
+            return Vec::new();
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(route) = sessions.get_mut(session_id) else {
+            return Vec::new();
+        };
+        route.used = Some(Instant::now());
+        let mut headers = Vec::new();
+        if let Some(value) = &route.header {
+            headers.push((AFFINITY_HEADER, value.clone()));
+        }
+        if !route.cookies.is_empty() {
+            let cookie = route
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join("; ");
+            headers.push((COOKIE.as_str(), cookie));
+        }
+        headers
+    }
+
+    /// Remember the replica that answered `session_id`.
+    pub fn capture(&self, session_id: Option<&str>, response: &HeaderMap) {
+        let Some(session_id) = session_id.filter(|_| self.enabled) else {
+            return;
+        };
+        let header = response
+            .get(AFFINITY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let cookies: Vec<_> = response
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(parse_set_cookie)
+            .filter(|(name, _)| AFFINITY_COOKIES.contains(&name.as_str()))
+            .collect();
+        if header.is_none() && cookies.is_empty() {
+            return;
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= MAX_SESSIONS && !sessions.contains_key(session_id) {
+            if let Some(oldest) = sessions
+                .iter()
+                .min_by_key(|(_, route)| route.used)
+                .map(|(id, _)| id.clone())
+            {
+                sessions.remove(&oldest);
+            }
+        }
+        let route = sessions.entry(session_id.to_string()).or_default();
+        route.used = Some(Instant::now());
+        if header.is_some() {
+            route.header = header;
+        }
+        for (name, value) in cookies {
+            route.cookies.retain(|(existing, _)| *existing != name);
+            route.cookies.push((name, value));
+        }
+    }
+}
+
+/// Name and value of a `Set-Cookie` header, ignoring its attributes.
+fn parse_set_cookie(value: &str) -> Option<(String, String)> {
+    let pair = value.split(';').next()?;
+    let (name, value) = pair.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn response_headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_captures_and_replays_per_session() {
+        let affinity = SessionAffinity::new(true);
+        assert!(affinity.headers(Some("a")).is_empty());
+
+        affinity.capture(
+            Some("a"),
+            &response_headers(&[
+                ("x-session-affinity", "replica-2"),
+                ("set-cookie", "JSESSIONID=abc; Path=/; HttpOnly"),
+                ("set-cookie", "__VCAP_ID__=f00d; Path=/; HttpOnly"),
+                ("set-cookie", "tracking=1; Path=/"),
+            ]),
+        );
+        assert_eq!(
+            affinity.headers(Some("a")),
+            vec![
+                (AFFINITY_HEADER, "replica-2".to_string()),
+                ("cookie", "JSESSIONID=abc; __VCAP_ID__=f00d".to_string()),
+            ]
+        );
+        assert!(affinity.headers(Some("b")).is_empty());
+        assert!(affinity.headers(None).is_empty());
+
+        affinity.capture(
+            Some("a"),
+            &response_headers(&[("set-cookie", "__VCAP_ID__=beef")]),
+        );
+        assert_eq!(
+            affinity.headers(Some("a"))[1].1,
+            "JSESSIONID=abc; __VCAP_ID__=beef"
+        );
+    }
+
+    #[test]
+    fn test_disabled_affinity_sends_nothing() {
+        let affinity = SessionAffinity::new(false);
+        affinity.capture(
+            Some("a"),
+            &response_headers(&[("x-session-affinity", "replica-2")]),
+        );
+        assert!(affinity.headers(Some("a")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_set_cookie() {
+        assert_eq!(
+            parse_set_cookie("__VCAP_ID__=f00d; Path=/"),
+            Some(("__VCAP_ID__".to_string(), "f00d".to_string()))
+        );
+        assert_eq!(parse_set_cookie("invalid"), None);
+    }
+}
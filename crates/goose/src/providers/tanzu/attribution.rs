@@ -0,0 +1,140 @@
+//! Cost attribution headers for the platform's billing pipeline.
+//!
+//! Foundations bill GenAI usage by org and space. When Goose runs as a Cloud Foundry app,
+//! `VCAP_APPLICATION` names the org, space and app, and every request carries them as
+//! `X-Tanzu-Org`, `X-Tanzu-Space` and `X-Tanzu-App` so the proxy can attribute usage.
+//! `TANZU_AI_ORG`, `TANZU_AI_SPACE` and `TANZU_AI_APP` set or override them, e.g. for
+//! local sessions billed to a team's space; `TANZU_AI_COST_ATTRIBUTION=false` sends none.
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::Deserialize;
+
+pub const ORG_HEADER: &str = "X-Tanzu-Org";
+pub const SPACE_HEADER: &str = "X-Tanzu-Space";
+pub const APP_HEADER: &str = "X-Tanzu-App";
+
+/// The fields of `VCAP_APPLICATION` used for attribution.
+#[derive(Debug, Default, Deserialize)]
+struct VcapApplication {
+    organization_name: Option<String>,
+    space_name: Option<String>,
+    application_name: Option<String>,
+}
+
+/// Org, space and app to attribute requests to.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Attribution {
+    pub org: Option<String>,
+    pub space: Option<String>,
+    pub app: Option<String>,
+}
+
+impl Attribution {
+    pub fn from_config() -> Self {
+        let config = crate::config::Config::global();
+        let enabled: bool = config
+            .get_param("TANZU_AI_COST_ATTRIBUTION")
+            .unwrap_or(true);
+        if !enabled {
+            return Self::default();
+        }
+        let vcap = std::env::var("VCAP_APPLICATION")
+            .ok()
+            .map(|json| Self::from_vcap_application(&json))
+            .unwrap_or_default();
+        let param = |key: &str| config.get_param::<String>(key).ok();
+        Self {
+            org: param("TANZU_AI_ORG").or(vcap.org),
+            space: param("TANZU_AI_SPACE").or(vcap.space),
+            app: param("TANZU_AI_APP").or(vcap.app),
+        }
+    }
+
+    /// Parse the `VCAP_APPLICATION` JSON; anything unparseable attributes nothing.
+    pub fn from_vcap_application(json: &str) -> Self {
+        let vcap: VcapApplication = serde_json::from_str(json).unwrap_or_else(|e| {
+            tracing::warn!("Could not parse VCAP_APPLICATION: {}", e);
+            VcapApplication::default()
+        });
+        Self {
+            org: vcap.organization_name,
+            space: vcap.space_name,
+            app: vcap.application_name,
+        }
+    }
+
+    /// Default headers for every request.
+    ///
+    /// Names that aren't valid header values are left out rather than failing requests.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (ORG_HEADER, &self.org),
+            (SPACE_HEADER, &self.space),
+            (APP_HEADER, &self.app),
+        ] {
+            let Some(value) = value.as_deref().filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            match HeaderValue::from_str(value) {
+                Ok(value) => {
+                    headers.insert(name, value);
+                }
+                Err(_) => tracing::warn!(
+                    "Not sending {}: {:?} is not a valid header value",
+                    name,
+                    value
+                ),
+            }
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vcap_application() {
+        let attribution = Attribution::from_vcap_application(
+            r#"{
+                "application_id": "fa05c1a9-0fc1-4fbd-bae1-139850dec7a3",
+                "application_name": "goose-agent",
+                "organization_name": "platform-eng",
+                "space_name": "dev",
+                "limits": {"mem": 1024}
+            }"#,
+        );
+        assert_eq!(
+            attribution,
+            Attribution {
+                org: Some("platform-eng".to_string()),
+                space: Some("dev".to_string()),
+                app: Some("goose-agent".to_string()),
+            }
+        );
+
+        let headers = attribution.headers();
+        assert_eq!(headers[ORG_HEADER], "platform-eng");
+        assert_eq!(headers[SPACE_HEADER], "dev");
+        assert_eq!(headers[APP_HEADER], "goose-agent");
+
+        assert_eq!(
+            Attribution::from_vcap_application("not json"),
+            Attribution::default()
+        );
+    }
+
+    #[test]
+    fn test_invalid_header_values_skipped() {
+        let attribution = Attribution {
+            org: Some("platform\neng".to_string()),
+            space: Some(String::new()),
+            app: Some("goose".to_string()),
+        };
+        let headers = attribution.headers();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[APP_HEADER], "goose");
+    }
+}
@@ -0,0 +1,223 @@
+//! Audio transcription via the OpenAI-compatible `/openai/v1/audio/transcriptions`
+//! endpoint.
+//!
+//! Plans serving whisper-class models advertise them with the AUDIO capability. The
+//! recording is uploaded as `multipart/form-data`, encoded here so it goes through the
+//! binding's transport with the same authentication, retries and circuit breaker as
+//! every other request.
+
+use super::trace;
+use super::transport::Transport;
+use crate::providers::errors::ProviderError;
+use serde::Deserialize;
+
+const TRANSCRIPTIONS_PATH: &str = "openai/v1/audio/transcriptions";
+
+#[derive(Debug, Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+/// A `multipart/form-data` body.
+#[derive(Debug, Clone)]
+pub struct MultipartForm {
+    boundary: String,
+    body: Vec<u8>,
+    /// Text fields, kept for labelling the request
+    fields: Vec<(String, String)>,
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self {
+            boundary: format!("tanzu-{}", trace::random_hex(12)),
+            body: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.part_header(name, None, None);
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn file(mut self, name: &str, file_name: &str, content_type: &str, data: &[u8]) -> Self {
+        self.part_header(name, Some(file_name), Some(content_type));
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// The value of a text field.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// The encoded body, closing boundary included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.body.clone();
+        bytes.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        bytes
+    }
+
+    fn part_header(&mut self, name: &str, file_name: Option<&str>, content_type: Option<&str>) {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            quoted(name)
+        );
+        if let Some(file_name) = file_name {
+            header.push_str(&format!("; filename=\"{}\"", quoted(file_name)));
+        }
+        header.push_str("\r\n");
+        if let Some(content_type) = content_type {
+            header.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        header.push_str("\r\n");
+        self.body.extend_from_slice(header.as_bytes());
+    }
+}
+
+impl Default for MultipartForm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drop characters that would end a quoted header parameter.
+fn quoted(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !matches!(c, '"' | '\r' | '\n'))
+        .collect()
+}
+
+/// MIME type for a recording, from its file extension.
+fn audio_content_type(file_name: &str) -> &'static str {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp3" | "mpga" | "mpeg" => "audio/mpeg",
+        "m4a" | "mp4" => "audio/mp4",
+        "wav" => "audio/wav",
+        "webm" => "audio/webm",
+        "ogg" | "oga" => "audio/ogg",
+        "flac" => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Transcribe a recording with `model` on a binding's endpoint.
+///
+/// `language` is an ISO-639-1 hint; without it the model detects the language.
+pub async fn transcribe(
+    transport: &Transport,
+    model: &str,
+    audio: &[u8],
+    file_name: &str,
+    language: Option<&str>,
+) -> Result<String, ProviderError> {
+    let mut form = MultipartForm::new()
+        .text("model", model)
+        .text("response_format", "json");
+    if let Some(language) = language {
+        form = form.text("language", language);
+    }
+    let form = form.file("file", file_name, audio_content_type(file_name), audio);
+
+    let response = transport.post_multipart(TRANSCRIPTIONS_PATH, &form).await?;
+    let parsed: TranscriptionResponse = serde_json::from_value(response).map_err(|e| {
+        ProviderError::RequestFailed(format!("Invalid transcription response: {}", e))
+    })?;
+    Ok(parsed.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tanzu::token::TokenManager;
+    use std::sync::Arc;
+    use wiremock::matchers::{body_string_contains, header, header_regex, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_multipart_encoding() {
+        let form = MultipartForm::new().text("model", "whisper-large-v3").file(
+            "file",
+            "memo \"1\".wav",
+            "audio/wav",
+            b"RIFF",
+        );
+        let boundary = form.boundary.clone();
+        let body = String::from_utf8(form.to_bytes()).unwrap();
+
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-large-v3\r\n\
+                 --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"memo 1.wav\"\r\n\
+                 Content-Type: audio/wav\r\n\r\nRIFF\r\n--{b}--\r\n",
+                b = boundary
+            )
+        );
+        assert_eq!(form.field("model"), Some("whisper-large-v3"));
+    }
+
+    #[test]
+    fn test_audio_content_type() {
+        assert_eq!(audio_content_type("standup.M4A"), "audio/mp4");
+        assert_eq!(audio_content_type("recording"), "application/octet-stream");
+    }
+
+    #[tokio::test]
+    async fn test_transcribe() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/audio/transcriptions"))
+            .and(header("Authorization", "Bearer test-jwt-token"))
+            .and(header_regex(
+                "Content-Type",
+                "^multipart/form-data; boundary=tanzu-",
+            ))
+            .and(body_string_contains("whisper-large-v3"))
+            .and(body_string_contains("filename=\"standup.mp3\""))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"text": "Ship it on Friday."})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tokens = TokenManager::new("test-jwt-token".to_string(), || async {
+            anyhow::bail!("no refresh")
+        });
+        let transport = Transport::new(
+            reqwest::Client::new(),
+            &format!("{}/plan", mock_server.uri()),
+            Arc::new(tokens),
+        );
+        let text = transcribe(
+            &transport,
+            "whisper-large-v3",
+            b"ID3",
+            "standup.mp3",
+            Some("en"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(text, "Ship it on Friday.");
+    }
+}
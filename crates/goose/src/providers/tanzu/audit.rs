@@ -0,0 +1,239 @@
+//! Opt-in audit log of requests to Tanzu AI Services.
+//!
+//! Set `TANZU_AI_AUDIT_LOG` to `stdout` or a file path to write one JSON line per chat
+//! request with its timestamp, model and revision, endpoint, token counts, latency and
+//! outcome. Message content is left out unless `TANZU_AI_AUDIT_INCLUDE_CONTENT=true`,
+//! and the binding's API key is redacted from everything that is written.
+
+use crate::providers::base::{MessageStream, Usage};
+use crate::providers::errors::ProviderError;
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const REDACTED: &str = "[REDACTED]";
+
+enum Sink {
+    Stdout,
+    File(Mutex<File>),
+}
+
+pub struct AuditLog {
+    sink: Sink,
+    include_content: bool,
+}
+
+/// One audited request.
+#[derive(Debug, Default, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub model: String,
+    pub endpoint: String,
+    pub status: &'static str,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<i32>,
+    /// Advertised revision of the model, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Request messages, only with `TANZU_AI_AUDIT_INCLUDE_CONTENT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+}
+
+impl AuditRecord {
+    pub fn new(model: &str, endpoint: &str, latency: Duration) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            model: model.to_string(),
+            endpoint: endpoint.to_string(),
+            status: "success",
+            latency_ms: latency.as_millis() as u64,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_usage(mut self, usage: &Usage) -> Self {
+        self.prompt_tokens = usage.input_tokens;
+        self.completion_tokens = usage.output_tokens;
+        self
+    }
+
+    pub fn with_revision(mut self, revision: Option<String>) -> Self {
+        self.revision = revision;
+        self
+    }
+
+    pub fn with_error(mut self, error: &ProviderError) -> Self {
+        self.status = "error";
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+impl AuditLog {
+    /// The configured audit log, or `None` when auditing is off.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let target: String = config.get_param("TANZU_AI_AUDIT_LOG").ok()?;
+        let include_content = config
+            .get_param("TANZU_AI_AUDIT_INCLUDE_CONTENT")
+            .unwrap_or(false);
+        match Self::open(&target, include_content) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                tracing::warn!("Tanzu AI audit log disabled, cannot open {}: {}", target, e);
+                None
+            }
+        }
+    }
+
+    fn open(target: &str, include_content: bool) -> std::io::Result<Self> {
+        let sink = match target.trim() {
+            "stdout" | "-" => Sink::Stdout,
+            path => Sink::File(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+        };
+        Ok(Self {
+            sink,
+            include_content,
+        })
+    }
+
+    pub fn include_content(&self) -> bool {
+        self.include_content
+    }
+
+    /// Write a record, redacting `api_key` wherever it appears.
+    pub fn record(&self, mut record: AuditRecord, api_key: &str) {
+        if !self.include_content {
+            record.request = None;
+        }
+        let mut value = match serde_json::to_value(&record) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        redact(&mut value, api_key);
+
+        let line = format!("{}\n", value);
+        let result = match &self.sink {
+            Sink::Stdout => std::io::stdout().lock().write_all(line.as_bytes()),
+            Sink::File(file) => file.lock().unwrap().write_all(line.as_bytes()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to write Tanzu AI audit record: {}", e);
+        }
+    }
+}
+
+/// Write `record` once `stream` finishes, with the usage and errors it carried and the
+/// latency of the whole stream.
+pub fn audit_stream(
+    stream: MessageStream,
+    log: Arc<AuditLog>,
+    mut record: AuditRecord,
+    api_key: String,
+    started: Instant,
+) -> MessageStream {
+    Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        while let Some(item) = stream.next().await {
+            match &item {
+                Ok((_, Some(usage))) => {
+                    record.model = usage.model.clone();
+                    record = record.with_usage(&usage.usage);
+                }
+                Err(e) => record = record.with_error(e),
+                _ => {}
+            }
+            yield item;
+        }
+        record.latency_ms = started.elapsed().as_millis() as u64;
+        log.record(record, &api_key);
+    })
+}
+
+/// Replace every occurrence of `secret` in the strings of `value`.
+fn redact(value: &mut Value, secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    match value {
+        Value::String(s) if s.contains(secret) => *s = s.replace(secret, REDACTED),
+        Value::Array(items) => items.iter_mut().for_each(|v| redact(v, secret)),
+        Value::Object(map) => map.values_mut().for_each(|v| redact(v, secret)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn read_lines(path: &std::path::Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_content_omitted_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(path.to_str().unwrap(), false).unwrap();
+
+        let mut record = AuditRecord::new(
+            "llama3.2:1b",
+            "https://genai/plan",
+            Duration::from_millis(42),
+        );
+        record.prompt_tokens = Some(10);
+        record.completion_tokens = Some(3);
+        record.request = Some(json!([{"role": "user", "content": "secret plans"}]));
+        log.record(record, "jwt-key");
+
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["model"], "llama3.2:1b");
+        assert_eq!(lines[0]["latency_ms"], 42);
+        assert_eq!(lines[0]["prompt_tokens"], 10);
+        assert_eq!(lines[0]["status"], "success");
+        assert!(lines[0].get("request").is_none());
+    }
+
+    #[test]
+    fn test_api_key_redacted_from_content_and_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(path.to_str().unwrap(), true).unwrap();
+
+        let mut record = AuditRecord::new("llama3.2:1b", "https://genai/plan", Duration::ZERO);
+        record.status = "error";
+        record.error = Some("401: invalid token jwt-key".to_string());
+        record.request = Some(json!([{"role": "user", "content": "my key is jwt-key"}]));
+        log.record(record, "jwt-key");
+        log.record(
+            AuditRecord::new("llama3.2:1b", "https://genai/plan", Duration::ZERO),
+            "jwt-key",
+        );
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("jwt-key"));
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["request"][0]["content"], "my key is [REDACTED]");
+        assert_eq!(lines[0]["error"], "401: invalid token [REDACTED]");
+    }
+}
@@ -0,0 +1,255 @@
+//! Load balancing across bindings that serve the same models.
+//!
+//! Binding the same plan more than once adds capacity, but by default every request for
+//! a model goes to the first binding that serves it. `TANZU_AI_LOAD_BALANCE` spreads
+//! requests over all bindings serving the model instead: `round_robin` takes them in
+//! turn, `least_inflight` picks the one with the fewest requests in progress.
+//!
+//! A binding whose requests fail with server errors `FAILURE_THRESHOLD` times in a row
+//! drops out of rotation for `RETRY_AFTER`, after which it is tried again. When every
+//! candidate is out of rotation, requests are spread over all of them anyway.
+
+use super::classify::RequestError;
+use crate::providers::errors::ProviderError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const FAILURE_THRESHOLD: u32 = 3;
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// How a binding is chosen among those serving a model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BalanceMode {
+    /// Always the first binding serving the model
+    #[default]
+    Primary,
+    RoundRobin,
+    LeastInflight,
+}
+
+impl BalanceMode {
+    pub fn from_config() -> Self {
+        crate::config::Config::global()
+            .get_param::<String>("TANZU_AI_LOAD_BALANCE")
+            .map(|mode| Self::parse(&mode))
+            .unwrap_or_default()
+    }
+
+    fn parse(mode: &str) -> Self {
+        match mode.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "round_robin" => Self::RoundRobin,
+            "least_inflight" => Self::LeastInflight,
+            "" | "primary" | "none" => Self::Primary,
+            other => {
+                tracing::warn!("Unknown TANZU_AI_LOAD_BALANCE mode {}; ignoring it", other);
+                Self::Primary
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    out_until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    inflight: Arc<AtomicUsize>,
+    health: Mutex<Health>,
+}
+
+/// Per-binding request counts and failure tracking, indexed like the provider's bindings.
+#[derive(Debug)]
+pub struct Balancer {
+    mode: BalanceMode,
+    next: AtomicUsize,
+    slots: Vec<Slot>,
+}
+
+/// Counts a request as in flight until dropped.
+#[derive(Debug)]
+pub struct Inflight(Arc<AtomicUsize>);
+
+impl Drop for Inflight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Balancer {
+    pub fn new(mode: BalanceMode, bindings: usize) -> Self {
+        Self {
+            mode,
+            next: AtomicUsize::new(0),
+            slots: (0..bindings).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    /// Choose one of the candidate binding indices, which must not be empty.
+    pub fn pick(&self, candidates: &[usize]) -> usize {
+        self.pick_at(candidates, Instant::now())
+    }
+
+    fn pick_at(&self, candidates: &[usize], now: Instant) -> usize {
+        if self.mode == BalanceMode::Primary || candidates.len() == 1 {
+            return candidates[0];
+        }
+        let healthy: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|&index| self.in_rotation(index, now))
+            .collect();
+        let pool = if healthy.is_empty() {
+            candidates
+        } else {
+            &healthy
+        };
+        let turn = self.next.fetch_add(1, Ordering::Relaxed);
+        match self.mode {
+            BalanceMode::LeastInflight => {
+                // Start the scan at the next turn so ties are spread too
+                (0..pool.len())
+                    .map(|offset| pool[(turn + offset) % pool.len()])
+                    .min_by_key(|&index| self.slots[index].inflight.load(Ordering::Relaxed))
+                    .expect("candidates are never empty")
+            }
+            _ => pool[turn % pool.len()],
+        }
+    }
+
+    /// Mark a request to `index` as in flight for as long as the guard lives.
+    pub fn start(&self, index: usize) -> Inflight {
+        let inflight = self.slots[index].inflight.clone();
+        inflight.fetch_add(1, Ordering::Relaxed);
+        Inflight(inflight)
+    }
+
+    /// Record the outcome of a request sent to `index`.
+    pub fn record<T>(&self, index: usize, result: &Result<T, RequestError>) {
+        let failed = matches!(result, Err(e) if is_endpoint_failure(e.error()));
+        self.record_at(index, failed, Instant::now());
+    }
+
+    fn record_at(&self, index: usize, failed: bool, now: Instant) {
+        let mut health = self.slots[index].health.lock().unwrap();
+        if !failed {
+            *health = Health::default();
+            return;
+        }
+        health.failures += 1;
+        if health.failures >= FAILURE_THRESHOLD && self.mode != BalanceMode::Primary {
+            tracing::warn!(
+                "Tanzu binding {} failed {} times in a row; taking it out of rotation for {:?}",
+                index,
+                health.failures,
+                RETRY_AFTER
+            );
+            *health = Health {
+                failures: 0,
+                out_until: Some(now + RETRY_AFTER),
+            };
+        }
+    }
+
+    fn in_rotation(&self, index: usize, now: Instant) -> bool {
+        let health = self.slots[index].health.lock().unwrap();
+        health.out_until.is_none_or(|until| now >= until)
+    }
+}
+
+/// Server errors and open circuits; client errors say nothing about the endpoint.
+fn is_endpoint_failure(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::ServerError(_) => true,
+        ProviderError::RequestFailed(msg) => msg.starts_with("Tanzu AI endpoint"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_error() -> Result<(), RequestError> {
+        Err(ProviderError::ServerError("Bad gateway".to_string()).into())
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(BalanceMode::parse("round-robin"), BalanceMode::RoundRobin);
+        assert_eq!(
+            BalanceMode::parse(" Least_Inflight "),
+            BalanceMode::LeastInflight
+        );
+        assert_eq!(BalanceMode::parse("random"), BalanceMode::Primary);
+    }
+
+    #[test]
+    fn test_primary_always_first() {
+        let balancer = Balancer::new(BalanceMode::Primary, 3);
+        for _ in 0..3 {
+            balancer.record(1, &server_error());
+        }
+        assert_eq!(balancer.pick(&[1, 2]), 1);
+        assert_eq!(balancer.pick(&[1, 2]), 1);
+    }
+
+    #[test]
+    fn test_round_robin_skips_failed_binding() {
+        let balancer = Balancer::new(BalanceMode::RoundRobin, 3);
+        let now = Instant::now();
+        let picks: Vec<_> = (0..4).map(|_| balancer.pick_at(&[0, 2], now)).collect();
+        assert_eq!(picks, [0, 2, 0, 2]);
+
+        for _ in 0..FAILURE_THRESHOLD {
+            balancer.record_at(2, true, now);
+        }
+        let picks: Vec<_> = (0..3).map(|_| balancer.pick_at(&[0, 2], now)).collect();
+        assert_eq!(picks, [0, 0, 0]);
+
+        // Back in rotation once the retry period has passed
+        let later = now + RETRY_AFTER;
+        let picks: Vec<_> = (0..2).map(|_| balancer.pick_at(&[0, 2], later)).collect();
+        assert!(picks.contains(&2));
+    }
+
+    #[test]
+    fn test_all_failed_still_spreads() {
+        let balancer = Balancer::new(BalanceMode::RoundRobin, 2);
+        let now = Instant::now();
+        for index in 0..2 {
+            for _ in 0..FAILURE_THRESHOLD {
+                balancer.record_at(index, true, now);
+            }
+        }
+        let picks: Vec<_> = (0..2).map(|_| balancer.pick_at(&[0, 1], now)).collect();
+        assert_eq!(picks, [0, 1]);
+    }
+
+    #[test]
+    fn test_least_inflight() {
+        let balancer = Balancer::new(BalanceMode::LeastInflight, 2);
+        let busy = balancer.start(0);
+        assert_eq!(balancer.pick(&[0, 1]), 1);
+        assert_eq!(balancer.pick(&[0, 1]), 1);
+        drop(busy);
+        let _busy = balancer.start(1);
+        assert_eq!(balancer.pick(&[0, 1]), 0);
+    }
+
+    #[test]
+    fn test_client_errors_do_not_count() {
+        let balancer = Balancer::new(BalanceMode::RoundRobin, 2);
+        let now = Instant::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            balancer.record(
+                1,
+                &Err::<(), _>(ProviderError::RequestFailed("400".into()).into()),
+            );
+        }
+        assert!(balancer.in_rotation(1, now));
+    }
+}
@@ -0,0 +1,108 @@
+//! Batched completions for non-interactive workloads.
+//!
+//! Recipes that run the same instructions over many items would otherwise send one
+//! request at a time. A batch fans the prompts out with at most
+//! `TANZU_AI_BATCH_CONCURRENCY` requests in flight (default 4), keeps results in input
+//! order, and sums the usage so the metered cost of the whole batch is visible at once.
+
+use crate::conversation::message::Message;
+use crate::providers::base::{ProviderUsage, Usage};
+use crate::providers::errors::ProviderError;
+use futures::{Future, StreamExt};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Results of a batch, one per prompt in input order.
+#[derive(Debug)]
+pub struct TanzuBatchResult {
+    pub results: Vec<Result<(Message, ProviderUsage), ProviderError>>,
+    /// Usage summed over the successful completions
+    pub usage: Usage,
+}
+
+impl TanzuBatchResult {
+    pub fn succeeded(&self) -> usize {
+        self.results.iter().filter(|r| r.is_ok()).count()
+    }
+}
+
+/// Requests in flight at once, from `TANZU_AI_BATCH_CONCURRENCY`.
+pub fn concurrency() -> usize {
+    crate::config::Config::global()
+        .get_param::<usize>("TANZU_AI_BATCH_CONCURRENCY")
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .max(1)
+}
+
+/// Run `complete` over `prompts` with at most `limit` calls in flight.
+pub async fn run<'a, F, Fut>(prompts: &'a [String], limit: usize, complete: F) -> TanzuBatchResult
+where
+    F: Fn(&'a str) -> Fut,
+    Fut: Future<Output = Result<(Message, ProviderUsage), ProviderError>>,
+{
+    let results: Vec<_> = futures::stream::iter(prompts.iter().map(|p| complete(p)))
+        .buffered(limit.max(1))
+        .collect()
+        .await;
+
+    let mut usage = Usage::default();
+    for (_, provider_usage) in results.iter().flatten() {
+        usage = add_usage(usage, &provider_usage.usage);
+    }
+    TanzuBatchResult { results, usage }
+}
+
+fn add_usage(total: Usage, usage: &Usage) -> Usage {
+    let add = |a: Option<i32>, b: Option<i32>| match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    };
+    Usage::new(
+        add(total.input_tokens, usage.input_tokens),
+        add(total.output_tokens, usage.output_tokens),
+        add(total.total_tokens, usage.total_tokens),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_run_keeps_order_and_limits_concurrency() {
+        let prompts: Vec<String> = (0..6).map(|i| format!("item-{}", i)).collect();
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let batch = run(&prompts, 2, |prompt| {
+            let in_flight = &in_flight;
+            let peak = &peak;
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if prompt == "item-3" {
+                    return Err(ProviderError::ServerError("boom".to_string()));
+                }
+                Ok((
+                    Message::assistant().with_text(prompt.to_uppercase()),
+                    ProviderUsage::new("m".to_string(), Usage::new(Some(2), Some(1), Some(3))),
+                ))
+            }
+        })
+        .await;
+
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(batch.results.len(), 6);
+        assert_eq!(batch.succeeded(), 5);
+        assert!(batch.results[3].is_err());
+        assert_eq!(
+            batch.results[5].as_ref().unwrap().0.as_concat_text(),
+            "ITEM-5"
+        );
+        assert_eq!(batch.usage.input_tokens, Some(10));
+        assert_eq!(batch.usage.total_tokens, Some(15));
+    }
+}
@@ -0,0 +1,352 @@
+//! Benchmarking the chat models of a plan.
+//!
+//! Picking the model to standardize on means comparing them on the same footing. Each
+//! advertised chat model runs the same small suite, streamed so that time to first
+//! token can be measured: a one-word factual answer, a few hundred words of prose for
+//! throughput, and a single tool call checked for the right tool and arguments. Models
+//! run one after another so they don't compete for the plan's capacity.
+
+use super::timing::StreamTiming;
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::base::MessageStream;
+use futures::StreamExt;
+use rmcp::model::Tool;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const SYSTEM_PROMPT: &str = "You are a helpful assistant. Follow instructions exactly.";
+
+const WEATHER_TOOL: &str = "get_weather";
+
+/// What makes a reply to a case correct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expectation {
+    /// The reply text contains this, ignoring case
+    Contains(&'static str),
+    /// A call to the case's tool whose `city` argument contains this, ignoring case
+    ToolCall { city: &'static str },
+    /// Any reply; the case measures throughput
+    Any,
+}
+
+/// One prompt of the suite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchmarkCase {
+    pub name: &'static str,
+    pub prompt: &'static str,
+    pub expectation: Expectation,
+}
+
+impl BenchmarkCase {
+    pub fn uses_tools(&self) -> bool {
+        matches!(self.expectation, Expectation::ToolCall { .. })
+    }
+
+    /// The tools offered with the prompt.
+    pub fn tools(&self) -> Vec<Tool> {
+        if !self.uses_tools() {
+            return Vec::new();
+        }
+        let schema = json!({
+            "type": "object",
+            "properties": {"city": {"type": "string", "description": "City name"}},
+            "required": ["city"],
+        });
+        vec![Tool::new(
+            WEATHER_TOOL,
+            "Get the current weather for a city.",
+            Arc::new(schema.as_object().cloned().unwrap_or_default()),
+        )]
+    }
+
+    /// Whether `reply` meets the expectation.
+    pub fn check(&self, reply: &Message) -> bool {
+        match self.expectation {
+            Expectation::Contains(expected) => reply
+                .as_concat_text()
+                .to_lowercase()
+                .contains(&expected.to_lowercase()),
+            Expectation::ToolCall { city } => reply.content.iter().any(|content| {
+                let MessageContent::ToolRequest(request) = content else {
+                    return false;
+                };
+                request.tool_call.as_ref().is_ok_and(|call| {
+                    call.name == WEATHER_TOOL
+                        && call
+                            .arguments
+                            .as_ref()
+                            .and_then(|args| args.get("city"))
+                            .and_then(Value::as_str)
+                            .is_some_and(|c| c.to_lowercase().contains(&city.to_lowercase()))
+                })
+            }),
+            Expectation::Any => true,
+        }
+    }
+}
+
+/// The standard suite.
+pub const SUITE: [BenchmarkCase; 3] = [
+    BenchmarkCase {
+        name: "short_answer",
+        prompt: "What is the capital of France? Answer with one word.",
+        expectation: Expectation::Contains("paris"),
+    },
+    BenchmarkCase {
+        name: "long_generation",
+        prompt: "Explain in about 300 words how a bicycle's gears work.",
+        expectation: Expectation::Any,
+    },
+    BenchmarkCase {
+        name: "tool_call",
+        prompt: "What is the weather in Paris right now? Use the available tool.",
+        expectation: Expectation::ToolCall { city: "paris" },
+    },
+];
+
+/// Outcome of one case on one model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkCaseResult {
+    pub case: &'static str,
+    /// Request sent to stream ended
+    pub latency: Duration,
+    pub timing: Option<StreamTiming>,
+    /// Whether the reply met the case's expectation; `None` when it did not run
+    pub passed: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl BenchmarkCaseResult {
+    /// A case that could not run or whose request failed.
+    pub fn not_run(case: &BenchmarkCase, reason: String) -> Self {
+        Self {
+            case: case.name,
+            latency: Duration::ZERO,
+            timing: None,
+            passed: None,
+            error: Some(reason),
+        }
+    }
+}
+
+/// Read a case's stream to the end, timing it from `started`.
+pub async fn run_case(
+    case: &BenchmarkCase,
+    mut stream: MessageStream,
+    started: Instant,
+) -> BenchmarkCaseResult {
+    let mut reply = Message::assistant();
+    let mut first = None;
+    let mut last = started;
+    let mut output_tokens = 0;
+    while let Some(item) = stream.next().await {
+        let (message, usage) = match item {
+            Ok(item) => item,
+            Err(e) => return BenchmarkCaseResult::not_run(case, e.to_string()),
+        };
+        if let Some(message) = message.filter(|m| !m.content.is_empty()) {
+            last = Instant::now();
+            first.get_or_insert(last);
+            reply.content.extend(message.content);
+        }
+        if let Some(usage) = usage {
+            output_tokens = usage.usage.output_tokens.unwrap_or(0).max(0) as u64;
+        }
+    }
+    BenchmarkCaseResult {
+        case: case.name,
+        latency: started.elapsed(),
+        timing: first.map(|first| StreamTiming {
+            time_to_first_token: first - started,
+            generation: last - first,
+            output_tokens,
+        }),
+        passed: Some(case.check(&reply)),
+        error: None,
+    }
+}
+
+/// Results of the suite on one model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelBenchmark {
+    pub model: String,
+    pub cases: Vec<BenchmarkCaseResult>,
+}
+
+impl ModelBenchmark {
+    fn completed(&self) -> impl Iterator<Item = &BenchmarkCaseResult> {
+        self.cases.iter().filter(|c| c.error.is_none())
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        mean(self.completed().map(|c| c.latency))
+    }
+
+    pub fn mean_time_to_first_token(&self) -> Option<Duration> {
+        mean(
+            self.completed()
+                .filter_map(|c| c.timing.map(|t| t.time_to_first_token)),
+        )
+    }
+
+    /// Output tokens over generation time, across every case that reported usage.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let (tokens, secs) = self
+            .completed()
+            .filter_map(|c| c.timing)
+            .fold((0, 0.0), |(tokens, secs), t| {
+                (tokens + t.output_tokens, secs + t.generation.as_secs_f64())
+            });
+        (tokens > 0 && secs > 0.0).then(|| tokens as f64 / secs)
+    }
+
+    /// Whether the tool call case called the right tool, if it ran.
+    pub fn tool_call_correct(&self) -> Option<bool> {
+        let tool_cases: Vec<&str> = SUITE
+            .iter()
+            .filter(|c| c.uses_tools())
+            .map(|c| c.name)
+            .collect();
+        self.cases
+            .iter()
+            .filter(|c| tool_cases.contains(&c.case))
+            .find_map(|c| c.passed)
+    }
+
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|c| c.passed == Some(true)).count()
+    }
+}
+
+fn mean(durations: impl Iterator<Item = Duration>) -> Option<Duration> {
+    let (total, count) = durations.fold((Duration::ZERO, 0u32), |(total, count), d| {
+        (total + d, count + 1)
+    });
+    (count > 0).then(|| total / count)
+}
+
+/// Results for every benchmarked model, in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TanzuBenchmarkReport {
+    pub models: Vec<ModelBenchmark>,
+}
+
+impl fmt::Display for TanzuBenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |d: Option<Duration>| d.map_or("-".to_string(), |d| d.as_millis().to_string());
+        writeln!(
+            f,
+            "{:<32} {:>10} {:>8} {:>7} {:>6} {:>6}",
+            "model", "latency_ms", "ttft_ms", "tok/s", "tools", "passed"
+        )?;
+        for model in &self.models {
+            let tools = match model.tool_call_correct() {
+                Some(true) => "ok",
+                Some(false) => "wrong",
+                None => "-",
+            };
+            writeln!(
+                f,
+                "{:<32} {:>10} {:>8} {:>7} {:>6} {:>3}/{}",
+                model.model,
+                millis(model.mean_latency()),
+                millis(model.mean_time_to_first_token()),
+                model
+                    .tokens_per_second()
+                    .map_or("-".to_string(), |tps| format!("{:.1}", tps)),
+                tools,
+                model.passed(),
+                model.cases.len()
+            )?;
+            for case in model.cases.iter().filter(|c| c.error.is_some()) {
+                writeln!(
+                    f,
+                    "  {}: {}",
+                    case.case,
+                    case.error.as_deref().unwrap_or_default()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::{ProviderUsage, Usage};
+    use rmcp::model::CallToolRequestParam;
+
+    fn stream(items: Vec<(Option<Message>, Option<ProviderUsage>)>) -> MessageStream {
+        Box::pin(futures::stream::iter(items.into_iter().map(Ok)))
+    }
+
+    #[tokio::test]
+    async fn test_checks_tool_call_and_times_stream() {
+        let case = &SUITE[2];
+        let call = Message::assistant().with_tool_request(
+            "call_1",
+            Ok(CallToolRequestParam {
+                name: WEATHER_TOOL.into(),
+                arguments: json!({"city": "Paris, France"}).as_object().cloned(),
+            }),
+        );
+        let usage = ProviderUsage::new(
+            "qwen3-30b".to_string(),
+            Usage::new(Some(40), Some(12), Some(52)),
+        );
+        let result = run_case(
+            case,
+            stream(vec![(Some(call), None), (None, Some(usage))]),
+            Instant::now(),
+        )
+        .await;
+        assert_eq!(result.passed, Some(true));
+        assert_eq!(result.timing.unwrap().output_tokens, 12);
+
+        let text = Message::assistant().with_text("It is sunny in Paris.");
+        let result = run_case(case, stream(vec![(Some(text), None)]), Instant::now()).await;
+        assert_eq!(result.passed, Some(false));
+    }
+
+    #[test]
+    fn test_report_summarizes_models() {
+        let timing = |ttft, generation, tokens| StreamTiming {
+            time_to_first_token: Duration::from_millis(ttft),
+            generation: Duration::from_millis(generation),
+            output_tokens: tokens,
+        };
+        let case = |case, latency, timing, passed| BenchmarkCaseResult {
+            case,
+            latency: Duration::from_millis(latency),
+            timing: Some(timing),
+            passed: Some(passed),
+            error: None,
+        };
+        let model = ModelBenchmark {
+            model: "openai/gpt-oss-120b".to_string(),
+            cases: vec![
+                case("short_answer", 300, timing(200, 100, 10), true),
+                case("long_generation", 4200, timing(100, 3900, 390), true),
+                case("tool_call", 900, timing(300, 0, 0), false),
+            ],
+        };
+        assert_eq!(model.mean_latency(), Some(Duration::from_millis(1800)));
+        assert_eq!(
+            model.mean_time_to_first_token(),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(model.tokens_per_second(), Some(100.0));
+        assert_eq!(model.tool_call_correct(), Some(false));
+
+        let report = TanzuBenchmarkReport {
+            models: vec![model],
+        };
+        let rendered = report.to_string();
+        assert!(rendered.contains("openai/gpt-oss-120b"));
+        assert!(rendered.contains("wrong"));
+        assert!(rendered.contains("2/3"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Best-of-k sampling through the `n` parameter.
+//!
+//! `TANZU_AI_CANDIDATES` asks chat endpoints for that many candidate completions per
+//! request, and `TANZU_AI_CANDIDATE_SELECTION` picks the one returned:
+//!
+//! - `first` (default): the first candidate, as the server ordered them
+//! - `longest`: the candidate with the most text
+//! - `judge`: a judge model (`TANZU_AI_JUDGE_MODEL`, else the requested model) reads
+//!   the candidates and names the best; the first is kept if it gives no usable answer
+//!
+//! Only the OpenAI wire format supports `n`. An `n` set by a model parameter profile
+//! takes precedence and is selected from the same way. Candidates are only compared
+//! on complete replies, so streams are served as a single completion while
+//! `TANZU_AI_CANDIDATES` is set.
+
+use crate::conversation::message::{Message, MessageContent};
+use rmcp::model::Role;
+use serde_json::Value;
+
+pub const JUDGE_SYSTEM_PROMPT: &str = "You compare candidate replies to a conversation and \
+pick the most helpful, correct one. Answer with the number of the best candidate only.";
+
+/// Replies longer than this are cut short in the judge prompt.
+const MAX_CANDIDATE_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Selection {
+    #[default]
+    First,
+    Longest,
+    /// Ask this model, or the requested one when unset
+    Judge(Option<String>),
+}
+
+/// Configured candidate count and selection strategy.
+#[derive(Debug, Clone, Default)]
+pub struct BestOf {
+    pub candidates: Option<u32>,
+    pub selection: Selection,
+}
+
+impl BestOf {
+    pub fn from_config() -> Self {
+        let config = crate::config::Config::global();
+        let candidates = config
+            .get_param::<u32>("TANZU_AI_CANDIDATES")
+            .ok()
+            .filter(|&n| n > 1);
+        let selection = match config
+            .get_param::<String>("TANZU_AI_CANDIDATE_SELECTION")
+            .map(|s| s.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Err(_) | Ok("first") => Selection::First,
+            Ok("longest") => Selection::Longest,
+            Ok("judge") => Selection::Judge(config.get_param("TANZU_AI_JUDGE_MODEL").ok()),
+            Ok(other) => {
+                tracing::warn!(
+                    "TANZU_AI_CANDIDATE_SELECTION must be first, longest or judge, not '{}'; using first",
+                    other
+                );
+                Selection::First
+            }
+        };
+        Self {
+            candidates,
+            selection,
+        }
+    }
+
+    /// Request `n` candidates unless the payload already asks for a number.
+    pub fn apply(&self, payload: &mut Value) {
+        let (Some(n), Some(payload)) = (self.candidates, payload.as_object_mut()) else {
+            return;
+        };
+        payload.entry("n").or_insert(Value::from(n));
+    }
+}
+
+/// The candidates of an OpenAI-format response, each as a response with a single
+/// choice, or `None` when there is only one.
+pub fn candidates(response: &Value) -> Option<Vec<Value>> {
+    let choices = response.get("choices")?.as_array()?;
+    if choices.len() < 2 {
+        return None;
+    }
+    let mut choices = choices.clone();
+    choices.sort_by_key(|c| c.get("index").and_then(Value::as_u64).unwrap_or(u64::MAX));
+    Some(
+        choices
+            .into_iter()
+            .map(|choice| {
+                let mut single = response.clone();
+                single["choices"] = Value::Array(vec![choice]);
+                single
+            })
+            .collect(),
+    )
+}
+
+/// Index of the candidate with the most text.
+pub fn longest(candidates: &[Message]) -> usize {
+    candidates
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, m)| m.as_concat_text().chars().count())
+        .map_or(0, |(i, _)| i)
+}
+
+/// The judge's prompt: the latest user request and the numbered candidates.
+pub fn judge_prompt(messages: &[Message], candidates: &[Message]) -> String {
+    let request = messages
+        .iter()
+        .rev()
+        .filter(|m| m.role == Role::User)
+        .map(|m| m.as_concat_text())
+        .find(|text| !text.trim().is_empty())
+        .unwrap_or_default();
+    let mut prompt = format!("Request:\n{}\n", request.trim());
+    for (i, candidate) in candidates.iter().enumerate() {
+        prompt.push_str(&format!(
+            "\nCandidate {}:\n{}\n",
+            i + 1,
+            describe(candidate)
+        ));
+    }
+    prompt.push_str(&format!(
+        "\nWhich candidate is best? Answer with a number from 1 to {}.",
+        candidates.len()
+    ));
+    prompt
+}
+
+/// Text and tool calls of a candidate, shortened for the judge.
+fn describe(candidate: &Message) -> String {
+    let mut parts = Vec::new();
+    for content in &candidate.content {
+        match content {
+            MessageContent::Text(text) => parts.push(text.text.clone()),
+            MessageContent::ToolRequest(request) => {
+                if let Ok(call) = &request.tool_call {
+                    parts.push(format!(
+                        "[calls {} with {}]",
+                        call.name,
+                        Value::from(call.arguments.clone().unwrap_or_default())
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    let text = parts.join("\n");
+    match text.char_indices().nth(MAX_CANDIDATE_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text,
+    }
+}
+
+/// The candidate index named in the judge's answer.
+pub fn parse_verdict(answer: &str, count: usize) -> Option<usize> {
+    answer
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|n| n.parse::<usize>().ok())
+        .find(|n| (1..=count).contains(n))
+        .map(|n| n - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_splits_choices_into_candidates() {
+        let response = json!({
+            "model": "llama3.2:1b",
+            "choices": [
+                {"index": 1, "message": {"role": "assistant", "content": "second"}},
+                {"index": 0, "message": {"role": "assistant", "content": "first"}}
+            ],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 4, "total_tokens": 9}
+        });
+        let split = candidates(&response).unwrap();
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0]["choices"][0]["message"]["content"], "first");
+        assert_eq!(split[1]["usage"], response["usage"]);
+
+        let single = json!({"choices": [{"index": 0, "message": {"content": "only"}}]});
+        assert!(candidates(&single).is_none());
+
+        let mut payload = json!({"model": "llama3.2:1b"});
+        let best_of = BestOf {
+            candidates: Some(3),
+            selection: Selection::Longest,
+        };
+        best_of.apply(&mut payload);
+        assert_eq!(payload["n"], 3);
+        let mut profiled = json!({"n": 2});
+        best_of.apply(&mut profiled);
+        assert_eq!(profiled["n"], 2);
+    }
+
+    #[test]
+    fn test_selects_longest_and_parses_verdict() {
+        let replies = [
+            Message::assistant().with_text("Paris."),
+            Message::assistant().with_text("The capital of France is Paris."),
+            Message::assistant().with_text("It is Paris, France's capital."),
+        ];
+        assert_eq!(longest(&replies), 1);
+
+        let prompt = judge_prompt(
+            &[Message::user().with_text("What is the capital of France?")],
+            &replies,
+        );
+        assert!(prompt.starts_with("Request:\nWhat is the capital of France?\n"));
+        assert!(prompt.contains("Candidate 3:\nIt is Paris, France's capital."));
+
+        assert_eq!(parse_verdict("Candidate 2", 3), Some(1));
+        assert_eq!(parse_verdict("2.", 3), Some(1));
+        assert_eq!(parse_verdict("7, so 3", 3), Some(2));
+        assert_eq!(parse_verdict("none of them", 3), None);
+    }
+}
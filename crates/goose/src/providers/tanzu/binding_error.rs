@@ -0,0 +1,124 @@
+//! Errors from parsing binding credentials.
+//!
+//! A malformed binding used to be skipped, leaving users with a generic "credentials
+//! not found" error. Each error names the offending field by its path in the source
+//! document, e.g. `genai[0].credentials.endpoint.api_key missing`.
+
+use serde_json::Value;
+use thiserror::Error;
+
+/// Why a binding's credentials could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BindingParseError {
+    #[error("{path} missing")]
+    Missing { path: String },
+
+    #[error("{path} must be {expected}")]
+    WrongType {
+        path: String,
+        expected: &'static str,
+    },
+
+    #[error("{path} is not valid JSON: {reason}")]
+    InvalidJson { path: String, reason: String },
+
+    #[error("{path} is an unresolved CredHub reference")]
+    UnresolvedCredHub { path: String },
+}
+
+impl BindingParseError {
+    /// Path of the offending field.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Missing { path }
+            | Self::WrongType { path, .. }
+            | Self::InvalidJson { path, .. }
+            | Self::UnresolvedCredHub { path } => path,
+        }
+    }
+}
+
+/// `parent.key`, or `key` at the top level.
+pub fn field_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// The field `key` of `value`, which must be present.
+pub fn required<'a>(
+    value: &'a Value,
+    parent: &str,
+    key: &str,
+) -> Result<&'a Value, BindingParseError> {
+    match value.get(key) {
+        Some(Value::Null) | None => Err(BindingParseError::Missing {
+            path: field_path(parent, key),
+        }),
+        Some(field) => Ok(field),
+    }
+}
+
+/// The string field `key` of `value`, which must be present.
+pub fn required_str<'a>(
+    value: &'a Value,
+    parent: &str,
+    key: &str,
+) -> Result<&'a str, BindingParseError> {
+    match required(value, parent, key)?.as_str() {
+        Some(field) => Ok(field),
+        None => Err(BindingParseError::WrongType {
+            path: field_path(parent, key),
+            expected: "a string",
+        }),
+    }
+}
+
+/// The optional string field `key` of `value`; present but not a string is an error.
+pub fn optional_str<'a>(
+    value: &'a Value,
+    parent: &str,
+    key: &str,
+) -> Result<Option<&'a str>, BindingParseError> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(field)) => Ok(Some(field)),
+        Some(_) => Err(BindingParseError::WrongType {
+            path: field_path(parent, key),
+            expected: "a string",
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reports_field_paths() {
+        let endpoint = json!({"api_base": "https://genai.example.com", "api_key": 42});
+        let parent = "genai[0].credentials.endpoint";
+
+        assert_eq!(
+            required_str(&endpoint, parent, "api_base"),
+            Ok("https://genai.example.com")
+        );
+        assert_eq!(
+            required_str(&endpoint, parent, "api_key")
+                .unwrap_err()
+                .to_string(),
+            "genai[0].credentials.endpoint.api_key must be a string"
+        );
+        assert_eq!(
+            required_str(&endpoint, parent, "config_url")
+                .unwrap_err()
+                .to_string(),
+            "genai[0].credentials.endpoint.config_url missing"
+        );
+        assert_eq!(optional_str(&endpoint, parent, "config_url"), Ok(None));
+        assert_eq!(field_path("", "endpoint"), "endpoint");
+    }
+}
@@ -0,0 +1,128 @@
+//! Custom binding formats.
+//!
+//! Some platforms hand out GenAI credentials wrapped in another service broker's
+//! schema. Embedding code teaches the provider to read them by registering a
+//! [`BindingParser`] with [`register_binding_parser`] before the provider is created.
+//! Every `VCAP_SERVICES` entry that is not already a GenAI binding is offered to the
+//! registered parsers in registration order; the first to recognise it returns the
+//! credentials in the GenAI broker's shape, which are then parsed, selected and
+//! validated like any other binding.
+
+use serde_json::Value;
+use std::sync::{Arc, LazyLock, RwLock};
+
+static PARSERS: LazyLock<RwLock<Vec<Arc<dyn BindingParser>>>> =
+    LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// Extracts GenAI credentials from a foreign binding schema.
+pub trait BindingParser: Send + Sync {
+    /// Name used in log messages.
+    fn name(&self) -> &str;
+
+    /// The GenAI credentials held by `binding`, an entry under `label` in
+    /// `VCAP_SERVICES`, or `None` when it is not in this parser's format.
+    ///
+    /// The result takes either GenAI form: `{"endpoint": {"api_base", "api_key",
+    /// "config_url"}}`, or the single-model `{"api_base", "api_key", "model_name"}`.
+    fn extract(&self, label: &str, binding: &Value) -> Option<Value>;
+}
+
+/// Add a parser, tried after the ones registered before it.
+pub fn register_binding_parser(parser: impl BindingParser + 'static) {
+    PARSERS.write().unwrap().push(Arc::new(parser));
+}
+
+/// Entries of `vcap` a registered parser recognises, outside the `skip` paths, each with
+/// its path in `VCAP_SERVICES` and its `credentials` replaced by the extracted ones.
+pub fn custom_bindings(vcap: &Value, skip: &[String]) -> Vec<(String, Value)> {
+    let parsers = PARSERS.read().unwrap().clone();
+    if parsers.is_empty() {
+        return Vec::new();
+    }
+    let Some(services) = vcap.as_object() else {
+        return Vec::new();
+    };
+
+    let mut found = Vec::new();
+    for (label, entries) in services {
+        let Some(entries) = entries.as_array() else {
+            continue;
+        };
+        for (index, binding) in entries.iter().enumerate() {
+            let path = format!("{}[{}]", label, index);
+            if skip.contains(&path) {
+                continue;
+            }
+            let Some((parser, credentials)) = parsers
+                .iter()
+                .find_map(|p| Some((p, p.extract(label, binding)?)))
+            else {
+                continue;
+            };
+            tracing::debug!(
+                "Binding {} read by the {} binding parser",
+                path,
+                parser.name()
+            );
+            let mut binding = binding.clone();
+            match binding.as_object_mut() {
+                Some(object) => {
+                    object.insert("credentials".to_string(), credentials);
+                }
+                None => binding = serde_json::json!({ "credentials": credentials }),
+            }
+            found.push((path, binding));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Reads the `ai` block of a fictional `acme-broker` service.
+    struct AcmeParser;
+
+    impl BindingParser for AcmeParser {
+        fn name(&self) -> &str {
+            "acme"
+        }
+
+        fn extract(&self, label: &str, binding: &Value) -> Option<Value> {
+            if label != "acme-broker" {
+                return None;
+            }
+            let ai = binding.get("credentials")?.get("ai")?;
+            Some(json!({
+                "endpoint": {"api_base": ai.get("url")?, "api_key": ai.get("token")?}
+            }))
+        }
+    }
+
+    #[test]
+    fn test_registered_parser_extracts_credentials() {
+        register_binding_parser(AcmeParser);
+        let vcap = json!({
+            "genai": [{"name": "genai-chat", "credentials": {}}],
+            "acme-broker": [
+                {"name": "acme-db", "credentials": {"db": {"url": "postgres://db"}}},
+                {
+                    "name": "acme-ai",
+                    "credentials": {"ai": {"url": "https://ai.acme.example.com/plan", "token": "t"}}
+                }
+            ]
+        });
+
+        let found = custom_bindings(&vcap, &["genai[0]".to_string()]);
+        assert_eq!(found.len(), 1);
+        let (path, binding) = &found[0];
+        assert_eq!(path, "acme-broker[1]");
+        assert_eq!(binding["name"], "acme-ai");
+        assert_eq!(
+            binding["credentials"]["endpoint"]["api_base"],
+            "https://ai.acme.example.com/plan"
+        );
+    }
+}
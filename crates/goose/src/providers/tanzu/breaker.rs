@@ -0,0 +1,212 @@
+//! Circuit breaker for GenAI proxy endpoints.
+//!
+//! After `TANZU_AI_BREAKER_THRESHOLD` consecutive server errors or connection failures
+//! the breaker opens and requests to that endpoint fail immediately. Once
+//! `TANZU_AI_BREAKER_COOLDOWN_SECS` has passed, a single probe request is let through:
+//! success closes the breaker, failure opens it for another cooldown. A probe that
+//! never reports back, say because it was cancelled, is replaced by another after a
+//! further cooldown. Breakers are shared by every binding and provider instance using
+//! the same endpoint base.
+
+use crate::providers::errors::ProviderError;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+static BREAKERS: LazyLock<Mutex<HashMap<String, Arc<CircuitBreaker>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The shared breaker for an endpoint base, created with configured limits on first use.
+pub fn for_endpoint(endpoint_base: &str) -> Arc<CircuitBreaker> {
+    BREAKERS
+        .lock()
+        .unwrap()
+        .entry(endpoint_base.to_string())
+        .or_insert_with(|| {
+            let config = crate::config::Config::global();
+            let threshold = config
+                .get_param("TANZU_AI_BREAKER_THRESHOLD")
+                .unwrap_or(DEFAULT_THRESHOLD);
+            let cooldown = config
+                .get_param("TANZU_AI_BREAKER_COOLDOWN_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_COOLDOWN);
+            Arc::new(CircuitBreaker::new(endpoint_base, threshold, cooldown))
+        })
+        .clone()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { since: Instant },
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    endpoint: String,
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub fn new(endpoint: &str, threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Check whether a request may be sent, fast-failing while the breaker is open.
+    pub fn allow(&self) -> Result<(), ProviderError> {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> Result<(), ProviderError> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now >= until => {
+                tracing::info!(
+                    "Circuit for {} half-open, sending a probe request",
+                    self.endpoint
+                );
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            State::Open { until } => Err(self.open_error(until - now)),
+            State::HalfOpen { since } if now >= since + self.cooldown => {
+                tracing::info!(
+                    "Probe for {} did not complete, sending another",
+                    self.endpoint
+                );
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            // A probe is already in flight
+            State::HalfOpen { since } => Err(self.open_error(since + self.cooldown - now)),
+        }
+    }
+
+    /// Record a response that shows the endpoint is healthy (anything but a 5xx).
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(*state, State::HalfOpen { .. }) {
+            tracing::info!("Circuit for {} closed", self.endpoint);
+        }
+        *state = State::Closed { failures: 0 };
+    }
+
+    /// Record a server error or connection failure.
+    pub fn record_failure(&self) {
+        self.record_failure_at(Instant::now());
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::HalfOpen { .. } => self.threshold,
+            // Late result from a request sent before the breaker opened
+            State::Open { .. } => return,
+        };
+        if failures >= self.threshold {
+            tracing::warn!(
+                "Circuit for {} opened after {} consecutive failures; failing fast for {:?}",
+                self.endpoint,
+                failures,
+                self.cooldown
+            );
+            *state = State::Open {
+                until: now + self.cooldown,
+            };
+        } else {
+            *state = State::Closed { failures };
+        }
+    }
+
+    fn open_error(&self, remaining: Duration) -> ProviderError {
+        ProviderError::RequestFailed(format!(
+            "Tanzu AI endpoint {} is failing repeatedly; requests are paused for {}s while it recovers",
+            self.endpoint,
+            remaining.as_secs().max(1)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let breaker = CircuitBreaker::new("https://genai", 3, Duration::from_secs(30));
+        let now = Instant::now();
+
+        breaker.record_failure_at(now);
+        breaker.record_failure_at(now);
+        assert!(breaker.allow_at(now).is_ok());
+
+        breaker.record_failure_at(now);
+        let err = breaker.allow_at(now).unwrap_err();
+        assert!(err.to_string().contains("https://genai"));
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let breaker = CircuitBreaker::new("https://genai", 2, Duration::from_secs(30));
+        let now = Instant::now();
+
+        breaker.record_failure_at(now);
+        breaker.record_success();
+        breaker.record_failure_at(now);
+        assert!(breaker.allow_at(now).is_ok());
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = CircuitBreaker::new("https://genai", 1, Duration::from_secs(30));
+        let now = Instant::now();
+        breaker.record_failure_at(now);
+
+        let later = now + Duration::from_secs(31);
+        assert!(breaker.allow_at(later).is_ok());
+        // Only one probe at a time
+        assert!(breaker.allow_at(later).is_err());
+
+        // A failed probe reopens for another cooldown
+        breaker.record_failure_at(later);
+        assert!(breaker.allow_at(later + Duration::from_secs(1)).is_err());
+
+        let recovered = later + Duration::from_secs(31);
+        assert!(breaker.allow_at(recovered).is_ok());
+        breaker.record_success();
+        assert!(breaker.allow_at(recovered).is_ok());
+        assert!(breaker.allow_at(recovered).is_ok());
+    }
+
+    #[test]
+    fn test_abandoned_probe_replaced_after_cooldown() {
+        let breaker = CircuitBreaker::new("https://genai", 1, Duration::from_secs(30));
+        let now = Instant::now();
+        breaker.record_failure_at(now);
+
+        // The probe is let through but never records a result
+        let probe = now + Duration::from_secs(31);
+        assert!(breaker.allow_at(probe).is_ok());
+        assert!(breaker.allow_at(probe + Duration::from_secs(29)).is_err());
+
+        let retry = probe + Duration::from_secs(30);
+        assert!(breaker.allow_at(retry).is_ok());
+        assert!(breaker.allow_at(retry).is_err());
+        breaker.record_success();
+        assert!(breaker.allow_at(retry).is_ok());
+    }
+}
@@ -0,0 +1,203 @@
+//! Advertised model capabilities for display.
+//!
+//! The config URL's `advertisedModels` say which models support TOOLS, VISION or
+//! EMBEDDING, and which are deprecated. Model pickers get them as `TanzuModelInfo`,
+//! either live from a provider or from what discovery has already seen in this process,
+//! which is also what `metadata()` lists as known models.
+//!
+//! [`TanzuCapabilities`] goes further and says what the provider will do with each
+//! configured model, combining advertised capabilities with what earlier requests
+//! found out, so callers can adapt before sending a request that would be rejected.
+
+use super::deprecation::Deprecation;
+use super::model_detail::ModelDetail;
+use super::{AdvertisedModel, DISCOVERY_CACHE};
+use crate::providers::base::ModelInfo;
+use serde::Serialize;
+
+/// An advertised model and what it can do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TanzuModelInfo {
+    pub name: String,
+    /// Capabilities as advertised, e.g. `CHAT`, `TOOLS`, `VISION`, `EMBEDDING`
+    pub capabilities: Vec<String>,
+    pub context_length: Option<usize>,
+    /// Set when the platform is retiring the model
+    pub deprecation: Option<Deprecation>,
+    /// Owner reported by the model detail endpoint, e.g. `openai` or `vllm`
+    pub owned_by: Option<String>,
+    /// Creation time reported by the model detail endpoint, in seconds since the epoch
+    pub created: Option<u64>,
+}
+
+impl TanzuModelInfo {
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(capability))
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecation.is_some()
+    }
+
+    /// Selection label, e.g. `openai/gpt-oss-120b (CHAT, TOOLS; 131072 tokens)`, ending
+    /// in `; deprecated` for models being retired.
+    pub fn label(&self) -> String {
+        let mut details = self.capabilities.join(", ");
+        if let Some(length) = self.context_length {
+            details = format!("{}; {} tokens", details, length);
+        }
+        if let Some(deprecation) = &self.deprecation {
+            details.push_str("; deprecated");
+            if let Some(replacement) = &deprecation.replacement {
+                details.push_str(&format!(", use {}", replacement));
+            }
+        }
+        if details.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} ({})", self.name, details)
+        }
+    }
+
+    /// Fill in what the detail endpoint reports; advertised context lengths win.
+    pub fn enrich(&mut self, detail: ModelDetail) {
+        self.owned_by = detail.owned_by.or(self.owned_by.take());
+        self.created = detail.created.or(self.created);
+        self.context_length = self.context_length.or(detail.context_length);
+    }
+
+    /// Goose model info, with the default context limit when none is advertised.
+    pub fn model_info(&self) -> ModelInfo {
+        let context_limit = self
+            .context_length
+            .unwrap_or_else(|| crate::model::ModelConfig::new_or_fail(&self.name).context_limit());
+        ModelInfo::new(self.name.clone(), context_limit)
+    }
+}
+
+impl From<AdvertisedModel> for TanzuModelInfo {
+    fn from(model: AdvertisedModel) -> Self {
+        Self {
+            name: model.name,
+            capabilities: model.capabilities,
+            context_length: model.context_length,
+            deprecation: model.deprecation,
+            owned_by: None,
+            created: None,
+        }
+    }
+}
+
+/// Whether a model supports a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Support {
+    Yes,
+    No,
+    /// Not advertised; the provider tries it and falls back if it is rejected
+    Unknown,
+}
+
+impl From<bool> for Support {
+    fn from(supported: bool) -> Self {
+        if supported {
+            Self::Yes
+        } else {
+            Self::No
+        }
+    }
+}
+
+/// Features of one configured model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TanzuModelCapabilities {
+    pub model: String,
+    pub streaming: bool,
+    /// `No` means tool calls are emulated in text
+    pub tools: Support,
+    pub vision: Support,
+    /// `No` means JSON is asked for in the prompt
+    pub structured_output: Support,
+    pub prompt_caching: Support,
+    pub context_length: Option<usize>,
+}
+
+/// Capability report for the provider and its configured models: the main model, the
+/// lead and worker models, and the fallbacks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TanzuCapabilities {
+    /// Whether an embedding model is advertised or configured
+    pub embeddings: bool,
+    pub models: Vec<TanzuModelCapabilities>,
+}
+
+/// Every model discovered so far in this process, once each, sorted by name.
+pub fn discovered_models() -> Vec<TanzuModelInfo> {
+    let mut models: Vec<TanzuModelInfo> = Vec::new();
+    for model in DISCOVERY_CACHE.models() {
+        if !models.iter().any(|m| m.name == model.name) {
+            models.push(model.into());
+        }
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_and_supports() {
+        let info = TanzuModelInfo {
+            name: "openai/gpt-oss-120b".to_string(),
+            capabilities: vec!["CHAT".to_string(), "TOOLS".to_string()],
+            context_length: Some(131072),
+            deprecation: None,
+            owned_by: None,
+            created: None,
+        };
+        assert_eq!(
+            info.label(),
+            "openai/gpt-oss-120b (CHAT, TOOLS; 131072 tokens)"
+        );
+        assert!(info.supports("tools"));
+        assert!(!info.supports("VISION"));
+        assert_eq!(info.model_info().context_limit, 131072);
+        assert!(!info.is_deprecated());
+
+        let retiring = TanzuModelInfo {
+            name: "llama3.1:8b".to_string(),
+            capabilities: vec!["CHAT".to_string()],
+            context_length: None,
+            deprecation: Some(Deprecation {
+                replacement: Some("llama3.3:70b".to_string()),
+                ..Default::default()
+            }),
+            owned_by: None,
+            created: None,
+        };
+        assert_eq!(
+            retiring.label(),
+            "llama3.1:8b (CHAT; deprecated, use llama3.3:70b)"
+        );
+
+        let mut served = retiring.clone();
+        served.enrich(ModelDetail {
+            id: "llama3.1:8b".to_string(),
+            owned_by: Some("vllm".to_string()),
+            created: Some(1721779200),
+            context_length: Some(131072),
+        });
+        assert_eq!(served.owned_by.as_deref(), Some("vllm"));
+        assert_eq!(served.context_length, Some(131072));
+        served.enrich(ModelDetail {
+            context_length: Some(8192),
+            ..Default::default()
+        });
+        assert_eq!(served.context_length, Some(131072));
+        assert_eq!(served.created, Some(1721779200));
+    }
+}
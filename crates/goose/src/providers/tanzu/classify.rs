@@ -0,0 +1,280 @@
+//! Classification of GenAI proxy error responses.
+//!
+//! The proxy reports several conditions a user can act on (model not in the plan, model
+//! scaled to zero, model still loading, plan quota used up) with otherwise generic
+//! status codes. They are recognised here from the status and error body and mapped to
+//! the `ProviderError` that gets the right handling: fallbacks for unavailable models,
+//! retries for cold starts, and an immediate actionable error for quotas.
+//!
+//! `ProviderError` belongs to Goose. It has a `QuotaExceeded` variant (added by
+//! `patches/0003`) but none for the model conditions, so requests fail with a
+//! [`RequestError`] that keeps the kind next to the error surfaced for it. It becomes a
+//! plain `ProviderError` once it leaves the provider.
+
+use crate::providers::errors::ProviderError;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::fmt;
+
+/// Start of the error returned when the endpoint does not serve the requested model.
+const MODEL_UNAVAILABLE: &str = "Model unavailable on Tanzu AI Services";
+/// Start of the error returned when the plan's quota is used up.
+const QUOTA_EXCEEDED: &str = "Tanzu AI Services plan quota exceeded";
+/// Start of the error returned while the model is starting up.
+const COLD_START: &str = "The Tanzu AI Services model is still starting up";
+
+const QUOTA_MARKERS: [&str; 3] = ["quota", "insufficient_quota", "plan limit"];
+const SCALED_TO_ZERO_MARKERS: [&str; 4] =
+    ["scaled to zero", "scaled down", "no replicas", "0 replicas"];
+const COLD_START_MARKERS: [&str; 5] = [
+    "cold start",
+    "loading",
+    // Also covers the `model-warming` hint of models scaled to zero
+    "warming",
+    "starting up",
+    "not ready",
+];
+const NOT_FOUND_MARKERS: [&str; 4] = [
+    "not found",
+    "does not exist",
+    "not available",
+    "unknown model",
+];
+
+/// Proxy error conditions with dedicated handling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyErrorKind {
+    /// The requested model is not part of the plan
+    ModelNotFound,
+    /// The model is in the plan but has no running replicas
+    ScaledToZero,
+    /// The upstream model server is still loading the model
+    ColdStart,
+    /// The plan's token or request quota is used up
+    QuotaExceeded,
+}
+
+impl ProxyErrorKind {
+    /// The error to surface for this condition, with the proxy's own message as detail.
+    pub fn into_error(self, detail: &str) -> ProviderError {
+        match self {
+            Self::ModelNotFound => {
+                ProviderError::RequestFailed(format!("{}: {}", MODEL_UNAVAILABLE, detail))
+            }
+            Self::ScaledToZero => ProviderError::RequestFailed(format!(
+                "{}: the model is scaled to zero; ask your platform operator to scale it up \
+                 or choose another model ({})",
+                MODEL_UNAVAILABLE, detail
+            )),
+            Self::ColdStart => ProviderError::ServerError(format!("{}: {}", COLD_START, detail)),
+            Self::QuotaExceeded => ProviderError::QuotaExceeded(format!(
+                "{}: {}. Wait for the quota to reset or ask your platform operator to raise \
+                 the plan limit",
+                QUOTA_EXCEEDED, detail
+            )),
+        }
+    }
+}
+
+/// A failed request, with the proxy condition it was recognised as.
+#[derive(Debug)]
+pub enum RequestError {
+    /// A condition with dedicated handling, and the error surfaced for it
+    Proxy(ProxyErrorKind, ProviderError),
+    Provider(ProviderError),
+}
+
+impl RequestError {
+    pub fn kind(&self) -> Option<ProxyErrorKind> {
+        match self {
+            Self::Proxy(kind, _) => Some(*kind),
+            Self::Provider(_) => None,
+        }
+    }
+
+    pub fn error(&self) -> &ProviderError {
+        match self {
+            Self::Proxy(_, error) | Self::Provider(error) => error,
+        }
+    }
+
+    /// Whether the endpoint does not serve the model, so a fallback may be tried.
+    pub fn is_model_unavailable(&self) -> bool {
+        matches!(
+            self.kind(),
+            Some(ProxyErrorKind::ModelNotFound | ProxyErrorKind::ScaledToZero)
+        )
+    }
+
+    /// Whether the model is still starting up.
+    pub fn is_cold_start(&self) -> bool {
+        self.kind() == Some(ProxyErrorKind::ColdStart)
+    }
+}
+
+impl From<ProviderError> for RequestError {
+    fn from(error: ProviderError) -> Self {
+        Self::Provider(error)
+    }
+}
+
+impl From<RequestError> for ProviderError {
+    fn from(error: RequestError) -> Self {
+        match error {
+            RequestError::Proxy(_, error) | RequestError::Provider(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error().fmt(f)
+    }
+}
+
+/// Recognise a proxy error condition from the response status and body.
+pub fn classify(status: StatusCode, body: Option<&Value>) -> Option<ProxyErrorKind> {
+    let text = body.map(error_text).unwrap_or_default();
+    let mentions = |markers: &[&str]| markers.iter().any(|m| text.contains(m));
+
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::FORBIDDEN | StatusCode::PAYMENT_REQUIRED
+    ) && mentions(&QUOTA_MARKERS)
+    {
+        return Some(ProxyErrorKind::QuotaExceeded);
+    }
+    if (status.is_server_error() || status == StatusCode::NOT_FOUND)
+        && mentions(&SCALED_TO_ZERO_MARKERS)
+    {
+        return Some(ProxyErrorKind::ScaledToZero);
+    }
+    if matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    ) && mentions(&COLD_START_MARKERS)
+    {
+        return Some(ProxyErrorKind::ColdStart);
+    }
+    // A bare 404 is as likely a wrong path or proxy route as a missing model
+    let not_found = match status {
+        StatusCode::NOT_FOUND => text.contains("model"),
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+            text.contains("model") && mentions(&NOT_FOUND_MARKERS)
+        }
+        _ => false,
+    };
+    not_found.then_some(ProxyErrorKind::ModelNotFound)
+}
+
+/// Lowercased message, type and code of an error body, for marker matching.
+fn error_text(body: &Value) -> String {
+    let mut parts: Vec<String> = error_message(body).into_iter().collect();
+    for pointer in ["/error/type", "/error/code", "/type", "/code"] {
+        if let Some(value) = body.pointer(pointer).and_then(Value::as_str) {
+            parts.push(value.to_string());
+        }
+    }
+    parts.join(" ").to_lowercase()
+}
+
+/// Extract the error message from an OpenAI-style or plain error body.
+pub fn error_message(body: &Value) -> Option<String> {
+    body.pointer("/error/message")
+        .or_else(|| body.get("error"))
+        .or_else(|| body.get("message"))
+        .or_else(|| body.get("detail"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_model_not_found_detection() {
+        let not_found = Some(ProxyErrorKind::ModelNotFound);
+        assert_eq!(
+            classify(
+                StatusCode::NOT_FOUND,
+                Some(&json!({"error": {"code": "model_not_found"}}))
+            ),
+            not_found
+        );
+        assert_eq!(classify(StatusCode::NOT_FOUND, None), None);
+        assert_eq!(
+            classify(StatusCode::NOT_FOUND, Some(&json!({"error": "no route"}))),
+            None
+        );
+        assert_eq!(
+            classify(
+                StatusCode::BAD_REQUEST,
+                Some(&json!({"error": {"message": "The model `llama3.2:1b` does not exist."}}))
+            ),
+            not_found
+        );
+        assert_eq!(
+            classify(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Some(&json!({"detail": "Model qwen3-30b is not available"}))
+            ),
+            not_found
+        );
+        assert_eq!(
+            classify(
+                StatusCode::BAD_REQUEST,
+                Some(&json!({"error": {"message": "context length exceeded"}}))
+            ),
+            None
+        );
+        assert_eq!(classify(StatusCode::INTERNAL_SERVER_ERROR, None), None);
+    }
+
+    #[test]
+    fn test_quota_exceeded() {
+        let body = json!({
+            "error": {"message": "Token quota for plan exhausted", "type": "quota_exceeded"}
+        });
+        let kind = classify(StatusCode::TOO_MANY_REQUESTS, Some(&body));
+        assert_eq!(kind, Some(ProxyErrorKind::QuotaExceeded));
+
+        let err = kind.unwrap().into_error("Token quota for plan exhausted");
+        assert!(matches!(err, ProviderError::QuotaExceeded(_)));
+        assert!(!RequestError::Proxy(kind.unwrap(), err).is_model_unavailable());
+
+        // Plain rate limiting is left to the generic mapping and retried
+        let body = json!({"error": {"message": "Rate limit reached, slow down"}});
+        assert_eq!(classify(StatusCode::TOO_MANY_REQUESTS, Some(&body)), None);
+    }
+
+    #[test]
+    fn test_scaled_to_zero_and_cold_start() {
+        let scaled = json!({"error": {"message": "Model llama3.2:1b is scaled to zero"}});
+        let kind = classify(StatusCode::SERVICE_UNAVAILABLE, Some(&scaled));
+        assert_eq!(kind, Some(ProxyErrorKind::ScaledToZero));
+        let err = RequestError::Proxy(kind.unwrap(), kind.unwrap().into_error("scaled"));
+        assert!(err.is_model_unavailable());
+        assert!(err.to_string().contains(MODEL_UNAVAILABLE));
+
+        let loading = json!({"message": "Model is loading, please retry"});
+        let kind = classify(StatusCode::SERVICE_UNAVAILABLE, Some(&loading));
+        assert_eq!(kind, Some(ProxyErrorKind::ColdStart));
+        let err = RequestError::Proxy(kind.unwrap(), kind.unwrap().into_error("loading"));
+        assert!(err.is_cold_start());
+        // Retried like any server error once it leaves the provider
+        assert!(matches!(
+            ProviderError::from(err),
+            ProviderError::ServerError(_)
+        ));
+
+        let warming = json!({"error": {"message": "Model is starting", "code": "model-warming"}});
+        assert_eq!(
+            classify(StatusCode::SERVICE_UNAVAILABLE, Some(&warming)),
+            Some(ProxyErrorKind::ColdStart)
+        );
+
+        assert_eq!(classify(StatusCode::SERVICE_UNAVAILABLE, None), None);
+    }
+}
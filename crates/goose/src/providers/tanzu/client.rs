@@ -0,0 +1,509 @@
+//! Library-level access to Tanzu AI Services, independent of the `Provider` trait.
+//!
+//! [`TanzuClient`] resolves bindings, authenticates, discovers models and sends chat
+//! requests. The Goose provider wraps one and layers the agent-loop features on top
+//! (fallback models, tool emulation, parameter profiles, compaction, usage and audit);
+//! other parts of an application can use a client directly for plain completions.
+
+use super::balance::{BalanceMode, Balancer};
+use super::capabilities::TanzuModelInfo;
+use super::classify::RequestError;
+use super::completion;
+use super::egress::EgressPolicy;
+use super::failover::{self, Failover, FailoverTarget};
+use super::flavor::UpstreamFlavor;
+use super::model_detail::{self, ModelDetail};
+use super::preflight::{self, PreflightError};
+use super::token::TokenManager;
+use super::transport::Transport;
+use super::wire::WireFormat;
+use super::{
+    endpoint, normalize_api_base, resolve_credentials, resolve_model_alias, route_binding,
+    shared_http_client, AdvertisedModel, AuthMethod, ClientSettings, TanzuBinding,
+    TanzuCredentials, DISCOVERY_CACHE,
+};
+use crate::conversation::message::Message;
+use crate::model::ModelConfig;
+use crate::providers::base::{MessageStream, ProviderUsage};
+use crate::providers::errors::ProviderError;
+use anyhow::Result;
+use rmcp::model::Tool;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Authenticated access to one or more Tanzu AI Services bindings.
+pub struct TanzuClient {
+    /// All usable bindings; the first one is the default route
+    pub(super) bindings: Vec<TanzuBinding>,
+    /// Spreads requests over bindings serving the same model (`TANZU_AI_LOAD_BALANCE`)
+    pub(super) balancer: Balancer,
+}
+
+impl TanzuClient {
+    /// Connect to the bindings the provider would use: explicit configuration, a
+    /// service key file, or every `genai` binding in `VCAP_SERVICES`.
+    pub async fn from_env() -> Result<Self> {
+        let all_creds = resolve_credentials().await?;
+
+        // Routing only matters when there is more than one binding, so skip
+        // discovery round-trips in the common single-binding case.
+        let discover = all_creds.len() > 1;
+
+        let failover_target = FailoverTarget::from_config()?;
+        let egress =
+            EgressPolicy::for_credentials(&all_creds)?.map(|policy| match &failover_target {
+                Some(target) => policy.with_base(&target.endpoint_base),
+                None => policy,
+            });
+        let settings = ClientSettings {
+            egress,
+            ..ClientSettings::from_config()
+        };
+        let timeouts = settings.timeouts;
+        let http = shared_http_client(&settings)?;
+
+        let mut bindings = Vec::with_capacity(all_creds.len());
+        for creds in all_creds {
+            let endpoint = endpoint::Endpoint::parse(&creds.endpoint_base);
+            tracing::debug!(
+                "Tanzu AI endpoint {} is {} (plan {:?})",
+                endpoint.base,
+                endpoint.layout,
+                endpoint.plan
+            );
+            bindings.push(TanzuBinding::new(creds, http.clone(), timeouts, discover).await?);
+        }
+        // Active/passive foundations serve the same plan, which is the default route
+        if let (Some(target), Some(primary)) = (failover_target, bindings.first_mut()) {
+            let tokens = Arc::new(TokenManager::new(
+                target.api_key,
+                failover::reresolve_api_key,
+            ));
+            let secondary = Transport::new(http.clone(), &target.endpoint_base, tokens)
+                .with_timeouts(timeouts)
+                .with_flavor(UpstreamFlavor::detect(None, &target.endpoint_base));
+            let state =
+                Failover::from_config(&primary.credentials.endpoint_base, &target.endpoint_base);
+            primary.transport = primary.transport.clone().with_failover(secondary, state);
+        }
+        Ok(Self::from_bindings(bindings, BalanceMode::from_config()))
+    }
+
+    /// Connect to a single endpoint with an API key, e.g. one read from a secret store.
+    ///
+    /// `endpoint` is the binding's `api_base`, with or without the `/openai` suffix.
+    pub fn new(endpoint: &str, api_key: &str) -> Result<Self> {
+        let endpoint_base = normalize_api_base(endpoint);
+        let credentials = TanzuCredentials {
+            flavor: UpstreamFlavor::detect(None, &endpoint_base),
+            endpoint_base,
+            api_key: api_key.to_string(),
+            config_url: None,
+            config_api_key: None,
+            model_name: None,
+            binding_name: None,
+            binding_guid: None,
+            region: None,
+            model_aliases: Vec::new(),
+            wire_format: WireFormat::OpenAi,
+            auth: AuthMethod::ApiKey,
+        };
+        let settings = ClientSettings {
+            egress: EgressPolicy::for_credentials(std::slice::from_ref(&credentials))?,
+            ..ClientSettings::from_config()
+        };
+        let http = shared_http_client(&settings)?;
+        let mut binding = TanzuBinding::build(credentials, http);
+        binding.transport = binding.transport.with_timeouts(settings.timeouts);
+        Ok(Self::from_bindings(vec![binding], BalanceMode::Primary))
+    }
+
+    pub(super) fn from_bindings(bindings: Vec<TanzuBinding>, mode: BalanceMode) -> Self {
+        let balancer = Balancer::new(mode, bindings.len());
+        Self { bindings, balancer }
+    }
+
+    /// Endpoint base URLs of the bindings, the default route first.
+    pub fn endpoints(&self) -> Vec<String> {
+        self.bindings
+            .iter()
+            .map(|b| b.credentials.endpoint_base.clone())
+            .collect()
+    }
+
+    /// Check that every binding is reachable and accepts its credentials, returning
+    /// the models they serve.
+    pub async fn verify(&self) -> Result<Vec<String>, PreflightError> {
+        let mut available: Vec<String> = Vec::new();
+        for binding in &self.bindings {
+            let token = binding.transport.bearer_token().await.map_err(|e| {
+                PreflightError::TokenExchange {
+                    endpoint: binding.credentials.endpoint_base.clone(),
+                    reason: e.to_string(),
+                }
+            })?;
+            let served = preflight::check_endpoint(
+                binding.transport.http(),
+                &binding.credentials.endpoint_base,
+                &token,
+                &binding.credentials.flavor,
+            )
+            .await?;
+            for model in served.into_iter().chain(binding.models()) {
+                if !available.contains(&model) {
+                    available.push(model);
+                }
+            }
+        }
+        Ok(available)
+    }
+
+    /// Every model the bindings advertise, embedding models included, with its
+    /// capabilities and context length, and its owner and creation time where the
+    /// proxy serves model details.
+    pub async fn models(&self) -> Result<Vec<TanzuModelInfo>, ProviderError> {
+        let mut models: Vec<TanzuModelInfo> = Vec::new();
+        let mut last_error = None;
+        for binding in &self.bindings {
+            match binding.discover().await {
+                Ok(advertised) => {
+                    let new: Vec<TanzuModelInfo> = advertised
+                        .into_iter()
+                        .filter(|model| !models.iter().any(|m| m.name == model.name))
+                        .map(TanzuModelInfo::from)
+                        .collect();
+                    let details = futures::future::join_all(
+                        new.iter().map(|m| model_detail::fetch(binding, &m.name)),
+                    )
+                    .await;
+                    for (mut model, detail) in new.into_iter().zip(details) {
+                        match detail {
+                            Ok(Some(detail)) => model.enrich(detail),
+                            Ok(None) => {}
+                            Err(e) => {
+                                tracing::debug!("No details for model {}: {}", model.name, e)
+                            }
+                        }
+                        models.push(model);
+                    }
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if models.is_empty() => Err(ProviderError::RequestFailed(format!(
+                "Failed to list Tanzu AI Services models: {}",
+                e
+            ))),
+            _ => Ok(models),
+        }
+    }
+
+    /// What the proxy serving `model` reports about it at `/openai/v1/models/{id}`, or
+    /// `None` when the proxy does not implement that endpoint or does not know the model.
+    pub async fn model_detail(&self, model: &str) -> Result<Option<ModelDetail>, ProviderError> {
+        let model = self.resolve_model_name(model);
+        let binding = self.binding_for_model(&model).await;
+        model_detail::fetch(binding, &model).await.map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to fetch details of {}: {}", model, e))
+        })
+    }
+
+    /// Drop cached discovery results for these bindings.
+    pub fn invalidate_discovery(&self) {
+        for binding in &self.bindings {
+            DISCOVERY_CACHE.invalidate(&binding.credentials.discovery_key());
+        }
+    }
+
+    /// Send one chat request to the binding serving `model_config.model_name`.
+    pub async fn complete(
+        &self,
+        model_config: &ModelConfig,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let model_name = self.resolve_model_name(&model_config.model_name);
+        let mut model_config = model_config.clone();
+        model_config.model_name = model_name.clone();
+
+        let (index, binding) = self.dispatch_binding(&model_name).await;
+        let format = self.wire_format_for(binding, &model_name).await;
+        let payload = format.create_request(&model_config, system, messages, tools, false)?;
+        let response = self.chat_completion(None, index, format, &payload).await?;
+        let (message, usage) = format.parse_response(&response)?;
+        let served_by = response
+            .get("model")
+            .and_then(Value::as_str)
+            .unwrap_or(&model_name);
+        Ok((message, ProviderUsage::new(served_by.to_string(), usage)))
+    }
+
+    /// Stream one chat request from the binding serving `model_config.model_name`.
+    pub async fn stream(
+        &self,
+        model_config: &ModelConfig,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<MessageStream, ProviderError> {
+        let model_name = self.resolve_model_name(&model_config.model_name);
+        let mut model_config = model_config.clone();
+        model_config.model_name = model_name.clone();
+
+        let (index, binding) = self.dispatch_binding(&model_name).await;
+        let format = self.wire_format_for(binding, &model_name).await;
+        let payload = format.create_request(&model_config, system, messages, tools, true)?;
+        Ok(self.chat_stream(None, index, format, &payload).await?)
+    }
+
+    /// POST a chat payload to binding `index`, tracked by the balancer.
+    pub(super) async fn chat_completion(
+        &self,
+        session_id: Option<&str>,
+        index: usize,
+        format: WireFormat,
+        payload: &Value,
+    ) -> Result<Value, RequestError> {
+        let response = {
+            let _inflight = self.balancer.start(index);
+            self.bindings[index]
+                .transport
+                .chat_completion(session_id, format, payload)
+                .await
+        };
+        self.balancer.record(index, &response);
+        response
+    }
+
+    /// Stream a chat payload from binding `index`, tracked by the balancer.
+    pub(super) async fn chat_stream(
+        &self,
+        session_id: Option<&str>,
+        index: usize,
+        format: WireFormat,
+        payload: &Value,
+    ) -> Result<MessageStream, RequestError> {
+        let inflight = self.balancer.start(index);
+        let response = self.bindings[index]
+            .transport
+            .chat_stream(session_id, format, payload)
+            .await;
+        self.balancer.record(index, &response);
+        // The request stays in flight until the stream is consumed or dropped
+        let stream = response?;
+        Ok(Box::pin(futures::StreamExt::map(stream, move |item| {
+            let _inflight = &inflight;
+            item
+        })))
+    }
+
+    /// Whether `model_name` is advertised for legacy completions but not for chat.
+    pub(super) async fn completion_only(&self, model_name: &str) -> bool {
+        self.advertised_model(model_name).await.is_some_and(|m| {
+            m.has_capability(completion::COMPLETION_CAPABILITY)
+                && !m.has_capability(completion::CHAT_CAPABILITY)
+        })
+    }
+
+    /// The wire format for a request to `model_name` on `binding`: the binding's own,
+    /// or legacy completions for COMPLETION-only models on OpenAI-compatible bindings.
+    pub(super) async fn wire_format_for(
+        &self,
+        binding: &TanzuBinding,
+        model_name: &str,
+    ) -> WireFormat {
+        let format = binding.credentials.wire_format;
+        if format == WireFormat::OpenAi && self.completion_only(model_name).await {
+            tracing::debug!(
+                "{} only supports completions, using the completions endpoint",
+                model_name
+            );
+            return WireFormat::Completion;
+        }
+        format
+    }
+
+    /// What the binding serving `model_name` advertises about it, if discovery succeeds.
+    pub(super) async fn advertised_model(&self, model_name: &str) -> Option<AdvertisedModel> {
+        let binding = self.binding_for_model(model_name).await;
+        match binding.discover().await {
+            Ok(models) => models.into_iter().find(|m| m.name == model_name),
+            Err(e) => {
+                tracing::debug!("Model discovery failed for {}: {}", model_name, e);
+                None
+            }
+        }
+    }
+
+    /// Map a model alias to the deployed model name, leaving other names untouched.
+    pub(super) fn resolve_model_name(&self, model_name: &str) -> String {
+        resolve_model_alias(self.bindings.iter().map(|b| &b.credentials), model_name)
+            .unwrap_or(model_name)
+            .to_string()
+    }
+
+    /// Pick the binding that serves `model_name`, falling back to the first binding.
+    ///
+    /// Lead/worker configurations can request a model no routing table knows about yet,
+    /// for example when discovery failed at startup, so bindings are re-discovered before
+    /// giving up on finding the one that serves it.
+    pub(super) async fn binding_for_model(&self, model_name: &str) -> &TanzuBinding {
+        if self.bindings.len() > 1 && !self.bindings.iter().any(|b| b.serves(model_name)) {
+            for binding in &self.bindings {
+                match binding.discover_served_models().await {
+                    Ok(models) if !models.is_empty() => binding.set_models(models),
+                    Ok(_) => {}
+                    Err(e) => tracing::debug!(
+                        "Model discovery failed for {}: {}",
+                        binding.credentials.endpoint_base,
+                        e
+                    ),
+                }
+                if binding.serves(model_name) {
+                    break;
+                }
+            }
+        }
+        route_binding(&self.bindings, model_name)
+    }
+
+    /// Pick the binding to send a request for `model_name` to, with its index.
+    ///
+    /// Like `binding_for_model`, but when several bindings serve the model the balancer
+    /// chooses among them.
+    pub(super) async fn dispatch_binding(&self, model_name: &str) -> (usize, &TanzuBinding) {
+        self.binding_for_model(model_name).await;
+        let candidates: Vec<usize> = (0..self.bindings.len())
+            .filter(|&index| self.bindings[index].serves(model_name))
+            .collect();
+        let index = if candidates.is_empty() {
+            0
+        } else {
+            self.balancer.pick(&candidates)
+        };
+        (index, &self.bindings[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_client_complete_and_models() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/client-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": "llama3.2:1b"}]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/client-plan/openai/v1/chat/completions"))
+            .and(header("Authorization", "Bearer client-key"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "llama3.2:1b"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hello from Tanzu"},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = TanzuClient::new(
+            &format!("{}/client-plan/openai", mock_server.uri()),
+            "client-key",
+        )
+        .unwrap();
+        assert_eq!(
+            client.endpoints(),
+            vec![format!("{}/client-plan", mock_server.uri())]
+        );
+
+        let models = client.models().await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "llama3.2:1b");
+        assert_eq!(client.verify().await.unwrap(), vec!["llama3.2:1b"]);
+
+        let (message, usage) = client
+            .complete(
+                &ModelConfig::new_or_fail("llama3.2:1b"),
+                "You are terse.",
+                &[Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "Hello from Tanzu");
+        assert_eq!(usage.model, "llama3.2:1b");
+    }
+
+    #[tokio::test]
+    async fn test_models_enriched_from_detail_endpoint() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/detail-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": "openai/gpt-oss-120b"}, {"id": "llama3.2:1b"}]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/detail-plan/openai/v1/models/openai%2Fgpt-oss-120b"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "openai/gpt-oss-120b",
+                "object": "model",
+                "created": 1754352000,
+                "owned_by": "openai",
+                "max_model_len": 131072
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // The unmatched llama3.2:1b detail is a 404 and keeps what the listing said
+        Mock::given(method("GET"))
+            .and(path("/legacy-plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": "llama3.2:1b"}, {"id": "qwen3:8b"}]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/legacy-plan/openai/v1/models/llama3.2:1b"))
+            .respond_with(ResponseTemplate::new(501))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client =
+            TanzuClient::new(&format!("{}/detail-plan", mock_server.uri()), "detail-key").unwrap();
+        let models = client.models().await.unwrap();
+        assert_eq!(models[0].owned_by.as_deref(), Some("openai"));
+        assert_eq!(models[0].created, Some(1754352000));
+        assert_eq!(models[0].context_length, Some(131072));
+        assert_eq!(models[1].owned_by, None);
+        assert_eq!(models[1].context_length, None);
+        // Cached for the discovery TTL
+        let detail = client.model_detail("openai/gpt-oss-120b").await.unwrap();
+        assert_eq!(detail.unwrap().owned_by.as_deref(), Some("openai"));
+
+        // A proxy without the endpoint is asked once, then left alone
+        let legacy =
+            TanzuClient::new(&format!("{}/legacy-plan", mock_server.uri()), "legacy-key").unwrap();
+        assert!(legacy.model_detail("llama3.2:1b").await.unwrap().is_none());
+        let models = legacy.models().await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert!(models.iter().all(|m| m.owned_by.is_none()));
+    }
+}
@@ -0,0 +1,155 @@
+//! Automatic compaction of conversations that outgrow the model's context.
+//!
+//! Small plans often serve a single model with a short window, and a long session then
+//! fails with `ContextLengthExceeded`. With `TANZU_AI_AUTO_COMPACT=true` the provider
+//! instead summarizes the older turns with the same model and retries once with the
+//! summary in their place. Older turns that don't fit one summarization request are
+//! split into chunks summarized separately (map) whose summaries are then summarized
+//! together (reduce).
+
+use super::context::CHARS_PER_TOKEN;
+use super::tools::flatten_tool_messages;
+use crate::conversation::message::{Message, MessageContent};
+use rmcp::model::Role;
+
+/// System prompt for every summarization request.
+pub const SUMMARY_PROMPT: &str = "You condense conversations between a user and an AI \
+    assistant. Summarize the transcript you are given in a few paragraphs. Keep every \
+    fact, decision, file name, command and open task the assistant needs to continue; \
+    drop pleasantries and repeated content. Reply with the summary only.";
+
+/// Share of the context window one chunk of transcript may fill.
+const CHUNK_SHARE_PERCENT: usize = 50;
+
+/// Whether conversations are compacted instead of failing (`TANZU_AI_AUTO_COMPACT`).
+pub fn enabled() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_AUTO_COMPACT")
+        .unwrap_or(false)
+}
+
+/// Split `messages` into older turns to summarize and recent turns to keep verbatim,
+/// or `None` when there is nothing older to summarize.
+///
+/// The recent half is kept, moved back as needed so a tool response is never separated
+/// from the request that produced it.
+pub fn split(messages: &[Message]) -> Option<(&[Message], &[Message])> {
+    let mut start = messages.len() / 2;
+    while start > 0 && has_tool_response(&messages[start]) {
+        start -= 1;
+    }
+    (start > 0).then(|| messages.split_at(start))
+}
+
+fn has_tool_response(message: &Message) -> bool {
+    message
+        .content
+        .iter()
+        .any(|c| matches!(c, MessageContent::ToolResponse(_)))
+}
+
+/// Render `messages` as plain-text transcript chunks that each fit a summarization
+/// request for a model with `context_length` tokens.
+pub fn transcript_chunks(messages: &[Message], context_length: usize) -> Vec<String> {
+    let max_chars = (context_length * CHUNK_SHARE_PERCENT / 100 * CHARS_PER_TOKEN).max(1);
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for message in flatten_tool_messages(messages) {
+        let speaker = match message.role {
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        };
+        let mut turn = format!("{}: {}\n\n", speaker, message.as_concat_text());
+        if turn.len() > max_chars {
+            // A single oversized turn keeps its beginning
+            let mut end = max_chars;
+            while !turn.is_char_boundary(end) {
+                end -= 1;
+            }
+            turn.truncate(end);
+            turn.push_str("\n[...]\n\n");
+        }
+        if !chunk.is_empty() && chunk.len() + turn.len() > max_chars {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        chunk.push_str(&turn);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// The request sent to summarize one transcript chunk or the chunk summaries.
+pub fn summary_request(transcript: &str) -> Message {
+    Message::user().with_text(format!(
+        "Summarize this conversation transcript:\n\n{}",
+        transcript
+    ))
+}
+
+/// The conversation with `summary` standing in for the older turns.
+pub fn compacted(summary: &str, recent: &[Message]) -> Vec<Message> {
+    let mut messages = vec![Message::user().with_text(format!(
+        "Summary of the earlier conversation, which was compacted to fit the model's \
+         context window:\n\n{}",
+        summary
+    ))];
+    messages.extend_from_slice(recent);
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{CallToolRequestParam, Content};
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::user().with_text("deploy the app"),
+            Message::assistant().with_text("Which space?"),
+            Message::user().with_text("staging"),
+            Message::assistant().with_tool_request(
+                "call_1",
+                Ok(CallToolRequestParam {
+                    name: "cf_push".into(),
+                    arguments: None,
+                }),
+            ),
+            Message::user().with_tool_response("call_1", Ok(vec![Content::text("pushed")])),
+            Message::assistant().with_text("Deployed to staging."),
+        ]
+    }
+
+    #[test]
+    fn test_split_keeps_tool_pairs_together() {
+        let messages = conversation();
+        let (older, recent) = split(&messages).unwrap();
+        assert_eq!(older.len(), 3);
+        assert_eq!(recent.len(), 3);
+
+        // Starting at a tool response moves the split back to its request
+        let (older, recent) = split(&messages[2..]).unwrap();
+        assert_eq!(older.len(), 1);
+        assert!(recent[0]
+            .content
+            .iter()
+            .all(|c| !matches!(c, MessageContent::ToolResponse(_))));
+
+        assert!(split(&messages[..1]).is_none());
+    }
+
+    #[test]
+    fn test_transcript_chunks() {
+        let messages = conversation();
+        let chunks = transcript_chunks(&messages, 10_000);
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].starts_with("User: deploy the app\n\nAssistant: Which space?"));
+        assert!(chunks[0].contains("cf_push"));
+
+        // 8 tokens a chunk: every turn gets its own
+        let chunks = transcript_chunks(&messages, 16);
+        assert_eq!(chunks.len(), messages.len());
+        assert!(chunks.iter().all(|c| c.len() <= 32 + "\n[...]\n\n".len()));
+    }
+}
@@ -0,0 +1,118 @@
+//! Legacy completions for models without a chat endpoint.
+//!
+//! Some bindings advertise models with the COMPLETION capability but not CHAT, served only
+//! by `POST {endpoint}/openai/v1/completions`. Requests for them flatten the conversation
+//! into a single prompt with `System:`, `User:` and `Assistant:` turns that ends on an
+//! open assistant turn; generation stops before the model writes the next user turn.
+//! Tool calls go through the same prompt-based emulation as models without native tools.
+
+use super::tools::flatten_tool_messages;
+use crate::conversation::message::Message;
+use crate::model::ModelConfig;
+use crate::providers::base::Usage;
+use crate::providers::formats::openai;
+use anyhow::{anyhow, Result};
+use rmcp::model::Role;
+use serde_json::{json, Value};
+
+pub const COMPLETION_CAPABILITY: &str = "COMPLETION";
+pub const CHAT_CAPABILITY: &str = "CHAT";
+
+/// Turn prefixes the model must not continue into.
+const STOP: [&str; 2] = ["\nUser:", "\nSystem:"];
+
+/// Flatten a conversation into a completion prompt.
+pub fn prompt(system: &str, messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    if !system.trim().is_empty() {
+        prompt.push_str(&format!("System: {}\n\n", system.trim()));
+    }
+    for message in flatten_tool_messages(messages) {
+        let speaker = match message.role {
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        };
+        prompt.push_str(&format!(
+            "{}: {}\n\n",
+            speaker,
+            message.as_concat_text().trim()
+        ));
+    }
+    prompt.push_str("Assistant:");
+    prompt
+}
+
+/// Build a `/v1/completions` request body.
+pub fn create_request(model_config: &ModelConfig, system: &str, messages: &[Message]) -> Value {
+    let mut payload = json!({
+        "model": model_config.model_name,
+        "prompt": prompt(system, messages),
+        "stop": STOP,
+    });
+    if let Some(max_tokens) = model_config.max_tokens {
+        payload["max_tokens"] = json!(max_tokens);
+    }
+    if let Some(temperature) = model_config.temperature {
+        payload["temperature"] = json!(temperature);
+    }
+    payload
+}
+
+/// Convert a `/v1/completions` response into an assistant message.
+pub fn response_to_message(response: &Value) -> Result<Message> {
+    let text = response
+        .pointer("/choices/0/text")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("Completion response has no choices[0].text"))?;
+    Ok(Message::assistant().with_text(text.trim()))
+}
+
+/// Token usage reported in a `/v1/completions` response.
+pub fn usage(response: &Value) -> Usage {
+    response
+        .get("usage")
+        .map(openai::get_usage)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_flattening() {
+        let messages = [
+            Message::user().with_text("What is Tanzu?"),
+            Message::assistant().with_text("A platform."),
+            Message::user().with_text("Say more"),
+        ];
+        assert_eq!(
+            prompt("Be brief.", &messages),
+            "System: Be brief.\n\nUser: What is Tanzu?\n\nAssistant: A platform.\n\n\
+             User: Say more\n\nAssistant:"
+        );
+        assert_eq!(
+            prompt("", &messages[..1]),
+            "User: What is Tanzu?\n\nAssistant:"
+        );
+    }
+
+    #[test]
+    fn test_request_and_response() {
+        let model_config =
+            ModelConfig::new_or_fail("gpt-3.5-turbo-instruct").with_max_tokens(Some(64));
+        let payload = create_request(&model_config, "", &[Message::user().with_text("hi")]);
+        assert_eq!(payload["model"], "gpt-3.5-turbo-instruct");
+        assert_eq!(payload["max_tokens"], 64);
+        assert!(payload.get("messages").is_none());
+
+        let response = json!({
+            "choices": [{"index": 0, "text": " Hello!\n", "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 4, "completion_tokens": 2, "total_tokens": 6}
+        });
+        let message = response_to_message(&response).unwrap();
+        assert_eq!(message.as_concat_text(), "Hello!");
+        assert_eq!(usage(&response).total_tokens, Some(6));
+        assert!(response_to_message(&json!({"choices": []})).is_err());
+    }
+}
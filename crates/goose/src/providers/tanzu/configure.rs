@@ -0,0 +1,101 @@
+//! Model selection for the `goose configure` flow.
+//!
+//! Once the user has entered an endpoint and API key, the configure step asks the plan
+//! which chat models it advertises, shows them with their capabilities, and saves the
+//! one picked as the default model, so nobody has to guess model names.
+
+use super::{
+    filter_chat_models, normalize_api_base, shared_http_client, AuthMethod, ClientSettings,
+    TanzuBinding, TanzuCredentials, TanzuModelInfo, UpstreamFlavor, WireFormat,
+};
+use crate::providers::errors::ProviderError;
+
+/// Path of the config endpoint relative to the endpoint base, used when no URL is given.
+const DEFAULT_CONFIG_PATH: &str = "config/v1/endpoint";
+
+/// List the chat models advertised for `endpoint`, in the order the plan returns them.
+///
+/// Without a `config_url` the plan's default config endpoint is tried, falling back to
+/// the OpenAI models list.
+pub async fn advertised_chat_models(
+    endpoint: &str,
+    api_key: &str,
+    config_url: Option<&str>,
+) -> Result<Vec<TanzuModelInfo>, ProviderError> {
+    let endpoint_base = normalize_api_base(endpoint);
+    let config_url = config_url
+        .map(String::from)
+        .unwrap_or_else(|| format!("{}/{}", endpoint_base, DEFAULT_CONFIG_PATH));
+    let credentials = TanzuCredentials {
+        flavor: UpstreamFlavor::detect(None, &endpoint_base),
+        endpoint_base,
+        api_key: api_key.to_string(),
+        config_url: Some(config_url),
+        config_api_key: None,
+        model_name: None,
+        binding_name: None,
+        binding_guid: None,
+        region: None,
+        model_aliases: Vec::new(),
+        wire_format: WireFormat::OpenAi,
+        auth: AuthMethod::ApiKey,
+    };
+    let http = shared_http_client(&ClientSettings::from_config())
+        .map_err(|e| ProviderError::ExecutionError(e.to_string()))?;
+    let binding = TanzuBinding::build(credentials, http);
+
+    let models = binding.discover().await.map_err(|e| {
+        ProviderError::RequestFailed(format!("Failed to list Tanzu AI Services models: {}", e))
+    })?;
+    let chat = filter_chat_models(&models);
+    Ok(models
+        .into_iter()
+        .filter(|m| chat.contains(&m.name))
+        .map(TanzuModelInfo::from)
+        .collect())
+}
+
+/// Save the endpoint and the chosen model as the defaults in the Goose config.
+pub fn save_default_model(endpoint: &str, model: &str) -> Result<(), ProviderError> {
+    let config = crate::config::Config::global();
+    config
+        .set_param("TANZU_AI_ENDPOINT", endpoint)
+        .and_then(|_| config.set_param("GOOSE_MODEL", model))
+        .map_err(|e| ProviderError::ExecutionError(format!("Failed to save config: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_advertised_chat_models_uses_default_config_path() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/wizard-plan/config/v1/endpoint"))
+            .and(header("Authorization", "Bearer wizard-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [
+                    {"name": "qwen3-30b", "capabilities": ["CHAT", "TOOLS"], "contextLength": 32768},
+                    {"name": "nomic-embed-text", "capabilities": ["EMBEDDING"]},
+                    {"name": "llama3.2:1b", "capabilities": ["CHAT"]}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let models = advertised_chat_models(
+            &format!("{}/wizard-plan/openai", mock_server.uri()),
+            "wizard-key",
+            None,
+        )
+        .await
+        .unwrap();
+
+        let names: Vec<_> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["qwen3-30b", "llama3.2:1b"]);
+        assert_eq!(models[0].context_length, Some(32768));
+    }
+}
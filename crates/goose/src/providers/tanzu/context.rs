@@ -0,0 +1,179 @@
+//! Context window checks for Tanzu-hosted models.
+//!
+//! Small plans often serve models with 4k–32k windows. When the config URL advertises
+//! a model's context length, prompts that clearly cannot fit are rejected locally with
+//! `ContextLengthExceeded`, which lets Goose compact the conversation instead of waiting
+//! for a 400 from the proxy. The size is only a characters-per-token estimate, so a
+//! prompt is rejected once the estimate exceeds the window by [`ESTIMATE_MARGIN_PERCENT`];
+//! closer calls are left to the proxy.
+//!
+//! A prompt that fits can still fail when it leaves less room than the requested
+//! `max_tokens`, so the limit is lowered to what remains of the window, with a log line.
+//!
+//! The gorouter in front of the proxy also caps request bodies and answers larger ones
+//! with a bare 502. `TANZU_AI_MAX_REQUEST_BYTES` sets that cap locally, so an oversized
+//! payload fails the same way, with its measured size, before it is sent.
+
+use crate::providers::errors::ProviderError;
+use serde_json::Value;
+
+/// Rough characters-per-token ratio for English text and JSON.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// How far, in percent of the context length, an estimate must overshoot before a prompt
+/// is rejected locally.
+pub const ESTIMATE_MARGIN_PERCENT: usize = 25;
+
+/// Estimate the prompt tokens of a chat payload from its system prompt, messages and tools,
+/// or of a completion payload from its prompt.
+pub fn estimate_prompt_tokens(payload: &Value) -> usize {
+    let chars: usize = ["system", "messages", "tools", "prompt"]
+        .iter()
+        .filter_map(|key| payload.get(*key))
+        .map(text_len)
+        .sum();
+    chars.div_ceil(CHARS_PER_TOKEN)
+}
+
+fn text_len(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::Array(items) => items.iter().map(text_len).sum(),
+        Value::Object(map) => map.values().map(text_len).sum(),
+        _ => 0,
+    }
+}
+
+/// Reject payloads whose estimated prompt size exceeds the model's context length by more
+/// than the estimate's margin of error.
+pub fn check_context_length(
+    payload: &Value,
+    model: &str,
+    context_length: usize,
+) -> Result<(), ProviderError> {
+    let estimated = estimate_prompt_tokens(payload);
+    let margin = context_length * ESTIMATE_MARGIN_PERCENT / 100;
+    if estimated > context_length + margin {
+        return Err(ProviderError::ContextLengthExceeded(format!(
+            "Prompt is about {} tokens but {} has a context length of {} tokens",
+            estimated, model, context_length
+        )));
+    }
+    Ok(())
+}
+
+/// Output limit fields of the wire formats.
+const MAX_TOKENS_FIELDS: [&str; 2] = ["max_tokens", "max_completion_tokens"];
+
+/// Lower the payload's output token limit to what the prompt leaves of the model's
+/// context length.
+pub fn clamp_max_tokens(payload: &mut Value, model: &str, context_length: usize) {
+    let remaining = context_length
+        .saturating_sub(estimate_prompt_tokens(payload))
+        .max(1);
+    for field in MAX_TOKENS_FIELDS {
+        let Some(requested) = payload.get(field).and_then(Value::as_u64) else {
+            continue;
+        };
+        if requested as usize > remaining {
+            tracing::info!(
+                "Lowering {} for {} from {} to {}, the room left in its {}-token context",
+                field,
+                model,
+                requested,
+                remaining,
+                context_length
+            );
+            payload[field] = Value::from(remaining);
+        }
+    }
+}
+
+/// The request body limit, when `TANZU_AI_MAX_REQUEST_BYTES` is set.
+pub fn max_request_bytes() -> Option<usize> {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_MAX_REQUEST_BYTES")
+        .ok()
+        .filter(|bytes| *bytes > 0)
+}
+
+/// Reject payloads whose JSON body is larger than `max_bytes`.
+pub fn check_request_size(
+    payload: &Value,
+    model: &str,
+    max_bytes: usize,
+) -> Result<(), ProviderError> {
+    let size = serde_json::to_vec(payload)
+        .map(|body| body.len())
+        .unwrap_or_default();
+    if size > max_bytes {
+        return Err(ProviderError::ContextLengthExceeded(format!(
+            "Request to {} is {} bytes, over the TANZU_AI_MAX_REQUEST_BYTES limit of {} bytes",
+            model, size, max_bytes
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimate_prompt_tokens() {
+        let payload = json!({
+            "model": "llama3.2:1b",
+            "messages": [
+                {"role": "system", "content": "abcd"},
+                {"role": "user", "content": "abcdefgh"}
+            ]
+        });
+        // "system" + "abcd" + "user" + "abcdefgh" = 22 chars
+        assert_eq!(estimate_prompt_tokens(&payload), 6);
+    }
+
+    #[test]
+    fn test_check_context_length() {
+        let payload = json!({
+            "messages": [{"role": "user", "content": "x".repeat(4000)}]
+        });
+
+        assert!(check_context_length(&payload, "llama3.2:1b", 4096).is_ok());
+        let err = check_context_length(&payload, "llama3.2:1b", 512).unwrap_err();
+        assert!(matches!(err, ProviderError::ContextLengthExceeded(_)));
+
+        // About 1001 tokens: within the estimate's margin of a 900-token window
+        assert!(check_context_length(&payload, "llama3.2:1b", 900).is_ok());
+        assert!(check_context_length(&payload, "llama3.2:1b", 800).is_err());
+    }
+
+    #[test]
+    fn test_clamp_max_tokens() {
+        let mut payload = json!({
+            "messages": [{"role": "user", "content": "x".repeat(3996)}],
+            "max_tokens": 4096
+        });
+        // "user" + 3996 chars is 1000 tokens
+        clamp_max_tokens(&mut payload, "llama3.2:1b", 4096);
+        assert_eq!(payload["max_tokens"], 3096);
+
+        clamp_max_tokens(&mut payload, "llama3.2:1b", 8192);
+        assert_eq!(payload["max_tokens"], 3096);
+
+        let mut payload = json!({"prompt": "x".repeat(400), "max_completion_tokens": 500});
+        clamp_max_tokens(&mut payload, "gpt-3.5-turbo-instruct", 100);
+        assert_eq!(payload["max_completion_tokens"], 1);
+    }
+
+    #[test]
+    fn test_check_request_size() {
+        let payload = json!({"messages": [{"role": "user", "content": "x".repeat(1000)}]});
+        let size = payload.to_string().len();
+
+        assert!(check_request_size(&payload, "llama3.2:1b", size).is_ok());
+        let err = check_request_size(&payload, "llama3.2:1b", 512).unwrap_err();
+        assert!(matches!(err, ProviderError::ContextLengthExceeded(_)));
+        assert!(err.to_string().contains(&format!("{} bytes", size)));
+    }
+}
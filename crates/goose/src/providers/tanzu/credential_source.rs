@@ -0,0 +1,334 @@
+//! Where binding credentials come from.
+//!
+//! Each place credentials can be found is a [`CredentialSource`], tried in order until
+//! one has credentials. Sources that need disk or network I/O do it asynchronously, so
+//! resolution runs inside the provider's `from_env` future without blocking the
+//! runtime. A new source only needs an implementation and a slot in
+//! [`default_sources`].
+
+use super::flavor::UpstreamFlavor;
+use super::uaa::{AuthMethod, UaaClientConfig};
+use super::wire::WireFormat;
+use super::{
+    credhub, normalize_api_base, parse_service_key, parse_vcap_services, profile, service_bindings,
+    TanzuCredentials,
+};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+
+/// What a source found.
+#[derive(Debug)]
+pub enum Resolution {
+    /// The source is not configured in this environment
+    Absent,
+    Found(Vec<TanzuCredentials>),
+    /// The source is configured but its bindings are unusable; later sources still
+    /// apply, and this is reported if none of them has credentials
+    Invalid(anyhow::Error),
+}
+
+#[async_trait]
+pub trait CredentialSource: Send + Sync {
+    /// Where the credentials come from, for messages.
+    fn name(&self) -> &'static str;
+
+    /// Look for credentials. An error stops resolution, e.g. a configured file that
+    /// cannot be read.
+    async fn resolve(&self) -> Result<Resolution>;
+}
+
+/// The sources in priority order:
+/// 1. The selected profile (TANZU_AI_PROFILE; see [`profile`])
+/// 2. Explicit env vars (TANZU_AI_ENDPOINT + TANZU_AI_API_KEY or UAA client credentials)
+/// 3. A `cf service-key` JSON file (TANZU_AI_SERVICE_KEY_FILE)
+/// 4. VCAP_SERVICES auto-detection (every usable `genai` binding), with CredHub
+///    references interpolated
+/// 5. Bindings mounted as files under SERVICE_BINDING_ROOT (`/etc/cf-service-bindings`),
+///    as TAS 10.x and Kubernetes service binding projections lay them out
+pub fn default_sources() -> Vec<Box<dyn CredentialSource>> {
+    vec![
+        Box::new(ProfileConfig),
+        Box::new(ExplicitConfig),
+        Box::new(ServiceKeyFile),
+        Box::new(VcapServices),
+        Box::new(MountedBindings),
+    ]
+}
+
+/// Credentials from the first source that has any.
+pub async fn resolve(sources: &[Box<dyn CredentialSource>]) -> Result<Vec<TanzuCredentials>> {
+    // A malformed binding is reported only if no other source has credentials
+    let mut invalid = None;
+    for source in sources {
+        match source.resolve().await? {
+            Resolution::Found(credentials) if !credentials.is_empty() => {
+                tracing::debug!(
+                    "Using {} Tanzu AI binding(s) from {}",
+                    credentials.len(),
+                    source.name()
+                );
+                return Ok(credentials);
+            }
+            Resolution::Found(_) | Resolution::Absent => {}
+            Resolution::Invalid(e) => {
+                invalid.get_or_insert((source.name(), e));
+            }
+        }
+    }
+
+    if let Some((source, e)) = invalid {
+        bail!("Tanzu AI Services binding in {} is invalid: {}", source, e);
+    }
+    bail!(
+        "Tanzu AI Services credentials not found. Set TANZU_AI_ENDPOINT and TANZU_AI_API_KEY, \
+         point TANZU_AI_SERVICE_KEY_FILE at a `cf service-key` JSON file, \
+         or run on Cloud Foundry with a bound genai service instance."
+    )
+}
+
+/// The endpoint and key of the profile named by `TANZU_AI_PROFILE`.
+pub struct ProfileConfig;
+
+#[async_trait]
+impl CredentialSource for ProfileConfig {
+    fn name(&self) -> &'static str {
+        "TANZU_AI_PROFILE"
+    }
+
+    async fn resolve(&self) -> Result<Resolution> {
+        let Some(name) = profile::active() else {
+            return Ok(Resolution::Absent);
+        };
+        let profile = profile::load(&name)?;
+        let (Some(endpoint), Some(api_key)) = (profile.endpoint, profile.api_key) else {
+            return Ok(Resolution::Absent);
+        };
+        tracing::debug!("Using Tanzu AI profile {}", name);
+
+        let endpoint_base = normalize_api_base(&endpoint);
+        Ok(Resolution::Found(vec![TanzuCredentials {
+            flavor: UpstreamFlavor::detect(None, &endpoint_base),
+            endpoint_base,
+            api_key,
+            config_url: profile.config_url,
+            config_api_key: None,
+            model_name: profile.model_name,
+            binding_name: Some(name),
+            binding_guid: None,
+            region: None,
+            model_aliases: Vec::new(),
+            wire_format: WireFormat::OpenAi,
+            auth: AuthMethod::ApiKey,
+        }]))
+    }
+}
+
+/// `TANZU_AI_ENDPOINT` with `TANZU_AI_API_KEY` or UAA client credentials.
+pub struct ExplicitConfig;
+
+#[async_trait]
+impl CredentialSource for ExplicitConfig {
+    fn name(&self) -> &'static str {
+        "TANZU_AI_ENDPOINT"
+    }
+
+    async fn resolve(&self) -> Result<Resolution> {
+        let config = crate::config::Config::global();
+        let endpoint: Result<String, _> = config.get_param("TANZU_AI_ENDPOINT");
+        let api_key: Result<String, _> = config.get_secret("TANZU_AI_API_KEY");
+        let auth = UaaClientConfig::from_config()
+            .map(AuthMethod::ClientCredentials)
+            .unwrap_or_default();
+
+        // With UAA client credentials no API key is needed
+        let api_key = match (api_key, &auth) {
+            (Ok(api_key), _) => Some(api_key),
+            (Err(_), AuthMethod::ClientCredentials(_)) => Some(String::new()),
+            (Err(_), AuthMethod::ApiKey) => None,
+        };
+        let (Ok(endpoint), Some(api_key)) = (endpoint, api_key) else {
+            return Ok(Resolution::Absent);
+        };
+        let config_url: Option<String> = config.get_param("TANZU_AI_CONFIG_URL").ok();
+        let model_name: Option<String> = config.get_param("TANZU_AI_MODEL_NAME").ok();
+
+        let endpoint_base = normalize_api_base(&endpoint);
+        Ok(Resolution::Found(vec![TanzuCredentials {
+            flavor: UpstreamFlavor::detect(None, &endpoint_base),
+            endpoint_base,
+            api_key,
+            config_url,
+            config_api_key: None,
+            model_name,
+            binding_name: None,
+            binding_guid: None,
+            region: None,
+            model_aliases: Vec::new(),
+            wire_format: WireFormat::OpenAi,
+            auth,
+        }]))
+    }
+}
+
+/// A service key saved from `cf service-key` (`TANZU_AI_SERVICE_KEY_FILE`).
+pub struct ServiceKeyFile;
+
+#[async_trait]
+impl CredentialSource for ServiceKeyFile {
+    fn name(&self) -> &'static str {
+        "TANZU_AI_SERVICE_KEY_FILE"
+    }
+
+    async fn resolve(&self) -> Result<Resolution> {
+        let Ok(path) =
+            crate::config::Config::global().get_param::<String>("TANZU_AI_SERVICE_KEY_FILE")
+        else {
+            return Ok(Resolution::Absent);
+        };
+        let contents = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| anyhow!("Failed to read TANZU_AI_SERVICE_KEY_FILE {}: {}", path, e))?;
+        let credentials = parse_service_key(&contents).map_err(|e| {
+            anyhow!(
+                "{} does not contain Tanzu AI Services credentials (expected `cf service-key` JSON output): {}",
+                path,
+                e
+            )
+        })?;
+        Ok(Resolution::Found(vec![credentials]))
+    }
+}
+
+/// The `VCAP_SERVICES` variable, interpolated through CredHub when it holds references.
+pub struct VcapServices;
+
+#[async_trait]
+impl CredentialSource for VcapServices {
+    fn name(&self) -> &'static str {
+        "VCAP_SERVICES"
+    }
+
+    async fn resolve(&self) -> Result<Resolution> {
+        let Ok(vcap) = std::env::var("VCAP_SERVICES") else {
+            return Ok(Resolution::Absent);
+        };
+        credhub::interpolate_vcap_services().await;
+        Ok(parsed(&credhub::resolved(vcap)))
+    }
+}
+
+/// Bindings mounted as files under `SERVICE_BINDING_ROOT`.
+pub struct MountedBindings;
+
+#[async_trait]
+impl CredentialSource for MountedBindings {
+    fn name(&self) -> &'static str {
+        "the service bindings under SERVICE_BINDING_ROOT"
+    }
+
+    async fn resolve(&self) -> Result<Resolution> {
+        let root = service_bindings::root();
+        let vcap = tokio::task::spawn_blocking(move || service_bindings::read_vcap_services(&root))
+            .await?;
+        Ok(match vcap {
+            Some(vcap) => parsed(&credhub::resolved(vcap)),
+            None => Resolution::Absent,
+        })
+    }
+}
+
+fn parsed(vcap_json: &str) -> Resolution {
+    match parse_vcap_services(vcap_json) {
+        Ok(credentials) => Resolution::Found(credentials),
+        Err(e) => Resolution::Invalid(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct Fixed(&'static str, fn() -> Result<Resolution>);
+
+    #[async_trait]
+    impl CredentialSource for Fixed {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn resolve(&self) -> Result<Resolution> {
+            (self.1)()
+        }
+    }
+
+    struct Untouched(Arc<AtomicBool>);
+
+    #[async_trait]
+    impl CredentialSource for Untouched {
+        fn name(&self) -> &'static str {
+            "untouched"
+        }
+
+        async fn resolve(&self) -> Result<Resolution> {
+            self.0.store(true, Ordering::SeqCst);
+            Ok(Resolution::Absent)
+        }
+    }
+
+    fn found() -> Result<Resolution> {
+        Ok(parsed(
+            &serde_json::json!({
+                "genai": [{
+                    "name": "genai-chat",
+                    "credentials": {
+                        "endpoint": {"api_base": "https://genai.example.com/plan", "api_key": "k"}
+                    }
+                }]
+            })
+            .to_string(),
+        ))
+    }
+
+    fn invalid() -> Result<Resolution> {
+        Ok(parsed(
+            r#"{"genai": [{"name": "broken", "credentials": {}}]}"#,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_first_source_with_credentials_wins() {
+        let touched = Arc::new(AtomicBool::new(false));
+        let sources: Vec<Box<dyn CredentialSource>> = vec![
+            Box::new(Fixed("absent", || Ok(Resolution::Absent))),
+            Box::new(Fixed("invalid", invalid)),
+            Box::new(Fixed("found", found)),
+            Box::new(Untouched(touched.clone())),
+        ];
+        let credentials = resolve(&sources).await.unwrap();
+        assert_eq!(credentials[0].binding_name.as_deref(), Some("genai-chat"));
+        assert!(!touched.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_reports_invalid_binding_when_nothing_found() {
+        let sources: Vec<Box<dyn CredentialSource>> = vec![
+            Box::new(Fixed("VCAP_SERVICES", invalid)),
+            Box::new(Fixed("absent", || Ok(Resolution::Absent))),
+        ];
+        assert_eq!(
+            resolve(&sources).await.unwrap_err().to_string(),
+            "Tanzu AI Services binding in VCAP_SERVICES is invalid: genai[0].credentials.endpoint missing"
+        );
+
+        let sources: Vec<Box<dyn CredentialSource>> = vec![
+            Box::new(Fixed("failing", || bail!("unreadable"))),
+            Box::new(Fixed("found", found)),
+        ];
+        assert_eq!(
+            resolve(&sources).await.unwrap_err().to_string(),
+            "unreadable"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! CredHub interpolation of `VCAP_SERVICES`.
+//!
+//! On foundations with secure service credentials, binding credentials in
+//! `VCAP_SERVICES` are `{"credhub-ref": "/c/..."}` placeholders. When the buildpack has
+//! not already interpolated them, the provider posts `VCAP_SERVICES` to CredHub's
+//! interpolate endpoint, authenticating with the app's instance identity certificate
+//! (`CF_INSTANCE_CERT` / `CF_INSTANCE_KEY`), and parses the resolved document instead.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::sync::{LazyLock, RwLock};
+
+const DEFAULT_CREDHUB_API: &str = "https://credhub.service.cf.internal:8844";
+const CREDHUB_REF: &str = "credhub-ref";
+
+/// Raw and interpolated `VCAP_SERVICES`, so re-resolving credentials does not call
+/// CredHub again.
+static INTERPOLATED: LazyLock<RwLock<Option<(String, String)>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+/// Whether a service binding's credentials are an unresolved CredHub reference.
+pub fn is_reference(credentials: &Value) -> bool {
+    credentials.get(CREDHUB_REF).is_some_and(Value::is_string)
+}
+
+/// Whether any GenAI binding in `VCAP_SERVICES` still holds a CredHub reference.
+fn has_references(vcap_json: &str) -> bool {
+    let Ok(vcap) = serde_json::from_str::<Value>(vcap_json) else {
+        return false;
+    };
+    let referenced = super::genai_bindings(&vcap)
+        .into_iter()
+        .filter_map(|(_, _, b)| b.get("credentials"))
+        .any(is_reference);
+    referenced
+}
+
+/// `vcap_json` with CredHub references resolved, if it has been interpolated.
+pub fn resolved(vcap_json: String) -> String {
+    match &*INTERPOLATED.read().unwrap() {
+        Some((raw, interpolated)) if *raw == vcap_json => interpolated.clone(),
+        _ => vcap_json,
+    }
+}
+
+/// Interpolate `VCAP_SERVICES` through CredHub if its `genai` bindings need it.
+///
+/// Failures are logged rather than returned: explicit configuration may still apply,
+/// and credential resolution reports the unresolved binding otherwise.
+pub async fn interpolate_vcap_services() {
+    let Ok(vcap) = std::env::var("VCAP_SERVICES") else {
+        return;
+    };
+    let done = matches!(&*INTERPOLATED.read().unwrap(), Some((raw, _)) if *raw == vcap);
+    if done || !has_references(&vcap) {
+        return;
+    }
+
+    let api = std::env::var("CREDHUB_API").unwrap_or_else(|_| DEFAULT_CREDHUB_API.to_string());
+    let result = async {
+        let client = instance_identity_client()?;
+        interpolate(&client, &api, &vcap).await
+    }
+    .await;
+    match result {
+        Ok(interpolated) => {
+            tracing::debug!("Resolved CredHub references in VCAP_SERVICES via {}", api);
+            *INTERPOLATED.write().unwrap() = Some((vcap, interpolated));
+        }
+        Err(e) => tracing::warn!(
+            "Failed to resolve CredHub references in VCAP_SERVICES via {}: {:#}",
+            api,
+            e
+        ),
+    }
+}
+
+/// An HTTP client presenting the container's instance identity certificate.
+fn instance_identity_client() -> Result<reqwest::Client> {
+    let cert_path = std::env::var("CF_INSTANCE_CERT")
+        .context("CF_INSTANCE_CERT is not set; instance identity is required for CredHub")?;
+    let key_path = std::env::var("CF_INSTANCE_KEY")
+        .context("CF_INSTANCE_KEY is not set; instance identity is required for CredHub")?;
+    let mut pem = std::fs::read(&cert_path).with_context(|| format!("reading {}", cert_path))?;
+    pem.push(b'\n');
+    pem.extend(std::fs::read(&key_path).with_context(|| format!("reading {}", key_path))?);
+
+    let mut builder = reqwest::Client::builder()
+        .identity(reqwest::Identity::from_pem(&pem).context("invalid instance identity")?);
+
+    // CredHub's CA is one of the platform certificates mounted into the container
+    if let Ok(dir) = std::env::var("CF_SYSTEM_CERT_PATH") {
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let Ok(pem) = std::fs::read(entry.path()) else {
+                continue;
+            };
+            if let Ok(cert) = reqwest::Certificate::from_pem(&pem) {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// POST `vcap_json` to CredHub's interpolate endpoint and return the resolved document.
+async fn interpolate(client: &reqwest::Client, api: &str, vcap_json: &str) -> Result<String> {
+    let url = format!("{}/api/v1/interpolate", api.trim_end_matches('/'));
+    let response = client
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(vcap_json.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    let interpolated = response.text().await?;
+    // Make sure the result is still a services document before trusting it
+    serde_json::from_str::<Value>(&interpolated).context("CredHub returned invalid JSON")?;
+    Ok(interpolated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn referenced_vcap() -> Value {
+        json!({
+            "genai": [{
+                "name": "genai-chat",
+                "credentials": {"credhub-ref": "/c/genai/genai-chat/abc/credentials"}
+            }]
+        })
+    }
+
+    #[test]
+    fn test_detects_credhub_references() {
+        assert!(has_references(&referenced_vcap().to_string()));
+
+        let literal = json!({
+            "genai": [{"name": "genai-chat", "credentials": {"endpoint": {"api_key": "k"}}}]
+        });
+        assert!(!has_references(&literal.to_string()));
+        assert!(!has_references("not json"));
+    }
+
+    #[tokio::test]
+    async fn test_interpolate_posts_vcap_services() {
+        let mock_server = MockServer::start().await;
+        let resolved = json!({
+            "genai": [{
+                "name": "genai-chat",
+                "credentials": {
+                    "endpoint": {
+                        "api_base": "https://genai-proxy.sys.example.com/plan",
+                        "api_key": "resolved-jwt"
+                    }
+                }
+            }]
+        });
+        Mock::given(method("POST"))
+            .and(path("/api/v1/interpolate"))
+            .and(body_json(referenced_vcap()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&resolved))
+            .mount(&mock_server)
+            .await;
+
+        let interpolated = interpolate(
+            &reqwest::Client::new(),
+            &mock_server.uri(),
+            &referenced_vcap().to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&interpolated).unwrap(),
+            resolved
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interpolate_reports_credhub_errors() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/interpolate"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+
+        let err = interpolate(&reqwest::Client::new(), &mock_server.uri(), "{}")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("403"));
+    }
+}
@@ -0,0 +1,120 @@
+//! Request logging for debugging proxy issues.
+//!
+//! With `TANZU_AI_DEBUG_HTTP=true`, request bodies and JSON response bodies are logged at
+//! debug level, and every failed request is logged at warn level as an equivalent
+//! `curl` command for escalations to the platform team. The key is replaced with
+//! `$TANZU_AI_API_KEY` wherever it appears, so the command runs as-is once the key is
+//! exported. Bodies carry conversation content, so only enable this while reproducing
+//! an issue.
+
+/// Stands in for the key in logged bodies and `curl` commands.
+pub const KEY_PLACEHOLDER: &str = "$TANZU_AI_API_KEY";
+
+pub fn enabled() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_DEBUG_HTTP")
+        .unwrap_or(false)
+}
+
+/// `text` with every occurrence of `secret` replaced by [`KEY_PLACEHOLDER`].
+pub fn sanitize(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(secret, KEY_PLACEHOLDER)
+    }
+}
+
+pub fn log_request(url: &str, body: Option<&str>, secret: &str) {
+    tracing::debug!(
+        "Tanzu AI request to {}: {}",
+        url,
+        body.map_or_else(|| "<multipart form>".to_string(), |b| sanitize(b, secret))
+    );
+}
+
+pub fn log_response(url: &str, status: u16, body: &str, secret: &str) {
+    tracing::debug!(
+        "Tanzu AI response from {} ({}): {}",
+        url,
+        status,
+        sanitize(body, secret)
+    );
+}
+
+/// Log a `curl` command reproducing a failed request.
+pub fn log_failure(url: &str, headers: &[(&str, &str)], body: Option<&str>, secret: &str) {
+    tracing::warn!(
+        "Tanzu AI request failed; reproduce it with:\n{}",
+        curl_command(url, headers, body, secret)
+    );
+}
+
+/// A `curl` command sending `body` to `url` with `headers`, the key replaced.
+///
+/// The placeholder is left in double quotes so the shell expands it; everything else
+/// is single-quoted.
+pub fn curl_command(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+    secret: &str,
+) -> String {
+    let mut command = format!("curl -sS -X POST {}", quote(url));
+    for (name, value) in headers {
+        let header = format!("{}: {}", name, value);
+        command.push_str(" \\\n  -H ");
+        command.push_str(&quote_with_placeholder(&header, secret));
+    }
+    match body {
+        Some(body) => {
+            command.push_str(" \\\n  -H 'Content-Type: application/json' \\\n  --data-raw ");
+            command.push_str(&quote_with_placeholder(body, secret));
+        }
+        None => command.push_str(" \\\n  # multipart form body omitted"),
+    }
+    command
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Single-quote `text`, switching to double quotes around each occurrence of `secret`
+/// so the placeholder that replaces it expands.
+fn quote_with_placeholder(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        return quote(text);
+    }
+    text.split(secret)
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(&format!("\"{}\"", KEY_PLACEHOLDER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_command_hides_key() {
+        let command = curl_command(
+            "https://genai.example.com/plan/openai/v1/chat/completions",
+            &[
+                ("Authorization", "Bearer sk-secret"),
+                ("X-Idempotency-Key", "abc"),
+            ],
+            Some(r#"{"messages":[{"role":"user","content":"it's sk-secret"}]}"#),
+            "sk-secret",
+        );
+        assert!(!command.contains("sk-secret"));
+        assert_eq!(
+            command,
+            "curl -sS -X POST 'https://genai.example.com/plan/openai/v1/chat/completions' \\\n  \
+             -H 'Authorization: Bearer '\"$TANZU_AI_API_KEY\"'' \\\n  \
+             -H 'X-Idempotency-Key: abc' \\\n  \
+             -H 'Content-Type: application/json' \\\n  \
+             --data-raw '{\"messages\":[{\"role\":\"user\",\"content\":\"it'\\''s '\"$TANZU_AI_API_KEY\"'\"}]}'"
+        );
+    }
+}
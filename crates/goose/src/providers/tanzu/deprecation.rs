@@ -0,0 +1,133 @@
+//! Deprecated models announced by the config endpoint.
+//!
+//! Platform teams retire models by marking them in `advertisedModels`, either with
+//! `"deprecated": true` or with details of the replacement and removal date:
+//!
+//! ```json
+//! {"name": "llama3.1:8b", "deprecated": {"replacement": "llama3.3:70b", "removalDate": "2026-12-01"}}
+//! ```
+//!
+//! A session started on a deprecated model logs a warning naming the replacement, once
+//! per model and process. Model listings carry the deprecation so pickers can badge it,
+//! and automatic selection passes deprecated models over.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+
+static WARNED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Deprecation details for an advertised model; every field is optional.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deprecation {
+    /// Model to switch to
+    #[serde(default, alias = "replacedBy", alias = "replacement_model")]
+    pub replacement: Option<String>,
+    /// When the model stops being served, as given by the endpoint
+    #[serde(default, alias = "removalDate", alias = "sunsetDate")]
+    pub removal_date: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl Deprecation {
+    /// Warning for a session using `model_name`.
+    pub fn warning(&self, model_name: &str) -> String {
+        let mut warning = format!("Model {} is deprecated", model_name);
+        if let Some(date) = &self.removal_date {
+            warning.push_str(&format!(" and will be removed on {}", date));
+        }
+        if let Some(replacement) = &self.replacement {
+            warning.push_str(&format!("; switch to {}", replacement));
+        }
+        if let Some(message) = &self.message {
+            warning.push_str(&format!(" ({})", message));
+        }
+        warning
+    }
+}
+
+/// Read `deprecated` as a bool, a message or a details object.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Deprecation>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Field {
+        Flag(bool),
+        Message(String),
+        Details(Deprecation),
+    }
+
+    Ok(match Option::<Field>::deserialize(deserializer)? {
+        None | Some(Field::Flag(false)) => None,
+        Some(Field::Flag(true)) => Some(Deprecation::default()),
+        Some(Field::Message(message)) => Some(Deprecation {
+            message: Some(message),
+            ..Default::default()
+        }),
+        Some(Field::Details(details)) => Some(details),
+    })
+}
+
+/// Log the deprecation of `model_name` unless it was already logged by this process.
+pub fn warn_once(model_name: &str, deprecation: &Deprecation) {
+    if WARNED.lock().unwrap().insert(model_name.to_string()) {
+        tracing::warn!("{}", deprecation.warning(model_name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Model {
+        #[serde(default, deserialize_with = "deserialize")]
+        deprecated: Option<Deprecation>,
+    }
+
+    fn parse(json: &str) -> Option<Deprecation> {
+        serde_json::from_str::<Model>(json).unwrap().deprecated
+    }
+
+    #[test]
+    fn test_deprecation_forms() {
+        assert_eq!(parse("{}"), None);
+        assert_eq!(parse(r#"{"deprecated": false}"#), None);
+        assert_eq!(
+            parse(r#"{"deprecated": true}"#),
+            Some(Deprecation::default())
+        );
+        assert_eq!(
+            parse(r#"{"deprecated": "Use a larger model"}"#)
+                .unwrap()
+                .message
+                .as_deref(),
+            Some("Use a larger model")
+        );
+        let details =
+            parse(r#"{"deprecated": {"replacedBy": "llama3.3:70b", "removalDate": "2026-12-01"}}"#)
+                .unwrap();
+        assert_eq!(details.replacement.as_deref(), Some("llama3.3:70b"));
+        assert_eq!(details.removal_date.as_deref(), Some("2026-12-01"));
+    }
+
+    #[test]
+    fn test_warning() {
+        let deprecation = Deprecation {
+            replacement: Some("llama3.3:70b".to_string()),
+            removal_date: Some("2026-12-01".to_string()),
+            message: None,
+        };
+        assert_eq!(
+            deprecation.warning("llama3.1:8b"),
+            "Model llama3.1:8b is deprecated and will be removed on 2026-12-01; switch to llama3.3:70b"
+        );
+        assert_eq!(
+            Deprecation::default().warning("llama3.1:8b"),
+            "Model llama3.1:8b is deprecated"
+        );
+    }
+}
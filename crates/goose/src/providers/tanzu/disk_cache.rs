@@ -0,0 +1,313 @@
+//! Discovery results persisted across processes.
+//!
+//! Cloud Foundry tasks that start many short-lived Goose processes otherwise discover
+//! every binding's models on each start. With `TANZU_AI_DISK_CACHE=true` a binding's
+//! endpoint, advertised models and API key expiry are written to a file under
+//! `TANZU_AI_DISK_CACHE_DIR`, which defaults to `/tmp/goose-tanzu-ai` on Cloud Foundry
+//! and `$XDG_CACHE_HOME/goose/tanzu-ai` (`~/.cache/goose/tanzu-ai`) elsewhere.
+//!
+//! An entry is used while it is younger than the discovery TTL, the binding's API key
+//! has not expired, its endpoint matches and its checksum verifies; anything else is a
+//! miss and gets rewritten. Writers hold a lock file and rename a finished file into
+//! place, so concurrent processes never read a partial entry. API keys are not stored.
+
+use super::token::jwt_expiry;
+use super::{AdvertisedModel, TanzuCredentials};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bumped when the entry layout changes, so old files are ignored.
+const FORMAT_VERSION: u32 = 1;
+/// How long a writer waits for another process's lock.
+const LOCK_WAIT: Duration = Duration::from_secs(1);
+/// A lock older than this was left behind by a process that died while writing.
+const STALE_LOCK: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    version: u32,
+    endpoint: String,
+    discovery_url: String,
+    /// Seconds since the Unix epoch
+    stored_at: u64,
+    token_expires_at: Option<u64>,
+    models: Vec<AdvertisedModel>,
+    /// FNV-1a over the entry serialized with an empty checksum
+    #[serde(default)]
+    checksum: String,
+}
+
+impl Entry {
+    fn checksum(&self) -> String {
+        let unsigned = Entry {
+            checksum: String::new(),
+            ..self.clone()
+        };
+        let bytes = serde_json::to_vec(&unsigned).unwrap_or_default();
+        format!("{:016x}", fnv1a(&bytes))
+    }
+}
+
+/// A directory of discovery results.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The configured cache, or `None` unless `TANZU_AI_DISK_CACHE` is enabled.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let enabled: bool = config.get_param("TANZU_AI_DISK_CACHE").unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let dir = config
+            .get_param::<String>("TANZU_AI_DISK_CACHE_DIR")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(default_dir)?;
+        Some(Self::new(dir))
+    }
+
+    /// The models cached for `credentials`, if the entry is still usable.
+    pub fn load(
+        &self,
+        credentials: &TanzuCredentials,
+        ttl: Duration,
+    ) -> Option<Vec<AdvertisedModel>> {
+        let path = self.path(credentials);
+        let contents = std::fs::read(&path).ok()?;
+        let entry: Entry = match serde_json::from_slice(&contents) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::debug!(
+                    "Ignoring unreadable discovery cache {}: {}",
+                    path.display(),
+                    e
+                );
+                return None;
+            }
+        };
+        if entry.checksum != entry.checksum() {
+            tracing::warn!("Ignoring corrupt discovery cache {}", path.display());
+            return None;
+        }
+        let now = unix_secs(SystemTime::now());
+        let usable = entry.version == FORMAT_VERSION
+            && entry.endpoint == credentials.endpoint_base
+            && entry.discovery_url == credentials.discovery_key()
+            && now.saturating_sub(entry.stored_at) < ttl.as_secs()
+            && entry.token_expires_at.is_none_or(|expiry| now < expiry);
+        usable.then_some(entry.models)
+    }
+
+    /// Write the models discovered for `credentials`. Failures are logged, since the
+    /// cache only saves time.
+    pub fn store(&self, credentials: &TanzuCredentials, models: &[AdvertisedModel]) {
+        let mut entry = Entry {
+            version: FORMAT_VERSION,
+            endpoint: credentials.endpoint_base.clone(),
+            discovery_url: credentials.discovery_key(),
+            stored_at: unix_secs(SystemTime::now()),
+            token_expires_at: jwt_expiry(&credentials.api_key).map(unix_secs),
+            models: models.to_vec(),
+            checksum: String::new(),
+        };
+        entry.checksum = entry.checksum();
+        let path = self.path(credentials);
+        if let Err(e) = self.write(&path, &entry) {
+            tracing::debug!("Could not write discovery cache {}: {}", path.display(), e);
+        }
+    }
+
+    fn write(&self, path: &Path, entry: &Entry) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = Lock::acquire(&path.with_extension("lock"))?;
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let mut file = create_private(&tmp)?;
+        file.write_all(&serde_json::to_vec(entry)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+
+    fn path(&self, credentials: &TanzuCredentials) -> PathBuf {
+        let key = fnv1a(credentials.discovery_key().as_bytes());
+        self.dir.join(format!("discovery-{:016x}.json", key))
+    }
+}
+
+/// A lock file held while an entry is written, removed on drop.
+pub(super) struct Lock(PathBuf);
+
+impl Lock {
+    pub(super) fn acquire(path: &Path) -> std::io::Result<Self> {
+        let deadline = std::time::Instant::now() + LOCK_WAIT;
+        loop {
+            match create_private(path) {
+                Ok(_) => return Ok(Self(path.to_path_buf())),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = std::fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > STALE_LOCK);
+                    if stale {
+                        let _ = std::fs::remove_file(path);
+                    } else if std::time::Instant::now() >= deadline {
+                        return Err(e);
+                    } else {
+                        std::thread::sleep(Duration::from_millis(20));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Create a new file readable only by this user.
+pub(super) fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+pub(super) fn default_dir() -> Option<PathBuf> {
+    if std::env::var_os("VCAP_APPLICATION").is_some() {
+        return Some(PathBuf::from("/tmp/goose-tanzu-ai"));
+    }
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("goose").join("tanzu-ai"))
+}
+
+pub(super) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub(super) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tanzu::flavor::UpstreamFlavor;
+    use crate::providers::tanzu::uaa::AuthMethod;
+    use crate::providers::tanzu::wire::WireFormat;
+
+    const TTL: Duration = Duration::from_secs(300);
+
+    fn credentials(api_key: &str) -> TanzuCredentials {
+        TanzuCredentials {
+            endpoint_base: "https://genai.example.com/plan".to_string(),
+            api_key: api_key.to_string(),
+            config_url: Some("https://genai.example.com/plan/config/v1/endpoint".to_string()),
+            config_api_key: None,
+            model_name: None,
+            binding_name: None,
+            binding_guid: None,
+            region: None,
+            model_aliases: Vec::new(),
+            wire_format: WireFormat::OpenAi,
+            auth: AuthMethod::ApiKey,
+            flavor: UpstreamFlavor::Tanzu,
+        }
+    }
+
+    fn models() -> Vec<AdvertisedModel> {
+        serde_json::from_value(serde_json::json!([
+            {"name": "llama3.2:1b", "capabilities": ["CHAT"], "contextLength": 8192},
+            {"name": "llama3.1:8b", "capabilities": ["CHAT"], "deprecated": {"replacement": "llama3.2:1b"}}
+        ]))
+        .unwrap()
+    }
+
+    fn jwt(exp: u64) -> String {
+        use base64::Engine;
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        format!(
+            "{}.{}.sig",
+            engine.encode(r#"{"alg":"HS256"}"#),
+            engine.encode(serde_json::json!({"exp": exp}).to_string())
+        )
+    }
+
+    #[test]
+    fn test_round_trip_and_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().join("cache"));
+        let creds = credentials("opaque-key");
+        assert!(cache.load(&creds, TTL).is_none());
+
+        cache.store(&creds, &models());
+        let loaded = cache.load(&creds, TTL).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].context_length, Some(8192));
+        assert_eq!(
+            loaded[1]
+                .deprecation
+                .as_ref()
+                .unwrap()
+                .replacement
+                .as_deref(),
+            Some("llama3.2:1b")
+        );
+        assert!(cache.load(&creds, Duration::ZERO).is_none());
+
+        // Another endpoint behind the same config URL is a miss
+        let mut moved = creds.clone();
+        moved.endpoint_base = "https://genai2.example.com/plan".to_string();
+        assert!(cache.load(&moved, TTL).is_none());
+
+        // Expired API key
+        let expired = credentials(&jwt(1));
+        cache.store(&expired, &models());
+        assert!(cache.load(&expired, TTL).is_none());
+    }
+
+    #[test]
+    fn test_rejects_tampered_entry_and_waits_for_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf());
+        let creds = credentials("opaque-key");
+        cache.store(&creds, &models());
+
+        let path = cache.path(&creds);
+        let tampered = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace("llama3.2:1b", "evil-model");
+        std::fs::write(&path, tampered).unwrap();
+        assert!(cache.load(&creds, TTL).is_none());
+
+        // A held lock keeps the entry from being rewritten
+        let lock = Lock::acquire(&path.with_extension("lock")).unwrap();
+        cache.store(&creds, &models());
+        assert!(cache.load(&creds, TTL).is_none());
+        drop(lock);
+        cache.store(&creds, &models());
+        assert!(cache.load(&creds, TTL).is_some());
+    }
+}
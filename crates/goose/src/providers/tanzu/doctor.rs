@@ -0,0 +1,695 @@
+//! Step-by-step diagnostics for a Tanzu AI Services setup.
+//!
+//! Preflight stops at the first problem and only says which one it was. Support cases
+//! need the whole picture: which variables are set, what the bindings resolve to, and
+//! how far a request gets on each binding (DNS, TLS, token, discovery, a one-token
+//! completion). Every check runs and lands in a [`DoctorReport`] that prints as a
+//! pass/fail list.
+
+use super::token::jwt_expiry;
+use super::{
+    config_auth_message, resolve_credentials, trace, ConfigResponse, TanzuBinding,
+    TanzuCredentials, TANZU_DISCOVERY_TIMEOUT,
+};
+use crate::conversation::message::Message;
+use crate::model::ModelConfig;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A token closer than this to its expiry is reported as a warning.
+const EXPIRY_WARNING: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    /// Not run, because it does not apply or an earlier check failed
+    Skip,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+            Self::Skip => "SKIP",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoctorCheck {
+    /// The binding checked, or `None` for checks of the overall setup
+    pub binding: Option<String>,
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Every check that ran, in order.
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    /// Whether no check failed; warnings and skipped checks don't count.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+
+    fn push(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(DoctorCheck {
+            binding: None,
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Tanzu AI Services diagnostics")?;
+        let mut binding = None;
+        for check in &self.checks {
+            if check.binding.is_some() && check.binding != binding {
+                binding = check.binding.clone();
+                writeln!(f, "Binding {}", binding.as_deref().unwrap_or_default())?;
+            }
+            let indent = if check.binding.is_some() { "  " } else { "" };
+            writeln!(
+                f,
+                "{}[{}] {}: {}",
+                indent, check.status, check.name, check.detail
+            )?;
+        }
+        write!(
+            f,
+            "{} passed, {} warnings, {} failed, {} skipped",
+            self.count(CheckStatus::Pass),
+            self.count(CheckStatus::Warn),
+            self.count(CheckStatus::Fail),
+            self.count(CheckStatus::Skip)
+        )
+    }
+}
+
+/// Checks for one binding, labelled with its name or endpoint.
+struct BindingChecks<'a> {
+    label: String,
+    report: &'a mut DoctorReport,
+}
+
+impl BindingChecks<'_> {
+    fn push(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.report.checks.push(DoctorCheck {
+            binding: Some(self.label.clone()),
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+}
+
+/// Run every check against the configured bindings.
+///
+/// Never fails: problems are reported as failed checks.
+pub async fn run() -> DoctorReport {
+    let mut report = DoctorReport::default();
+    check_environment(&mut report);
+
+    let bindings = match resolve_credentials().await {
+        Ok(bindings) => {
+            let names: Vec<String> = bindings.iter().map(label).collect();
+            report.push(
+                "Credentials",
+                CheckStatus::Pass,
+                format!("{} binding(s): {}", bindings.len(), names.join(", ")),
+            );
+            bindings
+        }
+        Err(e) => {
+            report.push("Credentials", CheckStatus::Fail, e.to_string());
+            return report;
+        }
+    };
+
+    let settings = super::ClientSettings::from_config();
+    let http = match super::shared_http_client(&settings) {
+        Ok(http) => http,
+        Err(e) => {
+            report.push(
+                "HTTP client",
+                CheckStatus::Fail,
+                format!("{:#}; check the TLS and proxy settings", e),
+            );
+            return report;
+        }
+    };
+
+    for creds in bindings {
+        diagnose_binding(&mut report, creds, &http).await;
+    }
+    report
+}
+
+/// Report which credential sources are set.
+fn check_environment(report: &mut DoctorReport) {
+    let config = crate::config::Config::global();
+    let mut set = Vec::new();
+    let profile = super::profile::active().map(|name| format!("TANZU_AI_PROFILE ({})", name));
+    if let Some(profile) = &profile {
+        set.push(profile.as_str());
+    }
+    if config.get_param::<String>("TANZU_AI_ENDPOINT").is_ok() {
+        set.push("TANZU_AI_ENDPOINT");
+    }
+    if config.get_secret::<String>("TANZU_AI_API_KEY").is_ok() {
+        set.push("TANZU_AI_API_KEY");
+    }
+    if super::UaaClientConfig::from_config().is_some() {
+        set.push("UAA client credentials");
+    }
+    if config
+        .get_param::<String>("TANZU_AI_SERVICE_KEY_FILE")
+        .is_ok()
+    {
+        set.push("TANZU_AI_SERVICE_KEY_FILE");
+    }
+    if std::env::var("VCAP_SERVICES").is_ok() {
+        set.push("VCAP_SERVICES");
+    }
+    if super::service_bindings::root().is_dir() {
+        set.push("file-based service bindings");
+    }
+
+    if set.is_empty() {
+        report.push(
+            "Environment",
+            CheckStatus::Fail,
+            "none of TANZU_AI_ENDPOINT, TANZU_AI_SERVICE_KEY_FILE or VCAP_SERVICES is set",
+        );
+    } else {
+        report.push(
+            "Environment",
+            CheckStatus::Pass,
+            format!("{} set", set.join(", ")),
+        );
+    }
+    if let Some(faults) = super::fault::Faults::from_config() {
+        report.push(
+            "Fault injection",
+            CheckStatus::Warn,
+            format!(
+                "TANZU_AI_FAULT_PROFILE disrupts {:.0}% of requests on purpose ({:?}); unset it outside resilience tests",
+                faults.rate * 100.0,
+                faults.profile
+            ),
+        );
+    }
+}
+
+fn label(creds: &TanzuCredentials) -> String {
+    creds
+        .binding_name
+        .clone()
+        .unwrap_or_else(|| creds.endpoint_base.clone())
+}
+
+/// Walk one binding from name resolution to a test completion.
+async fn diagnose_binding(
+    report: &mut DoctorReport,
+    creds: TanzuCredentials,
+    http: &reqwest::Client,
+) {
+    let mut checks = BindingChecks {
+        label: label(&creds),
+        report,
+    };
+    let skip_rest = |checks: &mut BindingChecks, from: usize, reason: &str| {
+        for name in &BINDING_CHECKS[from..] {
+            checks.push(name, CheckStatus::Skip, reason);
+        }
+    };
+
+    // DNS
+    let url = match reqwest::Url::parse(&creds.endpoint_base) {
+        Ok(url) => url,
+        Err(e) => {
+            checks.push(
+                "DNS",
+                CheckStatus::Fail,
+                format!("{} is not a valid URL: {}", creds.endpoint_base, e),
+            );
+            skip_rest(&mut checks, 1, "the endpoint is not a valid URL");
+            return;
+        }
+    };
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    match tokio::net::lookup_host((host.as_str(), port)).await {
+        Ok(addrs) => {
+            let addrs: Vec<String> = addrs.map(|a| a.ip().to_string()).collect();
+            checks.push(
+                "DNS",
+                CheckStatus::Pass,
+                format!("{} resolved to {}", host, addrs.join(", ")),
+            );
+        }
+        Err(e) => {
+            checks.push(
+                "DNS",
+                CheckStatus::Fail,
+                format!("{} did not resolve: {}", host, e),
+            );
+            skip_rest(&mut checks, 1, "the endpoint did not resolve");
+            return;
+        }
+    }
+
+    // TLS
+    if url.scheme() == "https" {
+        match http
+            .get(url.as_str())
+            .timeout(TANZU_DISCOVERY_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(_) => checks.push(
+                "TLS",
+                CheckStatus::Pass,
+                format!("handshake with {} succeeded", host),
+            ),
+            Err(e) => {
+                checks.push("TLS", CheckStatus::Fail, error_chain(&e));
+                skip_rest(&mut checks, 2, "no connection to the endpoint");
+                return;
+            }
+        }
+    } else {
+        checks.push("TLS", CheckStatus::Skip, "the endpoint uses plain HTTP");
+    }
+
+    // Token
+    let binding = TanzuBinding::build(creds, http.clone());
+    let token = match binding.transport.bearer_token().await {
+        Ok(token) => token,
+        Err(e) => {
+            checks.push("Token", CheckStatus::Fail, e.to_string());
+            skip_rest(&mut checks, 3, "no bearer token");
+            return;
+        }
+    };
+    let (status, detail) = token_status(&token, SystemTime::now());
+    checks.push("Token", status, detail);
+
+    // Models endpoint, which also shows whether the token is accepted
+    let served = match super::preflight::check_endpoint(
+        http,
+        &binding.credentials.endpoint_base,
+        &token,
+        &binding.credentials.flavor,
+    )
+    .await
+    {
+        Ok(served) => {
+            checks.push(
+                "Models endpoint",
+                CheckStatus::Pass,
+                format!("accepted the token, serves {} model(s)", served.len()),
+            );
+            served
+        }
+        Err(e) => {
+            checks.push("Models endpoint", CheckStatus::Fail, e.to_string());
+            Vec::new()
+        }
+    };
+
+    // Config URL
+    match &binding.credentials.config_url {
+        Some(config_url) => {
+            let (status, detail) =
+                check_config_url(http, config_url, &token, &binding.credentials).await;
+            checks.push("Config URL", status, detail);
+        }
+        None => checks.push(
+            "Config URL",
+            CheckStatus::Skip,
+            "the binding has no config_url",
+        ),
+    }
+
+    // Test completion
+    let model = binding
+        .credentials
+        .model_name
+        .clone()
+        .or_else(|| served.first().cloned());
+    match model {
+        Some(model) => {
+            let (status, detail) = test_completion(&binding, &model).await;
+            checks.push("Completion", status, detail);
+        }
+        None => checks.push("Completion", CheckStatus::Skip, "no model to test with"),
+    }
+}
+
+/// Per-binding checks in the order they run, for skipping the rest after a failure.
+const BINDING_CHECKS: [&str; 6] = [
+    "DNS",
+    "TLS",
+    "Token",
+    "Models endpoint",
+    "Config URL",
+    "Completion",
+];
+
+/// Describe when a bearer token expires.
+fn token_status(token: &str, now: SystemTime) -> (CheckStatus, String) {
+    let Some(expiry) = jwt_expiry(token) else {
+        return (
+            CheckStatus::Pass,
+            "opaque API key without an expiry claim".to_string(),
+        );
+    };
+    match expiry.duration_since(now) {
+        Err(e) => (
+            CheckStatus::Fail,
+            format!(
+                "expired {} ago; rebind the service or refresh the key",
+                format_duration(e.duration())
+            ),
+        ),
+        Ok(left) if left < EXPIRY_WARNING => (
+            CheckStatus::Warn,
+            format!("expires in {}", format_duration(left)),
+        ),
+        Ok(left) => (
+            CheckStatus::Pass,
+            format!("valid for another {}", format_duration(left)),
+        ),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+async fn check_config_url(
+    http: &reqwest::Client,
+    config_url: &str,
+    token: &str,
+    credentials: &TanzuCredentials,
+) -> (CheckStatus, String) {
+    let key = credentials.config_api_key.as_deref().unwrap_or(token);
+    let response = trace::inject(http.get(config_url))
+        .bearer_auth(key)
+        .timeout(TANZU_DISCOVERY_TIMEOUT)
+        .send()
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return (CheckStatus::Fail, error_chain(&e)),
+    };
+    let status = response.status();
+    if matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    ) {
+        return (
+            CheckStatus::Fail,
+            config_auth_message(config_url, status, credentials.config_api_key.is_some()),
+        );
+    }
+    if !status.is_success() {
+        return (
+            CheckStatus::Fail,
+            format!("{} returned {}", config_url, status),
+        );
+    }
+    match response.json::<ConfigResponse>().await {
+        Ok(config) if config.advertised_models.is_empty() => (
+            CheckStatus::Warn,
+            "reachable but advertises no models; discovery falls back to the models endpoint"
+                .to_string(),
+        ),
+        Ok(config) => (
+            CheckStatus::Pass,
+            format!("advertises {} model(s)", config.advertised_models.len()),
+        ),
+        Err(e) => (
+            CheckStatus::Fail,
+            format!("{} did not return a config document: {}", config_url, e),
+        ),
+    }
+}
+
+/// Ask `model` for a single token.
+async fn test_completion(binding: &TanzuBinding, model: &str) -> (CheckStatus, String) {
+    let format = binding.credentials.wire_format;
+    let model_config = ModelConfig::new_or_fail(model).with_max_tokens(Some(1));
+    let messages = [Message::user().with_text("Reply with OK.")];
+    let payload = match format.create_request(&model_config, "", &messages, &[], false) {
+        Ok(payload) => payload,
+        Err(e) => return (CheckStatus::Fail, e.to_string()),
+    };
+
+    let started = Instant::now();
+    match binding
+        .transport
+        .post_with_headers(format.chat_path(), format.headers(), &payload)
+        .await
+    {
+        Ok(_) => (
+            CheckStatus::Pass,
+            format!("{} answered in {} ms", model, started.elapsed().as_millis()),
+        ),
+        Err(e) => (CheckStatus::Fail, format!("{}: {}", model, e)),
+    }
+}
+
+/// A request error with its causes, which carry the TLS or connection details.
+fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tanzu::flavor::UpstreamFlavor;
+    use crate::providers::tanzu::uaa::AuthMethod;
+    use crate::providers::tanzu::wire::WireFormat;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn credentials(endpoint_base: String, config_url: Option<String>) -> TanzuCredentials {
+        TanzuCredentials {
+            endpoint_base,
+            api_key: "test-key".to_string(),
+            config_url,
+            config_api_key: None,
+            model_name: None,
+            binding_name: Some("genai-dev".to_string()),
+            binding_guid: None,
+            region: None,
+            model_aliases: Vec::new(),
+            wire_format: WireFormat::OpenAi,
+            auth: AuthMethod::ApiKey,
+            flavor: UpstreamFlavor::Tanzu,
+        }
+    }
+
+    #[test]
+    fn test_token_status() {
+        use base64::Engine;
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let jwt = |exp: u64| {
+            format!(
+                "{}.{}.sig",
+                engine.encode(r#"{"alg":"HS256"}"#),
+                engine.encode(format!(r#"{{"exp":{}}}"#, exp))
+            )
+        };
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        assert_eq!(token_status("opaque", now).0, CheckStatus::Pass);
+        assert_eq!(
+            token_status(&jwt(1_000_000 - 7200), now),
+            (
+                CheckStatus::Fail,
+                "expired 2h 0m ago; rebind the service or refresh the key".to_string()
+            )
+        );
+        assert_eq!(
+            token_status(&jwt(1_000_000 + 90), now),
+            (CheckStatus::Warn, "expires in 1m".to_string())
+        );
+        assert_eq!(
+            token_status(&jwt(1_000_000 + 3 * 86400), now).0,
+            CheckStatus::Pass
+        );
+    }
+
+    #[test]
+    fn test_report_display() {
+        let mut report = DoctorReport::default();
+        report.push("Environment", CheckStatus::Pass, "VCAP_SERVICES set");
+        let mut checks = BindingChecks {
+            label: "genai-dev".to_string(),
+            report: &mut report,
+        };
+        checks.push(
+            "DNS",
+            CheckStatus::Fail,
+            "genai.example.com did not resolve",
+        );
+        checks.push("TLS", CheckStatus::Skip, "the endpoint did not resolve");
+
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "Tanzu AI Services diagnostics\n\
+             [PASS] Environment: VCAP_SERVICES set\n\
+             Binding genai-dev\n  \
+             [FAIL] DNS: genai.example.com did not resolve\n  \
+             [SKIP] TLS: the endpoint did not resolve\n\
+             1 passed, 0 warnings, 1 failed, 1 skipped"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_healthy_binding() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": "llama3.2:1b"}]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [{"name": "llama3.2:1b", "capabilities": ["CHAT"]}]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "llama3.2:1b",
+                "max_tokens": 1
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": "OK"}}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let creds = credentials(
+            format!("{}/plan", mock_server.uri()),
+            Some(format!("{}/plan/config/v1/endpoint", mock_server.uri())),
+        );
+        let mut report = DoctorReport::default();
+        diagnose_binding(&mut report, creds, &reqwest::Client::new()).await;
+
+        let statuses: Vec<(&str, CheckStatus)> =
+            report.checks.iter().map(|c| (c.name, c.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("DNS", CheckStatus::Pass),
+                ("TLS", CheckStatus::Skip),
+                ("Token", CheckStatus::Pass),
+                ("Models endpoint", CheckStatus::Pass),
+                ("Config URL", CheckStatus::Pass),
+                ("Completion", CheckStatus::Pass),
+            ]
+        );
+        assert!(report.passed());
+        assert_eq!(report.checks[4].detail, "advertises 1 model(s)");
+    }
+
+    #[tokio::test]
+    async fn test_config_url_auth_failure() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/plan/config/v1/endpoint"))
+            .and(header("Authorization", "Bearer config-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "advertisedModels": [{"name": "llama3.2:1b", "capabilities": ["CHAT"]}]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/plan/config/v1/endpoint"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let config_url = format!("{}/plan/config/v1/endpoint", mock_server.uri());
+        let mut creds = credentials(
+            format!("{}/plan", mock_server.uri()),
+            Some(config_url.clone()),
+        );
+        let http = reqwest::Client::new();
+        let (status, detail) = check_config_url(&http, &config_url, "test-key", &creds).await;
+        assert_eq!(status, CheckStatus::Fail);
+        assert!(detail.contains("rejected the inference API key (401 Unauthorized)"));
+        assert!(detail.contains("set TANZU_AI_CONFIG_API_KEY"));
+
+        creds.config_api_key = Some("config-key".to_string());
+        let (status, _) = check_config_url(&http, &config_url, "test-key", &creds).await;
+        assert_eq!(status, CheckStatus::Pass);
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_rejected_key() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+
+        let mut creds = credentials(format!("{}/plan", mock_server.uri()), None);
+        creds.model_name = Some("llama3.2:1b".to_string());
+        let mut report = DoctorReport::default();
+        diagnose_binding(&mut report, creds, &reqwest::Client::new()).await;
+
+        assert!(!report.passed());
+        let check = |name: &str| report.checks.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(check("Models endpoint").status, CheckStatus::Fail);
+        assert!(check("Models endpoint")
+            .detail
+            .contains("rejected the API key"));
+        assert_eq!(check("Config URL").status, CheckStatus::Skip);
+        assert_eq!(check("Completion").status, CheckStatus::Fail);
+    }
+}
@@ -0,0 +1,158 @@
+//! Strict egress: no requests beyond the bound GenAI endpoints.
+//!
+//! With `TANZU_AI_STRICT_EGRESS=true` the provider only talks to its bindings' endpoint
+//! bases. A binding whose config URL or UAA token URL lies elsewhere is rejected when the
+//! client is built, redirects that leave an endpoint base fail instead of being
+//! followed, and the provider refuses to start while Goose is configured to send
+//! anything to another provider (lead, planner or subagent providers, or the Ollama
+//! tool shim) or to export usage to a webhook. CredHub, which resolves the binding
+//! itself before any of this, is the only other platform service contacted.
+
+use super::uaa::AuthMethod;
+use super::TanzuCredentials;
+use super::TANZU_PROVIDER_NAME;
+use anyhow::{bail, Result};
+use reqwest::Url;
+
+/// Goose settings that select a provider for some of its traffic.
+const PROVIDER_KEYS: [&str; 4] = [
+    "GOOSE_PROVIDER",
+    "GOOSE_LEAD_PROVIDER",
+    "GOOSE_PLANNER_PROVIDER",
+    "GOOSE_SUBAGENT_PROVIDER",
+];
+
+pub fn strict() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_STRICT_EGRESS")
+        .unwrap_or(false)
+}
+
+/// The URLs requests may go to: everything under one of the endpoint bases.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct EgressPolicy {
+    bases: Vec<String>,
+}
+
+impl EgressPolicy {
+    /// The policy for these bindings when strict egress is on, after checking that
+    /// every URL they name is covered by it.
+    pub fn for_credentials(credentials: &[TanzuCredentials]) -> Result<Option<Self>> {
+        if !strict() {
+            return Ok(None);
+        }
+        let policy = Self::new(credentials.iter().map(|c| c.endpoint_base.as_str()));
+        for creds in credentials {
+            let token_url = match &creds.auth {
+                AuthMethod::ClientCredentials(client) => Some(&client.token_url),
+                AuthMethod::ApiKey => None,
+            };
+            for (what, url) in [
+                ("config URL", creds.config_url.as_ref()),
+                ("UAA token URL", token_url),
+            ] {
+                if let Some(url) = url.filter(|url| !policy.allows_str(url)) {
+                    bail!(
+                        "TANZU_AI_STRICT_EGRESS: the {} {} is outside the binding's endpoint {}",
+                        what,
+                        url,
+                        creds.endpoint_base
+                    );
+                }
+            }
+        }
+        Ok(Some(policy))
+    }
+
+    pub fn new<'a>(bases: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            bases: bases
+                .into_iter()
+                .map(|base| base.trim_end_matches('/').to_string())
+                .collect(),
+        }
+    }
+
+    /// Also allow `base`, an endpoint configured outside the bindings.
+    pub fn with_base(mut self, base: &str) -> Self {
+        self.bases.push(base.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Whether `url` is an endpoint base or lies below one.
+    pub fn allows(&self, url: &Url) -> bool {
+        self.bases.iter().any(|base| {
+            let Ok(base) = Url::parse(base) else {
+                return false;
+            };
+            let prefix = base.path().trim_end_matches('/');
+            base.scheme() == url.scheme()
+                && base.host_str() == url.host_str()
+                && base.port_or_known_default() == url.port_or_known_default()
+                && url
+                    .path()
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    fn allows_str(&self, url: &str) -> bool {
+        Url::parse(url).is_ok_and(|url| self.allows(&url))
+    }
+}
+
+/// Refuse to run under strict egress while Goose routes traffic to another provider.
+pub fn check_other_providers() -> Result<()> {
+    if !strict() {
+        return Ok(());
+    }
+    let config = crate::config::Config::global();
+    for key in PROVIDER_KEYS {
+        if let Ok(provider) = config.get_param::<String>(key) {
+            if provider != TANZU_PROVIDER_NAME {
+                bail!(
+                    "TANZU_AI_STRICT_EGRESS is set but {} selects the {} provider",
+                    key,
+                    provider
+                );
+            }
+        }
+    }
+    if config.get_param::<bool>("GOOSE_TOOLSHIM").unwrap_or(false) {
+        bail!("TANZU_AI_STRICT_EGRESS is set but GOOSE_TOOLSHIM sends tool calls to Ollama");
+    }
+    if let Ok(webhook) = config.get_param::<String>("TANZU_AI_USAGE_WEBHOOK") {
+        bail!(
+            "TANZU_AI_STRICT_EGRESS is set but TANZU_AI_USAGE_WEBHOOK sends usage to {}",
+            webhook
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_only_urls_under_endpoint_bases() {
+        let policy = EgressPolicy::new(["https://genai.sys.example.com/plan-a/"]);
+        for url in [
+            "https://genai.sys.example.com/plan-a",
+            "https://genai.sys.example.com/plan-a/openai/v1/chat/completions",
+            "https://genai.sys.example.com:443/plan-a/config/v1/endpoint",
+        ] {
+            assert!(policy.allows_str(url), "{} blocked", url);
+        }
+        for url in [
+            "https://genai.sys.example.com/plan-ab/openai/v1/models",
+            "https://genai.sys.example.com/plan-b/openai/v1/models",
+            "http://genai.sys.example.com/plan-a/openai/v1/models",
+            "https://genai.sys.example.com:8443/plan-a/openai/v1/models",
+            "https://uaa.sys.example.com/oauth/token",
+            "not a url",
+        ] {
+            assert!(!policy.allows_str(url), "{} allowed", url);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Embeddings via the OpenAI-compatible `/openai/v1/embeddings` endpoint.
+
+use super::transport::Transport;
+use crate::providers::errors::ProviderError;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Request embeddings for `texts` from a binding's endpoint.
+///
+/// Results are returned in the same order as the input texts.
+pub async fn create_embeddings(
+    transport: &Transport,
+    model: &str,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>, ProviderError> {
+    let payload = json!({
+        "model": model,
+        "input": texts,
+    });
+
+    let response = transport.post("openai/v1/embeddings", &payload).await?;
+    let mut parsed: EmbeddingResponse = serde_json::from_value(response)
+        .map_err(|e| ProviderError::RequestFailed(format!("Invalid embeddings response: {}", e)))?;
+    parsed.data.sort_by_key(|d| d.index);
+    Ok(parsed.data.into_iter().map(|d| d.embedding).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tanzu::token::TokenManager;
+    use std::sync::Arc;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_transport(endpoint_base: &str, api_key: &str) -> Transport {
+        let tokens = TokenManager::new(api_key.to_string(), || async {
+            anyhow::bail!("no refresh")
+        });
+        Transport::new(reqwest::Client::new(), endpoint_base, Arc::new(tokens))
+    }
+
+    #[tokio::test]
+    async fn test_create_embeddings_orders_by_index() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/embeddings"))
+            .and(header("Authorization", "Bearer test-jwt-token"))
+            .and(body_partial_json(json!({"model": "mxbai-embed-large"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [
+                    {"object": "embedding", "index": 1, "embedding": [0.3, 0.4]},
+                    {"object": "embedding", "index": 0, "embedding": [0.1, 0.2]}
+                ],
+                "model": "mxbai-embed-large"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let embeddings = create_embeddings(
+            &test_transport(&format!("{}/plan", mock_server.uri()), "test-jwt-token"),
+            "mxbai-embed-large",
+            vec!["first".to_string(), "second".to_string()],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(embeddings, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+    }
+
+    #[tokio::test]
+    async fn test_create_embeddings_auth_error() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": {"message": "Invalid or expired JWT token"}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let result = create_embeddings(
+            &test_transport(&format!("{}/plan", mock_server.uri()), "bad-token"),
+            "mxbai-embed-large",
+            vec!["text".to_string()],
+        )
+        .await;
+
+        assert!(matches!(result, Err(ProviderError::Authentication(_))));
+    }
+}
@@ -0,0 +1,130 @@
+//! Endpoint URL layouts.
+//!
+//! Most foundations expose each plan as a path on the shared proxy host
+//! (`https://genai-proxy.sys.example.com/<plan>`), while others give every plan its own
+//! subdomain (`https://<plan-guid>.genai.sys.example.com`). Bindings and users may also
+//! include the OpenAI suffix (`/openai` or `/openai/v1`). Endpoint bases are normalised
+//! here so API paths are appended the same way for both layouts.
+
+use std::fmt;
+
+/// How the plan is identified in an endpoint URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointLayout {
+    /// `https://genai-proxy.sys.example.com/<plan>`
+    Path,
+    /// `https://<plan>.genai.sys.example.com`
+    Subdomain,
+}
+
+impl fmt::Display for EndpointLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path => write!(f, "path-style"),
+            Self::Subdomain => write!(f, "subdomain-style"),
+        }
+    }
+}
+
+/// A binding's endpoint base with its layout detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// Base URL that API paths such as `openai/v1/chat/completions` are appended to
+    pub base: String,
+    pub layout: EndpointLayout,
+    /// Plan identifier: the last path segment or the first host label
+    pub plan: Option<String>,
+}
+
+impl Endpoint {
+    /// Parse an `api_base`, dropping any trailing `/openai` or `/openai/v1`.
+    pub fn parse(api_base: &str) -> Self {
+        let trimmed = api_base.trim().trim_end_matches('/');
+        let Ok(url) = reqwest::Url::parse(trimmed) else {
+            // Not a URL we can inspect; keep the old suffix handling
+            return Self {
+                base: trimmed.trim_end_matches("/openai").to_string(),
+                layout: EndpointLayout::Path,
+                plan: None,
+            };
+        };
+
+        let mut segments: Vec<&str> = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        if segments.last() == Some(&"v1") && segments.len() >= 2 {
+            if segments[segments.len() - 2] == "openai" {
+                segments.truncate(segments.len() - 2);
+            }
+        } else if segments.last() == Some(&"openai") {
+            segments.pop();
+        }
+
+        let origin = url.origin().ascii_serialization();
+        if segments.is_empty() {
+            let plan = url
+                .host_str()
+                .and_then(|host| host.split('.').next())
+                .filter(|label| !label.is_empty() && url.domain().is_some())
+                .map(String::from);
+            Self {
+                base: origin,
+                layout: EndpointLayout::Subdomain,
+                plan,
+            }
+        } else {
+            Self {
+                base: format!("{}/{}", origin, segments.join("/")),
+                layout: EndpointLayout::Path,
+                plan: segments.last().map(|s| s.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_style() {
+        for api_base in [
+            "https://genai-proxy.sys.example.com/plan-a",
+            "https://genai-proxy.sys.example.com/plan-a/",
+            "https://genai-proxy.sys.example.com/plan-a/openai",
+            "https://genai-proxy.sys.example.com/plan-a/openai/v1/",
+        ] {
+            let endpoint = Endpoint::parse(api_base);
+            assert_eq!(endpoint.base, "https://genai-proxy.sys.example.com/plan-a");
+            assert_eq!(endpoint.layout, EndpointLayout::Path);
+            assert_eq!(endpoint.plan.as_deref(), Some("plan-a"));
+        }
+    }
+
+    #[test]
+    fn test_subdomain_style() {
+        for api_base in [
+            "https://3f9a2c.genai.sys.example.com",
+            "https://3f9a2c.genai.sys.example.com/",
+            "https://3f9a2c.genai.sys.example.com/openai",
+            "https://3f9a2c.genai.sys.example.com/openai/v1",
+        ] {
+            let endpoint = Endpoint::parse(api_base);
+            assert_eq!(endpoint.base, "https://3f9a2c.genai.sys.example.com");
+            assert_eq!(endpoint.layout, EndpointLayout::Subdomain);
+            assert_eq!(endpoint.plan.as_deref(), Some("3f9a2c"));
+        }
+    }
+
+    #[test]
+    fn test_keeps_port_and_ignores_ip_hosts() {
+        let endpoint = Endpoint::parse("http://127.0.0.1:8080/openai");
+        assert_eq!(endpoint.base, "http://127.0.0.1:8080");
+        assert_eq!(endpoint.layout, EndpointLayout::Subdomain);
+        assert_eq!(endpoint.plan, None);
+
+        let endpoint = Endpoint::parse("http://127.0.0.1:8080/plan");
+        assert_eq!(endpoint.base, "http://127.0.0.1:8080/plan");
+    }
+}
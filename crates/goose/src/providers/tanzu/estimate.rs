@@ -0,0 +1,140 @@
+//! Client-side usage estimation for streams without a `usage` chunk.
+//!
+//! Some model deployments behind the GenAI proxy ignore `stream_options.include_usage`.
+//! When a stream ends without reporting usage, prompt and completion tokens are counted
+//! for the model's tokenizer family so session usage does not silently read zero.
+
+use super::metrics;
+use super::tokens::TokenizerFamily;
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::base::{MessageStream, ProviderUsage, Usage};
+use async_stream::try_stream;
+use futures::StreamExt;
+use serde_json::Value;
+
+/// Append an estimated usage chunk to `stream` if it finishes without one.
+pub fn fill_missing_usage(stream: MessageStream, payload: Value, model: String) -> MessageStream {
+    Box::pin(try_stream! {
+        let mut stream = stream;
+        let mut saw_usage = false;
+        let mut completion = String::new();
+
+        while let Some(item) = stream.next().await {
+            let (message, usage) = item?;
+            saw_usage |= usage.is_some();
+            if let Some(message) = &message {
+                append_output_text(&mut completion, message);
+            }
+            yield (message, usage);
+        }
+
+        if !saw_usage {
+            let usage = estimate_usage(&payload, &completion, &model).await;
+            tracing::debug!(
+                "{} stream reported no usage; estimated {:?} prompt and {:?} completion tokens",
+                model,
+                usage.input_tokens,
+                usage.output_tokens
+            );
+            metrics::record_usage(&model, &usage);
+            yield (None, Some(ProviderUsage::new(model.clone(), usage)));
+        }
+    })
+}
+
+/// Count prompt tokens over the request's system prompt, messages and tools, and
+/// completion tokens over the streamed output.
+async fn estimate_usage(payload: &Value, completion: &str, model: &str) -> Usage {
+    let family = TokenizerFamily::for_model(model);
+    let prompt: String = ["system", "messages", "tools"]
+        .iter()
+        .filter_map(|key| payload.get(*key))
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let input = family.count(&prompt).await as i32;
+    let output = family.count(completion).await as i32;
+    Usage::new(Some(input), Some(output), Some(input + output))
+}
+
+/// Collect the text a model generated: message text and tool call arguments.
+fn append_output_text(buffer: &mut String, message: &Message) {
+    for content in &message.content {
+        match content {
+            MessageContent::Text(text) => buffer.push_str(&text.text),
+            MessageContent::ToolRequest(request) => {
+                if let Ok(call) = &request.tool_call {
+                    buffer.push_str(&call.name);
+                    if let Some(arguments) = &call.arguments {
+                        buffer.push_str(&Value::Object(arguments.clone()).to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::errors::ProviderError;
+    use serde_json::json;
+
+    fn chunks(items: Vec<(Option<Message>, Option<ProviderUsage>)>) -> MessageStream {
+        Box::pin(futures::stream::iter(
+            items.into_iter().map(Ok::<_, ProviderError>),
+        ))
+    }
+
+    async fn collect(stream: MessageStream) -> Vec<(Option<Message>, Option<ProviderUsage>)> {
+        stream.map(|item| item.unwrap()).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_estimates_when_usage_missing() {
+        let payload = json!({
+            "messages": [{"role": "user", "content": "What is Tanzu?"}]
+        });
+        let stream = chunks(vec![
+            (Some(Message::assistant().with_text("Tanzu is")), None),
+            (Some(Message::assistant().with_text(" a platform")), None),
+        ]);
+
+        let items = collect(fill_missing_usage(
+            stream,
+            payload,
+            "llama3.2:1b".to_string(),
+        ))
+        .await;
+
+        assert_eq!(items.len(), 3);
+        let usage = items[2].1.as_ref().unwrap();
+        assert_eq!(usage.model, "llama3.2:1b");
+        assert!(usage.usage.input_tokens.unwrap() > 0);
+        assert!(usage.usage.output_tokens.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_reported_usage_is_kept() {
+        let reported = ProviderUsage::new(
+            "llama3.2:1b".to_string(),
+            Usage::new(Some(3), Some(2), Some(5)),
+        );
+        let stream = chunks(vec![(
+            Some(Message::assistant().with_text("Hi")),
+            Some(reported),
+        )]);
+
+        let items = collect(fill_missing_usage(
+            stream,
+            json!({}),
+            "llama3.2:1b".to_string(),
+        ))
+        .await;
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].1.as_ref().unwrap().usage.total_tokens, Some(5));
+    }
+}
@@ -0,0 +1,48 @@
+//! Conditional GETs for discovery documents.
+//!
+//! Model pickers list models far more often than plans change. When the config URL or
+//! the models endpoint returns an `ETag`, the document is kept with it and later requests
+//! send `If-None-Match`; a `304 Not Modified` answer reuses the kept document instead of
+//! transferring and parsing it again.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Discovery documents by URL, with the entity tag they were served with.
+#[derive(Debug, Default)]
+pub struct ETagCache {
+    entries: Mutex<HashMap<String, (String, Value)>>,
+}
+
+impl ETagCache {
+    /// The entity tag to send as `If-None-Match` for `url`, if one is known.
+    pub fn etag(&self, url: &str) -> Option<String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(url)
+            .map(|(etag, _)| etag.clone())
+    }
+
+    /// The document last served for `url`, for a `304 Not Modified` answer.
+    pub fn document(&self, url: &str) -> Option<Value> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(url)
+            .map(|(_, document)| document.clone())
+    }
+
+    pub fn store(&self, url: &str, etag: String, document: Value) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), (etag, document));
+    }
+
+    /// Forget `url`, e.g. when it answered without an entity tag.
+    pub fn remove(&self, url: &str) {
+        self.entries.lock().unwrap().remove(url);
+    }
+}
@@ -0,0 +1,228 @@
+//! Failover to a secondary foundation.
+//!
+//! Active/passive setups run the same plan on a second foundation. With
+//! `TANZU_AI_FAILOVER_ENDPOINT` and `TANZU_AI_FAILOVER_API_KEY` set, requests for the
+//! default binding move to the secondary after `TANZU_AI_FAILOVER_THRESHOLD`
+//! consecutive connection failures to the primary. Only requests that get no HTTP
+//! response count; an error status shows the primary is reachable. While failed over,
+//! one request every `TANZU_AI_FAILBACK_PROBE_SECS` goes to the primary as a probe, and
+//! the first probe that reaches it moves traffic back.
+//!
+//! Discovery is not failed over; the secondary is expected to serve the same models.
+
+use super::normalize_api_base;
+use anyhow::{anyhow, bail, Result};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_THRESHOLD: u32 = 3;
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The secondary endpoint and its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailoverTarget {
+    pub endpoint_base: String,
+    pub api_key: String,
+}
+
+impl FailoverTarget {
+    pub fn from_config() -> Result<Option<Self>> {
+        let config = crate::config::Config::global();
+        let endpoint: Option<String> = config.get_param("TANZU_AI_FAILOVER_ENDPOINT").ok();
+        match (endpoint, api_key()) {
+            (None, None) => Ok(None),
+            (Some(endpoint), Some(api_key)) => Ok(Some(Self {
+                endpoint_base: normalize_api_base(&endpoint),
+                api_key,
+            })),
+            _ => bail!(
+                "TANZU_AI_FAILOVER_ENDPOINT and TANZU_AI_FAILOVER_API_KEY must be set together"
+            ),
+        }
+    }
+}
+
+/// The secondary's key, read again whenever the token is refreshed.
+pub fn api_key() -> Option<String> {
+    crate::config::Config::global()
+        .get_secret("TANZU_AI_FAILOVER_API_KEY")
+        .ok()
+}
+
+/// Re-read the secondary's key for its token manager.
+pub async fn reresolve_api_key() -> Result<String> {
+    api_key().ok_or_else(|| anyhow!("TANZU_AI_FAILOVER_API_KEY is no longer set"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Primary,
+    Secondary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Primary {
+        failures: u32,
+    },
+    Secondary {
+        next_probe: Instant,
+    },
+    /// A probe request to the primary is in flight
+    Probing,
+}
+
+/// Which foundation requests go to.
+#[derive(Debug)]
+pub struct Failover {
+    primary: String,
+    secondary: String,
+    threshold: u32,
+    probe_interval: Duration,
+    state: Mutex<State>,
+}
+
+impl Failover {
+    pub fn new(primary: &str, secondary: &str, threshold: u32, probe_interval: Duration) -> Self {
+        Self {
+            primary: primary.to_string(),
+            secondary: secondary.to_string(),
+            threshold: threshold.max(1),
+            probe_interval,
+            state: Mutex::new(State::Primary { failures: 0 }),
+        }
+    }
+
+    pub fn from_config(primary: &str, secondary: &str) -> Self {
+        let config = crate::config::Config::global();
+        let threshold = config
+            .get_param("TANZU_AI_FAILOVER_THRESHOLD")
+            .unwrap_or(DEFAULT_THRESHOLD);
+        let probe_interval = config
+            .get_param("TANZU_AI_FAILBACK_PROBE_SECS")
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_PROBE_INTERVAL);
+        Self::new(primary, secondary, threshold, probe_interval)
+    }
+
+    /// Where to send the next request; a request routed to the primary while failed
+    /// over is the probe.
+    pub fn route(&self) -> Route {
+        self.route_at(Instant::now())
+    }
+
+    fn route_at(&self, now: Instant) -> Route {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Primary { .. } => Route::Primary,
+            State::Secondary { next_probe } if now >= next_probe => {
+                tracing::info!("Probing primary Tanzu AI endpoint {}", self.primary);
+                *state = State::Probing;
+                Route::Primary
+            }
+            State::Secondary { .. } | State::Probing => Route::Secondary,
+        }
+    }
+
+    /// Whether requests currently go to the secondary.
+    pub fn failed_over(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), State::Primary { .. })
+    }
+
+    /// Record a response from the primary, of any status.
+    pub fn record_reached(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Primary { .. }) {
+            tracing::info!(
+                "Primary Tanzu AI endpoint {} is reachable again, failing back",
+                self.primary
+            );
+        }
+        *state = State::Primary { failures: 0 };
+    }
+
+    /// Record a request to the primary that got no response.
+    pub fn record_unreachable(&self) {
+        self.record_unreachable_at(Instant::now());
+    }
+
+    fn record_unreachable_at(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let next_probe = now + self.probe_interval;
+        match *state {
+            State::Primary { failures } if failures + 1 >= self.threshold => {
+                tracing::warn!(
+                    "Primary Tanzu AI endpoint {} unreachable after {} attempts, failing over to {}",
+                    self.primary,
+                    failures + 1,
+                    self.secondary
+                );
+                *state = State::Secondary { next_probe };
+            }
+            State::Primary { failures } => {
+                *state = State::Primary {
+                    failures: failures + 1,
+                }
+            }
+            State::Probing => *state = State::Secondary { next_probe },
+            State::Secondary { .. } => {}
+        }
+    }
+
+    /// End a probe that neither reached the primary nor failed to connect, e.g. one
+    /// refused by the primary's open circuit breaker.
+    pub fn settle_probe(&self) {
+        let mut state = self.state.lock().unwrap();
+        if *state == State::Probing {
+            *state = State::Secondary {
+                next_probe: Instant::now() + self.probe_interval,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fails_over_and_back() {
+        let failover = Failover::new(
+            "https://genai.east.example.com/plan",
+            "https://genai.west.example.com/plan",
+            2,
+            Duration::from_secs(60),
+        );
+        let start = Instant::now();
+
+        failover.record_unreachable_at(start);
+        assert_eq!(failover.route_at(start), Route::Primary);
+        failover.record_reached();
+        failover.record_unreachable_at(start);
+        assert_eq!(failover.route_at(start), Route::Primary);
+        failover.record_unreachable_at(start);
+        assert_eq!(failover.route_at(start), Route::Secondary);
+
+        // One probe once the interval has passed; others stay on the secondary meanwhile
+        let later = start + Duration::from_secs(61);
+        assert_eq!(failover.route_at(later), Route::Primary);
+        assert_eq!(failover.route_at(later), Route::Secondary);
+        failover.record_unreachable_at(later);
+        assert_eq!(failover.route_at(later), Route::Secondary);
+
+        let much_later = later + Duration::from_secs(61);
+        assert_eq!(failover.route_at(much_later), Route::Primary);
+        failover.record_reached();
+        assert!(!failover.failed_over());
+        assert_eq!(failover.route_at(much_later), Route::Primary);
+    }
+
+    #[test]
+    fn test_unanswered_probe_returns_to_secondary() {
+        let failover = Failover::new("https://a", "https://b", 1, Duration::ZERO);
+        failover.record_unreachable();
+        assert_eq!(failover.route(), Route::Primary);
+        failover.settle_probe();
+        assert!(failover.failed_over());
+    }
+}
@@ -0,0 +1,222 @@
+//! Fault injection for resilience testing.
+//!
+//! `TANZU_AI_FAULT_PROFILE` makes the transport misbehave on purpose, so teams can see
+//! how their Goose automation copes before it meets a real outage:
+//!
+//! - `flaky`: requests fail with a 502 or time out, and streams are cut off mid-reply.
+//! - `slow`: requests wait 1–5 seconds before being sent, and stream chunks arrive late.
+//! - `rate-limited`: requests are rejected with a 429 and a `Retry-After` of 1–5 seconds.
+//!
+//! `TANZU_AI_FAULT_RATE` is the share of requests and streams affected, 0.2 for `flaky`,
+//! 0.5 for `slow` and 0.3 for `rate-limited` by default. Injected failures go through
+//! the usual retries, circuit breaker and failover, and their messages start with
+//! "Injected fault" so they are not mistaken for platform problems. No request is sent
+//! for an injected failure.
+
+use super::trace;
+use crate::providers::errors::ProviderError;
+use futures::StreamExt;
+use std::sync::Once;
+use std::time::Duration;
+
+/// What goes wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultProfile {
+    Flaky,
+    Slow,
+    RateLimited,
+}
+
+impl FaultProfile {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "flaky" => Some(Self::Flaky),
+            "slow" => Some(Self::Slow),
+            "rate-limited" => Some(Self::RateLimited),
+            _ => None,
+        }
+    }
+
+    fn default_rate(self) -> f64 {
+        match self {
+            Self::Flaky => 0.2,
+            Self::Slow => 0.5,
+            Self::RateLimited => 0.3,
+        }
+    }
+}
+
+/// A fault profile and how often it strikes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Faults {
+    pub profile: FaultProfile,
+    /// Share of requests and streams affected, from 0 to 1
+    pub rate: f64,
+}
+
+impl Faults {
+    /// The configured faults, or `None` unless `TANZU_AI_FAULT_PROFILE` names a profile.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let name = config.get_param::<String>("TANZU_AI_FAULT_PROFILE").ok()?;
+        if matches!(name.trim(), "" | "off" | "none") {
+            return None;
+        }
+        let Some(profile) = FaultProfile::parse(&name) else {
+            tracing::warn!(
+                "Ignoring unknown TANZU_AI_FAULT_PROFILE {}; use flaky, slow or rate-limited",
+                name
+            );
+            return None;
+        };
+        let rate = config
+            .get_param::<f64>("TANZU_AI_FAULT_RATE")
+            .map(|rate| rate.clamp(0.0, 1.0))
+            .unwrap_or_else(|_| profile.default_rate());
+        static WARNED: Once = Once::new();
+        WARNED.call_once(|| {
+            tracing::warn!(
+                "Tanzu AI fault injection is on ({:?} at {:.0}%); requests will fail on purpose",
+                profile,
+                rate * 100.0
+            )
+        });
+        Some(Self { profile, rate })
+    }
+
+    /// Delay or fail a request for `model` before it is sent.
+    pub async fn before_request(&self, model: &str) -> Result<(), ProviderError> {
+        if !self.strikes() {
+            return Ok(());
+        }
+        match self.profile {
+            FaultProfile::Flaky if roll() < 0.5 => {
+                tracing::debug!("Injecting a 502 into a request for {}", model);
+                Err(ProviderError::ServerError(
+                    "Injected fault: 502 Bad Gateway".to_string(),
+                ))
+            }
+            FaultProfile::Flaky => {
+                tracing::debug!("Injecting a timeout into a request for {}", model);
+                Err(ProviderError::RequestFailed(
+                    "Injected fault: request timed out".to_string(),
+                ))
+            }
+            FaultProfile::Slow => {
+                let delay = between(Duration::from_secs(1), Duration::from_secs(5));
+                tracing::debug!("Delaying a request for {} by {:?}", model, delay);
+                tokio::time::sleep(delay).await;
+                Ok(())
+            }
+            FaultProfile::RateLimited => {
+                let retry_after = Duration::from_secs(1 + (roll() * 5.0) as u64);
+                tracing::debug!("Injecting a 429 into a request for {}", model);
+                Err(ProviderError::RateLimitExceeded {
+                    details: "Injected fault: 429 Too Many Requests".to_string(),
+                    retry_delay: Some(retry_after),
+                })
+            }
+        }
+    }
+
+    /// Cut off or slow down a streamed response body.
+    pub fn disrupt<S, B>(self, bytes: S) -> impl futures::Stream<Item = std::io::Result<B>>
+    where
+        S: futures::Stream<Item = std::io::Result<B>>,
+    {
+        let strikes = self.strikes();
+        // Cut off after a chunk or a few, so the reply is partly delivered
+        let cut_after = 1 + (roll() * 4.0) as usize;
+        async_stream::stream! {
+            let mut bytes = std::pin::pin!(bytes);
+            let mut delivered = 0;
+            while let Some(chunk) = bytes.next().await {
+                match self.profile {
+                    FaultProfile::Flaky if strikes && delivered == cut_after => {
+                        yield Err(std::io::Error::new(
+                            std::io::ErrorKind::ConnectionReset,
+                            "Injected fault: stream truncated",
+                        ));
+                        break;
+                    }
+                    FaultProfile::Slow if strikes => {
+                        let delay = between(Duration::from_millis(100), Duration::from_millis(500));
+                        tokio::time::sleep(delay).await;
+                    }
+                    _ => {}
+                }
+                delivered += 1;
+                yield chunk;
+            }
+        }
+    }
+
+    fn strikes(&self) -> bool {
+        roll() < self.rate
+    }
+}
+
+/// A random duration from `min` up to `max`.
+fn between(min: Duration, max: Duration) -> Duration {
+    min + (max - min).mul_f64(roll())
+}
+
+/// A random number from 0 up to 1.
+fn roll() -> f64 {
+    let bits = u64::from_str_radix(&trace::random_hex(8), 16).unwrap_or_default();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_util::bytes::Bytes;
+
+    #[test]
+    fn test_parse_profiles() {
+        assert_eq!(FaultProfile::parse("flaky"), Some(FaultProfile::Flaky));
+        assert_eq!(
+            FaultProfile::parse(" Rate_Limited "),
+            Some(FaultProfile::RateLimited)
+        );
+        assert_eq!(FaultProfile::parse("chaos"), None);
+        assert!((0..100).map(|_| roll()).all(|r| (0.0..1.0).contains(&r)));
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures() {
+        let always = |profile| Faults { profile, rate: 1.0 };
+        let never = Faults {
+            profile: FaultProfile::Flaky,
+            rate: 0.0,
+        };
+        assert!(never.before_request("llama3.2:1b").await.is_ok());
+
+        let err = always(FaultProfile::Flaky)
+            .before_request("llama3.2:1b")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Injected fault"));
+        let err = always(FaultProfile::RateLimited)
+            .before_request("llama3.2:1b")
+            .await
+            .unwrap_err();
+        let ProviderError::RateLimitExceeded {
+            retry_delay: Some(delay),
+            ..
+        } = err
+        else {
+            panic!("expected a rate limit, got {:?}", err);
+        };
+        assert!((1..=5).contains(&delay.as_secs()));
+
+        let chunks = (0..10).map(|i| Ok(Bytes::from(format!("data: {}\n\n", i))));
+        let received: Vec<_> = always(FaultProfile::Flaky)
+            .disrupt(futures::stream::iter(chunks))
+            .collect()
+            .await;
+        assert!(received.len() < 10);
+        let last = received.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(last.kind(), std::io::ErrorKind::ConnectionReset);
+    }
+}
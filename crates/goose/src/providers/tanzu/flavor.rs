@@ -0,0 +1,167 @@
+//! Azure OpenAI upstreams on hybrid foundations.
+//!
+//! Some foundations front Azure OpenAI instead of models hosted on the platform. Azure
+//! addresses a model as a deployment in the path, requires an `api-version` query
+//! parameter and authenticates with an `api-key` header rather than a bearer token. A
+//! binding is treated as Azure when its credentials say `"upstream_flavor": "azure"` or
+//! carry an `api_version`, or when its endpoint is an `*.openai.azure.com` host;
+//! `TANZU_AI_UPSTREAM_FLAVOR` (`tanzu` or `azure`) overrides the detection for every
+//! binding. The API version comes from the binding, then `TANZU_AI_AZURE_API_VERSION`.
+//!
+//! Azure has no listing of deployments on current API versions, so an Azure binding
+//! without a config URL advertises only the model named in its credentials.
+
+use serde_json::Value;
+
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// OpenAI-compatible paths that Azure scopes to a deployment.
+const DEPLOYMENT_PATHS: [&str; 4] = [
+    "chat/completions",
+    "completions",
+    "embeddings",
+    "audio/transcriptions",
+];
+
+/// The API behind a binding's OpenAI-compatible paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum UpstreamFlavor {
+    /// Models hosted on the platform, addressed by name in the request body
+    #[default]
+    Tanzu,
+    AzureOpenAi {
+        api_version: String,
+    },
+}
+
+impl UpstreamFlavor {
+    /// The flavor of a binding from its credentials, if any, and endpoint.
+    pub fn detect(credentials: Option<&Value>, endpoint_base: &str) -> Self {
+        let config = crate::config::Config::global();
+        let field = |key: &str| {
+            let credentials = credentials?;
+            [Some(credentials), credentials.get("endpoint")]
+                .into_iter()
+                .flatten()
+                .find_map(|c| c.get(key).and_then(Value::as_str))
+                .map(|v| v.trim().to_ascii_lowercase())
+        };
+        let api_version = field("api_version")
+            .or_else(|| config.get_param("TANZU_AI_AZURE_API_VERSION").ok())
+            .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string());
+
+        let configured: Option<String> = config.get_param("TANZU_AI_UPSTREAM_FLAVOR").ok();
+        let azure = match configured.or_else(|| field("upstream_flavor")).as_deref() {
+            Some("azure") | Some("azure_openai") => true,
+            Some("tanzu") | Some("openai") => false,
+            Some(other) => {
+                tracing::warn!("Unknown Tanzu AI upstream flavor '{}', ignoring it", other);
+                is_azure_host(endpoint_base)
+            }
+            None => field("api_version").is_some() || is_azure_host(endpoint_base),
+        };
+        if azure {
+            Self::AzureOpenAi { api_version }
+        } else {
+            Self::Tanzu
+        }
+    }
+
+    pub fn is_azure(&self) -> bool {
+        matches!(self, Self::AzureOpenAi { .. })
+    }
+
+    /// Rewrite an OpenAI-style path for a request to `model`.
+    ///
+    /// `openai/v1/chat/completions` becomes
+    /// `openai/deployments/<model>/chat/completions?api-version=<version>` on Azure.
+    pub fn path(&self, path: &str, model: &str) -> String {
+        let Self::AzureOpenAi { api_version } = self else {
+            return path.to_string();
+        };
+        let Some(rest) = path.trim_start_matches('/').strip_prefix("openai/v1/") else {
+            return path.to_string();
+        };
+        if DEPLOYMENT_PATHS.contains(&rest) {
+            format!(
+                "openai/deployments/{}/{}?api-version={}",
+                model, rest, api_version
+            )
+        } else {
+            format!("openai/{}?api-version={}", rest, api_version)
+        }
+    }
+
+    /// Authenticate `request` with `token` the way the upstream expects.
+    pub fn authorize(
+        &self,
+        request: reqwest::RequestBuilder,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        let (name, value) = self.auth_header(token);
+        request.header(name, value)
+    }
+
+    /// The header carrying `token`.
+    pub fn auth_header(&self, token: &str) -> (&'static str, String) {
+        match self {
+            Self::Tanzu => ("Authorization", format!("Bearer {}", token)),
+            Self::AzureOpenAi { .. } => ("api-key", token.to_string()),
+        }
+    }
+}
+
+fn is_azure_host(endpoint_base: &str) -> bool {
+    reqwest::Url::parse(endpoint_base)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        .is_some_and(|host| host.ends_with(".openai.azure.com"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_from_binding_metadata() {
+        let tanzu = "https://genai-proxy.sys.example.com/plan";
+        assert_eq!(UpstreamFlavor::detect(None, tanzu), UpstreamFlavor::Tanzu);
+        assert_eq!(
+            UpstreamFlavor::detect(
+                Some(&json!({"endpoint": {"api_version": "2025-01-01-preview"}})),
+                tanzu
+            ),
+            UpstreamFlavor::AzureOpenAi {
+                api_version: "2025-01-01-preview".to_string()
+            }
+        );
+        assert!(
+            UpstreamFlavor::detect(Some(&json!({"upstream_flavor": "Azure"})), tanzu).is_azure()
+        );
+        assert!(UpstreamFlavor::detect(None, "https://contoso.openai.azure.com").is_azure());
+    }
+
+    #[test]
+    fn test_azure_paths() {
+        let azure = UpstreamFlavor::AzureOpenAi {
+            api_version: "2024-10-21".to_string(),
+        };
+        assert_eq!(
+            azure.path("openai/v1/chat/completions", "gpt-4o"),
+            "openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(
+            azure.path("openai/v1/models", "gpt-4o"),
+            "openai/models?api-version=2024-10-21"
+        );
+        assert_eq!(
+            azure.path("anthropic/v1/messages", "claude"),
+            "anthropic/v1/messages"
+        );
+        assert_eq!(
+            UpstreamFlavor::Tanzu.path("openai/v1/chat/completions", "gpt-4o"),
+            "openai/v1/chat/completions"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Custom headers sent with every request.
+//!
+//! Platform WAFs and gateways in front of the GenAI proxy can require headers of their
+//! own, such as an `X-Client-Id`. `TANZU_AI_EXTRA_HEADERS` is a JSON map of header names
+//! to values added to every request. Headers the provider sets itself cannot be
+//! overridden; a map naming one is rejected when the client is built.
+
+use anyhow::{bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
+
+/// Headers owned by the provider.
+const RESERVED: [&str; 2] = ["authorization", "content-type"];
+
+/// `TANZU_AI_EXTRA_HEADERS` as configured, validated by [`ExtraHeaders::header_map`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ExtraHeaders {
+    json: Option<String>,
+}
+
+impl ExtraHeaders {
+    pub fn from_config() -> Self {
+        match crate::config::Config::global().get_param::<Value>("TANZU_AI_EXTRA_HEADERS") {
+            // Environment variables arrive as strings, config files as maps
+            Ok(Value::String(json)) => Self::from_json(&json),
+            Ok(value) => Self::from_json(&value.to_string()),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Self {
+        Self {
+            json: Some(json.to_string()),
+        }
+    }
+
+    /// The headers to add, or an error naming the entry that can't be sent.
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let Some(json) = self.json.as_deref().filter(|json| !json.trim().is_empty()) else {
+            return Ok(headers);
+        };
+        let map: serde_json::Map<String, Value> = serde_json::from_str(json)
+            .context("TANZU_AI_EXTRA_HEADERS must be a JSON object of header names to values")?;
+        for (name, value) in map {
+            if RESERVED.contains(&name.to_ascii_lowercase().as_str()) {
+                bail!(
+                    "TANZU_AI_EXTRA_HEADERS cannot set {}; it is managed by the provider",
+                    name
+                );
+            }
+            let value = match value {
+                Value::String(value) => value,
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => bail!("TANZU_AI_EXTRA_HEADERS value for {} must be a string", name),
+            };
+            let header_name = HeaderName::from_bytes(name.as_bytes()).with_context(|| {
+                format!("Invalid header name in TANZU_AI_EXTRA_HEADERS: {}", name)
+            })?;
+            let header_value = HeaderValue::from_str(&value)
+                .with_context(|| format!("Invalid value for {} in TANZU_AI_EXTRA_HEADERS", name))?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_map() {
+        let headers = ExtraHeaders::from_json(r#"{"X-Client-Id": "goose-desktop", "X-Tier": 2}"#)
+            .header_map()
+            .unwrap();
+        assert_eq!(headers["x-client-id"], "goose-desktop");
+        assert_eq!(headers["x-tier"], "2");
+        assert!(ExtraHeaders::default().header_map().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reserved_and_invalid_headers_rejected() {
+        for json in [
+            r#"{"Authorization": "Bearer other"}"#,
+            r#"{"content-type": "text/plain"}"#,
+            r#"{"X-Client-Id": {"nested": true}}"#,
+            r#"{"bad header": "x"}"#,
+            r#"["X-Client-Id"]"#,
+        ] {
+            assert!(
+                ExtraHeaders::from_json(json).header_map().is_err(),
+                "{} accepted",
+                json
+            );
+        }
+    }
+}
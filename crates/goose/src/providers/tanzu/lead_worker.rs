@@ -0,0 +1,155 @@
+//! Lead and worker models for planning and execution.
+//!
+//! `TANZU_AI_LEAD_MODEL` and `TANZU_AI_WORKER_MODEL` split a session between two models
+//! the plan serves, without Goose's generic lead/worker variables. The lead model answers
+//! each new user prompt, when the agent plans its approach; the worker model takes the
+//! tool-calling turns that follow, until the user writes again. Either key alone pairs
+//! that model with the configured model. Both are resolved against the plan's aliases
+//! and must be advertised chat models, so a typo fails when the provider is created
+//! rather than on the first request.
+//!
+//! Only requests for the provider's own model are routed, like tiered routing, which
+//! lead/worker routing replaces when both are configured.
+
+use crate::conversation::message::{Message, MessageContent};
+use anyhow::{bail, Result};
+use rmcp::model::Role;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Answering a user prompt
+    Plan,
+    /// Continuing after tool results
+    Execute,
+}
+
+impl Phase {
+    /// The phase a request is in, from its last message.
+    pub fn of(messages: &[Message]) -> Self {
+        match messages.last() {
+            Some(last)
+                if last.role == Role::User
+                    && !last.content.is_empty()
+                    && last
+                        .content
+                        .iter()
+                        .all(|c| matches!(c, MessageContent::ToolResponse(_))) =>
+            {
+                Self::Execute
+            }
+            _ => Self::Plan,
+        }
+    }
+}
+
+/// The configured lead and worker models.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeadWorker {
+    pub lead: String,
+    pub worker: String,
+}
+
+impl LeadWorker {
+    /// The configured pair, filling a missing side with `own_model`, or `None` when
+    /// neither key is set.
+    pub fn from_config(own_model: &str) -> Option<Self> {
+        let config = crate::config::Config::global();
+        let lead: Option<String> = config.get_param("TANZU_AI_LEAD_MODEL").ok();
+        let worker: Option<String> = config.get_param("TANZU_AI_WORKER_MODEL").ok();
+        Self::new(lead, worker, own_model)
+    }
+
+    fn new(lead: Option<String>, worker: Option<String>, own_model: &str) -> Option<Self> {
+        let lead = lead.filter(|m| !m.trim().is_empty());
+        let worker = worker.filter(|m| !m.trim().is_empty());
+        if lead.is_none() && worker.is_none() {
+            return None;
+        }
+        Some(Self {
+            lead: lead.unwrap_or_else(|| own_model.to_string()),
+            worker: worker.unwrap_or_else(|| own_model.to_string()),
+        })
+    }
+
+    /// Resolve both models with `resolve`, e.g. alias resolution.
+    pub fn resolved(self, resolve: impl Fn(&str) -> String) -> Self {
+        Self {
+            lead: resolve(&self.lead),
+            worker: resolve(&self.worker),
+        }
+    }
+
+    /// Check both models against the advertised chat models.
+    pub fn validate(&self, chat_models: &[String]) -> Result<()> {
+        for (key, model) in [
+            ("TANZU_AI_LEAD_MODEL", &self.lead),
+            ("TANZU_AI_WORKER_MODEL", &self.worker),
+        ] {
+            if !chat_models.contains(model) {
+                bail!(
+                    "{} names {}, which the bound plan does not serve as a chat model; available: {}",
+                    key,
+                    model,
+                    chat_models.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The model for a request in `phase`.
+    pub fn model_for(&self, phase: Phase) -> &str {
+        match phase {
+            Phase::Plan => &self.lead,
+            Phase::Execute => &self.worker,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_from_last_message() {
+        let prompt = Message::user().with_text("Fix the failing test");
+        let call = Message::assistant().with_tool_request(
+            "call_1",
+            Ok(rmcp::model::CallToolRequestParam {
+                name: "shell".into(),
+                arguments: None,
+            }),
+        );
+        let result = Message::user().with_tool_response("call_1", Ok(vec![]));
+
+        assert_eq!(Phase::of(std::slice::from_ref(&prompt)), Phase::Plan);
+        assert_eq!(
+            Phase::of(&[prompt.clone(), call.clone(), result]),
+            Phase::Execute
+        );
+        assert_eq!(Phase::of(&[prompt, call]), Phase::Plan);
+        assert_eq!(Phase::of(&[]), Phase::Plan);
+    }
+
+    #[test]
+    fn test_missing_side_uses_own_model() {
+        assert_eq!(LeadWorker::new(None, None, "qwen3-30b"), None);
+        let pair = LeadWorker::new(Some("openai/gpt-oss-120b".into()), None, "qwen3-30b").unwrap();
+        assert_eq!(pair.model_for(Phase::Plan), "openai/gpt-oss-120b");
+        assert_eq!(pair.model_for(Phase::Execute), "qwen3-30b");
+    }
+
+    #[test]
+    fn test_validate_against_advertised_models() {
+        let pair = LeadWorker {
+            lead: "openai/gpt-oss-120b".to_string(),
+            worker: "qwen3-30b".to_string(),
+        };
+        let served = vec!["openai/gpt-oss-120b".to_string(), "qwen3-30b".to_string()];
+        assert!(pair.validate(&served).is_ok());
+
+        let err = pair.validate(&served[..1]).unwrap_err().to_string();
+        assert!(err.contains("TANZU_AI_WORKER_MODEL"));
+        assert!(err.contains("openai/gpt-oss-120b"));
+    }
+}
@@ -0,0 +1,174 @@
+//! Limits that keep agent loops under a plan's rate limits.
+//!
+//! Parallel tool calls and subagents can send many requests at once and push the plan
+//! into rate limiting, where retries only make it worse.
+//!
+//! - `TANZU_AI_MAX_CONCURRENT_REQUESTS` caps the requests in flight across every provider
+//!   instance in the process; further requests wait for a slot. Streams hold their slot
+//!   until they are consumed or dropped. Unset or `0` means no limit.
+//! - The retry budget stops retrying once more than `TANZU_AI_RETRY_BUDGET_RATIO` (0.5 by
+//!   default) of the requests to an endpoint in the last `TANZU_AI_RETRY_BUDGET_WINDOW_SECS`
+//!   (60) were rate limited or failed with a server error; errors are then returned
+//!   straight away. Budgets are kept per endpoint base, as plan limits apply per plan.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_FAILURE_RATIO: f64 = 0.5;
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+/// Requests needed in the window before the budget can run out
+const MIN_REQUESTS: usize = 10;
+
+static CONCURRENCY: LazyLock<Option<Arc<Semaphore>>> = LazyLock::new(|| {
+    crate::config::Config::global()
+        .get_param::<usize>("TANZU_AI_MAX_CONCURRENT_REQUESTS")
+        .ok()
+        .filter(|max| *max > 0)
+        .map(|max| Arc::new(Semaphore::new(max)))
+});
+
+static BUDGETS: LazyLock<Mutex<HashMap<String, Arc<RetryBudget>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Wait for a request slot; `None` when concurrency is unlimited.
+pub async fn acquire() -> Option<OwnedSemaphorePermit> {
+    let semaphore = CONCURRENCY.as_ref()?.clone();
+    if semaphore.available_permits() == 0 {
+        tracing::debug!("TANZU_AI_MAX_CONCURRENT_REQUESTS reached, waiting for a request slot");
+    }
+    semaphore.acquire_owned().await.ok()
+}
+
+/// The shared retry budget for an endpoint base, created with configured limits on first use.
+pub fn budget_for(endpoint_base: &str) -> Arc<RetryBudget> {
+    BUDGETS
+        .lock()
+        .unwrap()
+        .entry(endpoint_base.to_string())
+        .or_insert_with(|| {
+            let config = crate::config::Config::global();
+            let ratio = config
+                .get_param("TANZU_AI_RETRY_BUDGET_RATIO")
+                .unwrap_or(DEFAULT_FAILURE_RATIO);
+            let window = config
+                .get_param("TANZU_AI_RETRY_BUDGET_WINDOW_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_WINDOW);
+            Arc::new(RetryBudget::new(ratio, window))
+        })
+        .clone()
+}
+
+/// Recent request outcomes for an endpoint, deciding whether failures may be retried.
+#[derive(Debug)]
+pub struct RetryBudget {
+    max_failure_ratio: f64,
+    window: Duration,
+    /// When each recent request finished and whether it failed
+    outcomes: Mutex<VecDeque<(Instant, bool)>>,
+}
+
+impl RetryBudget {
+    pub fn new(max_failure_ratio: f64, window: Duration) -> Self {
+        Self {
+            max_failure_ratio,
+            window,
+            outcomes: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record a finished request; `failed` for rate limits and server errors.
+    pub fn record(&self, failed: bool) {
+        self.record_at(Instant::now(), failed);
+    }
+
+    fn record_at(&self, now: Instant, failed: bool) {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        outcomes.push_back((now, failed));
+        self.expire(&mut outcomes, now);
+    }
+
+    /// Whether a failed request may be retried.
+    pub fn allows_retry(&self) -> bool {
+        self.allows_retry_at(Instant::now())
+    }
+
+    fn allows_retry_at(&self, now: Instant) -> bool {
+        // A ratio of 1 or more can never be exceeded
+        if self.max_failure_ratio >= 1.0 {
+            return true;
+        }
+        let mut outcomes = self.outcomes.lock().unwrap();
+        self.expire(&mut outcomes, now);
+        if outcomes.len() < MIN_REQUESTS {
+            return true;
+        }
+        let failures = outcomes.iter().filter(|(_, failed)| *failed).count();
+        let allowed = (failures as f64) <= self.max_failure_ratio * outcomes.len() as f64;
+        if !allowed {
+            tracing::warn!(
+                "Retry budget exhausted: {} of the last {} requests failed; not retrying",
+                failures,
+                outcomes.len()
+            );
+        }
+        allowed
+    }
+
+    fn expire(&self, outcomes: &mut VecDeque<(Instant, bool)>, now: Instant) {
+        while outcomes
+            .front()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) > self.window)
+        {
+            outcomes.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_exhausted_by_failures() {
+        let budget = RetryBudget::new(0.5, Duration::from_secs(60));
+        let now = Instant::now();
+
+        // Too few requests to judge
+        for _ in 0..MIN_REQUESTS - 1 {
+            budget.record_at(now, true);
+        }
+        assert!(budget.allows_retry_at(now));
+
+        budget.record_at(now, true);
+        assert!(!budget.allows_retry_at(now));
+
+        for _ in 0..MIN_REQUESTS {
+            budget.record_at(now, false);
+        }
+        assert!(budget.allows_retry_at(now));
+    }
+
+    #[test]
+    fn test_failures_expire() {
+        let budget = RetryBudget::new(0.5, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..MIN_REQUESTS {
+            budget.record_at(now, true);
+        }
+        assert!(!budget.allows_retry_at(now));
+        assert!(budget.allows_retry_at(now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_ratio_of_one_always_retries() {
+        let budget = RetryBudget::new(1.0, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..MIN_REQUESTS {
+            budget.record_at(now, true);
+        }
+        assert!(budget.allows_retry_at(now));
+    }
+}
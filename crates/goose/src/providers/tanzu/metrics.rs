@@ -0,0 +1,190 @@
+//! Request metrics for Tanzu AI Services.
+//!
+//! Metrics are emitted as tracing events using the `monotonic_counter.` and
+//! `histogram.` field prefixes, which Goose's OpenTelemetry layer exports as OTLP
+//! metrics. Every event carries `provider = "tanzu_ai"` so operators can graph GenAI
+//! consumption per app once the collector forwards to Prometheus. Process-wide totals
+//! are also kept in memory for diagnostics.
+
+use super::timing::StreamTiming;
+use super::TANZU_PROVIDER_NAME;
+use crate::providers::base::Usage;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static TOTALS: Totals = Totals::new();
+
+struct Totals {
+    requests: AtomicU64,
+    failures: AtomicU64,
+    retries: AtomicU64,
+    rate_limited: AtomicU64,
+    input_tokens: AtomicU64,
+    output_tokens: AtomicU64,
+    cached_input_tokens: AtomicU64,
+    latency_ms: AtomicU64,
+}
+
+impl Totals {
+    const fn new() -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            input_tokens: AtomicU64::new(0),
+            output_tokens: AtomicU64::new(0),
+            cached_input_tokens: AtomicU64::new(0),
+            latency_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Totals recorded by every Tanzu AI provider in this process.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub requests: u64,
+    pub failures: u64,
+    pub retries: u64,
+    pub rate_limited: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub latency_ms: u64,
+}
+
+/// Read the current process-wide totals.
+pub fn snapshot() -> MetricsSnapshot {
+    MetricsSnapshot {
+        requests: TOTALS.requests.load(Ordering::Relaxed),
+        failures: TOTALS.failures.load(Ordering::Relaxed),
+        retries: TOTALS.retries.load(Ordering::Relaxed),
+        rate_limited: TOTALS.rate_limited.load(Ordering::Relaxed),
+        input_tokens: TOTALS.input_tokens.load(Ordering::Relaxed),
+        output_tokens: TOTALS.output_tokens.load(Ordering::Relaxed),
+        cached_input_tokens: TOTALS.cached_input_tokens.load(Ordering::Relaxed),
+        latency_ms: TOTALS.latency_ms.load(Ordering::Relaxed),
+    }
+}
+
+/// Record one HTTP attempt against a binding, successful or not.
+pub fn record_request(model: &str, status: u16, latency: Duration) {
+    let latency_ms = latency.as_millis() as u64;
+    TOTALS.requests.fetch_add(1, Ordering::Relaxed);
+    TOTALS.latency_ms.fetch_add(latency_ms, Ordering::Relaxed);
+    if !(200..300).contains(&status) {
+        TOTALS.failures.fetch_add(1, Ordering::Relaxed);
+    }
+    if status == 429 {
+        TOTALS.rate_limited.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(
+            monotonic_counter.goose.provider.rate_limited = 1u64,
+            provider = TANZU_PROVIDER_NAME,
+            model,
+        );
+    }
+
+    tracing::info!(
+        monotonic_counter.goose.provider.requests = 1u64,
+        histogram.goose.provider.request_latency_ms = latency_ms,
+        provider = TANZU_PROVIDER_NAME,
+        model,
+        status,
+    );
+}
+
+/// Record a retry scheduled after a retryable failure.
+pub fn record_retry(model: &str) {
+    TOTALS.retries.fetch_add(1, Ordering::Relaxed);
+    tracing::info!(
+        monotonic_counter.goose.provider.retries = 1u64,
+        provider = TANZU_PROVIDER_NAME,
+        model,
+    );
+}
+
+/// Record token usage reported by a completion.
+pub fn record_usage(model: &str, usage: &Usage) {
+    let input = usage.input_tokens.unwrap_or(0).max(0) as u64;
+    let output = usage.output_tokens.unwrap_or(0).max(0) as u64;
+    if input == 0 && output == 0 {
+        return;
+    }
+    TOTALS.input_tokens.fetch_add(input, Ordering::Relaxed);
+    TOTALS.output_tokens.fetch_add(output, Ordering::Relaxed);
+    tracing::info!(
+        monotonic_counter.goose.provider.input_tokens = input,
+        monotonic_counter.goose.provider.output_tokens = output,
+        provider = TANZU_PROVIDER_NAME,
+        model,
+    );
+}
+
+/// Record prompt tokens served from a model's prefix cache.
+pub fn record_cached_tokens(model: &str, tokens: u64) {
+    if tokens == 0 {
+        return;
+    }
+    TOTALS
+        .cached_input_tokens
+        .fetch_add(tokens, Ordering::Relaxed);
+    tracing::info!(
+        monotonic_counter.goose.provider.cached_input_tokens = tokens,
+        provider = TANZU_PROVIDER_NAME,
+        model,
+    );
+}
+
+/// Record the latency and throughput of a streamed completion.
+pub fn record_stream_timing(model: &str, timing: &StreamTiming) {
+    let ttft_ms = timing.time_to_first_token.as_millis() as u64;
+    let inter_token_ms = timing
+        .inter_token_latency()
+        .map_or(0.0, |latency| latency.as_secs_f64() * 1000.0);
+    let tokens_per_second = timing.tokens_per_second().unwrap_or(0.0);
+    tracing::info!(
+        histogram.goose.provider.time_to_first_token_ms = ttft_ms,
+        histogram.goose.provider.inter_token_latency_ms = inter_token_ms,
+        histogram.goose.provider.output_tokens_per_second = tokens_per_second,
+        provider = TANZU_PROVIDER_NAME,
+        model,
+    );
+}
+
+/// Record matches of a content filter in an outgoing request.
+pub fn record_content_filter(model: &str, filter: &str, matches: u64, blocked: bool) {
+    let action = if blocked { "block" } else { "redact" };
+    tracing::info!(
+        monotonic_counter.goose.provider.content_filter_matches = matches,
+        provider = TANZU_PROVIDER_NAME,
+        model,
+        filter,
+        action,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Totals are process-wide and other tests record into them concurrently,
+    // so only assert lower bounds on the deltas.
+    #[test]
+    fn test_totals_accumulate() {
+        let before = snapshot();
+
+        record_request("llama3.2:1b", 200, Duration::from_millis(40));
+        record_request("llama3.2:1b", 429, Duration::from_millis(5));
+        record_retry("llama3.2:1b");
+        record_usage("llama3.2:1b", &Usage::new(Some(12), Some(3), Some(15)));
+
+        let after = snapshot();
+        assert!(after.requests >= before.requests + 2);
+        assert!(after.failures > before.failures);
+        assert!(after.rate_limited > before.rate_limited);
+        assert!(after.retries > before.retries);
+        assert!(after.input_tokens >= before.input_tokens + 12);
+        assert!(after.output_tokens >= before.output_tokens + 3);
+        assert!(after.latency_ms >= before.latency_ms + 45);
+    }
+}
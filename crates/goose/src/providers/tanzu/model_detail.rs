@@ -0,0 +1,164 @@
+//! Per-model details from `GET /openai/v1/models/{id}`.
+//!
+//! The model listing only names the models. Proxies that implement the detail endpoint
+//! also say who owns a model, when it was created and, depending on the upstream, its
+//! context length (`context_length`, `context_window` or vLLM's `max_model_len`).
+//! Details are cached for the discovery TTL. Proxies that do not implement the
+//! endpoint answer 404, 405 or 501; that is remembered per endpoint and those models
+//! keep what discovery advertised.
+
+use super::{discovery_ttl, fetch_discovery, TanzuBinding};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+/// A lookup and when it was made; `None` for a model the proxy does not know.
+type Lookup = (Instant, Option<ModelDetail>);
+
+/// Details looked up in this process, keyed by URL.
+static DETAILS: LazyLock<Mutex<HashMap<String, Lookup>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Endpoints whose proxy does not implement the detail endpoint.
+static UNSUPPORTED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// What the detail endpoint says about a model.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ModelDetail {
+    pub id: String,
+    #[serde(default)]
+    pub owned_by: Option<String>,
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    pub created: Option<u64>,
+    #[serde(default, alias = "context_window", alias = "max_model_len")]
+    pub context_length: Option<usize>,
+}
+
+/// The details of `id` on `binding`, or `None` when the proxy does not implement the
+/// endpoint or does not know the model.
+pub async fn fetch(binding: &TanzuBinding, id: &str) -> anyhow::Result<Option<ModelDetail>> {
+    let base = binding.credentials.endpoint_base.trim_end_matches('/');
+    // Azure addresses deployments, not models
+    if binding.credentials.flavor.is_azure() || UNSUPPORTED.lock().unwrap().contains(base) {
+        return Ok(None);
+    }
+    let url = detail_url(base, id);
+    if let Some((fetched_at, detail)) = DETAILS.lock().unwrap().get(&url) {
+        if fetched_at.elapsed() < discovery_ttl() {
+            return Ok(detail.clone());
+        }
+    }
+
+    let token = binding.transport.bearer_token().await?;
+    let fixture_path = format!("openai/v1/models/{}", id);
+    let detail = match fetch_discovery(
+        binding.transport.http(),
+        &binding.credentials.flavor,
+        &url,
+        &fixture_path,
+        &token,
+        &binding.etags,
+    )
+    .await
+    {
+        Ok(json) => parse(json),
+        Err(e) => match not_served(&e) {
+            Some(reqwest::StatusCode::NOT_FOUND) => None,
+            Some(status) => {
+                tracing::debug!(
+                    "{} does not implement the model detail endpoint ({})",
+                    base,
+                    status
+                );
+                UNSUPPORTED.lock().unwrap().insert(base.to_string());
+                return Ok(None);
+            }
+            None => return Err(e),
+        },
+    };
+    DETAILS
+        .lock()
+        .unwrap()
+        .insert(url, (Instant::now(), detail.clone()));
+    Ok(detail)
+}
+
+/// The detail URL of `id`, which is one path segment even when the name has a `/`.
+fn detail_url(base: &str, id: &str) -> String {
+    match reqwest::Url::parse(base) {
+        Ok(mut url) => {
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments
+                    .pop_if_empty()
+                    .extend(["openai", "v1", "models", id]);
+            }
+            url.to_string()
+        }
+        Err(_) => format!("{}/openai/v1/models/{}", base, id),
+    }
+}
+
+/// A detail document, bare or wrapped in `data` as some proxies do.
+fn parse(json: Value) -> Option<ModelDetail> {
+    let json = match json {
+        Value::Object(mut object) if object.get("data").is_some_and(Value::is_object) => {
+            object.remove("data")?
+        }
+        json => json,
+    };
+    match serde_json::from_value(json) {
+        Ok(detail) => Some(detail),
+        Err(e) => {
+            tracing::debug!("Ignoring unreadable model detail: {}", e);
+            None
+        }
+    }
+}
+
+/// The status of a failed detail request that means the model or route is not there.
+fn not_served(error: &anyhow::Error) -> Option<reqwest::StatusCode> {
+    let status = error.downcast_ref::<reqwest::Error>()?.status()?;
+    matches!(
+        status,
+        reqwest::StatusCode::NOT_FOUND
+            | reqwest::StatusCode::METHOD_NOT_ALLOWED
+            | reqwest::StatusCode::NOT_IMPLEMENTED
+    )
+    .then_some(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_detail_variants() {
+        let openai = parse(json!({
+            "id": "openai/gpt-oss-120b",
+            "object": "model",
+            "created": 1754352000,
+            "owned_by": "openai"
+        }))
+        .unwrap();
+        assert_eq!(openai.owned_by.as_deref(), Some("openai"));
+        assert_eq!(openai.created, Some(1754352000));
+        assert_eq!(openai.context_length, None);
+
+        let vllm = parse(json!({"id": "llama3.2:1b", "max_model_len": 8192})).unwrap();
+        assert_eq!(vllm.context_length, Some(8192));
+
+        let wrapped = parse(json!({"data": {"id": "qwen3", "context_window": 32768}})).unwrap();
+        assert_eq!(wrapped.id, "qwen3");
+        assert_eq!(wrapped.context_length, Some(32768));
+
+        assert!(parse(json!({"error": "no such model"})).is_none());
+        assert_eq!(
+            detail_url("https://genai.example.com/plan", "openai/gpt-oss-120b"),
+            "https://genai.example.com/plan/openai/v1/models/openai%2Fgpt-oss-120b"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Per-model control of parallel tool calls.
+//!
+//! Some vLLM deployments mishandle `parallel_tool_calls` and llama models behind them
+//! return malformed replies with several tool calls. `TANZU_AI_PARALLEL_TOOL_CALLS`
+//! sets the behaviour for every model (`true`/`false`) or per model with the pattern
+//! keys of `TANZU_AI_MODEL_PARAMS`, e.g. `{"llama*": false, "openai/gpt-oss-*": true}`.
+//! Models without a setting get `true` when they advertise the PARALLEL_TOOLS
+//! capability, and are otherwise left to the server's default.
+//!
+//! Enabled, requests with tools carry `parallel_tool_calls: true`. Disabled, the field is
+//! omitted and only the first tool call of a reply is kept, so tools run one at a time.
+
+use super::params::{matches, specificity};
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::base::MessageStream;
+use futures::StreamExt;
+use serde_json::Value;
+
+pub const PARALLEL_TOOLS_CAPABILITY: &str = "PARALLEL_TOOLS";
+
+const FIELD: &str = "parallel_tool_calls";
+
+/// Configured settings from `TANZU_AI_PARALLEL_TOOL_CALLS`, least specific first.
+#[derive(Debug, Clone, Default)]
+pub struct ParallelToolCalls {
+    rules: Vec<(String, bool)>,
+}
+
+impl ParallelToolCalls {
+    pub fn from_config() -> Self {
+        match crate::config::Config::global().get_param::<Value>("TANZU_AI_PARALLEL_TOOL_CALLS") {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Parse a bool for every model or a map of model patterns to bools.
+    pub fn parse(value: &Value) -> Self {
+        let mut rules = match value {
+            Value::Bool(enabled) => vec![("*".to_string(), *enabled)],
+            // Environment variables arrive as strings
+            Value::String(s) => match s.trim().parse::<bool>() {
+                Ok(enabled) => vec![("*".to_string(), enabled)],
+                Err(_) => return Self::parse(&serde_json::from_str(s).unwrap_or(Value::Null)),
+            },
+            Value::Object(map) => map
+                .iter()
+                .filter_map(|(pattern, enabled)| match enabled.as_bool() {
+                    Some(enabled) => Some((pattern.clone(), enabled)),
+                    None => {
+                        tracing::warn!(
+                            "TANZU_AI_PARALLEL_TOOL_CALLS entry for {} is not a bool; ignoring it",
+                            pattern
+                        );
+                        None
+                    }
+                })
+                .collect(),
+            _ => {
+                tracing::warn!(
+                    "TANZU_AI_PARALLEL_TOOL_CALLS must be a bool or a JSON object; ignoring it"
+                );
+                Vec::new()
+            }
+        };
+        rules.sort_by_key(|(pattern, _)| specificity(pattern));
+        Self { rules }
+    }
+
+    /// The most specific setting matching `model_name`.
+    pub fn setting(&self, model_name: &str) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| matches(pattern, model_name))
+            .map(|(_, enabled)| *enabled)
+    }
+}
+
+/// Set or omit `parallel_tool_calls` in a request offering tools.
+pub fn apply(payload: &mut Value, enabled: bool) {
+    let Some(payload) = payload.as_object_mut() else {
+        return;
+    };
+    if enabled {
+        payload.insert(FIELD.to_string(), Value::Bool(true));
+    } else {
+        payload.remove(FIELD);
+    }
+}
+
+/// Keep only the first tool call of a reply.
+pub fn first_tool_call_only(mut message: Message) -> Message {
+    let mut seen = false;
+    retain_first_tool_call(&mut message, &mut seen);
+    message
+}
+
+/// Keep only the first tool call across the messages of a stream.
+pub fn serialize_stream(stream: MessageStream) -> MessageStream {
+    let mut seen = false;
+    Box::pin(stream.map(move |item| {
+        let (message, usage) = item?;
+        let message = message.and_then(|mut message| {
+            let had_content = !message.content.is_empty();
+            retain_first_tool_call(&mut message, &mut seen);
+            (!had_content || !message.content.is_empty()).then_some(message)
+        });
+        Ok((message, usage))
+    }))
+}
+
+fn retain_first_tool_call(message: &mut Message, seen: &mut bool) {
+    let before = message.content.len();
+    message.content.retain(|content| {
+        if !matches!(content, MessageContent::ToolRequest(_)) {
+            return true;
+        }
+        !std::mem::replace(seen, true)
+    });
+    let dropped = before - message.content.len();
+    if dropped > 0 {
+        tracing::debug!(
+            "Parallel tool calls are disabled; dropped {} extra tool call(s)",
+            dropped
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::CallToolRequestParam;
+    use serde_json::json;
+
+    fn tool_call(id: &str) -> Message {
+        Message::assistant().with_tool_request(
+            id,
+            Ok(CallToolRequestParam {
+                name: "shell".into(),
+                arguments: None,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_settings_by_specificity() {
+        let settings = ParallelToolCalls::parse(&json!({
+            "*": true,
+            "llama*": false,
+            "llama3.3:70b": true,
+            "mistral": "no",
+        }));
+        assert_eq!(settings.setting("openai/gpt-oss-120b"), Some(true));
+        assert_eq!(settings.setting("llama3.2:1b"), Some(false));
+        assert_eq!(settings.setting("llama3.3:70b"), Some(true));
+
+        assert_eq!(
+            ParallelToolCalls::parse(&json!("false")).setting("any"),
+            Some(false)
+        );
+        assert_eq!(
+            ParallelToolCalls::parse(&json!(r#"{"llama*": false}"#)).setting("llama3.2:1b"),
+            Some(false)
+        );
+        assert_eq!(ParallelToolCalls::default().setting("any"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut payload = json!({"model": "m", "parallel_tool_calls": true});
+        apply(&mut payload, false);
+        assert!(payload.get(FIELD).is_none());
+        apply(&mut payload, true);
+        assert_eq!(payload[FIELD], true);
+    }
+
+    #[test]
+    fn test_first_tool_call_only() {
+        let message = tool_call("call_1")
+            .with_text("running both")
+            .with_tool_request(
+                "call_2",
+                Ok(CallToolRequestParam {
+                    name: "shell".into(),
+                    arguments: None,
+                }),
+            );
+        let message = first_tool_call_only(message);
+        assert_eq!(message.content.len(), 2);
+        assert!(matches!(
+            &message.content[0],
+            MessageContent::ToolRequest(r) if r.id == "call_1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_serialize_stream() {
+        let items = vec![
+            Ok((Some(Message::assistant().with_text("ok")), None)),
+            Ok((Some(tool_call("call_1")), None)),
+            Ok((Some(tool_call("call_2")), None)),
+        ];
+        let stream: MessageStream = Box::pin(futures::stream::iter(items));
+        let messages: Vec<_> = serialize_stream(stream)
+            .map(|item| item.unwrap().0)
+            .collect()
+            .await;
+        assert_eq!(messages.len(), 3);
+        assert!(messages[1].is_some());
+        assert!(messages[2].is_none());
+    }
+}
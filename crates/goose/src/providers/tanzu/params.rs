@@ -0,0 +1,133 @@
+//! Per-model request parameter profiles.
+//!
+//! Models hosted on the same plan often want different defaults: gpt-oss takes a
+//! `reasoning_effort`, small llama models behave better with a lower temperature.
+//! `TANZU_AI_MODEL_PARAMS` maps model names to JSON objects merged into every request
+//! for that model, e.g.
+//!
+//! ```json
+//! {"openai/gpt-oss-*": {"reasoning_effort": "high"}, "llama3.2:1b": {"temperature": 0.2}}
+//! ```
+//!
+//! Keys are exact model names, prefixes ending in `*`, or `*` for every model. More
+//! specific profiles are applied last, so they win, and profile values replace those
+//! derived from the Goose model config.
+
+use serde_json::{Map, Value};
+
+/// Payload fields that carry the conversation itself and cannot be overridden.
+const PROTECTED_FIELDS: &[&str] = &["model", "messages", "stream", "tools"];
+
+/// Parameter profiles from `TANZU_AI_MODEL_PARAMS`.
+#[derive(Debug, Clone, Default)]
+pub struct ModelParams {
+    profiles: Vec<(String, Map<String, Value>)>,
+}
+
+impl ModelParams {
+    pub fn from_config() -> Self {
+        match crate::config::Config::global().get_param::<Value>("TANZU_AI_MODEL_PARAMS") {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Parse a profile map, skipping entries that are not JSON objects.
+    pub fn parse(value: &Value) -> Self {
+        let Some(map) = value.as_object() else {
+            tracing::warn!("TANZU_AI_MODEL_PARAMS must be a JSON object; ignoring it");
+            return Self::default();
+        };
+        let mut profiles = Vec::new();
+        for (pattern, params) in map {
+            let Some(params) = params.as_object() else {
+                tracing::warn!(
+                    "TANZU_AI_MODEL_PARAMS entry for {} is not an object; ignoring it",
+                    pattern
+                );
+                continue;
+            };
+            let mut params = params.clone();
+            for field in PROTECTED_FIELDS {
+                if params.remove(*field).is_some() {
+                    tracing::warn!(
+                        "TANZU_AI_MODEL_PARAMS entry for {} cannot set `{}`; ignoring it",
+                        pattern,
+                        field
+                    );
+                }
+            }
+            profiles.push((pattern.clone(), params));
+        }
+        profiles.sort_by_key(|(pattern, _)| specificity(pattern));
+        Self { profiles }
+    }
+
+    /// Merge the profiles matching `model_name` into a request payload.
+    pub fn apply(&self, payload: &mut Value, model_name: &str) {
+        let Some(payload) = payload.as_object_mut() else {
+            return;
+        };
+        for (pattern, params) in &self.profiles {
+            if matches(pattern, model_name) {
+                for (key, value) in params {
+                    payload.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+pub fn matches(pattern: &str, model_name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model_name.starts_with(prefix),
+        None => pattern == model_name,
+    }
+}
+
+/// Sort key putting `*` first, then prefixes by length, then exact names.
+pub fn specificity(pattern: &str) -> (bool, usize) {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => (false, prefix.len()),
+        None => (true, pattern.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_profiles_merge_by_specificity() {
+        let params = ModelParams::parse(&json!({
+            "llama3.2:1b": {"temperature": 0.2},
+            "*": {"temperature": 0.7, "top_p": 0.9},
+            "llama*": {"temperature": 0.4, "messages": []},
+            "openai/gpt-oss-*": {"reasoning_effort": "high"},
+        }));
+
+        let mut payload = json!({"model": "llama3.2:1b", "messages": [{"role": "user"}]});
+        params.apply(&mut payload, "llama3.2:1b");
+        assert_eq!(payload["temperature"], 0.2);
+        assert_eq!(payload["top_p"], 0.9);
+        assert_eq!(payload["messages"], json!([{"role": "user"}]));
+
+        let mut payload = json!({"model": "openai/gpt-oss-120b", "temperature": 1.0});
+        params.apply(&mut payload, "openai/gpt-oss-120b");
+        assert_eq!(payload["reasoning_effort"], "high");
+        assert_eq!(payload["temperature"], 0.7);
+    }
+
+    #[test]
+    fn test_invalid_profiles_ignored() {
+        let params = ModelParams::parse(&json!({"qwen3-30b": "hot"}));
+        let mut payload = json!({"model": "qwen3-30b"});
+        params.apply(&mut payload, "qwen3-30b");
+        assert_eq!(payload, json!({"model": "qwen3-30b"}));
+
+        assert!(ModelParams::parse(&json!(["not", "a", "map"]))
+            .profiles
+            .is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! Platform context added to the system prompt.
+//!
+//! Agents running on a foundation work better when they know where they run and what
+//! the model can do. With `TANZU_AI_PLATFORM_PROMPT=true` a short block describing the
+//! app and the model is appended to every system prompt.
+//! `TANZU_AI_PLATFORM_PROMPT_TEMPLATE` replaces the default block and turns the feature
+//! on. Templates may use
+//!
+//! - `{org}`, `{space}`, `{app}`: from `VCAP_APPLICATION`, or `TANZU_AI_ORG`,
+//!   `TANZU_AI_SPACE` and `TANZU_AI_APP`
+//! - `{memory_mb}`, `{disk_mb}`: the app's limits from `VCAP_APPLICATION`
+//! - `{model}`, `{capabilities}`, `{context_length}`: the model a request goes to, as
+//!   discovery advertises it
+//!
+//! A template line using a placeholder with no value, say `{app}` outside Cloud Foundry,
+//! is left out, so the block only states what is known.
+
+use super::attribution::Attribution;
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+const DEFAULT_TEMPLATE: &str = "\
+Runtime context from Tanzu Platform:
+- Cloud Foundry app {app} in org {org}, space {space}
+- App limits: {memory_mb} MB memory, {disk_mb} MB disk
+- Model: {model}
+- Model capabilities: {capabilities}
+- Context window: {context_length} tokens";
+
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{(\w+)\}").unwrap());
+
+/// The fields of `VCAP_APPLICATION` describing the app's limits.
+#[derive(Debug, Default, Deserialize)]
+struct VcapApplication {
+    #[serde(default)]
+    limits: Limits,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Limits {
+    mem: Option<u64>,
+    disk: Option<u64>,
+}
+
+/// What is known about the model a request goes to.
+#[derive(Debug, Default, Clone)]
+pub struct ModelContext {
+    pub name: String,
+    pub capabilities: Vec<String>,
+    pub context_length: Option<usize>,
+}
+
+/// A template and the platform values it is filled with.
+#[derive(Debug, Clone)]
+pub struct PlatformPrompt {
+    template: String,
+    app: Attribution,
+    memory_mb: Option<u64>,
+    disk_mb: Option<u64>,
+}
+
+impl PlatformPrompt {
+    pub fn new(template: &str, app: Attribution, vcap_application: Option<&str>) -> Self {
+        let limits = vcap_application
+            .and_then(|json| serde_json::from_str::<VcapApplication>(json).ok())
+            .unwrap_or_default()
+            .limits;
+        Self {
+            template: template.to_string(),
+            app,
+            memory_mb: limits.mem,
+            disk_mb: limits.disk,
+        }
+    }
+
+    /// The configured prompt, or `None` unless `TANZU_AI_PLATFORM_PROMPT` or
+    /// `TANZU_AI_PLATFORM_PROMPT_TEMPLATE` is set.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let template = config
+            .get_param::<String>("TANZU_AI_PLATFORM_PROMPT_TEMPLATE")
+            .ok()
+            .filter(|t| !t.trim().is_empty());
+        let enabled = template.is_some()
+            || config
+                .get_param::<bool>("TANZU_AI_PLATFORM_PROMPT")
+                .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let vcap = std::env::var("VCAP_APPLICATION").ok();
+        let mut app = vcap
+            .as_deref()
+            .map(Attribution::from_vcap_application)
+            .unwrap_or_default();
+        let param = |key: &str| config.get_param::<String>(key).ok();
+        app.org = param("TANZU_AI_ORG").or(app.org);
+        app.space = param("TANZU_AI_SPACE").or(app.space);
+        app.app = param("TANZU_AI_APP").or(app.app);
+        Some(Self::new(
+            template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+            app,
+            vcap.as_deref(),
+        ))
+    }
+
+    /// `system` followed by the filled-in template.
+    pub fn apply(&self, system: &str, model: &ModelContext) -> String {
+        let context = self.render(model);
+        match (system.trim().is_empty(), context.is_empty()) {
+            (_, true) => system.to_string(),
+            (true, false) => context,
+            (false, false) => format!("{}\n\n{}", system.trim_end(), context),
+        }
+    }
+
+    fn render(&self, model: &ModelContext) -> String {
+        let capabilities = (!model.capabilities.is_empty()).then(|| model.capabilities.join(", "));
+        let value = |name: &str| -> Option<Option<String>> {
+            Some(match name {
+                "org" => self.app.org.clone(),
+                "space" => self.app.space.clone(),
+                "app" => self.app.app.clone(),
+                "memory_mb" => self.memory_mb.map(|mb| mb.to_string()),
+                "disk_mb" => self.disk_mb.map(|mb| mb.to_string()),
+                "model" => Some(model.name.clone()).filter(|name| !name.is_empty()),
+                "capabilities" => capabilities.clone(),
+                "context_length" => model.context_length.map(|n| n.to_string()),
+                // Not a placeholder; kept as written
+                _ => return None,
+            })
+        };
+
+        let mut lines = Vec::new();
+        'lines: for line in self.template.lines() {
+            let mut filled = String::with_capacity(line.len());
+            let mut last = 0;
+            for captures in PLACEHOLDER.captures_iter(line) {
+                let whole = captures.get(0).unwrap();
+                let Some(known) = value(&captures[1]) else {
+                    continue;
+                };
+                let Some(known) = known else {
+                    continue 'lines;
+                };
+                filled.push_str(&line[last..whole.start()]);
+                filled.push_str(&known);
+                last = whole.end();
+            }
+            filled.push_str(&line[last..]);
+            lines.push(filled);
+        }
+        lines.join("\n").trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VCAP_APPLICATION: &str = r#"{
+        "application_name": "release-bot",
+        "space_name": "ci",
+        "organization_name": "platform-eng",
+        "limits": {"mem": 1024, "disk": 2048, "fds": 16384}
+    }"#;
+
+    fn model() -> ModelContext {
+        ModelContext {
+            name: "openai/gpt-oss-120b".to_string(),
+            capabilities: vec!["CHAT".to_string(), "TOOLS".to_string()],
+            context_length: Some(131072),
+        }
+    }
+
+    #[test]
+    fn test_default_template_on_cloud_foundry() {
+        let prompt = PlatformPrompt::new(
+            DEFAULT_TEMPLATE,
+            Attribution::from_vcap_application(VCAP_APPLICATION),
+            Some(VCAP_APPLICATION),
+        );
+        assert_eq!(
+            prompt.apply("You are Goose.", &model()),
+            "You are Goose.\n\n\
+             Runtime context from Tanzu Platform:\n\
+             - Cloud Foundry app release-bot in org platform-eng, space ci\n\
+             - App limits: 1024 MB memory, 2048 MB disk\n\
+             - Model: openai/gpt-oss-120b\n\
+             - Model capabilities: CHAT, TOOLS\n\
+             - Context window: 131072 tokens"
+        );
+    }
+
+    #[test]
+    fn test_lines_without_values_are_left_out() {
+        let prompt = PlatformPrompt::new(DEFAULT_TEMPLATE, Attribution::default(), None);
+        let bare = ModelContext {
+            name: "llama3.2:1b".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            prompt.apply("", &bare),
+            "Runtime context from Tanzu Platform:\n- Model: llama3.2:1b"
+        );
+
+        let custom = PlatformPrompt::new(
+            "Reply in JSON {like: this}.\nServing from {space}.",
+            Attribution::default(),
+            None,
+        );
+        assert_eq!(
+            custom.apply("sys", &bare),
+            "sys\n\nReply in JSON {like: this}."
+        );
+        let empty = PlatformPrompt::new("On {app}", Attribution::default(), None);
+        assert_eq!(empty.apply("sys", &bare), "sys");
+    }
+}
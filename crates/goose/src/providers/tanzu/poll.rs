@@ -0,0 +1,117 @@
+//! Background refresh of advertised models.
+//!
+//! Operators add and remove models from plans without rebinding the app. With
+//! `TANZU_AI_CONFIG_POLL_SECS` set, a task re-reads each binding's config URL at that
+//! interval, refreshes the discovery cache and routing table, and logs when the active
+//! model leaves or rejoins the plan.
+
+use super::etag::ETagCache;
+use super::transport::Transport;
+use super::{discover_models, filter_served_models, TanzuCredentials, DISCOVERY_CACHE};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// What the poller needs from one binding.
+pub struct PollTarget {
+    pub credentials: TanzuCredentials,
+    pub transport: Transport,
+    pub models: Arc<RwLock<Vec<String>>>,
+    pub etags: Arc<ETagCache>,
+}
+
+impl PollTarget {
+    /// Re-discover the binding's models, returning the models it now serves.
+    async fn refresh(&self) -> Option<Vec<String>> {
+        let discovered = async {
+            let token = self.transport.bearer_token().await?;
+            discover_models(
+                self.transport.http(),
+                &self.credentials,
+                &token,
+                &self.etags,
+            )
+            .await
+        };
+        let advertised = match discovered.await {
+            Ok(advertised) => advertised,
+            Err(e) => {
+                tracing::debug!(
+                    "Config poll failed for {}: {}",
+                    self.credentials.endpoint_base,
+                    e
+                );
+                return None;
+            }
+        };
+
+        DISCOVERY_CACHE.insert(self.credentials.discovery_key(), advertised.clone());
+        let served = filter_served_models(&advertised);
+        if !served.is_empty() {
+            *self.models.write().unwrap() = served.clone();
+        }
+        Some(served)
+    }
+}
+
+/// Poll every target at `interval` until the returned task is aborted.
+pub fn spawn(targets: Vec<PollTarget>, active_model: String, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires immediately and discovery just ran on startup
+        ticker.tick().await;
+        let mut active_available = true;
+
+        loop {
+            ticker.tick().await;
+            let mut served = Vec::new();
+            let mut complete = true;
+            for target in &targets {
+                match target.refresh().await {
+                    Some(models) => served.extend(models),
+                    None => complete = false,
+                }
+            }
+            // Only judge availability when every binding answered
+            if complete {
+                active_available = report_active_model(&active_model, &served, active_available);
+            }
+        }
+    })
+}
+
+/// Log transitions of the active model in and out of the plan; returns the new state.
+fn report_active_model(active_model: &str, served: &[String], was_available: bool) -> bool {
+    let available = served.is_empty() || served.iter().any(|m| m == active_model);
+    match (was_available, available) {
+        (true, false) => tracing::warn!(
+            model = active_model,
+            "Active model {} is no longer advertised by the bound Tanzu AI Services plan; \
+             available models: {}",
+            active_model,
+            served.join(", ")
+        ),
+        (false, true) => tracing::info!(
+            model = active_model,
+            "Active model {} is advertised again by the bound plan",
+            active_model
+        ),
+        _ => {}
+    }
+    available
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_active_model_transitions() {
+        let served = vec!["llama3.2:1b".to_string()];
+        assert!(!report_active_model("qwen3-30b", &served, true));
+        assert!(!report_active_model("qwen3-30b", &served, false));
+        assert!(report_active_model("llama3.2:1b", &served, false));
+        // An empty listing says nothing about the model
+        assert!(report_active_model("qwen3-30b", &[], true));
+    }
+}
@@ -0,0 +1,196 @@
+//! Startup validation of a Tanzu AI Services binding.
+//!
+//! A misconfigured binding otherwise only shows up as an opaque failure on the first
+//! completion. The preflight check distinguishes the common causes up front. It is
+//! opt-in (`TANZU_AI_PREFLIGHT=true`), since it makes provider creation depend on the
+//! proxy being reachable at that moment.
+
+use super::flavor::UpstreamFlavor;
+use super::token::jwt_expiry;
+use super::trace;
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Why a binding failed its preflight check.
+#[derive(Debug, Error)]
+pub enum PreflightError {
+    #[error("Tanzu AI Services endpoint {endpoint} is unreachable: {reason}")]
+    Unreachable { endpoint: String, reason: String },
+
+    #[error(
+        "Tanzu AI Services endpoint {endpoint} returned {status}; check that TANZU_AI_ENDPOINT \
+         is the binding's endpoint.api_base"
+    )]
+    BadEndpoint { endpoint: String, status: u16 },
+
+    #[error("Tanzu AI Services API key for {endpoint} has expired; rebind the service or refresh the key")]
+    ExpiredToken { endpoint: String },
+
+    #[error("Tanzu AI Services rejected the API key for {endpoint} ({status})")]
+    RejectedToken { endpoint: String, status: u16 },
+
+    #[error("Could not obtain a UAA token for {endpoint}: {reason}")]
+    TokenExchange { endpoint: String, reason: String },
+
+    #[error("Model {model} is not offered by the bound plan; available models: {}", available.join(", "))]
+    UnknownModel {
+        model: String,
+        available: Vec<String>,
+    },
+}
+
+/// Check that an endpoint is reachable and accepts the key, returning the model ids it serves.
+///
+/// Azure lists base models rather than the deployments requests address, so only
+/// reachability and the key are checked there and no models are returned.
+pub async fn check_endpoint(
+    client: &reqwest::Client,
+    endpoint_base: &str,
+    api_key: &str,
+    flavor: &UpstreamFlavor,
+) -> Result<Vec<String>, PreflightError> {
+    let endpoint = endpoint_base.trim_end_matches('/').to_string();
+    let url = format!("{}/{}", endpoint, flavor.path("openai/v1/models", ""));
+
+    let response = flavor
+        .authorize(trace::inject(client.get(&url)), api_key)
+        .send()
+        .await
+        .map_err(|e| PreflightError::Unreachable {
+            endpoint: endpoint.clone(),
+            reason: e.to_string(),
+        })?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        let expired = jwt_expiry(api_key).is_some_and(|exp| exp <= SystemTime::now());
+        return Err(if expired {
+            PreflightError::ExpiredToken { endpoint }
+        } else {
+            PreflightError::RejectedToken {
+                endpoint,
+                status: status.as_u16(),
+            }
+        });
+    }
+    if !status.is_success() {
+        return Err(PreflightError::BadEndpoint {
+            endpoint,
+            status: status.as_u16(),
+        });
+    }
+
+    if flavor.is_azure() {
+        return Ok(Vec::new());
+    }
+    let json: serde_json::Value = response.json().await.unwrap_or_default();
+    Ok(json
+        .get("data")
+        .and_then(|d| d.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|m| m.get("id")?.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_check_endpoint_lists_models() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/plan/openai/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{"id": "llama3.2:1b"}, {"id": "openai/gpt-oss-120b"}]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let models = check_endpoint(
+            &reqwest::Client::new(),
+            &format!("{}/plan", mock_server.uri()),
+            "key",
+            &UpstreamFlavor::Tanzu,
+        )
+        .await
+        .unwrap();
+        assert_eq!(models, vec!["llama3.2:1b", "openai/gpt-oss-120b"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_bad_url() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let result = check_endpoint(
+            &reqwest::Client::new(),
+            &format!("{}/wrong-plan", mock_server.uri()),
+            "key",
+            &UpstreamFlavor::Tanzu,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(PreflightError::BadEndpoint { status: 404, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_expired_token() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let expired = format!(
+            "{}.{}.sig",
+            engine.encode(r#"{"alg":"HS256"}"#),
+            engine.encode(r#"{"exp":1000}"#)
+        );
+
+        let result = check_endpoint(
+            &reqwest::Client::new(),
+            &mock_server.uri(),
+            &expired,
+            &UpstreamFlavor::Tanzu,
+        )
+        .await;
+        assert!(matches!(result, Err(PreflightError::ExpiredToken { .. })));
+
+        let result = check_endpoint(
+            &reqwest::Client::new(),
+            &mock_server.uri(),
+            "opaque-key",
+            &UpstreamFlavor::Tanzu,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(PreflightError::RejectedToken { status: 401, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_unreachable() {
+        let result = check_endpoint(
+            &reqwest::Client::new(),
+            "http://127.0.0.1:1/plan",
+            "key",
+            &UpstreamFlavor::Tanzu,
+        )
+        .await;
+        assert!(matches!(result, Err(PreflightError::Unreachable { .. })));
+    }
+}
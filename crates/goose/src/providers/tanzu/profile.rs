@@ -0,0 +1,185 @@
+//! Named connection profiles for apps that target several foundations.
+//!
+//! `TANZU_AI_PROFILE=staging` takes the endpoint and key from the `staging` profile
+//! instead of `TANZU_AI_ENDPOINT` and `TANZU_AI_API_KEY`. A profile is defined either
+//! by its own variables, `TANZU_AI_STAGING_ENDPOINT`, `TANZU_AI_STAGING_API_KEY` and
+//! optionally `_CONFIG_URL` and `_MODEL_NAME`, or by an entry in `TANZU_AI_PROFILES`
+//! in the Goose config:
+//!
+//! ```yaml
+//! TANZU_AI_PROFILES:
+//!   staging: {endpoint: "https://genai.staging.example.com/plan", api_key: "..."}
+//! ```
+//!
+//! The profile's own variables win over the config entry. A selected profile that is
+//! not fully defined fails credential resolution rather than silently falling back to
+//! another foundation. Embedding code can list profiles and switch between them with
+//! [`TanzuAIServicesProvider::profiles`](super::TanzuAIServicesProvider::profiles) and
+//! [`TanzuAIServicesProvider::switch_profile`](super::TanzuAIServicesProvider::switch_profile).
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, RwLock};
+
+/// Prefixes of other settings that end in `_ENDPOINT` or `_API_KEY`.
+const RESERVED: [&str; 2] = ["CONFIG", "FAILOVER"];
+
+/// A profile switched to at runtime, overriding `TANZU_AI_PROFILE`.
+static SWITCHED: LazyLock<RwLock<Option<String>>> = LazyLock::new(|| RwLock::new(None));
+
+/// Connection settings of one profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Profile {
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    pub config_url: Option<String>,
+    pub model_name: Option<String>,
+}
+
+/// The selected profile's name, if any.
+pub fn active() -> Option<String> {
+    if let Some(switched) = SWITCHED.read().unwrap().clone() {
+        return Some(switched);
+    }
+    crate::config::Config::global()
+        .get_param::<String>("TANZU_AI_PROFILE")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Select `name` for this process, or clear the selection with `None`. Returns the
+/// previous runtime selection.
+pub fn select(name: Option<&str>) -> Option<String> {
+    std::mem::replace(&mut *SWITCHED.write().unwrap(), name.map(String::from))
+}
+
+/// Every defined profile, sorted by name.
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = configured().into_keys().collect();
+    for (key, _) in std::env::vars() {
+        let Some(name) = key
+            .strip_prefix("TANZU_AI_")
+            .and_then(|rest| rest.strip_suffix("_ENDPOINT"))
+            .filter(|name| !name.is_empty() && !RESERVED.contains(name))
+        else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        if !names.iter().any(|n| env_name(n) == env_name(&name)) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    names
+}
+
+/// The settings of profile `name`, with its variables over its config entry.
+pub fn load(name: &str) -> Result<Profile> {
+    let config = crate::config::Config::global();
+    let prefix = format!("TANZU_AI_{}", env_name(name));
+    if RESERVED.contains(&env_name(name).as_str()) {
+        bail!("'{}' cannot be used as a Tanzu AI profile name", name);
+    }
+    let entry = configured()
+        .into_iter()
+        .find(|(n, _)| env_name(n) == env_name(name))
+        .map(|(_, profile)| profile)
+        .unwrap_or_default();
+    let param = |field: &str| {
+        config
+            .get_param::<String>(&format!("{}_{}", prefix, field))
+            .ok()
+    };
+    let profile = Profile {
+        endpoint: param("ENDPOINT").or(entry.endpoint),
+        api_key: config
+            .get_secret::<String>(&format!("{}_API_KEY", prefix))
+            .ok()
+            .or(entry.api_key),
+        config_url: param("CONFIG_URL").or(entry.config_url),
+        model_name: param("MODEL_NAME").or(entry.model_name),
+    };
+    if profile.endpoint.is_none() || profile.api_key.is_none() {
+        bail!(
+            "Tanzu AI profile '{}' needs an endpoint and an API key: set {}_ENDPOINT and \
+             {}_API_KEY, or add them under '{}' in TANZU_AI_PROFILES",
+            name,
+            prefix,
+            prefix,
+            name
+        );
+    }
+    Ok(profile)
+}
+
+/// Profiles defined in `TANZU_AI_PROFILES`.
+fn configured() -> BTreeMap<String, Profile> {
+    let Ok(value) = crate::config::Config::global().get_param::<Value>("TANZU_AI_PROFILES") else {
+        return BTreeMap::new();
+    };
+    match serde_json::from_value(value) {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            tracing::warn!(
+                "TANZU_AI_PROFILES must map profile names to settings; ignoring it: {}",
+                e
+            );
+            BTreeMap::new()
+        }
+    }
+}
+
+/// A profile name as it appears in variable names, e.g. `eu-prod` as `EU_PROD`.
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_from_variables_and_config() {
+        std::env::set_var(
+            "TANZU_AI_QA_EAST_ENDPOINT",
+            "https://genai.qa.example.com/plan",
+        );
+        std::env::set_var("TANZU_AI_QA_EAST_API_KEY", "qa-key");
+        std::env::set_var(
+            "TANZU_AI_PROFILES",
+            serde_json::json!({
+                "qa-east": {"endpoint": "https://ignored.example.com", "model_name": "llama3.2:1b"},
+                "perf": {"endpoint": "https://genai.perf.example.com/plan"}
+            })
+            .to_string(),
+        );
+
+        let names = names();
+        assert!(names.contains(&"qa-east".to_string()));
+        assert!(names.contains(&"perf".to_string()));
+        assert!(!names.iter().any(|n| n == "failover" || n == "qa_east"));
+
+        let qa = load("qa-east").unwrap();
+        assert_eq!(
+            qa.endpoint.as_deref(),
+            Some("https://genai.qa.example.com/plan")
+        );
+        assert_eq!(qa.api_key.as_deref(), Some("qa-key"));
+        assert_eq!(qa.model_name.as_deref(), Some("llama3.2:1b"));
+
+        let err = load("perf").unwrap_err().to_string();
+        assert!(err.contains("set TANZU_AI_PERF_ENDPOINT and TANZU_AI_PERF_API_KEY"));
+        assert!(load("failover").is_err());
+    }
+}
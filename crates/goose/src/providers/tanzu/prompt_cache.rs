@@ -0,0 +1,137 @@
+//! Prefix-cache hints for vLLM-backed models.
+//!
+//! vLLM reuses the KV cache of a prompt prefix it has already seen. For models
+//! advertising the PROMPT_CACHE capability, requests mark the system prompt, which stays
+//! the same across a session, with `cache_control` so gateways that honour it keep the
+//! prefix cached, and carry a `cache_salt`. vLLM only shares cached prefixes between
+//! requests with the same salt, so other tenants of a shared backend cannot probe this
+//! plan's prompts through cache timing. The salt is `TANZU_AI_CACHE_SALT` when set and is
+//! otherwise derived from the plan's endpoint.
+//!
+//! Cache hits are read from `usage.prompt_tokens_details.cached_tokens` or the proxy's
+//! `X-Cached-Prompt-Tokens` header and counted in metrics and the usage ledger.
+
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+
+pub const PROMPT_CACHE_CAPABILITY: &str = "PROMPT_CACHE";
+
+/// Header the GenAI proxy reports prefix-cache hits in.
+pub const CACHED_TOKENS_HEADER: &str = "X-Cached-Prompt-Tokens";
+
+/// The cache salt for a plan's endpoint.
+pub fn salt(endpoint_base: &str) -> String {
+    crate::config::Config::global()
+        .get_param::<String>("TANZU_AI_CACHE_SALT")
+        .ok()
+        .filter(|salt| !salt.trim().is_empty())
+        .unwrap_or_else(|| {
+            let endpoint = endpoint_base.trim_end_matches('/');
+            format!("{:016x}", super::replay::fnv1a(endpoint))
+        })
+}
+
+/// Mark the system prompt as a cacheable prefix and add the salt to an OpenAI payload.
+pub fn apply(payload: &mut Value, salt: &str) {
+    payload["cache_salt"] = json!(salt);
+    let Some(system) = payload
+        .get_mut("messages")
+        .and_then(Value::as_array_mut)
+        .and_then(|messages| messages.first_mut())
+        .filter(|message| message.get("role").and_then(Value::as_str) == Some("system"))
+    else {
+        return;
+    };
+    if let Some(text) = system.get("content").and_then(Value::as_str) {
+        system["content"] = json!([{
+            "type": "text",
+            "text": text,
+            "cache_control": {"type": "ephemeral"}
+        }]);
+    }
+}
+
+/// Cached prompt tokens reported in a response header.
+pub fn header_tokens(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CACHED_TOKENS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Cached prompt tokens reported in a response body's usage.
+pub fn cached_tokens(response: &Value) -> Option<u64> {
+    response
+        .pointer("/usage/prompt_tokens_details/cached_tokens")
+        .and_then(Value::as_u64)
+}
+
+/// Fold a header-reported hit count into a response body without one.
+pub fn annotate(response: &mut Value, header_tokens: Option<u64>) {
+    let (Some(tokens), None) = (header_tokens, cached_tokens(response)) else {
+        return;
+    };
+    if let Some(usage) = response.get_mut("usage").filter(|usage| usage.is_object()) {
+        usage["prompt_tokens_details"]["cached_tokens"] = json!(tokens);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_apply_marks_system_prompt() {
+        let mut payload = json!({
+            "messages": [
+                {"role": "system", "content": "You are goose."},
+                {"role": "user", "content": "hi"}
+            ]
+        });
+        apply(&mut payload, "s1");
+        assert_eq!(payload["cache_salt"], "s1");
+        assert_eq!(
+            payload["messages"][0]["content"][0]["text"],
+            "You are goose."
+        );
+        assert_eq!(
+            payload["messages"][0]["content"][0]["cache_control"]["type"],
+            "ephemeral"
+        );
+        assert_eq!(payload["messages"][1]["content"], "hi");
+
+        let mut without_system = json!({"messages": [{"role": "user", "content": "hi"}]});
+        apply(&mut without_system, "s1");
+        assert_eq!(without_system["messages"][0]["content"], "hi");
+    }
+
+    #[test]
+    fn test_salt_is_stable_per_endpoint() {
+        assert_eq!(
+            salt("https://genai.example.com/plan-a/"),
+            salt("https://genai.example.com/plan-a")
+        );
+        assert_ne!(
+            salt("https://genai.example.com/plan-a"),
+            salt("https://genai.example.com/plan-b")
+        );
+    }
+
+    #[test]
+    fn test_cached_tokens_from_body_or_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHED_TOKENS_HEADER, HeaderValue::from_static("96"));
+
+        let mut response = json!({"usage": {"prompt_tokens": 128}});
+        assert_eq!(cached_tokens(&response), None);
+        annotate(&mut response, header_tokens(&headers));
+        assert_eq!(cached_tokens(&response), Some(96));
+
+        let mut reported = json!({
+            "usage": {"prompt_tokens": 128, "prompt_tokens_details": {"cached_tokens": 64}}
+        });
+        annotate(&mut reported, Some(96));
+        assert_eq!(cached_tokens(&reported), Some(64));
+    }
+}
@@ -0,0 +1,167 @@
+//! Reasoning output from gpt-oss and other reasoning models.
+//!
+//! vLLM serves the analysis channel of models like `openai/gpt-oss-120b` as
+//! `reasoning_content` (or `reasoning` in newer builds) next to the visible `content`.
+//! The generic OpenAI decoder doesn't know these fields, so they are lifted out here
+//! and surfaced as thinking content. `TANZU_AI_SHOW_REASONING=false` drops them.
+//!
+//! `TANZU_AI_REASONING_EFFORT` (`low`, `medium` or `high`) is sent as `reasoning_effort`
+//! to models that advertise the REASONING capability. Other models reject the field
+//! with a 400, so it is left out for them.
+
+use crate::conversation::message::{Message, MessageContent};
+use serde_json::Value;
+
+pub const REASONING_CAPABILITY: &str = "REASONING";
+
+const REASONING_FIELDS: [&str; 2] = ["reasoning_content", "reasoning"];
+
+const EFFORTS: [&str; 3] = ["low", "medium", "high"];
+
+/// The configured reasoning effort, ignoring values models would reject.
+pub fn configured_effort() -> Option<String> {
+    let effort: String = crate::config::Config::global()
+        .get_param("TANZU_AI_REASONING_EFFORT")
+        .ok()?;
+    let effort = effort.trim().to_ascii_lowercase();
+    if EFFORTS.contains(&effort.as_str()) {
+        Some(effort)
+    } else {
+        tracing::warn!(
+            "TANZU_AI_REASONING_EFFORT must be one of {}, not '{}'; ignoring it",
+            EFFORTS.join(", "),
+            effort
+        );
+        None
+    }
+}
+
+/// Whether reasoning should be shown as thinking content (`TANZU_AI_SHOW_REASONING`).
+pub fn show_reasoning() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_SHOW_REASONING")
+        .unwrap_or(true)
+}
+
+/// Remove the reasoning text from a message or delta object.
+fn take_reasoning(object: &mut Value) -> Option<String> {
+    let map = object.as_object_mut()?;
+    let mut reasoning = String::new();
+    for field in REASONING_FIELDS {
+        if map.get(field).is_some_and(Value::is_string) {
+            if let Some(Value::String(text)) = map.remove(field) {
+                reasoning.push_str(&text);
+            }
+        }
+    }
+    (!reasoning.is_empty()).then_some(reasoning)
+}
+
+/// Strip reasoning from an SSE line, returning the line to decode and the reasoning text.
+pub fn split_line(line: String) -> (String, Option<String>) {
+    let Some(data) = line.strip_prefix("data: ") else {
+        return (line, None);
+    };
+    let Ok(mut chunk) = serde_json::from_str::<Value>(data) else {
+        return (line, None);
+    };
+    match chunk
+        .pointer_mut("/choices/0/delta")
+        .and_then(take_reasoning)
+    {
+        Some(reasoning) => (format!("data: {}", chunk), Some(reasoning)),
+        None => (line, None),
+    }
+}
+
+/// Reasoning text of a non-streaming response.
+pub fn response_reasoning(response: &Value) -> Option<String> {
+    let message = response.pointer("/choices/0/message")?;
+    let reasoning: String = REASONING_FIELDS
+        .iter()
+        .filter_map(|field| message.get(*field).and_then(Value::as_str))
+        .collect();
+    (!reasoning.is_empty()).then_some(reasoning)
+}
+
+/// Put reasoning in front of the message's visible content.
+pub fn prepend_thinking(message: &mut Message, reasoning: String) {
+    message
+        .content
+        .insert(0, MessageContent::thinking(reasoning, ""));
+}
+
+/// Whether a decoded chunk carries nothing worth forwarding, as happens for deltas that
+/// only held reasoning.
+pub fn is_empty(message: &Message) -> bool {
+    message
+        .content
+        .iter()
+        .all(|c| matches!(c.as_text(), Some(text) if text.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_split_line_lifts_reasoning() {
+        let line = format!(
+            "data: {}",
+            json!({"choices": [{"index": 0, "delta": {"role": "assistant", "reasoning_content": "The user wants"}}]})
+        );
+        let (line, reasoning) = split_line(line);
+        assert_eq!(reasoning.as_deref(), Some("The user wants"));
+        assert!(!line.contains("reasoning"));
+
+        let (_, reasoning) = split_line(format!(
+            "data: {}",
+            json!({"choices": [{"index": 0, "delta": {"reasoning": " a summary"}}]})
+        ));
+        assert_eq!(reasoning.as_deref(), Some(" a summary"));
+    }
+
+    #[test]
+    fn test_split_line_leaves_other_lines() {
+        let content = format!(
+            "data: {}",
+            json!({"choices": [{"index": 0, "delta": {"content": "Hi"}}]})
+        );
+        assert_eq!(split_line(content.clone()), (content, None));
+        assert_eq!(
+            split_line("data: [DONE]".to_string()),
+            ("data: [DONE]".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_response_reasoning_becomes_thinking() {
+        let response = json!({
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "4",
+                    "reasoning_content": "2 + 2 is 4"
+                }
+            }]
+        });
+        let reasoning = response_reasoning(&response).unwrap();
+
+        let mut message = Message::assistant().with_text("4");
+        prepend_thinking(&mut message, reasoning);
+        assert!(matches!(
+            &message.content[0],
+            MessageContent::Thinking(t) if t.thinking == "2 + 2 is 4"
+        ));
+        assert_eq!(message.as_concat_text(), "4");
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(is_empty(&Message::assistant()));
+        assert!(is_empty(&Message::assistant().with_text("")));
+        assert!(!is_empty(&Message::assistant().with_text("Hi")));
+    }
+}
@@ -0,0 +1,357 @@
+//! Provider metrics for the Cloud Foundry Metric Registrar.
+//!
+//! Operators can chart GenAI consumption per app instance without running a collector.
+//! Both of the registrar's sources are supported:
+//!
+//! - `TANZU_AI_METRICS_PORT` serves the process-wide totals of [`super::metrics`] in
+//!   Prometheus text format at `/metrics` on that port, for
+//!   `cf register-metrics-endpoint APP /metrics --internal-port PORT`.
+//! - `TANZU_AI_METRICS_LOG_INTERVAL_SECS` writes them to stderr at that interval in the
+//!   registrar's JSON log format, for `cf register-log-format APP json`. Cloud Foundry
+//!   collects stderr with the app's logs; stdout is left alone, as it carries Goose's
+//!   interactive output and, under stdio transports, its protocol.
+//!
+//! Besides the counters, `tanzu_ai_tokens_per_second` and `tanzu_ai_error_rate` are
+//! gauges over the time since the previous scrape or log line. Exporters run on the
+//! runtime that started them and are started again by a later provider once it shuts
+//! down.
+
+use super::metrics::{self, MetricsSnapshot};
+use serde_json::json;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+static TASKS: Mutex<Tasks> = Mutex::new(Tasks {
+    server: None,
+    log: None,
+});
+
+/// The running exporters.
+struct Tasks {
+    server: Option<JoinHandle<()>>,
+    log: Option<JoinHandle<()>>,
+}
+
+/// Largest request head read from a scraper.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Counters in the order they are exported: name, help, value.
+fn counters(snapshot: &MetricsSnapshot) -> [(&'static str, &'static str, u64); 8] {
+    [
+        (
+            "tanzu_ai_requests_total",
+            "HTTP attempts against GenAI bindings",
+            snapshot.requests,
+        ),
+        (
+            "tanzu_ai_failures_total",
+            "Attempts that did not return 2xx",
+            snapshot.failures,
+        ),
+        (
+            "tanzu_ai_retries_total",
+            "Retries after retryable failures",
+            snapshot.retries,
+        ),
+        (
+            "tanzu_ai_rate_limited_total",
+            "Attempts answered with 429",
+            snapshot.rate_limited,
+        ),
+        (
+            "tanzu_ai_input_tokens_total",
+            "Prompt tokens reported by models",
+            snapshot.input_tokens,
+        ),
+        (
+            "tanzu_ai_output_tokens_total",
+            "Completion tokens reported by models",
+            snapshot.output_tokens,
+        ),
+        (
+            "tanzu_ai_cached_input_tokens_total",
+            "Prompt tokens served from prefix caches",
+            snapshot.cached_input_tokens,
+        ),
+        (
+            "tanzu_ai_request_latency_ms_total",
+            "Summed request latency in milliseconds",
+            snapshot.latency_ms,
+        ),
+    ]
+}
+
+/// Start the configured exporters that are not running, including ones that stopped
+/// with their runtime.
+pub fn start() {
+    let config = crate::config::Config::global();
+    let port: Option<u16> = config.get_param("TANZU_AI_METRICS_PORT").ok();
+    let log_interval = config
+        .get_param::<u64>("TANZU_AI_METRICS_LOG_INTERVAL_SECS")
+        .ok()
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    start_exporters(port, log_interval);
+}
+
+fn start_exporters(port: Option<u16>, log_interval: Option<Duration>) {
+    let mut tasks = TASKS.lock().unwrap();
+    if let Some(port) = port.filter(|_| !is_running(&tasks.server)) {
+        tasks.server = Some(tokio::spawn(async move {
+            match TcpListener::bind(("0.0.0.0", port)).await {
+                Ok(listener) => {
+                    tracing::info!("Serving Tanzu AI metrics on port {} at /metrics", port);
+                    serve(listener).await;
+                }
+                Err(e) => {
+                    tracing::warn!("Could not serve Tanzu AI metrics on port {}: {}", port, e)
+                }
+            }
+        }));
+    }
+    if let Some(interval) = log_interval.filter(|_| !is_running(&tasks.log)) {
+        tasks.log = Some(tokio::spawn(async move {
+            let rates = Rates::new();
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let lines = json_lines(&metrics::snapshot(), &rates);
+                if let Err(e) = write_lines(&mut std::io::stderr().lock(), &lines) {
+                    tracing::debug!("Could not write Tanzu AI metrics: {}", e);
+                }
+            }
+        }));
+    }
+}
+
+/// Whether `task` was started and has not ended, which a task only does when its runtime
+/// shuts down or, for the server, when its port can't be bound.
+fn is_running(task: &Option<JoinHandle<()>>) -> bool {
+    task.as_ref().is_some_and(|task| !task.is_finished())
+}
+
+/// Write one reading's lines together, so other output can't split them.
+fn write_lines(out: &mut impl Write, lines: &[String]) -> std::io::Result<()> {
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+/// Answer `GET /metrics` with the Prometheus exposition until the listener fails.
+pub async fn serve(listener: TcpListener) {
+    let rates = std::sync::Arc::new(Rates::new());
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("Tanzu AI metrics listener failed: {}", e);
+                return;
+            }
+        };
+        let rates = rates.clone();
+        tokio::spawn(async move {
+            let mut head = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => head.extend_from_slice(&buf[..n]),
+                }
+            }
+            let request_line = String::from_utf8_lossy(&head);
+            let response = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                ["GET", "/metrics"] => {
+                    let body = prometheus(&metrics::snapshot(), &rates);
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                }
+                _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+/// Token throughput and error rate since the previous reading.
+struct Rates {
+    last: Mutex<(Instant, MetricsSnapshot)>,
+}
+
+impl Rates {
+    fn new() -> Self {
+        Self {
+            last: Mutex::new((Instant::now(), metrics::snapshot())),
+        }
+    }
+
+    /// `(tokens_per_second, error_rate)` since the previous call.
+    fn update(&self, now: Instant, current: &MetricsSnapshot) -> (f64, f64) {
+        let mut last = self.last.lock().unwrap();
+        let (since, previous) = std::mem::replace(&mut *last, (now, *current));
+        let elapsed = now.saturating_duration_since(since).as_secs_f64();
+        let tokens = (current.input_tokens + current.output_tokens)
+            .saturating_sub(previous.input_tokens + previous.output_tokens);
+        let requests = current.requests.saturating_sub(previous.requests);
+        let failures = current.failures.saturating_sub(previous.failures);
+        let tokens_per_second = if elapsed > 0.0 {
+            tokens as f64 / elapsed
+        } else {
+            0.0
+        };
+        let error_rate = if requests > 0 {
+            failures as f64 / requests as f64
+        } else {
+            0.0
+        };
+        (tokens_per_second, error_rate)
+    }
+}
+
+fn prometheus(snapshot: &MetricsSnapshot, rates: &Rates) -> String {
+    let mut out = String::new();
+    for (name, help, value) in counters(snapshot) {
+        out.push_str(&format!(
+            "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n"
+        ));
+    }
+    let (tokens_per_second, error_rate) = rates.update(Instant::now(), snapshot);
+    out.push_str(&format!(
+        "# HELP tanzu_ai_tokens_per_second Tokens per second since the previous scrape\n\
+         # TYPE tanzu_ai_tokens_per_second gauge\ntanzu_ai_tokens_per_second {tokens_per_second}\n\
+         # HELP tanzu_ai_error_rate Share of failed attempts since the previous scrape\n\
+         # TYPE tanzu_ai_error_rate gauge\ntanzu_ai_error_rate {error_rate}\n"
+    ));
+    out
+}
+
+/// Metric Registrar JSON log lines: counters as deltas since the previous lines, and the
+/// rate gauges.
+fn json_lines(snapshot: &MetricsSnapshot, rates: &Rates) -> Vec<String> {
+    let previous = rates.last.lock().unwrap().1;
+    let mut lines: Vec<String> = counters(snapshot)
+        .iter()
+        .zip(counters(&previous))
+        .map(|((name, _, value), (_, _, before))| {
+            json!({"type": "counter", "name": name, "delta": value.saturating_sub(before)})
+                .to_string()
+        })
+        .collect();
+    let (tokens_per_second, error_rate) = rates.update(Instant::now(), snapshot);
+    lines.push(
+        json!({"type": "gauge", "name": "tanzu_ai_tokens_per_second", "value": tokens_per_second, "unit": "tokens/s"})
+            .to_string(),
+    );
+    lines.push(
+        json!({"type": "gauge", "name": "tanzu_ai_error_rate", "value": error_rate, "unit": "ratio"})
+            .to_string(),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(requests: u64, failures: u64, tokens: u64) -> MetricsSnapshot {
+        MetricsSnapshot {
+            requests,
+            failures,
+            input_tokens: tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rates_since_previous_reading() {
+        let start = Instant::now();
+        let rates = Rates {
+            last: Mutex::new((start, snapshot(10, 0, 1000))),
+        };
+        let (tokens_per_second, error_rate) =
+            rates.update(start + Duration::from_secs(10), &snapshot(20, 5, 3000));
+        assert_eq!(tokens_per_second, 200.0);
+        assert_eq!(error_rate, 0.5);
+
+        let (tokens_per_second, error_rate) =
+            rates.update(start + Duration::from_secs(20), &snapshot(20, 5, 3000));
+        assert_eq!((tokens_per_second, error_rate), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_json_lines_report_deltas() {
+        let rates = Rates {
+            last: Mutex::new((Instant::now(), snapshot(10, 1, 0))),
+        };
+        let lines = json_lines(&snapshot(15, 2, 0), &rates);
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["name"], "tanzu_ai_requests_total");
+        assert_eq!(first["delta"], 5);
+        assert!(lines.last().unwrap().contains("tanzu_ai_error_rate"));
+    }
+
+    #[test]
+    fn test_write_lines_one_per_line() {
+        let rates = Rates::new();
+        let lines = json_lines(&snapshot(1, 0, 0), &rates);
+        let mut out = Vec::new();
+        write_lines(&mut out, &lines).unwrap();
+
+        let written = String::from_utf8(out).unwrap();
+        assert_eq!(written.lines().count(), lines.len());
+        for line in written.lines() {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_exporters_restart_after_runtime_shutdown() {
+        let interval = Some(Duration::from_secs(3600));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async { start_exporters(None, interval) });
+        assert!(is_running(&TASKS.lock().unwrap().log));
+        drop(runtime);
+        assert!(!is_running(&TASKS.lock().unwrap().log));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async { start_exporters(None, interval) });
+        assert!(is_running(&TASKS.lock().unwrap().log));
+    }
+
+    #[tokio::test]
+    async fn test_serves_prometheus_metrics() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+
+        let response = reqwest::get(format!("http://{}/metrics", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("# TYPE tanzu_ai_requests_total counter"));
+        assert!(body.contains("tanzu_ai_tokens_per_second "));
+
+        let missing = reqwest::get(format!("http://{}/other", addr))
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), 404);
+    }
+}
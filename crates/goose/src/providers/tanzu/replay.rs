@@ -0,0 +1,330 @@
+//! Offline replay of recorded Tanzu AI Services traffic.
+//!
+//! `TANZU_AI_REPLAY_DIR` points at a directory of fixtures, one JSON file per distinct
+//! request. In the default `replay` mode (`TANZU_AI_REPLAY_MODE`) completions, streams,
+//! embeddings and model discovery are answered from the fixtures and never reach the
+//! network, so CI on air-gapped runners needs no live foundation; a request without a
+//! fixture fails and names the file it expected. In `record` mode requests go to the
+//! endpoint as usual and every successful response is saved.
+//!
+//! Fixtures are keyed by method, API path and request body, not by host, so a recording
+//! made against one foundation replays against any endpoint. The binding's API key is
+//! scrubbed from everything written and no headers are stored.
+
+use crate::providers::errors::ProviderError;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use tokio_util::bytes::Bytes;
+
+const SCRUBBED: &str = "[REDACTED]";
+
+/// Whether fixtures are served or captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    Replay,
+    Record,
+}
+
+/// A recorded response body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum FixtureBody {
+    Json(Value),
+    /// Raw server-sent events text of a streamed response
+    Sse(String),
+}
+
+impl FixtureBody {
+    pub fn into_json(self) -> Result<Value, ProviderError> {
+        match self {
+            Self::Json(value) => Ok(value),
+            Self::Sse(_) => Err(ProviderError::ExecutionError(
+                "Recorded fixture is a stream, expected a JSON response".to_string(),
+            )),
+        }
+    }
+
+    /// The recorded events as a byte stream, for decoding like a live response.
+    pub fn into_sse(self) -> Result<impl Stream<Item = std::io::Result<Bytes>>, ProviderError> {
+        match self {
+            Self::Sse(text) => Ok(futures::stream::iter([Ok(Bytes::from(text))])),
+            Self::Json(_) => Err(ProviderError::ExecutionError(
+                "Recorded fixture is a JSON response, expected a stream".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    request: Value,
+    response: FixtureBody,
+}
+
+/// The fixture directory configured by `TANZU_AI_REPLAY_DIR`.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    dir: PathBuf,
+    mode: ReplayMode,
+}
+
+impl Fixtures {
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let dir: String = config.get_param("TANZU_AI_REPLAY_DIR").ok()?;
+        let mode = match config
+            .get_param::<String>("TANZU_AI_REPLAY_MODE")
+            .map(|m| m.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Ok("record") => ReplayMode::Record,
+            _ => ReplayMode::Replay,
+        };
+        Some(Self::new(dir, mode))
+    }
+
+    pub fn new(dir: impl Into<PathBuf>, mode: ReplayMode) -> Self {
+        Self {
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> ReplayMode {
+        self.mode
+    }
+
+    /// The recorded response for a request, or `None` when not replaying.
+    pub fn replay(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Option<Result<FixtureBody, ProviderError>> {
+        if self.mode != ReplayMode::Replay {
+            return None;
+        }
+        let file = self.file_for(method, path, body);
+        let fixture = std::fs::read_to_string(&file)
+            .map_err(|e| {
+                ProviderError::ExecutionError(format!(
+                    "No recorded Tanzu AI fixture for {} {} ({}): {}",
+                    method,
+                    path,
+                    file.display(),
+                    e
+                ))
+            })
+            .and_then(|text| {
+                serde_json::from_str::<Fixture>(&text).map_err(|e| {
+                    ProviderError::ExecutionError(format!(
+                        "Invalid Tanzu AI fixture {}: {}",
+                        file.display(),
+                        e
+                    ))
+                })
+            });
+        Some(fixture.map(|f| f.response))
+    }
+
+    /// Save a response when recording; failures are logged, never surfaced.
+    pub fn record(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+        response: FixtureBody,
+        secret: &str,
+    ) {
+        if self.mode != ReplayMode::Record {
+            return;
+        }
+        let mut request = json!({"method": method, "path": path, "body": body});
+        scrub(&mut request, secret);
+        let response = match response {
+            FixtureBody::Json(mut value) => {
+                scrub(&mut value, secret);
+                FixtureBody::Json(value)
+            }
+            FixtureBody::Sse(text) => FixtureBody::Sse(scrub_text(&text, secret)),
+        };
+        let file = self.file_for(method, path, body);
+        let written = std::fs::create_dir_all(&self.dir).and_then(|_| {
+            let fixture = serde_json::to_string_pretty(&Fixture { request, response })?;
+            std::fs::write(&file, fixture)
+        });
+        match written {
+            Ok(()) => tracing::debug!("Recorded Tanzu AI fixture {}", file.display()),
+            Err(e) => tracing::warn!("Failed to record fixture {}: {}", file.display(), e),
+        }
+    }
+
+    /// Pass a streamed response through, saving it once it has been read completely.
+    pub fn record_stream<S>(
+        self,
+        path: String,
+        body: Value,
+        secret: String,
+        bytes: S,
+    ) -> impl Stream<Item = std::io::Result<Bytes>>
+    where
+        S: Stream<Item = std::io::Result<Bytes>>,
+    {
+        async_stream::stream! {
+            let mut bytes = std::pin::pin!(bytes);
+            let mut text = Vec::new();
+            let mut complete = true;
+            while let Some(chunk) = bytes.next().await {
+                match &chunk {
+                    Ok(chunk) => text.extend_from_slice(chunk),
+                    Err(_) => complete = false,
+                }
+                yield chunk;
+            }
+            if complete {
+                let text = String::from_utf8_lossy(&text).into_owned();
+                self.record("POST", &path, Some(&body), FixtureBody::Sse(text), &secret);
+            }
+        }
+    }
+
+    /// `<method>-<path>-<hash>.json`, the hash covering method, path and body.
+    fn file_for(&self, method: &str, path: &str, body: Option<&Value>) -> PathBuf {
+        let body = body.map(canonical).unwrap_or_default();
+        let hash = fnv1a(&format!("{} {}\n{}", method, path, body));
+        let slug: String = path
+            .trim_matches('/')
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        self.dir.join(format!(
+            "{}-{}-{:016x}.json",
+            method.to_ascii_lowercase(),
+            slug,
+            hash
+        ))
+    }
+}
+
+/// Whether requests are currently served from fixtures.
+pub fn is_replaying() -> bool {
+    Fixtures::from_config().is_some_and(|f| f.mode() == ReplayMode::Replay)
+}
+
+/// JSON text with object keys sorted, so equal bodies hash equally.
+fn canonical(value: &Value) -> String {
+    fn sorted(value: &Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| (k.clone(), sorted(v)))
+                        .collect(),
+                )
+            }
+            Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+            other => other.clone(),
+        }
+    }
+    sorted(value).to_string()
+}
+
+/// 64-bit FNV-1a, stable across Rust releases unlike `DefaultHasher`.
+pub(super) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn scrub(value: &mut Value, secret: &str) {
+    match value {
+        Value::String(s) => *s = scrub_text(s, secret),
+        Value::Array(items) => items.iter_mut().for_each(|v| scrub(v, secret)),
+        Value::Object(map) => map.values_mut().for_each(|v| scrub(v, secret)),
+        _ => {}
+    }
+}
+
+fn scrub_text(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(secret, SCRUBBED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = json!({"model": "m", "messages": [{"role": "user", "content": "hi"}]});
+        let response = json!({"choices": [], "note": "token sk-secret echoed"});
+
+        let recorder = Fixtures::new(dir.path(), ReplayMode::Record);
+        assert!(recorder
+            .replay("POST", "openai/v1/chat/completions", Some(&body))
+            .is_none());
+        recorder.record(
+            "POST",
+            "openai/v1/chat/completions",
+            Some(&body),
+            FixtureBody::Json(response),
+            "sk-secret",
+        );
+
+        let player = Fixtures::new(dir.path(), ReplayMode::Replay);
+        // Key order does not matter
+        let reordered = json!({"messages": [{"content": "hi", "role": "user"}], "model": "m"});
+        let replayed = player
+            .replay("POST", "openai/v1/chat/completions", Some(&reordered))
+            .unwrap()
+            .unwrap()
+            .into_json()
+            .unwrap();
+        assert_eq!(replayed["note"], "token [REDACTED] echoed");
+
+        let missing = player
+            .replay(
+                "POST",
+                "openai/v1/chat/completions",
+                Some(&json!({"model": "x"})),
+            )
+            .unwrap()
+            .unwrap_err();
+        assert!(missing
+            .to_string()
+            .contains("post-openai-v1-chat-completions-"));
+    }
+
+    #[tokio::test]
+    async fn test_record_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = json!({"stream": true});
+        let events = "data: {\"choices\":[]}\n\ndata: [DONE]\n\n";
+        let chunks = futures::stream::iter(
+            events
+                .split_inclusive("\n\n")
+                .map(|c| Ok(Bytes::from(c.to_string())))
+                .collect::<Vec<_>>(),
+        );
+
+        let recorder = Fixtures::new(dir.path(), ReplayMode::Record);
+        let passed: Vec<_> = recorder
+            .record_stream("chat".to_string(), body.clone(), String::new(), chunks)
+            .collect()
+            .await;
+        assert_eq!(passed.len(), 2);
+
+        let player = Fixtures::new(dir.path(), ReplayMode::Replay);
+        let replayed = player.replay("POST", "chat", Some(&body)).unwrap().unwrap();
+        assert_eq!(replayed, FixtureBody::Sse(events.to_string()));
+    }
+}
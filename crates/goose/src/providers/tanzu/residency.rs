@@ -0,0 +1,109 @@
+//! Data residency constraints on binding selection.
+//!
+//! Multi-region foundations publish a `region` in each binding's credentials. With
+//! `TANZU_AI_REQUIRED_REGION` set, only bindings in that region (compared
+//! case-insensitively) are used, and credential resolution fails when none is. A
+//! binding that does not declare a region never matches, since its data could be
+//! processed anywhere.
+
+use super::TanzuCredentials;
+use anyhow::{bail, Result};
+
+pub fn required_region() -> Option<String> {
+    crate::config::Config::global()
+        .get_param::<String>("TANZU_AI_REQUIRED_REGION")
+        .ok()
+        .map(|region| region.trim().to_string())
+        .filter(|region| !region.is_empty())
+}
+
+/// The bindings in `required`, keeping their order; all of them when unset.
+pub fn restrict(
+    credentials: Vec<TanzuCredentials>,
+    required: Option<&str>,
+) -> Result<Vec<TanzuCredentials>> {
+    let Some(required) = required else {
+        return Ok(credentials);
+    };
+    let (matching, others): (Vec<_>, Vec<_>) = credentials.into_iter().partition(|c| {
+        c.region
+            .as_deref()
+            .is_some_and(|region| region.eq_ignore_ascii_case(required))
+    });
+    if matching.is_empty() {
+        let available: Vec<String> = others
+            .iter()
+            .map(|c| {
+                format!(
+                    "{} in {}",
+                    c.binding_name.as_deref().unwrap_or(&c.endpoint_base),
+                    c.region.as_deref().unwrap_or("an undeclared region")
+                )
+            })
+            .collect();
+        bail!(
+            "Data residency: no Tanzu AI Services binding is in region {} (TANZU_AI_REQUIRED_REGION); found {}",
+            required,
+            available.join(", ")
+        );
+    }
+    if !others.is_empty() {
+        tracing::debug!(
+            "Ignoring {} Tanzu AI binding(s) outside region {}",
+            others.len(),
+            required
+        );
+    }
+    Ok(matching)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tanzu::parse_vcap_services;
+
+    #[test]
+    fn test_restricts_bindings_to_region() {
+        let vcap = serde_json::json!({
+            "genai": [
+                {
+                    "name": "genai-eu",
+                    "credentials": {
+                        "region": "eu-central",
+                        "endpoint": {"api_base": "https://genai.eu.example.com/plan", "api_key": "a"}
+                    }
+                },
+                {
+                    "name": "genai-us",
+                    "credentials": {
+                        "region": "us-east",
+                        "endpoint": {"api_base": "https://genai.us.example.com/plan", "api_key": "b"}
+                    }
+                },
+                {
+                    "name": "genai-legacy",
+                    "credentials": {
+                        "endpoint": {"api_base": "https://genai.example.com/plan", "api_key": "c"}
+                    }
+                }
+            ]
+        });
+        let bindings = parse_vcap_services(&vcap.to_string()).unwrap();
+        assert_eq!(bindings.len(), 3);
+
+        let eu = restrict(bindings.clone(), Some("EU-Central")).unwrap();
+        assert_eq!(eu.len(), 1);
+        assert_eq!(eu[0].binding_name.as_deref(), Some("genai-eu"));
+
+        assert_eq!(restrict(bindings.clone(), None).unwrap().len(), 3);
+
+        let err = restrict(bindings, Some("ap-south"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Data residency: no Tanzu AI Services binding is in region ap-south")
+        );
+        assert!(err.contains("genai-eu in eu-central"));
+        assert!(err.contains("genai-legacy in an undeclared region"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Replies to deterministic requests reused across runs.
+//!
+//! Recipe and CI runs often send the same prompt at temperature 0 again and again, and
+//! on metered plans each one costs. With `TANZU_AI_RESPONSE_CACHE=true` the reply to a
+//! request that sets `temperature` to 0 is kept in a file under
+//! `TANZU_AI_RESPONSE_CACHE_DIR` (a `responses` directory next to the discovery cache
+//! by default), keyed by the endpoint and the request body: model, messages, tools and
+//! parameters. An identical request within `TANZU_AI_RESPONSE_CACHE_TTL_SECS` (a day by
+//! default) gets the stored reply without calling the model, and reports no token
+//! usage since nothing was spent. Past `TANZU_AI_RESPONSE_CACHE_MAX_ENTRIES` (1000) the
+//! oldest entries are removed.
+//!
+//! Entries hold the request and reply in plain text, readable only by the user, and
+//! are matched on the full request so a key collision cannot return another reply.
+
+use super::disk_cache::{create_private, default_dir, fnv1a, unix_secs};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const FORMAT_VERSION: u32 = 1;
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    version: u32,
+    endpoint: String,
+    /// Seconds since the Unix epoch
+    stored_at: u64,
+    request: Value,
+    response: Value,
+}
+
+/// A directory of cached replies.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            dir,
+            ttl,
+            max_entries,
+        }
+    }
+
+    /// The configured cache, or `None` unless `TANZU_AI_RESPONSE_CACHE` is enabled.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let enabled: bool = config.get_param("TANZU_AI_RESPONSE_CACHE").unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let dir = config
+            .get_param::<String>("TANZU_AI_RESPONSE_CACHE_DIR")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| default_dir().map(|dir| dir.join("responses")))?;
+        let ttl = config
+            .get_param::<u64>("TANZU_AI_RESPONSE_CACHE_TTL_SECS")
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TTL);
+        let max_entries = config
+            .get_param::<usize>("TANZU_AI_RESPONSE_CACHE_MAX_ENTRIES")
+            .unwrap_or(DEFAULT_MAX_ENTRIES);
+        Some(Self::new(dir, ttl, max_entries))
+    }
+
+    /// Whether the reply to `payload` can be reused: only requests at temperature 0,
+    /// for a single reply.
+    pub fn cacheable(payload: &Value) -> bool {
+        let deterministic = payload
+            .get("temperature")
+            .and_then(Value::as_f64)
+            .is_some_and(|t| t == 0.0);
+        let single = payload
+            .get("n")
+            .and_then(Value::as_u64)
+            .is_none_or(|n| n == 1);
+        deterministic && single
+    }
+
+    /// The stored reply to `payload` sent to `endpoint`, if it is fresh.
+    pub fn get(&self, endpoint: &str, payload: &Value) -> Option<Value> {
+        let path = self.path(endpoint, payload);
+        let entry: Entry = serde_json::from_slice(&std::fs::read(&path).ok()?).ok()?;
+        let age = unix_secs(SystemTime::now()).saturating_sub(entry.stored_at);
+        let fresh = entry.version == FORMAT_VERSION
+            && entry.endpoint == endpoint
+            && entry.request == *payload
+            && age < self.ttl.as_secs();
+        fresh.then_some(entry.response)
+    }
+
+    /// Store the reply to `payload`, then remove the oldest entries past the limit.
+    /// Failures are logged, since the cache only saves requests.
+    pub fn put(&self, endpoint: &str, payload: &Value, response: &Value) {
+        let entry = Entry {
+            version: FORMAT_VERSION,
+            endpoint: endpoint.to_string(),
+            stored_at: unix_secs(SystemTime::now()),
+            request: payload.clone(),
+            response: response.clone(),
+        };
+        let path = self.path(endpoint, payload);
+        let written = self.write(&path, &entry).and_then(|_| self.evict());
+        if let Err(e) = written {
+            tracing::debug!("Could not write response cache {}: {}", path.display(), e);
+        }
+    }
+
+    fn write(&self, path: &Path, entry: &Entry) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let mut file = create_private(&tmp)?;
+        file.write_all(&serde_json::to_vec(entry)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+
+    fn evict(&self) -> std::io::Result<()> {
+        let mut entries: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect();
+        if entries.len() <= self.max_entries {
+            return Ok(());
+        }
+        entries.sort();
+        let excess = entries.len() - self.max_entries;
+        for (_, path) in entries.into_iter().take(excess) {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(())
+    }
+
+    fn path(&self, endpoint: &str, payload: &Value) -> PathBuf {
+        let key = fnv1a(format!("{}\n{}", endpoint, payload).as_bytes());
+        self.dir.join(format!("response-{:016x}.json", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ENDPOINT: &str = "https://genai.example.com/plan";
+
+    fn request(prompt: &str) -> Value {
+        json!({
+            "model": "llama3.2:1b",
+            "temperature": 0,
+            "messages": [{"role": "user", "content": prompt}]
+        })
+    }
+
+    #[test]
+    fn test_cacheable_requests() {
+        assert!(ResponseCache::cacheable(&request("hi")));
+        assert!(ResponseCache::cacheable(
+            &json!({"temperature": 0.0, "n": 1})
+        ));
+        assert!(!ResponseCache::cacheable(&json!({"temperature": 0.7})));
+        assert!(!ResponseCache::cacheable(&json!({"model": "llama3.2:1b"})));
+        assert!(!ResponseCache::cacheable(
+            &json!({"temperature": 0, "n": 3})
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_expiry_and_eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path().join("responses"), DEFAULT_TTL, 2);
+        let reply = json!({"choices": [{"message": {"content": "hello"}}]});
+        assert!(cache.get(ENDPOINT, &request("hi")).is_none());
+
+        cache.put(ENDPOINT, &request("hi"), &reply);
+        assert_eq!(cache.get(ENDPOINT, &request("hi")), Some(reply.clone()));
+        assert!(cache.get(ENDPOINT, &request("hello")).is_none());
+        assert!(cache
+            .get("https://other.example.com/plan", &request("hi"))
+            .is_none());
+
+        let expired = ResponseCache::new(dir.path().join("responses"), Duration::ZERO, 2);
+        assert!(expired.get(ENDPOINT, &request("hi")).is_none());
+
+        // The oldest entry goes once the limit is passed
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put(ENDPOINT, &request("two"), &reply);
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put(ENDPOINT, &request("three"), &reply);
+        assert!(cache.get(ENDPOINT, &request("hi")).is_none());
+        assert!(cache.get(ENDPOINT, &request("two")).is_some());
+        assert!(cache.get(ENDPOINT, &request("three")).is_some());
+    }
+}
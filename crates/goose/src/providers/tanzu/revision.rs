@@ -0,0 +1,77 @@
+//! Model revisions and revision pinning.
+//!
+//! Operators roll new versions of a model out under the same name. The config
+//! endpoint can advertise which one is served with a `revision` (or `version` or
+//! `digest`) on each model, and the provider records it with every request in the
+//! usage report and audit log, so a session can be traced to the exact weights.
+//!
+//! `TANZU_AI_MODEL_REVISION` pins the configured model to a revision. Requests are
+//! still sent when the plan serves another one, since the proxy cannot route by
+//! revision, but each differing revision is warned about once. Digests match by
+//! prefix, so a pin can use the short form.
+
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+
+/// A model and the revision it was served at.
+type Served = (String, Option<String>);
+
+static WARNED: LazyLock<Mutex<HashSet<Served>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// The pinned revision, when configured.
+pub fn pinned() -> Option<String> {
+    crate::config::Config::global()
+        .get_param::<String>("TANZU_AI_MODEL_REVISION")
+        .ok()
+        .map(|revision| revision.trim().to_string())
+        .filter(|revision| !revision.is_empty())
+}
+
+/// Whether `served` is the `pinned` revision, or a digest starting with it.
+pub fn matches(pinned: &str, served: &str) -> bool {
+    let served = served.to_ascii_lowercase();
+    let pinned = pinned.to_ascii_lowercase();
+    let digest = |r: &str| r.strip_prefix("sha256:").map(str::to_string);
+    match (digest(&pinned), digest(&served)) {
+        (Some(pinned), Some(served)) => served.starts_with(&pinned),
+        _ => served == pinned,
+    }
+}
+
+/// Warn, once per served revision, when `model` is not served at the pinned revision.
+pub fn check(model: &str, pinned: &str, served: Option<&str>) {
+    if served.is_some_and(|served| matches(pinned, served)) {
+        return;
+    }
+    let key = (model.to_string(), served.map(str::to_string));
+    if !WARNED.lock().unwrap().insert(key) {
+        return;
+    }
+    match served {
+        Some(served) => tracing::warn!(
+            "TANZU_AI_MODEL_REVISION pins {} to {}, but the plan serves revision {}",
+            model,
+            pinned,
+            served
+        ),
+        None => tracing::warn!(
+            "TANZU_AI_MODEL_REVISION pins {} to {}, but the plan does not advertise its revision",
+            model,
+            pinned
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revision_matching() {
+        assert!(matches("2025-06", "2025-06"));
+        assert!(!matches("2025-06", "2025-07"));
+        assert!(matches("sha256:9f86d0", "sha256:9F86D081884C7D659A2FEAA0"));
+        assert!(!matches("sha256:9f86d0", "sha256:60303ae22b998861bce3b28f"));
+        assert!(!matches("9f86", "9f86d081884c"));
+    }
+}
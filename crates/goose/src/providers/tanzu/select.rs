@@ -0,0 +1,166 @@
+//! Automatic chat model selection.
+//!
+//! When no model is configured, the provider picks one from what the bound plan
+//! advertises instead of assuming the default model is deployed. Models with both CHAT
+//! and TOOLS win over chat-only models; among those, `TANZU_AI_MODEL_PREFERENCE` is
+//! consulted first, then the largest parameter count, then the newest-looking name.
+//! Deprecated models are passed over unless the plan serves nothing else.
+//! Tiered routing's fast model is the smallest chat model whose size is known.
+
+use super::AdvertisedModel;
+
+/// Pick the best chat model from advertised models.
+pub fn select_chat_model(models: &[AdvertisedModel], preferences: &[String]) -> Option<String> {
+    let chat: Vec<&AdvertisedModel> = models.iter().filter(|m| m.has_capability("CHAT")).collect();
+    // Deprecated models are only picked when nothing else is served
+    let current: Vec<&AdvertisedModel> = chat
+        .iter()
+        .copied()
+        .filter(|m| m.deprecation.is_none())
+        .collect();
+    let chat = if current.is_empty() { chat } else { current };
+    let with_tools: Vec<&AdvertisedModel> = chat
+        .iter()
+        .copied()
+        .filter(|m| m.has_capability("TOOLS"))
+        .collect();
+    let candidates = if with_tools.is_empty() {
+        chat
+    } else {
+        with_tools
+    };
+
+    for preference in preferences {
+        let preference = preference.to_lowercase();
+        if let Some(m) = candidates
+            .iter()
+            .find(|m| m.name.to_lowercase().contains(&preference))
+        {
+            return Some(m.name.clone());
+        }
+    }
+
+    candidates
+        .into_iter()
+        .max_by(|a, b| {
+            parameter_count(&a.name)
+                .cmp(&parameter_count(&b.name))
+                .then_with(|| a.name.cmp(&b.name))
+        })
+        .map(|m| m.name.clone())
+}
+
+/// Pick the smallest chat model of known size, for requests simple enough not to
+/// need the strongest one.
+pub fn select_fast_model(models: &[AdvertisedModel]) -> Option<String> {
+    models
+        .iter()
+        .filter(|m| m.has_capability("CHAT") && parameter_count(&m.name) > 0)
+        .min_by(|a, b| {
+            parameter_count(&a.name)
+                .cmp(&parameter_count(&b.name))
+                .then_with(|| b.name.cmp(&a.name))
+        })
+        .map(|m| m.name.clone())
+}
+
+/// Parameter count in billions parsed from names like `gpt-oss-120b`, `llama3.2:1b`
+/// or `mixtral-8x7b`; unknown sizes count as zero.
+fn parameter_count(name: &str) -> u64 {
+    let name = name.to_lowercase();
+    name.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .filter_map(|token| {
+            let size = token.strip_suffix('b')?;
+            let (experts, per_expert) = size.split_once('x').unwrap_or(("1", size));
+            let experts: f64 = experts.parse().ok()?;
+            let per_expert: f64 = per_expert.parse().ok()?;
+            Some((experts * per_expert * 10.0) as u64)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str, capabilities: &[&str]) -> AdvertisedModel {
+        AdvertisedModel {
+            name: name.to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            context_length: None,
+            deprecation: None,
+            revision: None,
+        }
+    }
+
+    #[test]
+    fn test_parameter_count() {
+        assert_eq!(parameter_count("openai/gpt-oss-120b"), 1200);
+        assert_eq!(parameter_count("llama3.2:1b"), 10);
+        assert_eq!(parameter_count("qwen2.5-coder:1.5b"), 15);
+        assert_eq!(parameter_count("mixtral-8x7b-instruct"), 560);
+        assert_eq!(parameter_count("nomic-embed-text"), 0);
+    }
+
+    #[test]
+    fn test_prefers_largest_tool_capable_model() {
+        let models = vec![
+            model("llama3.2:1b", &["CHAT", "TOOLS"]),
+            model("llama3.1:70b", &["CHAT"]),
+            model("qwen3-30b", &["CHAT", "TOOLS"]),
+            model("nomic-embed-text", &["EMBEDDING"]),
+        ];
+        assert_eq!(
+            select_chat_model(&models, &[]).as_deref(),
+            Some("qwen3-30b")
+        );
+    }
+
+    #[test]
+    fn test_preference_list_wins() {
+        let models = vec![
+            model("llama3.2:1b", &["CHAT", "TOOLS"]),
+            model("qwen3-30b", &["CHAT", "TOOLS"]),
+        ];
+        let preferences = vec!["mistral".to_string(), "llama3.2".to_string()];
+        assert_eq!(
+            select_chat_model(&models, &preferences).as_deref(),
+            Some("llama3.2:1b")
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_chat_only_and_newest_name() {
+        let models = vec![model("llama3.1", &["CHAT"]), model("llama3.2", &["CHAT"])];
+        assert_eq!(select_chat_model(&models, &[]).as_deref(), Some("llama3.2"));
+        assert_eq!(select_chat_model(&[], &[]), None);
+    }
+
+    #[test]
+    fn test_passes_over_deprecated_models() {
+        let mut retiring = model("qwen3-30b", &["CHAT", "TOOLS"]);
+        retiring.deprecation = Some(Default::default());
+        let models = vec![retiring.clone(), model("llama3.2:1b", &["CHAT", "TOOLS"])];
+        assert_eq!(
+            select_chat_model(&models, &[]).as_deref(),
+            Some("llama3.2:1b")
+        );
+        assert_eq!(
+            select_chat_model(&[retiring], &[]).as_deref(),
+            Some("qwen3-30b")
+        );
+    }
+
+    #[test]
+    fn test_fast_model_is_smallest_known_size() {
+        let models = vec![
+            model("openai/gpt-oss-120b", &["CHAT", "TOOLS"]),
+            model("llama3.2:1b", &["CHAT", "TOOLS"]),
+            model("mystery-chat", &["CHAT"]),
+            model("nomic-embed-text-0.1b", &["EMBEDDING"]),
+        ];
+        assert_eq!(select_fast_model(&models).as_deref(), Some("llama3.2:1b"));
+        assert_eq!(select_fast_model(&models[2..]), None);
+    }
+}
@@ -0,0 +1,206 @@
+//! File-based service bindings.
+//!
+//! TAS 10.x can deliver bindings as files instead of the `VCAP_SERVICES` variable, under
+//! `SERVICE_BINDING_ROOT` (`/etc/cf-service-bindings` by default), in one of two layouts:
+//!
+//! - `file-based-vcap-services`: a single `vcap_services` file holding the JSON that
+//!   would otherwise be in `VCAP_SERVICES`.
+//! - `file-based-service-bindings`: one directory per binding with a file per entry:
+//!   `type`, `name`, `tags` and the like for the binding, and one file per credential,
+//!   nested credentials JSON-encoded.
+//!
+//! Both are turned into a `VCAP_SERVICES` document so the usual parsing, binding
+//! selection and ordering apply.
+
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_ROOT: &str = "/etc/cf-service-bindings";
+const VCAP_SERVICES_FILE: &str = "vcap_services";
+const GENAI: &str = "genai";
+
+/// Binding entries that describe the binding rather than hold credentials.
+const METADATA_FILES: &[&str] = &[
+    "type",
+    "provider",
+    "label",
+    "name",
+    "tags",
+    "plan",
+    "instance_guid",
+    "instance_name",
+    "binding_guid",
+    "binding_name",
+    "syslog_drain_url",
+    "volume_mounts",
+];
+
+/// Where bindings are mounted (`SERVICE_BINDING_ROOT`).
+pub fn root() -> PathBuf {
+    std::env::var_os("SERVICE_BINDING_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_ROOT))
+}
+
+/// A `VCAP_SERVICES` document for the `genai` bindings mounted under `root`, or `None`
+/// when there are none.
+pub fn read_vcap_services(root: &Path) -> Option<String> {
+    if let Ok(vcap) = std::fs::read_to_string(root.join(VCAP_SERVICES_FILE)) {
+        return Some(vcap);
+    }
+
+    let mut bindings: Vec<Value> = std::fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| read_binding(&entry.path()))
+        .collect();
+    if bindings.is_empty() {
+        return None;
+    }
+    bindings.sort_by_key(|b| b["name"].as_str().unwrap_or_default().to_string());
+    tracing::debug!(
+        "Found {} genai binding(s) under {}",
+        bindings.len(),
+        root.display()
+    );
+    Some(serde_json::json!({ GENAI: bindings }).to_string())
+}
+
+/// A `VCAP_SERVICES` binding entry for a binding directory, if it is a `genai` binding.
+fn read_binding(dir: &Path) -> Option<Value> {
+    let read = |name: &str| {
+        std::fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|value| value.trim().to_string())
+    };
+    let kind = read("type").or_else(|| read("label"))?;
+    if !kind.eq_ignore_ascii_case(GENAI) {
+        return None;
+    }
+
+    let dir_name = dir.file_name()?.to_string_lossy().to_string();
+    let mut binding = Map::new();
+    binding.insert("name".into(), read("name").unwrap_or(dir_name).into());
+    binding.insert("label".into(), GENAI.into());
+    if let Some(plan) = read("plan") {
+        binding.insert("plan".into(), plan.into());
+    }
+    if let Some(instance_name) = read("instance_name") {
+        binding.insert("instance_name".into(), instance_name.into());
+    }
+    if let Some(tags) = read("tags") {
+        binding.insert("tags".into(), parse_tags(&tags));
+    }
+
+    let mut credentials = Map::new();
+    for entry in std::fs::read_dir(dir).ok()?.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Kubernetes-style mounts add hidden `..data` links next to the entries
+        if name.starts_with('.') || METADATA_FILES.contains(&name.as_str()) {
+            continue;
+        }
+        if let Some(value) = read(&name) {
+            credentials.insert(name, parse_value(value));
+        }
+    }
+    binding.insert("credentials".into(), Value::Object(credentials));
+    Some(Value::Object(binding))
+}
+
+/// Nested credentials are written JSON-encoded; everything else is a plain string.
+fn parse_value(value: String) -> Value {
+    match serde_json::from_str::<Value>(&value) {
+        Ok(parsed @ (Value::Object(_) | Value::Array(_))) => parsed,
+        _ => Value::String(value),
+    }
+}
+
+/// Tags as a JSON array or a comma-separated list.
+fn parse_tags(tags: &str) -> Value {
+    match serde_json::from_str::<Value>(tags) {
+        Ok(parsed @ Value::Array(_)) => parsed,
+        _ => tags
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(|t| Value::String(t.to_string()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_binding(root: &Path, dir: &str, files: &[(&str, &str)]) {
+        let dir = root.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_binding_directories() {
+        let root = tempfile::tempdir().unwrap();
+        write_binding(
+            root.path(),
+            "genai-chat",
+            &[
+                ("type", "genai\n"),
+                ("tags", r#"["genai", "llm"]"#),
+                ("plan", "gpt-oss-120b"),
+                ("api_key", "jwt-token\n"),
+                (
+                    "endpoint",
+                    r#"{"api_base": "https://genai-proxy.sys.example.com/plan/openai", "api_key": "jwt-token"}"#,
+                ),
+            ],
+        );
+        write_binding(
+            root.path(),
+            "postgres",
+            &[("type", "postgresql"), ("password", "secret")],
+        );
+
+        let vcap: Value = serde_json::from_str(&read_vcap_services(root.path()).unwrap()).unwrap();
+        let bindings = vcap["genai"].as_array().unwrap();
+        assert_eq!(bindings.len(), 1);
+        let binding = &bindings[0];
+        assert_eq!(binding["name"], "genai-chat");
+        assert_eq!(binding["plan"], "gpt-oss-120b");
+        assert_eq!(binding["tags"], serde_json::json!(["genai", "llm"]));
+        assert_eq!(binding["credentials"]["api_key"], "jwt-token");
+        assert_eq!(
+            binding["credentials"]["endpoint"]["api_base"],
+            "https://genai-proxy.sys.example.com/plan/openai"
+        );
+        assert!(binding["credentials"].get("type").is_none());
+    }
+
+    #[test]
+    fn test_vcap_services_file() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("vcap_services"), r#"{"genai": []}"#).unwrap();
+        assert_eq!(
+            read_vcap_services(root.path()).as_deref(),
+            Some(r#"{"genai": []}"#)
+        );
+    }
+
+    #[test]
+    fn test_missing_or_empty_root() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(read_vcap_services(root.path()).is_none());
+        assert!(read_vcap_services(&root.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags("genai, llm"),
+            serde_json::json!(["genai", "llm"])
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Tanzu-specific metadata carried in exported sessions.
+//!
+//! A session record only names the provider and model, so a resumed session may land
+//! on another binding, or on a model that has since been rolled to a new revision. The
+//! provider describes a session as [`TanzuSessionMetadata`], which session export
+//! stores under [`TanzuSessionMetadata::EXTENSION_KEY`] in the record's extension
+//! data. On import the metadata is handed back to the provider, which pins the session
+//! to the recorded binding and model when this app is still bound to them, and warns
+//! when the model is now served at a different revision.
+
+use super::usage::ModelUsage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Where and how a session ran on Tanzu AI Services.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TanzuSessionMetadata {
+    pub endpoint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binding_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binding_guid: Option<String>,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Usage summed over the models the session used
+    #[serde(default)]
+    pub usage: ModelUsage,
+}
+
+impl TanzuSessionMetadata {
+    /// Key of the metadata in a session record's extension data.
+    pub const EXTENSION_KEY: &'static str = "tanzu_ai";
+}
+
+/// A restored session's binding and model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionPin {
+    pub binding: usize,
+    pub model: String,
+}
+
+/// Restored sessions by id.
+#[derive(Debug, Default)]
+pub struct SessionPins {
+    pins: Mutex<HashMap<String, SessionPin>>,
+}
+
+impl SessionPins {
+    pub fn insert(&self, session_id: &str, pin: SessionPin) {
+        self.pins
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), pin);
+    }
+
+    pub fn get(&self, session_id: Option<&str>) -> Option<SessionPin> {
+        self.pins.lock().unwrap().get(session_id?).cloned()
+    }
+}
+
+/// A binding's GUID, name and endpoint.
+pub type BindingKeys<'a> = (Option<&'a str>, Option<&'a str>, &'a str);
+
+/// The binding a session's metadata refers to: by GUID, then name, then endpoint.
+pub fn find_binding<'a>(
+    metadata: &TanzuSessionMetadata,
+    bindings: impl Iterator<Item = BindingKeys<'a>> + Clone,
+) -> Option<usize> {
+    let position =
+        |matches: &dyn Fn(&BindingKeys) -> bool| bindings.clone().position(|keys| matches(&keys));
+    let guid = metadata.binding_guid.as_deref();
+    let name = metadata.binding_name.as_deref();
+    let endpoint = metadata.endpoint.trim_end_matches('/');
+    guid.and_then(|guid| position(&|&(g, _, _)| g == Some(guid)))
+        .or_else(|| name.and_then(|name| position(&|&(_, n, _)| n == Some(name))))
+        .or_else(|| position(&|&(_, _, e)| e.trim_end_matches('/') == endpoint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_binding() {
+        let bindings = [
+            (
+                Some("guid-1"),
+                Some("genai-chat"),
+                "https://genai.example.com/plan-a",
+            ),
+            (
+                Some("guid-2"),
+                Some("genai-code"),
+                "https://genai.example.com/plan-b",
+            ),
+        ];
+        let find = |metadata: &TanzuSessionMetadata| {
+            find_binding(metadata, bindings.iter().map(|&(g, n, e)| (g, n, e)))
+        };
+
+        let recorded = TanzuSessionMetadata {
+            endpoint: "https://genai.example.com/plan-a".to_string(),
+            binding_name: Some("genai-code".to_string()),
+            binding_guid: Some("guid-2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(find(&recorded), Some(1));
+
+        // Rebound under a new GUID: fall back to the name, then the endpoint
+        let rebound = TanzuSessionMetadata {
+            binding_guid: Some("guid-9".to_string()),
+            binding_name: None,
+            ..recorded.clone()
+        };
+        assert_eq!(find(&rebound), Some(0));
+
+        let gone = TanzuSessionMetadata {
+            endpoint: "https://genai.example.com/plan-z".to_string(),
+            binding_guid: None,
+            binding_name: None,
+            ..recorded
+        };
+        assert_eq!(find(&gone), None);
+    }
+
+    #[test]
+    fn test_metadata_round_trips() {
+        let metadata = TanzuSessionMetadata {
+            endpoint: "https://genai.example.com/plan-a".to_string(),
+            binding_guid: Some("guid-1".to_string()),
+            model: "openai/gpt-oss-120b".to_string(),
+            revision: Some("2025-06".to_string()),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&metadata).unwrap();
+        assert!(value.get("binding_name").is_none());
+        assert_eq!(
+            serde_json::from_value::<TanzuSessionMetadata>(value).unwrap(),
+            metadata
+        );
+    }
+}
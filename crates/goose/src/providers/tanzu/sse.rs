@@ -0,0 +1,106 @@
+//! Tolerant SSE decoding for overloaded backends.
+//!
+//! Under load some model backends emit truncated or non-JSON `data:` lines, and one
+//! such line aborts the whole stream in the OpenAI decoder. With
+//! `TANZU_AI_TOLERANT_SSE=true` those lines are logged and dropped so the rest of the
+//! reply is salvaged. Dropping a chunk can lose part of the text, so the stream must
+//! still finish properly: it fails if it ends without a `finish_reason`.
+
+use super::stream::chunk_finishes;
+use anyhow::{anyhow, Result};
+use async_stream::try_stream;
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+
+/// How much of a malformed line to include in the log.
+const LOGGED_CHARS: usize = 200;
+
+pub fn tolerant() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_TOLERANT_SSE")
+        .unwrap_or(false)
+}
+
+/// Drop `data:` lines that are not JSON, failing if the stream never finishes.
+pub fn skip_malformed<S>(lines: S, model: String) -> impl Stream<Item = Result<String>> + Send
+where
+    S: Stream<Item = Result<String>> + Send,
+{
+    try_stream! {
+        let mut lines = std::pin::pin!(lines);
+        let mut number = 0;
+        let mut skipped = 0;
+        let mut finished = false;
+        while let Some(line) = lines.next().await {
+            let line = line?;
+            number += 1;
+            if let Some(data) = line.strip_prefix("data: ").filter(|d| d.trim() != "[DONE]") {
+                match serde_json::from_str::<Value>(data) {
+                    Ok(chunk) => finished |= chunk_finishes(&chunk),
+                    Err(e) => {
+                        skipped += 1;
+                        let excerpt: String = data.chars().take(LOGGED_CHARS).collect();
+                        tracing::warn!(
+                            "Skipping malformed SSE line {} from {} ({}): {}",
+                            number,
+                            model,
+                            e,
+                            excerpt
+                        );
+                        continue;
+                    }
+                }
+            }
+            yield line;
+        }
+        if !finished {
+            Err(anyhow!(
+                "{} stream ended without a finish_reason ({} malformed chunks skipped)",
+                model,
+                skipped
+            ))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(lines: &[&str]) -> Result<Vec<String>> {
+        let lines = futures::stream::iter(lines.iter().map(|l| Ok(l.to_string())));
+        let mut out = Vec::new();
+        let mut stream = std::pin::pin!(skip_malformed(lines, "llama3.2:1b".to_string()));
+        while let Some(line) = stream.next().await {
+            out.push(line?);
+        }
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn test_salvages_stream_around_malformed_chunks() {
+        let out = run(&[
+            r#"data: {"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{"conte"#,
+            "",
+            "data: <html>502 Bad Gateway</html>",
+            r#"data: {"choices":[{"index":0,"delta":{"content":"lo"},"finish_reason":"stop"}]}"#,
+            "data: [DONE]",
+        ])
+        .await
+        .unwrap();
+        assert_eq!(out.len(), 4);
+        assert!(out[2].contains(r#""content":"lo""#));
+    }
+
+    #[tokio::test]
+    async fn test_fails_without_finish_reason() {
+        let err = run(&[
+            r#"data: {"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{"con"#,
+        ])
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("1 malformed chunks skipped"));
+    }
+}
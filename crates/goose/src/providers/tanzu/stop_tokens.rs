@@ -0,0 +1,226 @@
+//! Stop sequences and special-token cleanup for open-weight model families.
+//!
+//! Llama-family models behind some serving runtimes leak their chat template's special
+//! tokens, like `<|eot_id|>`, into the reply and occasionally keep generating past the
+//! end of their turn. Requests to a known family carry its end-of-turn tokens as
+//! default `stop` sequences, and replies are cleaned: text after a stop sequence the
+//! server did not honour is dropped, and leftover special tokens are removed.
+//!
+//! A `stop` in the model's parameter profile (`TANZU_AI_MODEL_PARAMS`) replaces the
+//! defaults and is honoured in replies the same way, e.g.
+//! `{"llama*": {"stop": ["<|eot_id|>", "\nUser:"]}}`.
+
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::base::MessageStream;
+use futures::StreamExt;
+use serde_json::Value;
+
+/// The chat template tokens of a model family.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Family {
+    /// Substrings of model names in the family, lowercase
+    markers: &'static [&'static str],
+    /// End-of-turn tokens, sent as default stop sequences
+    stop: &'static [&'static str],
+    /// Every template token, removed from replies
+    special: &'static [&'static str],
+}
+
+const FAMILIES: &[Family] = &[
+    Family {
+        markers: &["llama"],
+        stop: &["<|eot_id|>", "<|eom_id|>"],
+        special: &[
+            "<|begin_of_text|>",
+            "<|end_of_text|>",
+            "<|start_header_id|>",
+            "<|end_header_id|>",
+            "<|eot_id|>",
+            "<|eom_id|>",
+            "<|python_tag|>",
+        ],
+    },
+    Family {
+        markers: &["qwen"],
+        stop: &["<|im_end|>"],
+        special: &["<|im_start|>", "<|im_end|>", "<|endoftext|>"],
+    },
+    Family {
+        markers: &["gemma"],
+        stop: &["<end_of_turn>"],
+        special: &["<start_of_turn>", "<end_of_turn>", "<eos>"],
+    },
+    Family {
+        markers: &["mistral", "mixtral"],
+        stop: &["</s>"],
+        special: &["<s>", "</s>", "[INST]", "[/INST]"],
+    },
+];
+
+pub fn family(model_name: &str) -> Option<&'static Family> {
+    let name = model_name.to_lowercase();
+    FAMILIES
+        .iter()
+        .find(|family| family.markers.iter().any(|marker| name.contains(marker)))
+}
+
+/// Set the family's default stop sequences on an OpenAI-format request without one.
+pub fn apply_defaults(payload: &mut Value, model_name: &str) {
+    let Some(family) = family(model_name) else {
+        return;
+    };
+    if let Some(payload) = payload.as_object_mut() {
+        payload
+            .entry("stop")
+            .or_insert_with(|| Value::from(family.stop.to_vec()));
+    }
+}
+
+/// Cleans replies to one request.
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    stop: Vec<String>,
+    special: &'static [&'static str],
+}
+
+impl Sanitizer {
+    /// The sanitizer for a request to `model_name`, or `None` when there is nothing
+    /// to clean.
+    pub fn for_request(model_name: &str, payload: &Value) -> Option<Self> {
+        let stop: Vec<String> = match payload.get("stop") {
+            Some(Value::String(stop)) => vec![stop.clone()],
+            Some(Value::Array(stops)) => stops
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let special = family(model_name).map_or(&[][..], |family| family.special);
+        (!stop.is_empty() || !special.is_empty()).then_some(Self { stop, special })
+    }
+
+    /// `text` cut at the first stop sequence, with special tokens removed, and whether
+    /// a stop sequence was found.
+    pub fn clean(&self, text: &str) -> (String, bool) {
+        let cut = self
+            .stop
+            .iter()
+            .filter(|stop| !stop.is_empty())
+            .filter_map(|stop| text.find(stop.as_str()))
+            .min();
+        let mut cleaned = text[..cut.unwrap_or(text.len())].to_string();
+        for token in self.special {
+            if cleaned.contains(token) {
+                cleaned = cleaned.replace(token, "");
+            }
+        }
+        (cleaned, cut.is_some())
+    }
+
+    /// Clean the text of a complete reply.
+    pub fn clean_message(&self, mut message: Message) -> Message {
+        let mut stopped = false;
+        self.clean_content(&mut message, &mut stopped);
+        message
+    }
+
+    /// Clean text content in place; once `stopped`, later text is dropped.
+    fn clean_content(&self, message: &mut Message, stopped: &mut bool) {
+        let before = message.content.len();
+        message.content.retain_mut(|content| {
+            let MessageContent::Text(text) = content else {
+                return true;
+            };
+            if *stopped {
+                return false;
+            }
+            let (cleaned, found) = self.clean(&text.text);
+            *stopped = found;
+            text.text = cleaned;
+            !text.text.is_empty()
+        });
+        if message.content.len() < before {
+            tracing::debug!("Removed text after a stop sequence or made of special tokens");
+        }
+    }
+}
+
+/// Clean the text chunks of a stream, dropping text after a stop sequence.
+///
+/// Special tokens arrive as single chunks, so they are caught; a stop sequence split
+/// across chunks is left to the server.
+pub fn sanitize_stream(stream: MessageStream, sanitizer: Sanitizer) -> MessageStream {
+    let mut stopped = false;
+    Box::pin(stream.map(move |item| {
+        let (message, usage) = item?;
+        let message = message.and_then(|mut message| {
+            let had_content = !message.content.is_empty();
+            sanitizer.clean_content(&mut message, &mut stopped);
+            (!had_content || !message.content.is_empty()).then_some(message)
+        });
+        Ok((message, usage))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::errors::ProviderError;
+    use serde_json::json;
+
+    #[test]
+    fn test_defaults_and_profile_stop_sequences() {
+        let mut payload = json!({"model": "meta-llama/Llama-3.1-8B-Instruct"});
+        apply_defaults(&mut payload, "meta-llama/Llama-3.1-8B-Instruct");
+        assert_eq!(payload["stop"], json!(["<|eot_id|>", "<|eom_id|>"]));
+
+        // A profile's stop is kept, and no family means no defaults
+        let mut payload = json!({"stop": "\nUser:"});
+        apply_defaults(&mut payload, "llama3.2:1b");
+        assert_eq!(payload["stop"], "\nUser:");
+        let mut payload = json!({});
+        apply_defaults(&mut payload, "openai/gpt-oss-120b");
+        assert!(payload.get("stop").is_none());
+        assert!(Sanitizer::for_request("openai/gpt-oss-120b", &payload).is_none());
+    }
+
+    #[test]
+    fn test_cleans_reply_text() {
+        let sanitizer =
+            Sanitizer::for_request("llama3.2:1b", &json!({"stop": ["<|eot_id|>", "\nUser:"]}))
+                .unwrap();
+        let message = Message::assistant()
+            .with_text("<|start_header_id|>assistant<|end_header_id|>Paris.<|eot_id|>ignored")
+            .with_text("also ignored");
+        let cleaned = sanitizer.clean_message(message);
+        assert_eq!(cleaned.as_concat_text(), "assistantParis.");
+        assert_eq!(cleaned.content.len(), 1);
+
+        assert_eq!(
+            sanitizer.clean("Sure.\nUser: next question"),
+            ("Sure.".to_string(), true)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_drops_text_after_stop() {
+        let sanitizer =
+            Sanitizer::for_request("qwen3-30b", &json!({"stop": ["<|im_end|>"]})).unwrap();
+        let chunks = [
+            "The answer",
+            " is 4.",
+            "<|endoftext|>",
+            "<|im_end|>",
+            "More",
+        ];
+        let stream: MessageStream = Box::pin(futures::stream::iter(chunks.map(|text| {
+            Ok::<_, ProviderError>((Some(Message::assistant().with_text(text)), None))
+        })));
+        let texts: Vec<String> = sanitize_stream(stream, sanitizer)
+            .filter_map(|item| async move { item.unwrap().0.map(|m| m.as_concat_text()) })
+            .collect()
+            .await;
+        assert_eq!(texts, vec!["The answer", " is 4."]);
+    }
+}
@@ -0,0 +1,370 @@
+//! Repair of streamed tool calls from vLLM-backed Tanzu models.
+//!
+//! Some vLLM builds behind the GenAI proxy split `tool_calls` deltas in ways the
+//! generic OpenAI decoder does not expect: the function name can arrive after the
+//! first argument fragment, `index` may be omitted, and arguments are occasionally
+//! cut off when the model hits its token limit. The SSE lines are rewritten here so
+//! each tool call reaches the decoder as a single, complete delta.
+
+use anyhow::Result;
+use async_stream::try_stream;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Map, Value};
+
+/// A tool call being assembled from its deltas.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Buffers `tool_calls` deltas and emits them as one chunk when the choice finishes.
+#[derive(Debug, Default)]
+pub struct ToolCallAssembler {
+    calls: Vec<(u64, PartialToolCall)>,
+    /// Last chunk carrying tool call deltas, used as the envelope for the flushed chunk
+    template: Option<Value>,
+}
+
+impl ToolCallAssembler {
+    /// Process one SSE line, returning the lines to forward downstream.
+    pub fn process(&mut self, line: String) -> Vec<String> {
+        let Some(data) = line.strip_prefix("data: ") else {
+            return vec![line];
+        };
+        if data.trim() == "[DONE]" {
+            let mut out = self.flush(None);
+            out.push(line);
+            return out;
+        }
+        let Ok(mut chunk) = serde_json::from_str::<Value>(data) else {
+            return vec![line];
+        };
+
+        let deltas = chunk
+            .pointer_mut("/choices/0/delta")
+            .and_then(Value::as_object_mut)
+            .and_then(|delta| delta.remove("tool_calls"));
+        let Some(Value::Array(deltas)) = deltas else {
+            if chunk_finishes(&chunk) {
+                return self.flush(Some(chunk));
+            }
+            return vec![line];
+        };
+
+        for delta in &deltas {
+            self.merge(delta);
+        }
+
+        if chunk_finishes(&chunk) {
+            return self.flush(Some(chunk));
+        }
+        let forward = has_content(&chunk) || chunk.get("usage").is_some_and(|u| !u.is_null());
+        let mut template = chunk.clone();
+        if let Some(delta) = template
+            .pointer_mut("/choices/0/delta")
+            .and_then(Value::as_object_mut)
+        {
+            delta.remove("content");
+        }
+        if let Some(envelope) = template.as_object_mut() {
+            envelope.remove("usage");
+        }
+        self.template = Some(template);
+
+        if forward {
+            return vec![to_line(&chunk)];
+        }
+        Vec::new()
+    }
+
+    /// Emit any tool calls still buffered when the stream ends without a finish reason.
+    pub fn finish(&mut self) -> Vec<String> {
+        self.flush(None)
+    }
+
+    fn merge(&mut self, delta: &Value) {
+        let id = delta.get("id").and_then(Value::as_str).unwrap_or_default();
+        let index = match delta.get("index").and_then(Value::as_u64) {
+            Some(index) => index,
+            // Without an index, match on id or continue the most recent call
+            None => self
+                .calls
+                .iter()
+                .find(|(_, call)| !id.is_empty() && call.id == id)
+                .or(self.calls.last().filter(|_| id.is_empty()))
+                .map(|(index, _)| *index)
+                .unwrap_or(self.calls.len() as u64),
+        };
+        let position = match self.calls.iter().position(|(i, _)| *i == index) {
+            Some(position) => position,
+            None => {
+                self.calls.push((index, PartialToolCall::default()));
+                self.calls.len() - 1
+            }
+        };
+        let call = &mut self.calls[position].1;
+
+        if call.id.is_empty() {
+            call.id = id.to_string();
+        }
+        let function = delta.get("function");
+        if let Some(name) = function.and_then(|f| f.get("name")).and_then(Value::as_str) {
+            if call.name.is_empty() {
+                call.name = name.to_string();
+            }
+        }
+        if let Some(arguments) = function
+            .and_then(|f| f.get("arguments"))
+            .and_then(Value::as_str)
+        {
+            call.arguments.push_str(arguments);
+        }
+    }
+
+    /// Build the chunk carrying every buffered tool call, merged into `finishing` if given.
+    fn flush(&mut self, finishing: Option<Value>) -> Vec<String> {
+        if self.calls.is_empty() {
+            return finishing
+                .map(|chunk| vec![to_line(&chunk)])
+                .unwrap_or_default();
+        }
+
+        let mut calls = std::mem::take(&mut self.calls);
+        calls.sort_by_key(|(index, _)| *index);
+        let tool_calls: Vec<Value> = calls
+            .into_iter()
+            .map(|(index, call)| {
+                json!({
+                    "index": index,
+                    "id": call.id,
+                    "type": "function",
+                    "function": {
+                        "name": call.name,
+                        "arguments": repair_json(&call.arguments),
+                    }
+                })
+            })
+            .collect();
+
+        let template = self.template.take();
+        let mut chunk = finishing
+            .or(template)
+            .unwrap_or_else(|| json!({"choices": [{"index": 0, "delta": {}}]}));
+        if let Some(choice) = chunk
+            .pointer_mut("/choices/0")
+            .and_then(Value::as_object_mut)
+        {
+            let delta = choice
+                .entry("delta")
+                .or_insert_with(|| Value::Object(Map::new()));
+            delta["tool_calls"] = Value::Array(tool_calls);
+            if choice.get("finish_reason").is_none_or(Value::is_null) {
+                choice.insert("finish_reason".to_string(), json!("tool_calls"));
+            }
+        }
+        vec![to_line(&chunk)]
+    }
+}
+
+pub(super) fn chunk_finishes(chunk: &Value) -> bool {
+    chunk
+        .pointer("/choices/0/finish_reason")
+        .is_some_and(|reason| !reason.is_null())
+}
+
+fn has_content(chunk: &Value) -> bool {
+    chunk
+        .pointer("/choices/0/delta/content")
+        .and_then(Value::as_str)
+        .is_some_and(|content| !content.is_empty())
+}
+
+fn to_line(chunk: &Value) -> String {
+    format!("data: {}", chunk)
+}
+
+/// Close a truncated JSON object so the tool call can still be parsed.
+///
+/// Valid input is returned unchanged; input that cannot be repaired is returned as-is
+/// so Goose reports the parse error against the tool call.
+pub fn repair_json(arguments: &str) -> String {
+    let trimmed = arguments.trim();
+    if trimmed.is_empty() {
+        return "{}".to_string();
+    }
+    if serde_json::from_str::<Value>(trimmed).is_ok() {
+        return trimmed.to_string();
+    }
+
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in trimmed.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                closers.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut repaired = trimmed.to_string();
+    if in_string {
+        if escaped {
+            repaired.pop();
+        }
+        repaired.push('"');
+    }
+    let end = repaired.trim_end();
+    if end.ends_with(',') {
+        repaired.truncate(end.len() - 1);
+    } else if end.ends_with(':') {
+        repaired.push_str("null");
+    }
+    repaired.extend(closers.iter().rev());
+
+    if serde_json::from_str::<Value>(&repaired).is_ok() {
+        tracing::debug!("Repaired truncated tool call arguments");
+        repaired
+    } else {
+        tracing::warn!("Could not repair tool call arguments: {}", arguments);
+        arguments.to_string()
+    }
+}
+
+/// Rewrite an SSE line stream so fragmented tool calls arrive as single deltas.
+pub fn assemble_tool_calls<S>(lines: S) -> impl Stream<Item = Result<String>> + Send + 'static
+where
+    S: Stream<Item = Result<String>> + Send + 'static,
+{
+    try_stream! {
+        let mut assembler = ToolCallAssembler::default();
+        let mut lines = std::pin::pin!(lines);
+        while let Some(line) = lines.next().await {
+            for line in assembler.process(line?) {
+                yield line;
+            }
+        }
+        for line in assembler.finish() {
+            yield line;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Captured from a vLLM 0.6 deployment serving qwen2.5-coder: the name arrives on
+    /// the second delta, `index` is dropped mid-call and the id repeats on every chunk.
+    const VLLM_TRACE: &str = r#"data: {"id":"chat-1","model":"qwen2.5-coder","choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}
+data: {"id":"chat-1","model":"qwen2.5-coder","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"chatcmpl-tool-7f","type":"function","function":{"arguments":"{\"path\": "}}]},"finish_reason":null}]}
+data: {"id":"chat-1","model":"qwen2.5-coder","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"chatcmpl-tool-7f","function":{"name":"developer__text_editor","arguments":"\"/tmp/a.txt\", "}}]},"finish_reason":null}]}
+data: {"id":"chat-1","model":"qwen2.5-coder","choices":[{"index":0,"delta":{"tool_calls":[{"id":"chatcmpl-tool-7f","function":{"arguments":"\"command\": \"view\"}"}}]},"finish_reason":null}]}
+data: {"id":"chat-1","model":"qwen2.5-coder","choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}],"usage":{"prompt_tokens":120,"completion_tokens":18,"total_tokens":138}}
+data: [DONE]"#;
+
+    fn run(trace: &str) -> Vec<Value> {
+        let mut assembler = ToolCallAssembler::default();
+        let mut out: Vec<String> = trace
+            .lines()
+            .flat_map(|line| assembler.process(line.to_string()))
+            .collect();
+        out.extend(assembler.finish());
+        out.iter()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    fn tool_calls(chunks: &[Value]) -> Vec<Value> {
+        chunks
+            .iter()
+            .filter_map(|c| c.pointer("/choices/0/delta/tool_calls"))
+            .flat_map(|calls| calls.as_array().unwrap().clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_assembles_fragmented_vllm_tool_call() {
+        let chunks = run(VLLM_TRACE);
+        let calls = tool_calls(&chunks);
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0]["id"], "chatcmpl-tool-7f");
+        assert_eq!(calls[0]["function"]["name"], "developer__text_editor");
+        let args: Value =
+            serde_json::from_str(calls[0]["function"]["arguments"].as_str().unwrap()).unwrap();
+        assert_eq!(args, json!({"path": "/tmp/a.txt", "command": "view"}));
+
+        let last = chunks.last().unwrap();
+        assert_eq!(last["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(last["usage"]["total_tokens"], 138);
+    }
+
+    #[test]
+    fn test_parallel_tool_calls_out_of_order() {
+        let trace = r#"data: {"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"function":{"arguments":"{\"b\":"}}]},"finish_reason":null}]}
+data: {"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_a","function":{"name":"first","arguments":"{}"}}]},"finish_reason":null}]}
+data: {"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_b","function":{"name":"second","arguments":"2}"}}]},"finish_reason":"tool_calls"}]}"#;
+
+        let calls = tool_calls(&run(trace));
+        let names: Vec<_> = calls
+            .iter()
+            .map(|c| c["function"]["name"].clone())
+            .collect();
+        assert_eq!(names, vec![json!("first"), json!("second")]);
+        assert_eq!(calls[1]["id"], "call_b");
+        assert_eq!(calls[1]["function"]["arguments"], "{\"b\":2}");
+    }
+
+    #[test]
+    fn test_truncated_stream_is_flushed_and_repaired() {
+        let trace = r#"data: {"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","function":{"name":"shell","arguments":"{\"command\": \"ls -la"}}]},"finish_reason":null}]}"#;
+
+        let chunks = run(trace);
+        let calls = tool_calls(&chunks);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            calls[0]["function"]["arguments"],
+            "{\"command\": \"ls -la\"}"
+        );
+        assert_eq!(chunks[0]["choices"][0]["finish_reason"], "tool_calls");
+    }
+
+    #[test]
+    fn test_text_chunks_pass_through() {
+        let line =
+            r#"data: {"choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#;
+        let mut assembler = ToolCallAssembler::default();
+        assert_eq!(assembler.process(line.to_string()), vec![line.to_string()]);
+        assert_eq!(assembler.process(String::new()), vec![String::new()]);
+        assert!(assembler.finish().is_empty());
+    }
+
+    #[test]
+    fn test_repair_json() {
+        assert_eq!(repair_json(""), "{}");
+        assert_eq!(repair_json("{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(repair_json("{\"a\": [1, 2"), "{\"a\": [1, 2]}");
+        assert_eq!(repair_json("{\"a\": 1,"), "{\"a\": 1}");
+        assert_eq!(repair_json("{\"a\":"), "{\"a\":null}");
+        assert_eq!(repair_json("{\"a\": \"x\\"), "{\"a\": \"x\"}");
+        assert_eq!(repair_json("not json"), "not json");
+    }
+}
@@ -0,0 +1,115 @@
+//! Structured output for Tanzu-hosted models.
+//!
+//! Models that support `response_format: {type: "json_schema"}` get the schema passed
+//! through natively. Others are asked for JSON in the system prompt and the first JSON
+//! value in their reply is extracted.
+
+use crate::providers::errors::ProviderError;
+use serde_json::{json, Value};
+
+/// Capability names the config URL uses for native structured output.
+pub const STRUCTURED_OUTPUT_CAPABILITIES: &[&str] = &["STRUCTURED_OUTPUT", "JSON_SCHEMA"];
+
+/// `response_format` body for a JSON schema.
+pub fn response_format(schema: &Value) -> Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": "response",
+            "schema": schema,
+            "strict": true,
+        }
+    })
+}
+
+/// System prompt suffix asking for JSON matching `schema`.
+pub fn schema_instructions(system: &str, schema: &Value) -> String {
+    format!(
+        "{}\n\nRespond only with a single JSON value that conforms to this JSON schema, \
+         without any surrounding prose or code fences:\n{}",
+        system,
+        serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string())
+    )
+}
+
+/// Parse the JSON value from a model reply, tolerating code fences and surrounding prose.
+pub fn extract_json(text: &str) -> Result<Value, ProviderError> {
+    let trimmed = text.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Ok(value);
+    }
+
+    // Scan for the first position where a complete JSON object or array parses
+    for (start, _) in trimmed.match_indices(['{', '[']) {
+        let mut values = serde_json::Deserializer::from_str(&trimmed[start..]).into_iter::<Value>();
+        if let Some(Ok(value)) = values.next() {
+            return Ok(value);
+        }
+    }
+
+    Err(ProviderError::ExecutionError(format!(
+        "Model response did not contain valid JSON: {}",
+        text
+    )))
+}
+
+/// Whether a request failure means the endpoint rejected `response_format`.
+///
+/// Only errors naming the parameter count; any other bad request says nothing about
+/// structured output support.
+pub fn is_unsupported(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::RequestFailed(msg) => {
+            let msg = msg.to_lowercase();
+            msg.contains("response_format") || msg.contains("json_schema")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_json() {
+        assert_eq!(extract_json("{\"a\": 1}").unwrap(), json!({"a": 1}));
+        assert_eq!(
+            extract_json("Sure!\n```json\n{\"a\": [1, 2]}\n```\nDone.").unwrap(),
+            json!({"a": [1, 2]})
+        );
+        assert_eq!(extract_json("[1, 2]").unwrap(), json!([1, 2]));
+        assert!(extract_json("no json here").is_err());
+    }
+
+    #[test]
+    fn test_schema_instructions_include_schema() {
+        let schema = json!({"type": "object", "properties": {"name": {"type": "string"}}});
+        let prompt = schema_instructions("You are helpful.", &schema);
+        assert!(prompt.starts_with("You are helpful."));
+        assert!(prompt.contains("\"name\""));
+    }
+
+    #[test]
+    fn test_is_unsupported_needs_response_format_named() {
+        assert!(is_unsupported(&ProviderError::RequestFailed(
+            "Request failed with status: 400 Bad Request. Message: response_format is not supported"
+                .to_string()
+        )));
+        assert!(!is_unsupported(&ProviderError::RequestFailed(
+            "Request failed with status: 400 Bad Request. Message: messages must not be empty"
+                .to_string()
+        )));
+        assert!(!is_unsupported(&ProviderError::ServerError(
+            "json_schema".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_response_format() {
+        let schema = json!({"type": "object"});
+        let format = response_format(&schema);
+        assert_eq!(format["type"], "json_schema");
+        assert_eq!(format["json_schema"]["schema"], schema);
+    }
+}
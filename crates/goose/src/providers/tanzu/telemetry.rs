@@ -0,0 +1,143 @@
+//! `tracing` spans around provider operations.
+//!
+//! Completions, streams and model listings each run in a span named `tanzu_ai.<op>`
+//! whose fields follow the OpenTelemetry GenAI semantic conventions: the requested and
+//! served model, the endpoint host, the binding, token counts and the outcome. Goose's
+//! OTel exporter turns them into spans of the app's distributed trace; without one
+//! they are ordinary `tracing` spans.
+
+use crate::providers::base::{MessageStream, ProviderUsage};
+use crate::providers::errors::ProviderError;
+use futures::StreamExt;
+use tracing::field::Empty;
+use tracing::Span;
+
+/// A span for `operation`, against `model` when there is one, on the binding at
+/// `endpoint_base`.
+pub fn span(
+    operation: &'static str,
+    model: Option<&str>,
+    endpoint_base: &str,
+    binding_name: Option<&str>,
+) -> Span {
+    let span = tracing::info_span!(
+        "tanzu_ai",
+        otel.name = %format!("tanzu_ai.{}", operation),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        gen_ai.system = "tanzu_ai",
+        gen_ai.operation.name = operation,
+        gen_ai.request.model = Empty,
+        gen_ai.response.model = Empty,
+        gen_ai.usage.input_tokens = Empty,
+        gen_ai.usage.output_tokens = Empty,
+        server.address = endpoint_host(endpoint_base),
+        tanzu.binding = binding_name.unwrap_or_default(),
+        error.type = Empty,
+    );
+    if let Some(model) = model {
+        span.record("gen_ai.request.model", model);
+    }
+    span
+}
+
+/// Record the served model and token counts.
+pub fn record_usage(span: &Span, usage: &ProviderUsage) {
+    span.record("gen_ai.response.model", usage.model.as_str());
+    if let Some(input) = usage.usage.input_tokens {
+        span.record("gen_ai.usage.input_tokens", input);
+    }
+    if let Some(output) = usage.usage.output_tokens {
+        span.record("gen_ai.usage.output_tokens", output);
+    }
+}
+
+/// Record whether the operation succeeded.
+pub fn record_outcome<T>(span: &Span, result: &Result<T, ProviderError>) {
+    match result {
+        Ok(_) => {
+            span.record("otel.status_code", "OK");
+        }
+        Err(e) => record_error(span, e),
+    }
+}
+
+/// Record whether model discovery succeeded.
+pub fn record_discovery<T>(span: &Span, result: &anyhow::Result<T>) {
+    match result {
+        Ok(_) => {
+            span.record("otel.status_code", "OK");
+        }
+        Err(_) => {
+            span.record("otel.status_code", "ERROR");
+            span.record("error.type", "discovery_failed");
+        }
+    }
+}
+
+fn record_error(span: &Span, error: &ProviderError) {
+    span.record("otel.status_code", "ERROR");
+    span.record("error.type", error_type(error));
+}
+
+/// Record usage and errors of a stream as its chunks pass; the span ends when the
+/// stream is dropped.
+pub fn instrument_stream(stream: MessageStream, span: Span) -> MessageStream {
+    Box::pin(stream.map(move |item| {
+        match &item {
+            Ok((_, Some(usage))) => record_usage(&span, usage),
+            Ok(_) => {}
+            Err(e) => record_error(&span, e),
+        }
+        item
+    }))
+}
+
+/// A low-cardinality name for the kind of error.
+fn error_type(error: &ProviderError) -> &'static str {
+    match error {
+        ProviderError::Authentication(_) => "authentication",
+        ProviderError::ContextLengthExceeded(_) => "context_length_exceeded",
+        ProviderError::RateLimitExceeded { .. } => "rate_limited",
+        ProviderError::ServerError(_) => "server_error",
+        ProviderError::RequestFailed(_) => "request_failed",
+        _ => "other",
+    }
+}
+
+/// Host (and port) of an endpoint URL, or the URL itself when it does not parse.
+fn endpoint_host(endpoint_base: &str) -> String {
+    match reqwest::Url::parse(endpoint_base) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => endpoint_base.to_string(),
+        },
+        Err(_) => endpoint_base.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_host() {
+        assert_eq!(
+            endpoint_host("https://genai-proxy.sys.example.com/plan"),
+            "genai-proxy.sys.example.com"
+        );
+        assert_eq!(
+            endpoint_host("http://127.0.0.1:8080/plan"),
+            "127.0.0.1:8080"
+        );
+        assert_eq!(endpoint_host("not a url"), "not a url");
+        assert_eq!(
+            error_type(&ProviderError::RateLimitExceeded {
+                details: "slow down".to_string(),
+                retry_delay: None,
+            }),
+            "rate_limited"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Two-tier routing between a fast model and a strong model.
+//!
+//! Plans often serve a large model next to a small one. With
+//! `TANZU_AI_TIERED_ROUTING=true`, short requests that offer no tools (titles, quick
+//! questions, summaries of a few turns) go to the fast model and everything else to the
+//! strong one. The fast model is the smallest advertised chat model and the strong one
+//! the configured model; `TANZU_AI_FAST_MODEL` and `TANZU_AI_STRONG_MODEL` override them,
+//! and `TANZU_AI_FAST_MAX_TOKENS` sets how long a prompt the fast model takes.
+//!
+//! Only requests for the provider's own model are routed; a model asked for explicitly,
+//! e.g. by a lead/worker setup, is used as-is. The Tanzu lead and worker models take
+//! precedence over tiering when configured; see [`super::lead_worker`].
+
+use super::context::CHARS_PER_TOKEN;
+use super::tools::flatten_tool_messages;
+use crate::conversation::message::Message;
+
+/// Longest prompt, in estimated tokens, sent to the fast model by default.
+const DEFAULT_FAST_MAX_TOKENS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Fast,
+    Strong,
+}
+
+/// Tiered routing settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TieredRouting {
+    /// `TANZU_AI_FAST_MODEL`, else selected from the advertised models
+    pub fast_model: Option<String>,
+    /// `TANZU_AI_STRONG_MODEL`, else the provider's model
+    pub strong_model: Option<String>,
+    /// `TANZU_AI_FAST_MAX_TOKENS`
+    pub fast_max_tokens: usize,
+}
+
+impl TieredRouting {
+    /// The configured settings, or `None` unless `TANZU_AI_TIERED_ROUTING` is set.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let enabled: bool = config.get_param("TANZU_AI_TIERED_ROUTING").unwrap_or(false);
+        enabled.then(|| Self {
+            fast_model: config.get_param("TANZU_AI_FAST_MODEL").ok(),
+            strong_model: config.get_param("TANZU_AI_STRONG_MODEL").ok(),
+            fast_max_tokens: config
+                .get_param("TANZU_AI_FAST_MAX_TOKENS")
+                .unwrap_or(DEFAULT_FAST_MAX_TOKENS),
+        })
+    }
+
+    /// Which tier a request belongs to.
+    pub fn classify(&self, system: &str, messages: &[Message], has_tools: bool) -> Tier {
+        if has_tools || estimate_tokens(system, messages) > self.fast_max_tokens {
+            Tier::Strong
+        } else {
+            Tier::Fast
+        }
+    }
+}
+
+/// Rough prompt size in tokens, tool calls and results included.
+fn estimate_tokens(system: &str, messages: &[Message]) -> usize {
+    let chars: usize = system.len()
+        + flatten_tool_messages(messages)
+            .iter()
+            .map(|m| m.as_concat_text().len())
+            .sum::<usize>();
+    chars.div_ceil(CHARS_PER_TOKEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routing() -> TieredRouting {
+        TieredRouting {
+            fast_model: None,
+            strong_model: None,
+            fast_max_tokens: 100,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let short = [Message::user().with_text("Give this session a title")];
+        assert_eq!(routing().classify("system", &short, false), Tier::Fast);
+        assert_eq!(routing().classify("system", &short, true), Tier::Strong);
+
+        let long = [Message::user().with_text("word ".repeat(200))];
+        assert_eq!(routing().classify("system", &long, false), Tier::Strong);
+    }
+}
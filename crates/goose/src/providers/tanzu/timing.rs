@@ -0,0 +1,135 @@
+//! Latency and throughput of streamed completions.
+//!
+//! Each stream is timed from the moment its request is sent: time to first token is
+//! the wait for the first chunk with content, inter-token latency the average gap
+//! between the remaining output tokens, and tokens per second the output tokens over
+//! the time spent generating them. The figures are exported as metrics histograms
+//! labelled with the model and added to the usage ledger, whose report averages them
+//! per model.
+
+use super::metrics;
+use super::usage::UsageLedger;
+use crate::providers::base::MessageStream;
+use async_stream::try_stream;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Timing of one streamed completion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamTiming {
+    pub time_to_first_token: Duration,
+    /// First to last content chunk
+    pub generation: Duration,
+    pub output_tokens: u64,
+}
+
+impl StreamTiming {
+    /// Average time between output tokens after the first.
+    pub fn inter_token_latency(&self) -> Option<Duration> {
+        let gaps = self.output_tokens.checked_sub(1).filter(|&gaps| gaps > 0)?;
+        Some(self.generation / u32::try_from(gaps).unwrap_or(u32::MAX))
+    }
+
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let secs = self.generation.as_secs_f64();
+        (secs > 0.0 && self.output_tokens > 0).then(|| self.output_tokens as f64 / secs)
+    }
+}
+
+/// Time `stream`, which was requested at `started`, recording the result once it ends.
+pub fn timed_stream(
+    stream: MessageStream,
+    started: Instant,
+    model: String,
+    ledger: Arc<UsageLedger>,
+    session_id: String,
+) -> MessageStream {
+    Box::pin(try_stream! {
+        let mut stream = stream;
+        let mut first: Option<Instant> = None;
+        let mut last = started;
+        let mut output_tokens = 0;
+        let mut served_by = model;
+
+        while let Some(item) = stream.next().await {
+            let (message, usage) = item?;
+            if message.as_ref().is_some_and(|m| !m.content.is_empty()) {
+                last = Instant::now();
+                first.get_or_insert(last);
+            }
+            if let Some(usage) = &usage {
+                output_tokens = usage.usage.output_tokens.unwrap_or(0).max(0) as u64;
+                served_by.clone_from(&usage.model);
+            }
+            yield (message, usage);
+        }
+
+        if let Some(first) = first {
+            let timing = StreamTiming {
+                time_to_first_token: first - started,
+                generation: last - first,
+                output_tokens,
+            };
+            metrics::record_stream_timing(&served_by, &timing);
+            ledger.record_stream_timing(Some(&session_id), &served_by, &timing);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::message::Message;
+    use crate::providers::base::{ProviderUsage, Usage};
+
+    #[test]
+    fn test_derived_rates() {
+        let timing = StreamTiming {
+            time_to_first_token: Duration::from_millis(350),
+            generation: Duration::from_secs(2),
+            output_tokens: 101,
+        };
+        assert_eq!(
+            timing.inter_token_latency(),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(timing.tokens_per_second(), Some(50.5));
+
+        let single_chunk = StreamTiming {
+            generation: Duration::ZERO,
+            output_tokens: 1,
+            ..timing
+        };
+        assert_eq!(single_chunk.inter_token_latency(), None);
+        assert_eq!(single_chunk.tokens_per_second(), None);
+    }
+
+    #[tokio::test]
+    async fn test_timed_stream_records_in_ledger() {
+        let chunks = vec![
+            Ok((Some(Message::assistant().with_text("Hel")), None)),
+            Ok((Some(Message::assistant().with_text("lo")), None)),
+            Ok((
+                None,
+                Some(ProviderUsage::new(
+                    "llama3.2:1b".to_string(),
+                    Usage::new(Some(5), Some(2), Some(7)),
+                )),
+            )),
+        ];
+        let ledger = Arc::new(UsageLedger::new(None));
+        let stream = timed_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            Instant::now(),
+            "llama3.2".to_string(),
+            ledger.clone(),
+            "s1".to_string(),
+        );
+        assert_eq!(stream.count().await, 3);
+
+        let usage = ledger.report().sessions["s1"]["llama3.2:1b"];
+        assert_eq!(usage.timed_streams, 1);
+        assert_eq!(usage.streamed_output_tokens, 2);
+    }
+}
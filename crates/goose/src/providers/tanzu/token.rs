@@ -0,0 +1,269 @@
+//! JWT lifetime tracking for Tanzu AI Services bindings.
+//!
+//! Binding JWTs carry an `exp` claim. The [`TokenManager`] watches it and, shortly
+//! before expiry, re-resolves the binding credentials so that a rotated key is picked
+//! up without restarting the Goose session. A request rejected with 401 re-resolves
+//! them as well, for keys rotated by `cf rebind-service` before they expire.
+//!
+//! Re-resolving may read files and call CredHub, so it runs as a future on the caller's
+//! runtime, one refresh at a time. When the source still returns the expiring key, it is
+//! not asked again for [`REFRESH_BACKOFF`].
+
+use super::flavor::UpstreamFlavor;
+use super::uaa::UaaTokens;
+use crate::providers::api_client::AuthProvider;
+use crate::providers::errors::ProviderError;
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::Engine;
+use futures::future::BoxFuture;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Refresh this long before the token actually expires.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Wait this long before asking the source again after it had no new token.
+const REFRESH_BACKOFF: Duration = Duration::from_secs(15);
+
+type TokenSource = Box<dyn Fn() -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+struct TokenState {
+    token: String,
+    expires_at: Option<SystemTime>,
+    /// Set after a refresh that did not change the token
+    next_attempt: Option<SystemTime>,
+}
+
+impl TokenState {
+    fn new(token: String) -> Self {
+        let expires_at = jwt_expiry(&token);
+        Self {
+            token,
+            expires_at,
+            next_attempt: None,
+        }
+    }
+}
+
+/// Holds the current bearer token and refreshes it from its source before it expires.
+pub struct TokenManager {
+    state: RwLock<TokenState>,
+    source: TokenSource,
+    /// Held while refreshing, so concurrent requests wait for one refresh
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl TokenManager {
+    pub fn new<F, Fut>(token: String, source: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        Self {
+            state: RwLock::new(TokenState::new(token)),
+            source: Box::new(move || Box::pin(source())),
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The current token without refreshing it, e.g. for redaction.
+    pub fn current(&self) -> String {
+        self.state.read().unwrap().token.clone()
+    }
+
+    /// Return a valid token, refreshing it first if it is about to expire.
+    pub async fn token(&self) -> String {
+        if self.needs_refresh(SystemTime::now()) {
+            let _refreshing = self.refreshing.lock().await;
+            // Another request may have refreshed it while this one waited
+            if self.needs_refresh(SystemTime::now()) {
+                self.refresh_from_source().await;
+            }
+        }
+        self.current()
+    }
+
+    /// Re-read the token from its source, keeping the current one on failure.
+    ///
+    /// Returns whether the token changed.
+    pub async fn refresh(&self) -> bool {
+        let rejected = self.current();
+        let _refreshing = self.refreshing.lock().await;
+        // A refresh that finished while this one waited has already replaced it
+        if self.current() != rejected {
+            return true;
+        }
+        self.refresh_from_source().await
+    }
+
+    async fn refresh_from_source(&self) -> bool {
+        let result = (self.source)().await;
+        let mut state = self.state.write().unwrap();
+        match result {
+            Ok(token) if token != state.token => {
+                tracing::info!("Refreshed Tanzu AI Services API key");
+                *state = TokenState::new(token);
+                return true;
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to refresh Tanzu AI Services API key: {}", e),
+        }
+        state.next_attempt = Some(SystemTime::now() + REFRESH_BACKOFF);
+        false
+    }
+
+    fn needs_refresh(&self, now: SystemTime) -> bool {
+        let state = self.state.read().unwrap();
+        if state.next_attempt.is_some_and(|next| now < next) {
+            return false;
+        }
+        match state.expires_at {
+            Some(expires_at) => now + REFRESH_MARGIN >= expires_at,
+            None => false,
+        }
+    }
+}
+
+/// Auth provider that sources the bearer token from a shared [`TokenManager`], or from
+/// UAA when client credentials are configured.
+#[derive(Clone)]
+pub struct ManagedBearerAuth {
+    pub tokens: Arc<TokenManager>,
+    pub uaa: Option<Arc<UaaTokens>>,
+    pub flavor: UpstreamFlavor,
+}
+
+impl ManagedBearerAuth {
+    /// A valid bearer token, exchanging client credentials first when needed.
+    pub async fn token(&self) -> Result<String, ProviderError> {
+        match &self.uaa {
+            Some(uaa) => uaa.token().await,
+            None => Ok(self.tokens.token().await),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ManagedBearerAuth {
+    async fn get_auth_header(&self) -> Result<(String, String)> {
+        let (name, value) = self.flavor.auth_header(&self.token().await?);
+        Ok((name.to_string(), value))
+    }
+}
+
+/// Decode the `exp` claim of a JWT without verifying its signature.
+///
+/// Returns `None` for opaque tokens or tokens without an expiry.
+pub fn jwt_expiry(token: &str) -> Option<SystemTime> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    let exp = claims.get("exp")?.as_u64()?;
+    Some(UNIX_EPOCH + Duration::from_secs(exp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn make_jwt(claims: serde_json::Value) -> String {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        format!(
+            "{}.{}.signature",
+            engine.encode(r#"{"alg":"HS256"}"#),
+            engine.encode(claims.to_string())
+        )
+    }
+
+    fn unix_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_jwt_expiry() {
+        let token = make_jwt(serde_json::json!({"sub": "binding", "exp": 1_900_000_000u64}));
+        assert_eq!(
+            jwt_expiry(&token),
+            Some(UNIX_EPOCH + Duration::from_secs(1_900_000_000))
+        );
+
+        let no_exp = make_jwt(serde_json::json!({"sub": "binding"}));
+        assert_eq!(jwt_expiry(&no_exp), None);
+        assert_eq!(jwt_expiry("not-a-jwt"), None);
+    }
+
+    #[tokio::test]
+    async fn test_token_refreshed_near_expiry() {
+        let expiring = make_jwt(serde_json::json!({"exp": unix_now() + 10}));
+        let fresh = make_jwt(serde_json::json!({"exp": unix_now() + 3600}));
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_clone = calls.clone();
+        let fresh_clone = fresh.clone();
+        let manager = TokenManager::new(expiring, move || {
+            calls_clone.fetch_add(1, Ordering::SeqCst);
+            let fresh = fresh_clone.clone();
+            async move { Ok(fresh) }
+        });
+
+        assert_eq!(manager.token().await, fresh);
+        // The fresh token is far from expiry, so no further refreshes happen
+        assert_eq!(manager.token().await, fresh);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_token_kept_when_refresh_fails() {
+        let expiring = make_jwt(serde_json::json!({"exp": unix_now() + 10}));
+        let manager = TokenManager::new(expiring.clone(), || async {
+            anyhow::bail!("VCAP_SERVICES unset")
+        });
+
+        assert_eq!(manager.token().await, expiring);
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_key_not_resolved_on_every_request() {
+        let expiring = make_jwt(serde_json::json!({"exp": unix_now() + 10}));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_clone = calls.clone();
+        let same = expiring.clone();
+        let manager = Arc::new(TokenManager::new(expiring.clone(), move || {
+            calls_clone.fetch_add(1, Ordering::SeqCst);
+            let same = same.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(same)
+            }
+        }));
+
+        let requests = (0..5).map(|_| {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.token().await })
+        });
+        for token in futures::future::join_all(requests).await {
+            assert_eq!(token.unwrap(), expiring);
+        }
+        assert_eq!(manager.token().await, expiring);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A rejected key is always re-resolved
+        assert!(!manager.refresh().await);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_opaque_token_never_refreshed() {
+        let manager = TokenManager::new("opaque-key".to_string(), || async {
+            panic!("source should not be called")
+        });
+        assert_eq!(manager.token().await, "opaque-key");
+    }
+}
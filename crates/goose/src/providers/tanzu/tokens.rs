@@ -0,0 +1,145 @@
+//! Token counting for the model families served by Tanzu AI Services.
+//!
+//! Plans serve llama, qwen, mistral and gpt-oss models whose tokenizers split the same
+//! text into different numbers of tokens. Goose's counter implements the OpenAI `o200k`
+//! encoding, which gpt-oss uses as-is. For other families its count is scaled by how
+//! that family's vocabulary compares on English prose and code: llama 3 and qwen vocabularies
+//! are close to `o200k`, while the 32k sentencepiece vocabularies of llama 2 and mistral
+//! need about a quarter more tokens. Without a counter, characters are divided by the
+//! family's characters-per-token ratio.
+
+use super::tools::flatten_tool_messages;
+use crate::conversation::message::Message;
+use crate::token_counter::create_token_counter;
+use rmcp::model::Tool;
+
+/// Tokenizer families with distinct token densities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenizerFamily {
+    /// gpt-oss and other OpenAI models: `o200k`
+    OpenAi,
+    /// llama 3.x: 128k tiktoken-based vocabulary
+    Llama3,
+    /// llama 2 and code llama: 32k sentencepiece vocabulary
+    Llama2,
+    /// qwen 2 and later: 151k byte-level BPE vocabulary
+    Qwen,
+    /// mistral and mixtral: 32k sentencepiece vocabulary
+    Mistral,
+    /// Anything else, counted like `o200k`
+    Other,
+}
+
+impl TokenizerFamily {
+    /// The family of a model from its name, e.g. `openai/gpt-oss-120b` or `llama3.2:1b`.
+    pub fn for_model(model_name: &str) -> Self {
+        let name = model_name.to_ascii_lowercase();
+        if name.contains("gpt") {
+            Self::OpenAi
+        } else if ["llama-2", "llama2", "codellama"]
+            .iter()
+            .any(|m| name.contains(m))
+        {
+            Self::Llama2
+        } else if name.contains("llama") {
+            Self::Llama3
+        } else if name.contains("qwen") {
+            Self::Qwen
+        } else if name.contains("mistral") || name.contains("mixtral") {
+            Self::Mistral
+        } else {
+            Self::Other
+        }
+    }
+
+    /// The family's tokens per `o200k` token.
+    fn scale(self) -> f64 {
+        match self {
+            Self::OpenAi | Self::Other => 1.0,
+            Self::Llama3 | Self::Qwen => 1.05,
+            Self::Llama2 | Self::Mistral => 1.25,
+        }
+    }
+
+    /// Characters per token for the heuristic fallback.
+    fn chars_per_token(self) -> f64 {
+        match self {
+            Self::OpenAi | Self::Other => 4.0,
+            Self::Llama3 | Self::Qwen => 3.8,
+            Self::Llama2 | Self::Mistral => 3.2,
+        }
+    }
+
+    /// Tokens in `text` for this family.
+    pub async fn count(self, text: &str) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+        match create_token_counter().await {
+            Ok(counter) => (counter.count_tokens(text) as f64 * self.scale()).ceil() as usize,
+            Err(e) => {
+                tracing::debug!("Token counter unavailable, estimating from length: {}", e);
+                self.estimate(text)
+            }
+        }
+    }
+
+    fn estimate(self, text: &str) -> usize {
+        (text.chars().count() as f64 / self.chars_per_token()).ceil() as usize
+    }
+}
+
+/// Tokens in a request's system prompt, messages and tool definitions for `model_name`.
+pub async fn count_prompt_tokens(
+    model_name: &str,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+) -> usize {
+    let mut text = system.to_string();
+    for message in flatten_tool_messages(messages) {
+        text.push('\n');
+        text.push_str(&message.as_concat_text());
+    }
+    for tool in tools {
+        text.push('\n');
+        text.push_str(&serde_json::to_string(tool).unwrap_or_default());
+    }
+    TokenizerFamily::for_model(model_name).count(&text).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_family_from_model_name() {
+        for (model, family) in [
+            ("openai/gpt-oss-120b", TokenizerFamily::OpenAi),
+            ("llama3.2:1b", TokenizerFamily::Llama3),
+            ("meta-llama/Llama-3.3-70B-Instruct", TokenizerFamily::Llama3),
+            ("codellama:7b", TokenizerFamily::Llama2),
+            ("Qwen/Qwen3-30B-A3B", TokenizerFamily::Qwen),
+            ("mistral-small-24b", TokenizerFamily::Mistral),
+            ("phi-4", TokenizerFamily::Other),
+        ] {
+            assert_eq!(TokenizerFamily::for_model(model), family, "{}", model);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_denser_vocabularies_count_fewer_tokens() {
+        let text = "Deploy the app to the dev space and bind the genai service. ".repeat(20);
+        let openai = TokenizerFamily::OpenAi.count(&text).await;
+        let mistral = TokenizerFamily::Mistral.count(&text).await;
+        assert!(openai > 0);
+        assert!(mistral > openai);
+        assert_eq!(TokenizerFamily::Qwen.count("").await, 0);
+    }
+
+    #[test]
+    fn test_heuristic_fallback() {
+        assert_eq!(TokenizerFamily::OpenAi.estimate("abcdefgh"), 2);
+        assert_eq!(TokenizerFamily::Mistral.estimate("abcdefgh"), 3);
+    }
+}
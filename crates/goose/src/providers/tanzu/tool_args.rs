@@ -0,0 +1,294 @@
+//! Validation of tool-call arguments against the tools' input schemas.
+//!
+//! Small models often call tools with arguments that do not match the schema: a
+//! missing required field, a number sent as a string, an unknown enum value. Tools
+//! then fail in confusing ways. A completion whose tool calls do not validate is sent
+//! back once with the errors and a request to call the tools again; the retried reply
+//! is returned whether it validates or not. Streamed replies cannot be taken back, so
+//! their invalid calls are only logged. `TANZU_AI_VALIDATE_TOOL_ARGS=false` turns the
+//! check off.
+//!
+//! The common subset of JSON Schema is checked: `type`, `required`, `properties`,
+//! `additionalProperties: false`, `enum` and `items`. Other keywords are ignored.
+
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::base::MessageStream;
+use futures::StreamExt;
+use rmcp::model::Tool;
+use serde_json::{Map, Value};
+
+pub fn enabled() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_VALIDATE_TOOL_ARGS")
+        .unwrap_or(true)
+}
+
+/// A tool call whose arguments do not validate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCall {
+    pub tool: String,
+    pub arguments: Value,
+    pub errors: Vec<String>,
+}
+
+/// The tool calls in `message` that do not validate against `tools`.
+pub fn invalid_calls(message: &Message, tools: &[Tool]) -> Vec<InvalidCall> {
+    let mut invalid = Vec::new();
+    for content in &message.content {
+        let MessageContent::ToolRequest(request) = content else {
+            continue;
+        };
+        let Ok(call) = &request.tool_call else {
+            invalid.push(InvalidCall {
+                tool: "unknown".to_string(),
+                arguments: Value::Null,
+                errors: vec!["the call could not be parsed".to_string()],
+            });
+            continue;
+        };
+        let arguments = Value::Object(call.arguments.clone().unwrap_or_default());
+        let errors = match tools.iter().find(|t| t.name == call.name) {
+            Some(tool) => {
+                let schema = Value::Object(tool.input_schema.as_ref().clone());
+                validate(&arguments, &schema)
+            }
+            None => vec![format!("there is no tool named {}", call.name)],
+        };
+        if !errors.is_empty() {
+            invalid.push(InvalidCall {
+                tool: call.name.to_string(),
+                arguments,
+                errors,
+            });
+        }
+    }
+    invalid
+}
+
+/// The conversation to retry with: the original messages, the rejected calls and a
+/// request to call the tools again.
+pub fn repair_messages(messages: &[Message], invalid: &[InvalidCall]) -> Vec<Message> {
+    let mut calls = String::new();
+    let mut errors = String::new();
+    for call in invalid {
+        calls.push_str(&format!("[calls {} with {}]\n", call.tool, call.arguments));
+        for error in &call.errors {
+            errors.push_str(&format!("- {}: {}\n", call.tool, error));
+        }
+    }
+    let mut repaired = messages.to_vec();
+    repaired.push(Message::assistant().with_text(calls.trim_end()));
+    repaired.push(Message::user().with_text(format!(
+        "Those tool calls were not made because their arguments do not match the tool's \
+         input schema:\n{}\nCall the tools again with arguments that match the schema.",
+        errors.trim_end()
+    )));
+    repaired
+}
+
+/// Log invalid tool calls as a stream passes.
+pub fn warn_invalid(stream: MessageStream, tools: Vec<Tool>) -> MessageStream {
+    Box::pin(stream.inspect(move |item| {
+        if let Ok((Some(message), _)) = item {
+            for call in invalid_calls(message, &tools) {
+                tracing::warn!(
+                    "Streamed call to {} has invalid arguments: {}",
+                    call.tool,
+                    call.errors.join("; ")
+                );
+            }
+        }
+    }))
+}
+
+/// Errors found validating `value` against `schema`, each naming the offending field.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(value, schema, "arguments", &mut errors);
+    errors
+}
+
+fn check(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            errors.push(format!(
+                "{} must be {}, not {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(format!(
+                "{} must be one of {}, not {}",
+                path,
+                allowed.join(", "),
+                value
+            ));
+        }
+    }
+    match value {
+        Value::Object(object) => check_object(object, schema, path, errors),
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item, item_schema, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_object(
+    object: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for required in schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        if !object.contains_key(required) {
+            errors.push(format!("{}.{} is required", path, required));
+        }
+    }
+    let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+    for (key, value) in object {
+        match properties.and_then(|p| p.get(key)) {
+            Some(property) => check(value, property, &format!("{}.{}", path, key), errors),
+            None if closed => errors.push(format!("{}.{} is not an accepted field", path, key)),
+            None => {}
+        }
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        // Unknown types are not ours to reject
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::CallToolRequestParam;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn weather_tool() -> Tool {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string"},
+                "days": {"type": "integer"},
+                "units": {"type": "string", "enum": ["metric", "imperial"]},
+                "fields": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["city"],
+            "additionalProperties": false
+        });
+        Tool::new(
+            "get_weather",
+            "Get the forecast",
+            Arc::new(schema.as_object().cloned().unwrap()),
+        )
+    }
+
+    fn call(name: &str, arguments: Value) -> Message {
+        Message::assistant().with_tool_request(
+            "call_1",
+            Ok(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: arguments.as_object().cloned(),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_validates_arguments_against_schema() {
+        let schema = Value::Object(weather_tool().input_schema.as_ref().clone());
+        assert!(validate(&json!({"city": "Paris", "days": 3.0}), &schema).is_empty());
+        assert_eq!(
+            validate(
+                &json!({"days": "3", "units": "kelvin", "fields": ["temp", 1], "extra": true}),
+                &schema
+            ),
+            vec![
+                "arguments.city is required",
+                "arguments.days must be integer, not string",
+                "arguments.extra is not an accepted field",
+                "arguments.fields[1] must be string, not number",
+                "arguments.units must be one of \"metric\", \"imperial\", not \"kelvin\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_finds_invalid_calls_and_builds_repair_prompt() {
+        let tools = [weather_tool()];
+        assert!(invalid_calls(&call("get_weather", json!({"city": "Oslo"})), &tools).is_empty());
+
+        let invalid = invalid_calls(&call("get_weather", json!({"town": "Oslo"})), &tools);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(
+            invalid[0].errors,
+            vec![
+                "arguments.city is required",
+                "arguments.town is not an accepted field"
+            ]
+        );
+        assert_eq!(
+            invalid_calls(&call("get_forecast", json!({})), &tools)[0].errors,
+            vec!["there is no tool named get_forecast"]
+        );
+
+        let messages = repair_messages(&[Message::user().with_text("Weather in Oslo?")], &invalid);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[1].as_concat_text(),
+            "[calls get_weather with {\"town\":\"Oslo\"}]"
+        );
+        assert!(messages[2]
+            .as_concat_text()
+            .contains("- get_weather: arguments.city is required"));
+    }
+}
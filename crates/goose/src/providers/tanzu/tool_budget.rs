@@ -0,0 +1,170 @@
+//! Tool schema reduction for models with small tool budgets.
+//!
+//! Small models lose track when a request offers dozens of tools. With
+//! `TANZU_AI_MAX_TOOLS` set, only that many are sent: tools called in the conversation
+//! come first, most recent first, then tools whose name or description shares words
+//! with the latest user text. The selected tools keep Goose's order so prompts stay
+//! cacheable.
+//!
+//! A model that needs a tool it was not offered tends to invent one, which comes back
+//! as a "not found" tool error. The request after such an error sends every tool.
+
+use crate::conversation::message::{Message, MessageContent};
+use rmcp::model::{Role, Tool};
+use std::collections::{HashMap, HashSet};
+
+/// Words shorter than this are too common to say anything about relevance.
+const MIN_KEYWORD_CHARS: usize = 3;
+
+pub fn max_tools() -> Option<usize> {
+    crate::config::Config::global()
+        .get_param::<usize>("TANZU_AI_MAX_TOOLS")
+        .ok()
+        .filter(|&max| max > 0)
+}
+
+/// The tools to send when `tools` exceeds `max`, or `None` to send them all.
+pub fn reduce(tools: &[Tool], messages: &[Message], max: usize) -> Option<Vec<Tool>> {
+    if tools.len() <= max || tool_not_found(messages) {
+        return None;
+    }
+
+    // Position of each tool's latest call, counting tool requests through the conversation
+    let mut last_called: HashMap<&str, usize> = HashMap::new();
+    let calls = messages
+        .iter()
+        .flat_map(|m| &m.content)
+        .filter_map(|content| match content {
+            MessageContent::ToolRequest(request) => request.tool_call.as_ref().ok(),
+            _ => None,
+        });
+    for (position, call) in calls.enumerate() {
+        last_called.insert(&call.name, position);
+    }
+    let keywords = words(&latest_user_text(messages));
+
+    let mut ranked: Vec<(Option<usize>, usize, usize)> = tools
+        .iter()
+        .enumerate()
+        .map(|(index, tool)| {
+            let described = format!(
+                "{} {}",
+                tool.name,
+                tool.description.as_deref().unwrap_or_default()
+            );
+            let overlap = words(&described).intersection(&keywords).count();
+            (last_called.get(tool.name.as_ref()).copied(), overlap, index)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+
+    let mut selected: Vec<usize> = ranked.iter().take(max).map(|&(_, _, i)| i).collect();
+    selected.sort_unstable();
+    tracing::debug!(
+        "Sending {} of {} tools (TANZU_AI_MAX_TOOLS)",
+        selected.len(),
+        tools.len()
+    );
+    Some(selected.into_iter().map(|i| tools[i].clone()).collect())
+}
+
+/// Whether the latest tool results include a call to a tool that does not exist.
+fn tool_not_found(messages: &[Message]) -> bool {
+    messages.last().is_some_and(|message| {
+        message.content.iter().any(|content| match content {
+            MessageContent::ToolResponse(response) => response
+                .tool_result
+                .as_ref()
+                .is_err_and(|e| e.to_string().to_ascii_lowercase().contains("not found")),
+            _ => false,
+        })
+    })
+}
+
+fn latest_user_text(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .rev()
+        .filter(|m| m.role == Role::User)
+        .map(|m| m.as_concat_text())
+        .find(|text| !text.trim().is_empty())
+        .unwrap_or_default()
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_KEYWORD_CHARS)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{CallToolRequestParam, ErrorCode, ErrorData};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn tool(name: &str, description: &str) -> Tool {
+        Tool::new(
+            name.to_string(),
+            description.to_string(),
+            Arc::new(json!({"type": "object"}).as_object().cloned().unwrap()),
+        )
+    }
+
+    fn names(tools: &[Tool]) -> Vec<&str> {
+        tools.iter().map(|t| t.name.as_ref()).collect()
+    }
+
+    fn call(name: &str) -> Result<CallToolRequestParam, ErrorData> {
+        Ok(CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: None,
+        })
+    }
+
+    fn toolbox() -> Vec<Tool> {
+        vec![
+            tool("shell", "Run a shell command"),
+            tool("text_editor", "View and edit files"),
+            tool("browser_open", "Open a web page"),
+            tool("jira_search", "Search Jira issues"),
+            tool("calendar_list", "List calendar events"),
+        ]
+    }
+
+    #[test]
+    fn test_ranks_recent_calls_then_keywords() {
+        let messages = vec![
+            Message::user().with_text("Fix the build"),
+            Message::assistant().with_tool_request("1", call("shell")),
+            Message::assistant().with_tool_request("2", call("text_editor")),
+            Message::user().with_text("Now search Jira for related issues"),
+        ];
+        let reduced = reduce(&toolbox(), &messages, 1).unwrap();
+        assert_eq!(names(&reduced), vec!["text_editor"]);
+
+        let reduced = reduce(&toolbox(), &messages, 3).unwrap();
+        assert_eq!(names(&reduced), vec!["shell", "text_editor", "jira_search"]);
+
+        assert!(reduce(&toolbox(), &messages, 5).is_none());
+    }
+
+    #[test]
+    fn test_sends_everything_after_tool_not_found() {
+        let messages = vec![
+            Message::user().with_text("What is on my calendar?"),
+            Message::assistant().with_tool_request("1", call("calendar_today")),
+            Message::user().with_tool_response(
+                "1",
+                Err(ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    "Tool calendar_today not found",
+                    None,
+                )),
+            ),
+        ];
+        assert!(reduce(&toolbox(), &messages, 2).is_none());
+    }
+}
@@ -0,0 +1,250 @@
+//! Tool calling for models whose support is unknown.
+//!
+//! Deprecated single-model bindings have no config URL, so nothing says whether the
+//! model can return `tool_calls`. The first request with tools sends a small probe
+//! completion offering a dummy tool and the outcome is remembered per model. Models that
+//! cannot call tools get text-only emulation: the tools are described in the system
+//! prompt, earlier tool calls and results are replayed as text, and a JSON reply of the
+//! form `{"tool_calls": [...]}` is turned back into tool requests.
+
+use super::structured;
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::errors::ProviderError;
+use rmcp::model::{CallToolRequestParam, Tool};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Name of the dummy tool offered by the probe.
+const PROBE_TOOL: &str = "tanzu_probe";
+
+/// The dummy tool offered by the probe completion.
+pub fn probe_tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {"reply": {"type": "string"}},
+        "required": ["reply"],
+    });
+    Tool::new(
+        PROBE_TOOL,
+        "Send a short reply back to the caller.",
+        Arc::new(schema.as_object().cloned().unwrap_or_default()),
+    )
+}
+
+/// The single user turn sent by the probe completion.
+pub fn probe_messages() -> Vec<Message> {
+    vec![Message::user().with_text(format!(
+        "Call the {} tool with reply \"ok\". Do not answer in text.",
+        PROBE_TOOL
+    ))]
+}
+
+/// Interpret a probe completion: `Some(supported)` when the outcome is conclusive.
+///
+/// Transient failures are inconclusive so the probe runs again on the next request.
+pub fn probe_outcome(result: &Result<Message, ProviderError>) -> Option<bool> {
+    match result {
+        Ok(message) => Some(
+            message
+                .content
+                .iter()
+                .any(|c| matches!(c, MessageContent::ToolRequest(_))),
+        ),
+        Err(e) => is_unsupported(e).then_some(false),
+    }
+}
+
+/// Whether a request failure means the endpoint rejected the `tools` field.
+fn is_unsupported(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::RequestFailed(msg) => {
+            let msg = msg.to_lowercase();
+            msg.contains("tool")
+                && [
+                    "support",
+                    "not allowed",
+                    "unrecognized",
+                    "unknown",
+                    "invalid",
+                ]
+                .iter()
+                .any(|m| msg.contains(m))
+        }
+        _ => false,
+    }
+}
+
+/// System prompt suffix describing `tools` and how to call them in plain text.
+pub fn emulation_instructions(system: &str, tools: &[Tool]) -> String {
+    let descriptions = tools
+        .iter()
+        .map(|tool| {
+            format!(
+                "- {}: {}\n  arguments schema: {}",
+                tool.name,
+                tool.description.as_deref().unwrap_or(""),
+                Value::Object(tool.input_schema.as_ref().clone())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "{}\n\nYou can use the following tools:\n{}\n\nTo use tools, respond only with a \
+         single JSON object of the form \
+         {{\"tool_calls\": [{{\"name\": \"<tool name>\", \"arguments\": {{...}}}}]}} \
+         without any surrounding prose or code fences. Tool results are sent back to you \
+         in the next message. When no tool is needed, answer in plain text.",
+        system, descriptions
+    )
+}
+
+/// Replace tool requests and responses in `messages` with their text equivalents.
+pub fn flatten_tool_messages(messages: &[Message]) -> Vec<Message> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut flattened = message.clone();
+            flattened.content = message
+                .content
+                .iter()
+                .map(|content| match content {
+                    MessageContent::ToolRequest(request) => match &request.tool_call {
+                        Ok(call) => MessageContent::text(
+                            json!({"tool_calls": [{
+                                "name": call.name,
+                                "arguments": call.arguments.clone().unwrap_or_default(),
+                            }]})
+                            .to_string(),
+                        ),
+                        Err(e) => MessageContent::text(format!("Invalid tool call: {}", e)),
+                    },
+                    MessageContent::ToolResponse(response) => match &response.tool_result {
+                        Ok(contents) => MessageContent::text(format!(
+                            "Tool result:\n{}",
+                            contents
+                                .iter()
+                                .filter_map(|c| c.as_text().map(|t| t.text.as_str()))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        )),
+                        Err(e) => MessageContent::text(format!("Tool call failed: {}", e)),
+                    },
+                    other => other.clone(),
+                })
+                .collect();
+            flattened
+        })
+        .collect()
+}
+
+/// Turn an emulated `{"tool_calls": [...]}` reply into tool requests.
+///
+/// Replies without valid tool calls are returned unchanged.
+pub fn parse_tool_calls(message: Message) -> Message {
+    let Ok(value) = structured::extract_json(&message.as_concat_text()) else {
+        return message;
+    };
+    let calls: Vec<CallToolRequestParam> = value
+        .get("tool_calls")
+        .and_then(Value::as_array)
+        .map(|calls| {
+            calls
+                .iter()
+                .filter_map(|call| {
+                    Some(CallToolRequestParam {
+                        name: call.get("name")?.as_str()?.to_string().into(),
+                        arguments: call.get("arguments").and_then(Value::as_object).cloned(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if calls.is_empty() {
+        return message;
+    }
+
+    let mut converted = message;
+    converted
+        .content
+        .retain(|c| !matches!(c, MessageContent::Text(_)));
+    let batch = chrono::Utc::now().timestamp_micros();
+    for (i, call) in calls.into_iter().enumerate() {
+        converted = converted.with_tool_request(format!("emulated_{}_{}", batch, i), Ok(call));
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_outcome() {
+        let called = Message::assistant().with_tool_request(
+            "call_1",
+            Ok(CallToolRequestParam {
+                name: PROBE_TOOL.into(),
+                arguments: None,
+            }),
+        );
+        assert_eq!(probe_outcome(&Ok(called)), Some(true));
+        assert_eq!(
+            probe_outcome(&Ok(Message::assistant().with_text("ok"))),
+            Some(false)
+        );
+        assert_eq!(
+            probe_outcome(&Err(ProviderError::RequestFailed(
+                "Request failed with status: 400. Message: tools are not supported".to_string()
+            ))),
+            Some(false)
+        );
+        assert_eq!(
+            probe_outcome(&Err(ProviderError::ServerError("502".to_string()))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let reply = Message::assistant().with_text(
+            "```json\n{\"tool_calls\": [{\"name\": \"get_weather\", \"arguments\": {\"city\": \"SF\"}}]}\n```",
+        );
+        let message = parse_tool_calls(reply);
+        let requests: Vec<_> = message
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                MessageContent::ToolRequest(r) => r.tool_call.as_ref().ok(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].name, "get_weather");
+        assert_eq!(message.as_concat_text(), "");
+
+        let plain = parse_tool_calls(Message::assistant().with_text("It is sunny."));
+        assert_eq!(plain.as_concat_text(), "It is sunny.");
+    }
+
+    #[test]
+    fn test_flatten_and_instructions() {
+        let history = vec![Message::assistant().with_tool_request(
+            "call_1",
+            Ok(CallToolRequestParam {
+                name: "get_weather".into(),
+                arguments: json!({"city": "SF"}).as_object().cloned(),
+            }),
+        )];
+        let flattened = flatten_tool_messages(&history);
+        assert!(flattened[0].as_concat_text().contains("\"get_weather\""));
+        assert!(!flattened[0]
+            .content
+            .iter()
+            .any(|c| matches!(c, MessageContent::ToolRequest(_))));
+
+        let prompt = emulation_instructions("Be brief.", &[probe_tool()]);
+        assert!(prompt.starts_with("Be brief."));
+        assert!(prompt.contains(PROBE_TOOL));
+        assert!(prompt.contains("\"reply\""));
+    }
+}
@@ -0,0 +1,196 @@
+//! W3C trace context propagation and gorouter request IDs.
+//!
+//! With `TANZU_AI_TRACE_PROPAGATION` enabled every request carries a `traceparent`
+//! header (and `tracestate` when one is inherited) so platform operators can follow it
+//! through the gorouter and the GenAI proxy. A parent context is taken from the
+//! `TRACEPARENT`/`TRACESTATE` environment variables set by the process that launched
+//! Goose; otherwise a new trace is started for the provider. Each request is a new span
+//! of that trace.
+//!
+//! The gorouter's `X-Vcap-Request-Id` response header is logged for every request and
+//! appended to errors, so a failure can be looked up in the platform's router logs.
+
+use crate::providers::errors::ProviderError;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+
+/// Response header carrying the gorouter's request ID.
+pub const VCAP_REQUEST_ID: &str = "x-vcap-request-id";
+
+/// Trace this process's requests belong to, inherited or generated once.
+static PARENT: LazyLock<TraceParent> = LazyLock::new(|| {
+    std::env::var("TRACEPARENT")
+        .ok()
+        .and_then(|value| TraceParent::parse(&value))
+        .map(|parent| TraceParent {
+            state: std::env::var("TRACESTATE").ok().filter(|s| !s.is_empty()),
+            ..parent
+        })
+        .unwrap_or_else(|| TraceParent {
+            trace_id: random_hex(16),
+            span_id: random_hex(8),
+            flags: "01".to_string(),
+            state: None,
+        })
+});
+
+/// A W3C trace context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParent {
+    trace_id: String,
+    span_id: String,
+    flags: String,
+    state: Option<String>,
+}
+
+impl TraceParent {
+    /// Parse a version 00 `traceparent` header value.
+    pub fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<&str> = value.trim().split('-').collect();
+        let [version, trace_id, span_id, flags] = parts.as_slice() else {
+            return None;
+        };
+        let hex = |s: &str, len: usize| {
+            s.len() == len
+                && s.bytes()
+                    .all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+                && s.bytes().any(|b| b != b'0')
+        };
+        if *version != "00" || !hex(trace_id, 32) || !hex(span_id, 16) || flags.len() != 2 {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            flags: flags.to_string(),
+            state: None,
+        })
+    }
+
+    /// A new span in the same trace.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_hex(8),
+            ..self.clone()
+        }
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, self.flags)
+    }
+
+    pub fn tracestate(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+}
+
+/// Trace context for the next request, when `TANZU_AI_TRACE_PROPAGATION` is enabled.
+fn next_context() -> Option<TraceParent> {
+    let enabled = crate::config::Config::global()
+        .get_param::<bool>("TANZU_AI_TRACE_PROPAGATION")
+        .unwrap_or(false);
+    enabled.then(|| PARENT.child())
+}
+
+/// Trace context headers for the next request; empty unless propagation is enabled.
+pub fn headers() -> Vec<(&'static str, String)> {
+    let Some(context) = next_context() else {
+        return Vec::new();
+    };
+    let mut headers = vec![("traceparent", context.traceparent())];
+    if let Some(state) = context.tracestate() {
+        headers.push(("tracestate", state.to_string()));
+    }
+    headers
+}
+
+/// Add trace context headers to a request, when propagation is enabled.
+pub fn inject(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    headers()
+        .into_iter()
+        .fold(request, |request, (name, value)| {
+            request.header(name, value)
+        })
+}
+
+/// Append the gorouter request ID to an error message.
+pub fn with_request_id(error: ProviderError, request_id: Option<&str>) -> ProviderError {
+    let Some(id) = request_id else {
+        return error;
+    };
+    let tag = |msg: String| format!("{} (X-Vcap-Request-Id: {})", msg, id);
+    match error {
+        ProviderError::Authentication(msg) => ProviderError::Authentication(tag(msg)),
+        ProviderError::ContextLengthExceeded(msg) => ProviderError::ContextLengthExceeded(tag(msg)),
+        ProviderError::RateLimitExceeded {
+            details,
+            retry_delay,
+        } => ProviderError::RateLimitExceeded {
+            details: tag(details),
+            retry_delay,
+        },
+        ProviderError::ServerError(msg) => ProviderError::ServerError(tag(msg)),
+        ProviderError::RequestFailed(msg) => ProviderError::RequestFailed(tag(msg)),
+        other => other,
+    }
+}
+
+/// `len` random bytes as lowercase hex, never all zeros.
+pub fn random_hex(len: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(1);
+    let mut hex = String::with_capacity(len * 2 + 16);
+    while hex.len() < len * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default(),
+        );
+        hex.push_str(&format!("{:016x}", hasher.finish() | 1));
+    }
+    hex.truncate(len * 2);
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_child() {
+        let parent =
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let child = parent.child();
+        assert!(child
+            .traceparent()
+            .starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(child.traceparent().ends_with("-01"));
+        assert_ne!(child.span_id, parent.span_id);
+        assert_eq!(TraceParent::parse(&child.traceparent()), Some(child));
+
+        assert!(TraceParent::parse("garbage").is_none());
+        assert!(
+            TraceParent::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(
+            TraceParent::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_none()
+        );
+    }
+
+    #[test]
+    fn test_with_request_id() {
+        let err = with_request_id(
+            ProviderError::ServerError("Bad gateway".to_string()),
+            Some("f1c2-77"),
+        );
+        assert!(
+            matches!(err, ProviderError::ServerError(msg) if msg == "Bad gateway (X-Vcap-Request-Id: f1c2-77)")
+        );
+        let err = with_request_id(ProviderError::ServerError("x".to_string()), None);
+        assert!(matches!(err, ProviderError::ServerError(msg) if msg == "x"));
+    }
+}
@@ -0,0 +1,1760 @@
+//! HTTP transport for a single Tanzu AI Services binding.
+//!
+//! Requests go through Goose's `ApiClient`, built over a `reqwest::Client` configured for
+//! the platform, so TLS and egress proxy settings required by platform gateways (client
+//! certificates, private CAs, TAS egress proxies) apply to every request: completions,
+//! streaming, discovery and embeddings. Failed requests are retried with Goose's
+//! [`ProviderRetry`] policy.
+
+use super::affinity::{self, SessionAffinity};
+use super::attribution::Attribution;
+use super::audio::MultipartForm;
+use super::breaker::{self, CircuitBreaker};
+use super::classify::{classify, error_message, ProxyErrorKind, RequestError};
+use super::debug_http;
+use super::egress::EgressPolicy;
+use super::failover::{Failover, Route};
+use super::fault::Faults;
+use super::flavor::UpstreamFlavor;
+use super::headers::ExtraHeaders;
+use super::limits::{self, RetryBudget};
+use super::metrics;
+use super::prompt_cache;
+use super::reasoning;
+use super::replay::{FixtureBody, Fixtures};
+use super::sse;
+use super::stream::assemble_tool_calls;
+use super::token::{ManagedBearerAuth, TokenManager};
+use super::trace;
+use super::uaa::UaaTokens;
+use super::warmup::Warmup;
+use super::wire::WireFormat;
+use crate::conversation::message::Message;
+use crate::providers::api_client::{ApiClient, AuthMethod};
+use crate::providers::base::{MessageStream, ProviderUsage};
+use crate::providers::errors::ProviderError;
+use crate::providers::formats::openai::response_to_streaming_message;
+use crate::providers::openai_compatible::map_http_error_to_provider_error;
+use crate::providers::retry::{should_retry, ProviderRetry};
+use anyhow::{Context, Result};
+use async_stream::try_stream;
+use futures::{StreamExt, TryStreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OwnedSemaphorePermit;
+use tokio_util::bytes::Bytes;
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tokio_util::io::StreamReader;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+const DEFAULT_STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+const DEFAULT_POOL_MAX_IDLE: usize = 16;
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Default cap on a server-requested `Retry-After` delay
+const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Header the GenAI proxy deduplicates retried requests by
+const IDEMPOTENCY_KEY: &str = "X-Idempotency-Key";
+
+/// A streamed response body.
+type ByteStream = Pin<Box<dyn futures::Stream<Item = reqwest::Result<Bytes>> + Send>>;
+/// A reply and the slot it holds under `TANZU_AI_MAX_CONCURRENT_REQUESTS`
+type Sent<T> = (T, Option<OwnedSemaphorePermit>);
+
+/// TLS material for connecting through mTLS-enforcing gateways.
+///
+/// Each value may be a file path or inline PEM.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TlsSettings {
+    /// `TANZU_AI_CLIENT_CERT`
+    pub client_cert: Option<String>,
+    /// `TANZU_AI_CLIENT_KEY`
+    pub client_key: Option<String>,
+    /// `TANZU_AI_CA_CERT`
+    pub ca_cert: Option<String>,
+    /// `TANZU_AI_CA_BUNDLE`: one or more platform CA certificates
+    pub ca_bundle: Option<String>,
+    /// `TANZU_AI_INSECURE_SKIP_VERIFY`: disable certificate verification entirely
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsSettings {
+    pub fn from_config() -> Self {
+        let config = crate::config::Config::global();
+        Self {
+            client_cert: config.get_param("TANZU_AI_CLIENT_CERT").ok(),
+            client_key: config.get_secret("TANZU_AI_CLIENT_KEY").ok(),
+            ca_cert: config.get_param("TANZU_AI_CA_CERT").ok(),
+            ca_bundle: config.get_param("TANZU_AI_CA_BUNDLE").ok(),
+            insecure_skip_verify: config
+                .get_param("TANZU_AI_INSECURE_SKIP_VERIFY")
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Egress proxy for reaching the GenAI proxy from TAS cells.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ProxySettings {
+    /// `TANZU_AI_HTTPS_PROXY`, else `HTTPS_PROXY`: an `http://`, `https://` or `socks5://` URL
+    pub https_proxy: Option<String>,
+    /// `TANZU_AI_NO_PROXY`, else `NO_PROXY`: comma-separated hosts and domain suffixes
+    pub no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    pub fn from_config() -> Self {
+        let config = crate::config::Config::global();
+        let param = |tanzu_key: &str, env_keys: [&str; 2]| {
+            config
+                .get_param::<String>(tanzu_key)
+                .ok()
+                .or_else(|| env_keys.iter().find_map(|key| std::env::var(key).ok()))
+                .filter(|value| !value.trim().is_empty())
+        };
+        Self {
+            https_proxy: param("TANZU_AI_HTTPS_PROXY", ["HTTPS_PROXY", "https_proxy"]),
+            no_proxy: param("TANZU_AI_NO_PROXY", ["NO_PROXY", "no_proxy"]),
+        }
+    }
+}
+
+/// Deadlines for the different kinds of requests.
+///
+/// A single total timeout either cuts off long agentic generations or leaves quick calls
+/// hanging on a stalled proxy, so streams are bounded by the gap between chunks instead,
+/// and optionally by a shorter wait for their first data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeoutSettings {
+    /// `TANZU_AI_CONNECT_TIMEOUT_SECS`: establishing the connection
+    pub connect: Duration,
+    /// `TANZU_AI_TIMEOUT_SECS`: waiting for the response to a non-streaming request
+    pub total: Duration,
+    /// `TANZU_AI_STREAM_IDLE_TIMEOUT_SECS`: waiting for response headers or the next
+    /// chunk of a stream
+    pub stream_idle: Duration,
+    /// `TANZU_AI_FIRST_TOKEN_TIMEOUT_SECS`: waiting for a stream's response headers and
+    /// first chunk, after which the request is retried; unset, the idle timeout applies
+    pub first_token: Option<Duration>,
+}
+
+impl Default for TimeoutSettings {
+    fn default() -> Self {
+        Self {
+            connect: DEFAULT_CONNECT_TIMEOUT,
+            total: DEFAULT_TIMEOUT,
+            stream_idle: DEFAULT_STREAM_IDLE_TIMEOUT,
+            first_token: None,
+        }
+    }
+}
+
+impl TimeoutSettings {
+    pub fn from_config() -> Self {
+        let config = crate::config::Config::global();
+        let secs = |key: &str, default: Duration| {
+            config
+                .get_param::<u64>(key)
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        Self {
+            connect: secs("TANZU_AI_CONNECT_TIMEOUT_SECS", DEFAULT_CONNECT_TIMEOUT),
+            total: secs("TANZU_AI_TIMEOUT_SECS", DEFAULT_TIMEOUT),
+            stream_idle: secs(
+                "TANZU_AI_STREAM_IDLE_TIMEOUT_SECS",
+                DEFAULT_STREAM_IDLE_TIMEOUT,
+            ),
+            first_token: config
+                .get_param::<u64>("TANZU_AI_FIRST_TOKEN_TIMEOUT_SECS")
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        }
+    }
+
+    /// How long to wait for a stream's response headers, and the error when they are late.
+    fn stream_headers(&self) -> (Duration, ProviderError) {
+        match self.first_token {
+            Some(first) if first < self.stream_idle => (first, first_token_error(first)),
+            _ => (self.stream_idle, idle_timeout_error(self.stream_idle)),
+        }
+    }
+}
+
+/// Connection reuse for agent loops that send many requests to the same proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PoolSettings {
+    /// `TANZU_AI_POOL_MAX_IDLE`: idle connections kept per host
+    pub max_idle_per_host: usize,
+    /// `TANZU_AI_POOL_IDLE_TIMEOUT_SECS`: how long an unused connection is kept
+    pub idle_timeout: Duration,
+    /// `TANZU_AI_KEEPALIVE_SECS`: TCP keep-alive and HTTP/2 ping interval, short enough to
+    /// hold connections open through gorouter and load balancer idle timeouts
+    pub keepalive: Duration,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: DEFAULT_POOL_MAX_IDLE,
+            idle_timeout: DEFAULT_POOL_IDLE_TIMEOUT,
+            keepalive: DEFAULT_KEEPALIVE_INTERVAL,
+        }
+    }
+}
+
+impl PoolSettings {
+    pub fn from_config() -> Self {
+        let config = crate::config::Config::global();
+        let defaults = Self::default();
+        let secs = |key: &str, default: Duration| {
+            config
+                .get_param::<u64>(key)
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        Self {
+            max_idle_per_host: config
+                .get_param("TANZU_AI_POOL_MAX_IDLE")
+                .unwrap_or(defaults.max_idle_per_host),
+            idle_timeout: secs("TANZU_AI_POOL_IDLE_TIMEOUT_SECS", defaults.idle_timeout),
+            keepalive: secs("TANZU_AI_KEEPALIVE_SECS", defaults.keepalive),
+        }
+    }
+}
+
+/// Everything that shapes the HTTP client.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ClientSettings {
+    pub tls: TlsSettings,
+    pub proxy: ProxySettings,
+    pub timeouts: TimeoutSettings,
+    pub pool: PoolSettings,
+    pub attribution: Attribution,
+    pub extra_headers: ExtraHeaders,
+    /// Set by the client under `TANZU_AI_STRICT_EGRESS`, once the bindings are known
+    pub egress: Option<EgressPolicy>,
+}
+
+impl ClientSettings {
+    pub fn from_config() -> Self {
+        Self {
+            tls: TlsSettings::from_config(),
+            proxy: ProxySettings::from_config(),
+            timeouts: TimeoutSettings::from_config(),
+            pool: PoolSettings::from_config(),
+            attribution: Attribution::from_config(),
+            extra_headers: ExtraHeaders::from_config(),
+            egress: None,
+        }
+    }
+}
+
+/// Clients shared by provider instances with the same settings, so their connection
+/// pools are reused instead of renegotiating TLS for every new provider.
+static CLIENTS: LazyLock<Mutex<HashMap<ClientSettings, reqwest::Client>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The pooled client for `settings`, built on first use.
+pub fn shared_http_client(settings: &ClientSettings) -> Result<reqwest::Client> {
+    let mut clients = CLIENTS.lock().unwrap();
+    if let Some(client) = clients.get(settings) {
+        return Ok(client.clone());
+    }
+    let client = build_http_client(settings)?;
+    clients.insert(settings.clone(), client.clone());
+    Ok(client)
+}
+
+/// Hosts that bypass the egress proxy.
+#[derive(Debug, Clone, Default)]
+struct NoProxy {
+    patterns: Vec<String>,
+}
+
+impl NoProxy {
+    fn parse(value: &str) -> Self {
+        Self {
+            patterns: value
+                .split(',')
+                .map(|p| p.trim().trim_start_matches("*.").to_ascii_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Match `host` exactly or as a subdomain of an entry; `*` matches everything.
+    fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.patterns.iter().any(|pattern| {
+            if pattern == "*" {
+                return true;
+            }
+            let domain = pattern.trim_start_matches('.');
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+}
+
+/// Build the HTTP client shared by all requests to a binding.
+///
+/// Only the connect timeout is set on the client; request deadlines depend on whether the
+/// response is streamed and are applied by [`Transport`].
+pub fn build_http_client(settings: &ClientSettings) -> Result<reqwest::Client> {
+    let ClientSettings {
+        tls,
+        proxy,
+        timeouts,
+        pool,
+        attribution,
+        extra_headers,
+        egress,
+    } = settings;
+    let mut default_headers = attribution.headers();
+    default_headers.extend(extra_headers.header_map()?);
+    // Proxy settings are resolved here, so reqwest's own environment lookup is disabled
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(timeouts.connect)
+        .pool_max_idle_per_host(pool.max_idle_per_host)
+        .pool_idle_timeout(pool.idle_timeout)
+        .tcp_keepalive(pool.keepalive)
+        .http2_keep_alive_interval(pool.keepalive)
+        .http2_keep_alive_while_idle(true)
+        .default_headers(default_headers)
+        .no_proxy();
+
+    if let Some(policy) = egress.clone() {
+        builder = builder.redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if !policy.allows(attempt.url()) {
+                let refused = format!(
+                    "TANZU_AI_STRICT_EGRESS refused a redirect to {}",
+                    attempt.url()
+                );
+                attempt.error(refused)
+            } else if attempt.previous().len() >= 10 {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        }));
+    }
+
+    if let Some(proxy_url) = &proxy.https_proxy {
+        let proxy_url = reqwest::Url::parse(proxy_url)
+            .with_context(|| format!("Invalid egress proxy URL {}", proxy_url))?;
+        let no_proxy = proxy
+            .no_proxy
+            .as_deref()
+            .map(NoProxy::parse)
+            .unwrap_or_default();
+        tracing::debug!("Routing Tanzu AI requests through proxy {}", proxy_url);
+        builder = builder.proxy(reqwest::Proxy::custom(move |url| match url.host_str() {
+            Some(host) if no_proxy.matches(host) => None,
+            _ => Some(proxy_url.clone()),
+        }));
+    }
+
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let mut pem = read_pem(cert).context("Failed to read TANZU_AI_CLIENT_CERT")?;
+            pem.push(b'\n');
+            pem.extend(read_pem(key).context("Failed to read TANZU_AI_CLIENT_KEY")?);
+            let identity = reqwest::Identity::from_pem(&pem)
+                .context("Invalid client certificate or key for Tanzu AI Services")?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => anyhow::bail!(
+            "TANZU_AI_CLIENT_CERT and TANZU_AI_CLIENT_KEY must be set together for mTLS"
+        ),
+    }
+
+    for (key, value) in [
+        ("TANZU_AI_CA_CERT", &tls.ca_cert),
+        ("TANZU_AI_CA_BUNDLE", &tls.ca_bundle),
+    ] {
+        let Some(value) = value else { continue };
+        let pem = read_pem(value).with_context(|| format!("Failed to read {}", key))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA certificate in {}", key))?;
+        if certs.is_empty() {
+            anyhow::bail!("{} does not contain any PEM certificates", key);
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if tls.insecure_skip_verify {
+        tracing::warn!(
+            "TANZU_AI_INSECURE_SKIP_VERIFY is set; TLS certificates from the GenAI proxy are not verified"
+        );
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Read PEM data given either inline or as a path to a file.
+fn read_pem(value: &str) -> Result<Vec<u8>> {
+    if value.trim_start().starts_with("-----BEGIN") {
+        return Ok(value.as_bytes().to_vec());
+    }
+    std::fs::read(Path::new(value)).with_context(|| format!("Could not read {}", value))
+}
+
+/// Authenticated access to one binding's endpoint.
+#[derive(Clone)]
+pub struct Transport {
+    http: reqwest::Client,
+    /// JSON requests, sent over `http` and authenticated by [`ManagedBearerAuth`]
+    api: Arc<ApiClient>,
+    endpoint_base: String,
+    tokens: Arc<TokenManager>,
+    /// Client-credentials tokens, used instead of `tokens` when configured
+    uaa: Option<Arc<UaaTokens>>,
+    breaker: Arc<CircuitBreaker>,
+    budget: Arc<RetryBudget>,
+    affinity: Arc<SessionAffinity>,
+    timeouts: TimeoutSettings,
+    flavor: UpstreamFlavor,
+    /// Secondary foundation taken over by when this endpoint is unreachable
+    failover: Option<(Arc<Failover>, Arc<Transport>)>,
+    /// Failures injected on purpose (`TANZU_AI_FAULT_PROFILE`)
+    faults: Option<Faults>,
+}
+
+impl Transport {
+    pub fn new(http: reqwest::Client, endpoint_base: &str, tokens: Arc<TokenManager>) -> Self {
+        let endpoint_base = endpoint_base.trim_end_matches('/').to_string();
+        let auth = ManagedBearerAuth {
+            tokens: tokens.clone(),
+            uaa: None,
+            flavor: UpstreamFlavor::Tanzu,
+        };
+        Self {
+            api: Arc::new(api_client(&http, &endpoint_base, auth)),
+            http,
+            breaker: breaker::for_endpoint(&endpoint_base),
+            budget: limits::budget_for(&endpoint_base),
+            affinity: affinity::for_endpoint(&endpoint_base),
+            endpoint_base,
+            tokens,
+            uaa: None,
+            timeouts: TimeoutSettings::default(),
+            flavor: UpstreamFlavor::Tanzu,
+            failover: None,
+            faults: Faults::from_config(),
+        }
+    }
+
+    /// Authenticate with tokens exchanged at UAA instead of the binding's API key.
+    pub fn with_uaa(mut self, uaa: UaaTokens) -> Self {
+        self.uaa = Some(Arc::new(uaa));
+        self.reauthorize()
+    }
+
+    pub fn with_timeouts(mut self, timeouts: TimeoutSettings) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Address and authenticate OpenAI-compatible paths the way `flavor` expects.
+    pub fn with_flavor(mut self, flavor: UpstreamFlavor) -> Self {
+        self.flavor = flavor;
+        self.reauthorize()
+    }
+
+    /// Rebuild the `ApiClient` after a change to how requests are authenticated.
+    fn reauthorize(mut self) -> Self {
+        self.api = Arc::new(api_client(&self.http, &self.endpoint_base, self.auth()));
+        self
+    }
+
+    fn auth(&self) -> ManagedBearerAuth {
+        ManagedBearerAuth {
+            tokens: self.tokens.clone(),
+            uaa: self.uaa.clone(),
+            flavor: self.flavor.clone(),
+        }
+    }
+
+    /// Send requests to `secondary` while this endpoint is unreachable.
+    pub fn with_failover(mut self, secondary: Transport, failover: Failover) -> Self {
+        self.failover = Some((Arc::new(failover), Arc::new(secondary)));
+        self
+    }
+
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// The current bearer token without refreshing it, e.g. for redaction.
+    pub fn api_key(&self) -> String {
+        match &self.uaa {
+            Some(uaa) => uaa.current(),
+            None => self.tokens.current(),
+        }
+    }
+
+    /// A valid bearer token, exchanging client credentials first when needed.
+    pub async fn bearer_token(&self) -> Result<String, ProviderError> {
+        self.auth().token().await
+    }
+
+    /// Absolute URL for a path under the binding's endpoint base.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.endpoint_base, path.trim_start_matches('/'))
+    }
+
+    /// POST a JSON payload and return the JSON response, or the recorded one when
+    /// replaying fixtures.
+    ///
+    /// Retries and error mapping are those of [`Transport::post_with_headers`].
+    pub async fn post(&self, path: &str, payload: &Value) -> Result<Value, ProviderError> {
+        let fixtures = Fixtures::from_config();
+        if let Some(replayed) = fixtures
+            .as_ref()
+            .and_then(|f| f.replay("POST", path, Some(payload)))
+        {
+            return replayed?.into_json();
+        }
+        let response: Value = self
+            .post_with_headers(path, &[], payload)
+            .await?
+            .json()
+            .await?;
+        if let Some(fixtures) = &fixtures {
+            let body = FixtureBody::Json(response.clone());
+            fixtures.record("POST", path, Some(payload), body, &self.api_key());
+        }
+        self.log_response(path, payload, &response);
+        Ok(response)
+    }
+
+    fn log_response(&self, path: &str, payload: &Value, response: &Value) {
+        if debug_http::enabled() {
+            let url = self.url(&self.flavor.path(path, model_label(payload)));
+            debug_http::log_response(&url, 200, &response.to_string(), &self.api_key());
+        }
+    }
+
+    /// Like [`Transport::post`], adding headers required by the upstream API.
+    pub async fn post_with_headers(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        payload: &Value,
+    ) -> Result<reqwest::Response, ProviderError> {
+        Ok(self.send(path, headers, Body::Json(payload)).await?)
+    }
+
+    /// POST a multipart form and return the JSON response; forms are never recorded
+    /// as fixtures.
+    pub async fn post_multipart(
+        &self,
+        path: &str,
+        form: &MultipartForm,
+    ) -> Result<Value, ProviderError> {
+        Ok(self
+            .send(path, &[], Body::Multipart(form))
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn send(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+    ) -> Result<reqwest::Response, RequestError> {
+        let accept = |response| async { Ok(response) };
+        let (response, _permit) = self
+            .send_holding_slot(path, headers, body, true, &accept)
+            .await?;
+        Ok(response)
+    }
+
+    /// Send a request with retries, returning the reply together with its slot under
+    /// `TANZU_AI_MAX_CONCURRENT_REQUESTS` for callers that consume the body over time.
+    ///
+    /// Each successful response is passed to `finish`, whose errors are retried like
+    /// those of the request itself. Without `wait_for_warmup`, a cold start is returned
+    /// at once for the caller to wait out and report itself.
+    async fn send_holding_slot<T, F, Fut>(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+        wait_for_warmup: bool,
+        finish: &F,
+    ) -> Result<Sent<T>, RequestError>
+    where
+        T: Send,
+        F: Fn(reqwest::Response) -> Fut + Sync,
+        Fut: Future<Output = Result<T, ProviderError>> + Send,
+    {
+        let Some((failover, secondary)) = &self.failover else {
+            return self
+                .send_to_endpoint(path, headers, body, wait_for_warmup, finish)
+                .await;
+        };
+        if failover.route() == Route::Secondary {
+            return secondary
+                .send_to_endpoint(path, headers, body, wait_for_warmup, finish)
+                .await;
+        }
+        let result = self
+            .send_to_endpoint(path, headers, body, wait_for_warmup, finish)
+            .await;
+        failover.settle_probe();
+        match result {
+            Err(e) if failover.failed_over() => {
+                tracing::warn!(
+                    "Tanzu AI request to {} failed ({}), sending it to the failover endpoint",
+                    self.endpoint_base,
+                    e
+                );
+                secondary
+                    .send_to_endpoint(path, headers, body, wait_for_warmup, finish)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    /// Send a request to this endpoint with retries, without failing over.
+    async fn send_to_endpoint<T, F, Fut>(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+        wait_for_warmup: bool,
+        finish: &F,
+    ) -> Result<Sent<T>, RequestError>
+    where
+        T: Send,
+        F: Fn(reqwest::Response) -> Fut + Sync,
+        Fut: Future<Output = Result<T, ProviderError>> + Send,
+    {
+        let path = &self.flavor.path(path, body.model());
+        // Every attempt carries the same key, so when the gorouter timed out on a request
+        // the model completed, the proxy answers the retry without generating again
+        let idempotency_key = trace::random_hex(16);
+        let mut headers = headers.to_vec();
+        headers.push((IDEMPOTENCY_KEY, &idempotency_key));
+        let headers = &headers;
+        let attempts = &AtomicUsize::new(0);
+        let reauthenticated = &AtomicBool::new(false);
+        self.with_retry(move || async move {
+            if attempts.fetch_add(1, Ordering::Relaxed) > 0 {
+                metrics::record_retry(body.model());
+            }
+            self.attempt(
+                path,
+                headers,
+                body,
+                wait_for_warmup,
+                reauthenticated,
+                finish,
+            )
+            .await
+        })
+        .await?
+    }
+
+    /// One attempt at a request, re-authenticating once on 401 and waiting out a cold
+    /// start.
+    ///
+    /// Errors worth retrying are returned as `Err` for [`ProviderRetry`] to back off on.
+    /// Everything else, including errors that must not be retried, is returned as `Ok`.
+    async fn attempt<T, F, Fut>(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+        wait_for_warmup: bool,
+        reauthenticated: &AtomicBool,
+        finish: &F,
+    ) -> Result<Result<Sent<T>, RequestError>, ProviderError>
+    where
+        F: Fn(reqwest::Response) -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        let mut warmup = None;
+        loop {
+            // The slot is given up while backing off, so other requests can go ahead
+            let permit = limits::acquire().await;
+            let result = match self.post_once(path, headers, body).await {
+                // A response that stalls before its first data counts against the endpoint
+                Ok(response) => finish(response).await.map_err(|e| {
+                    self.breaker.record_failure();
+                    RequestError::from(e)
+                }),
+                Err(e) => Err(e),
+            };
+            // A model warming up is expected to fail for a while and spends no budget
+            self.budget.record(
+                result
+                    .as_ref()
+                    .is_err_and(|e| should_retry(e.error()) && !e.is_cold_start()),
+            );
+            let error = match result {
+                Ok(reply) => return Ok(Ok((reply, permit))),
+                Err(e) => e,
+            };
+            drop(permit);
+            match error {
+                RequestError::Provider(ProviderError::Authentication(e))
+                    if !reauthenticated.swap(true, Ordering::Relaxed) =>
+                {
+                    match &self.uaa {
+                        Some(uaa) => {
+                            // The UAA token may have been revoked before its expiry
+                            tracing::info!(
+                                "Tanzu AI request unauthorized ({}), exchanging a new UAA token",
+                                e
+                            );
+                            uaa.invalidate();
+                        }
+                        // `cf rebind-service` rotates the key under a running session
+                        None if self.tokens.refresh().await => tracing::info!(
+                            "Tanzu AI request unauthorized ({}), retrying with the re-resolved API key",
+                            e
+                        ),
+                        None => return Ok(Err(ProviderError::Authentication(e).into())),
+                    }
+                }
+                e if e.is_cold_start() => {
+                    if !wait_for_warmup {
+                        return Ok(Err(e));
+                    }
+                    let warmup = warmup.get_or_insert_with(Warmup::from_config);
+                    let Some(delay) = warmup.next_delay() else {
+                        return Ok(Err(warmup.timed_out(e.error()).into()));
+                    };
+                    tracing::info!("{}", warmup.progress(body.model(), delay));
+                    tokio::time::sleep(delay).await;
+                }
+                // Retrying here is pointless once the secondary has taken over
+                e if self.failover.as_ref().is_some_and(|(f, _)| f.failed_over()) => {
+                    return Ok(Err(e))
+                }
+                e if should_retry(e.error()) && self.budget.allows_retry() => {
+                    return Err(cap_retry_after(e.into(), max_retry_after()))
+                }
+                e => return Ok(Err(e)),
+            }
+        }
+    }
+
+    async fn post_once(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+    ) -> Result<reqwest::Response, RequestError> {
+        self.breaker.allow()?;
+        if let Some(faults) = &self.faults {
+            faults.before_request(body.model()).await?;
+        }
+
+        let token = self.bearer_token().await?;
+        let url = &self.url(path);
+        let debug = debug_http::enabled().then(|| body.debug_text());
+        if let Some(text) = &debug {
+            debug_http::log_request(url, text.as_deref(), &token);
+        }
+        let log_failure = || {
+            if let Some(text) = &debug {
+                let (name, value) = self.flavor.auth_header(&token);
+                let all: Vec<(&str, &str)> = std::iter::once((name, value.as_str()))
+                    .chain(headers.iter().copied())
+                    .collect();
+                debug_http::log_failure(url, &all, text.as_deref(), &token);
+            }
+        };
+        let started = Instant::now();
+        let trace = trace::headers();
+        let headers: Vec<(&str, &str)> = headers
+            .iter()
+            .copied()
+            .chain(trace.iter().map(|(name, value)| (*name, value.as_str())))
+            .collect();
+        // A streamed body may legitimately take longer than the total deadline; only
+        // the wait for its headers is bounded, by the idle or first-token timeout
+        let (wait, late) = if body.is_stream() {
+            self.timeouts.stream_headers()
+        } else {
+            (
+                self.timeouts.total,
+                total_timeout_error(self.timeouts.total),
+            )
+        };
+        let response =
+            match tokio::time::timeout(wait, self.send_body(path, &token, &headers, body)).await {
+                Ok(response) => response,
+                Err(_) => Err(late),
+            };
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                metrics::record_request(body.model(), 0, started.elapsed());
+                self.breaker.record_failure();
+                if let Some((failover, _)) = &self.failover {
+                    failover.record_unreachable();
+                }
+                log_failure();
+                return Err(e.into());
+            }
+        };
+        if let Some((failover, _)) = &self.failover {
+            failover.record_reached();
+        }
+
+        let status = response.status();
+        metrics::record_request(body.model(), status.as_u16(), started.elapsed());
+        let request_id = response
+            .headers()
+            .get(trace::VCAP_REQUEST_ID)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        tracing::debug!(
+            "Tanzu AI request to {} returned {} (X-Vcap-Request-Id: {})",
+            url,
+            status,
+            request_id.as_deref().unwrap_or("none")
+        );
+        if status.is_success() {
+            self.breaker.record_success();
+            return Ok(response);
+        }
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| parse_retry_after(v, chrono::Utc::now()));
+        let body = response.json::<Value>().await.ok();
+        if debug.is_some() {
+            let text = body.as_ref().map(Value::to_string).unwrap_or_default();
+            debug_http::log_response(url, status.as_u16(), &text, &token);
+            log_failure();
+        }
+        let kind = classify(status, body.as_ref());
+        // A model warming up is not a failing endpoint
+        if status.is_server_error() && kind != Some(ProxyErrorKind::ColdStart) {
+            self.breaker.record_failure();
+        } else {
+            self.breaker.record_success();
+        }
+        if let Some(kind) = kind {
+            let detail = body
+                .as_ref()
+                .and_then(error_message)
+                .unwrap_or_else(|| status.to_string());
+            let error = trace::with_request_id(kind.into_error(&detail), request_id.as_deref());
+            return Err(RequestError::Proxy(kind, error));
+        }
+        let error = match map_http_error_to_provider_error(status, body) {
+            ProviderError::RateLimitExceeded {
+                details,
+                retry_delay,
+            } => ProviderError::RateLimitExceeded {
+                details,
+                retry_delay: retry_after.or(retry_delay),
+            },
+            other => other,
+        };
+        Err(trace::with_request_id(error, request_id.as_deref()).into())
+    }
+
+    /// Send `body` once: JSON through the `ApiClient`, forms on the underlying client.
+    async fn send_body(
+        &self,
+        path: &str,
+        token: &str,
+        headers: &[(&str, &str)],
+        body: Body<'_>,
+    ) -> Result<reqwest::Response, ProviderError> {
+        match body {
+            Body::Json(payload) => {
+                let mut request = self.api.request(path);
+                for (name, value) in headers {
+                    request = request.header(name, value)?;
+                }
+                request.response_post(payload).await.map_err(request_error)
+            }
+            Body::Multipart(form) => {
+                let mut request = self.flavor.authorize(self.http.post(self.url(path)), token);
+                for (name, value) in headers {
+                    request = request.header(*name, *value);
+                }
+                Ok(request
+                    .header(reqwest::header::CONTENT_TYPE, form.content_type())
+                    .body(form.to_bytes())
+                    .send()
+                    .await?)
+            }
+        }
+    }
+
+    /// POST a chat request in the binding's wire format and return the raw JSON response.
+    ///
+    /// Requests for a session are routed to the replica that answered it last; see
+    /// [`super::affinity`].
+    pub async fn chat_completion(
+        &self,
+        session_id: Option<&str>,
+        format: WireFormat,
+        payload: &Value,
+    ) -> Result<Value, RequestError> {
+        let path = format.chat_path();
+        let fixtures = Fixtures::from_config();
+        if let Some(replayed) = fixtures
+            .as_ref()
+            .and_then(|f| f.replay("POST", path, Some(payload)))
+        {
+            return Ok(replayed?.into_json()?);
+        }
+        let affinity = self.affinity.headers(session_id);
+        let response = self
+            .send(path, &chat_headers(format, &affinity), Body::Json(payload))
+            .await?;
+        self.affinity.capture(session_id, response.headers());
+        let cached = prompt_cache::header_tokens(response.headers());
+        let mut response: Value = response.json().await.map_err(ProviderError::from)?;
+        prompt_cache::annotate(&mut response, cached);
+        self.log_response(path, payload, &response);
+        if let Some(fixtures) = &fixtures {
+            let body = FixtureBody::Json(response.clone());
+            fixtures.record("POST", path, Some(payload), body, &self.api_key());
+        }
+        metrics::record_usage(model_label(payload), &format.usage(&response));
+        if let Some(cached) = prompt_cache::cached_tokens(&response) {
+            metrics::record_cached_tokens(model_label(payload), cached);
+        }
+        Ok(response)
+    }
+
+    /// POST a streaming chat request and decode the SSE response.
+    ///
+    /// Formats without SSE support are sent as a regular request and yielded as one chunk.
+    pub async fn chat_stream(
+        &self,
+        session_id: Option<&str>,
+        format: WireFormat,
+        payload: &Value,
+    ) -> Result<MessageStream, RequestError> {
+        if !format.supports_streaming() {
+            let response = self.chat_completion(session_id, format, payload).await?;
+            let (message, usage) = format
+                .parse_response(&response)
+                .map_err(|e| ProviderError::RequestFailed(e.to_string()))?;
+            let model = response
+                .get("model")
+                .and_then(Value::as_str)
+                .unwrap_or(model_label(payload))
+                .to_string();
+            let item = Ok((Some(message), Some(ProviderUsage::new(model, usage))));
+            return Ok(Box::pin(futures::stream::iter([item])));
+        }
+
+        let path = format.chat_path();
+        let fixtures = Fixtures::from_config();
+        if let Some(replayed) = fixtures
+            .as_ref()
+            .and_then(|f| f.replay("POST", path, Some(payload)))
+        {
+            return Ok(decode_sse(replayed?.into_sse()?, model_label(payload)));
+        }
+        let (bytes, permit) = match self.open_stream(session_id, format, payload).await {
+            Err(e) if e.is_cold_start() => {
+                return Ok(self.clone().stream_after_warmup(
+                    session_id.map(String::from),
+                    format,
+                    payload.clone(),
+                    e.into(),
+                ))
+            }
+            result => result?,
+        };
+        Ok(self.decode_response(path, payload, bytes, permit, fixtures))
+    }
+
+    /// Send a streaming chat request, sending it again like any failed request when no
+    /// data arrives within the first-token timeout. Nothing has reached the caller by
+    /// then, so retrying is safe.
+    async fn open_stream(
+        &self,
+        session_id: Option<&str>,
+        format: WireFormat,
+        payload: &Value,
+    ) -> Result<(ByteStream, Option<OwnedSemaphorePermit>), RequestError> {
+        let first_token = self.timeouts.first_token;
+        let started = |response: reqwest::Response| async move {
+            self.affinity.capture(session_id, response.headers());
+            let cached = prompt_cache::header_tokens(response.headers());
+            Ok((first_data(response, first_token).await?, cached))
+        };
+        let affinity = self.affinity.headers(session_id);
+        let ((bytes, cached), permit) = self
+            .send_holding_slot(
+                format.chat_path(),
+                &chat_headers(format, &affinity),
+                Body::Json(payload),
+                false,
+                &started,
+            )
+            .await?;
+        if let Some(cached) = cached {
+            metrics::record_cached_tokens(model_label(payload), cached);
+        }
+        Ok((bytes, permit))
+    }
+
+    /// Wait for a cold-starting model in the stream, reporting progress as thinking
+    /// content, then stream its reply.
+    fn stream_after_warmup(
+        self,
+        session_id: Option<String>,
+        format: WireFormat,
+        payload: Value,
+        mut error: ProviderError,
+    ) -> MessageStream {
+        Box::pin(try_stream! {
+            let path = format.chat_path();
+            let model = model_label(&payload).to_string();
+            let mut warmup = Warmup::from_config();
+            let (bytes, permit) = loop {
+                let delay = warmup.next_delay().ok_or_else(|| warmup.timed_out(&error))?;
+                tracing::info!("{}", warmup.progress(&model, delay));
+                yield (Some(warmup.progress_message(&model, delay)), None);
+                tokio::time::sleep(delay).await;
+                match self.open_stream(session_id.as_deref(), format, &payload).await {
+                    Err(e) if e.is_cold_start() => error = e.into(),
+                    result => break result?,
+                }
+            };
+            let fixtures = Fixtures::from_config();
+            let mut stream = self.decode_response(path, &payload, bytes, permit, fixtures);
+            while let Some(item) = stream.next().await {
+                yield item?;
+            }
+        })
+    }
+
+    /// Decode a streamed chat response, recording it when fixtures are being recorded.
+    fn decode_response(
+        &self,
+        path: &str,
+        payload: &Value,
+        bytes: ByteStream,
+        permit: Option<OwnedSemaphorePermit>,
+        fixtures: Option<Fixtures>,
+    ) -> MessageStream {
+        // The request holds its slot until the stream is consumed or dropped
+        let bytes = with_idle_timeout(bytes, self.timeouts.stream_idle).map(move |chunk| {
+            let _permit = &permit;
+            chunk
+        });
+        let bytes: Pin<Box<dyn futures::Stream<Item = std::io::Result<Bytes>> + Send>> =
+            match self.faults {
+                Some(faults) => Box::pin(faults.disrupt(bytes)),
+                None => Box::pin(bytes),
+            };
+        match fixtures {
+            Some(fixtures) => decode_sse(
+                fixtures.record_stream(path.to_string(), payload.clone(), self.api_key(), bytes),
+                model_label(payload),
+            ),
+            None => decode_sse(bytes, model_label(payload)),
+        }
+    }
+}
+
+/// Turn an OpenAI-style SSE response into Goose message chunks.
+///
+/// Tool call deltas are reassembled first; see [`super::stream`]. Reasoning deltas are
+/// lifted out before decoding and yielded as thinking content ahead of the chunk that
+/// follows them; see [`super::reasoning`]. A malformed line ends the stream unless
+/// tolerant decoding is enabled; see [`super::sse`].
+fn decode_sse<S>(bytes: S, model: &str) -> MessageStream
+where
+    S: futures::Stream<Item = std::io::Result<Bytes>> + Send + 'static,
+{
+    let bytes = Box::pin(bytes);
+    let model = model.to_string();
+    let tolerant = sse::tolerant();
+    let show_reasoning = reasoning::show_reasoning();
+    let pending = Arc::new(Mutex::new(String::new()));
+    let lifted = pending.clone();
+    Box::pin(try_stream! {
+        let reader = StreamReader::new(bytes);
+        let lines = FramedRead::new(reader, LinesCodec::new()).map_err(anyhow::Error::from);
+        let lines: Pin<Box<dyn futures::Stream<Item = Result<String>> + Send>> = if tolerant {
+            Box::pin(sse::skip_malformed(lines, model))
+        } else {
+            Box::pin(lines)
+        };
+        let lines = lines.map_ok(move |line| {
+                let (line, reasoning) = reasoning::split_line(line);
+                if let Some(reasoning) = reasoning.filter(|_| show_reasoning) {
+                    lifted.lock().unwrap().push_str(&reasoning);
+                }
+                line
+            });
+        let lines = Box::pin(assemble_tool_calls(lines));
+        let mut messages = std::pin::pin!(response_to_streaming_message(lines));
+        while let Some(item) = messages.next().await {
+            let (message, usage) = item.map_err(|e| {
+                ProviderError::RequestFailed(format!("Stream decode error: {}", e))
+            })?;
+            let thinking = std::mem::take(&mut *pending.lock().unwrap());
+            let had_thinking = !thinking.is_empty();
+            if had_thinking {
+                yield (Some(Message::assistant().with_thinking(thinking, "")), None);
+            }
+            if let Some(usage) = &usage {
+                metrics::record_usage(&usage.model, &usage.usage);
+            }
+            // Deltas that only carried reasoning decode to empty messages
+            if had_thinking && usage.is_none() && message.as_ref().is_none_or(reasoning::is_empty) {
+                continue;
+            }
+            yield (message, usage);
+        }
+    })
+}
+
+/// The body of a streamed `response`, once its first chunk has arrived within
+/// `first_token`.
+async fn first_data(
+    response: reqwest::Response,
+    first_token: Option<Duration>,
+) -> Result<ByteStream, ProviderError> {
+    let mut bytes: ByteStream = Box::pin(response.bytes_stream());
+    let Some(limit) = first_token else {
+        return Ok(bytes);
+    };
+    match tokio::time::timeout(limit, bytes.next()).await {
+        Ok(first) => Ok(Box::pin(futures::stream::iter(first).chain(bytes))),
+        Err(_) => Err(first_token_error(limit)),
+    }
+}
+
+/// End `stream` with a timeout error if no item arrives within `idle`.
+fn with_idle_timeout<S, B>(
+    stream: S,
+    idle: Duration,
+) -> impl futures::Stream<Item = std::io::Result<B>>
+where
+    S: futures::Stream<Item = reqwest::Result<B>>,
+{
+    async_stream::stream! {
+        let mut stream = std::pin::pin!(stream);
+        loop {
+            match tokio::time::timeout(idle, stream.next()).await {
+                Ok(Some(chunk)) => yield chunk.map_err(std::io::Error::other),
+                Ok(None) => break,
+                Err(_) => {
+                    yield Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        idle_timeout_error(idle).to_string(),
+                    ));
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn idle_timeout_error(idle: Duration) -> ProviderError {
+    ProviderError::RequestFailed(format!(
+        "Tanzu AI Services sent no data for {}s (TANZU_AI_STREAM_IDLE_TIMEOUT_SECS)",
+        idle.as_secs()
+    ))
+}
+
+/// A model that never starts responding is treated as a failing server, so it is retried.
+fn first_token_error(limit: Duration) -> ProviderError {
+    ProviderError::ServerError(format!(
+        "Tanzu AI Services sent no data within {}s of the request \
+         (TANZU_AI_FIRST_TOKEN_TIMEOUT_SECS)",
+        limit.as_secs()
+    ))
+}
+
+/// A request body.
+#[derive(Clone, Copy)]
+enum Body<'a> {
+    Json(&'a Value),
+    Multipart(&'a MultipartForm),
+}
+
+impl Body<'_> {
+    /// Model name used to label metrics for the request.
+    fn model(&self) -> &str {
+        match self {
+            Self::Json(payload) => model_label(payload),
+            Self::Multipart(form) => form.field("model").unwrap_or("unknown"),
+        }
+    }
+
+    /// The body as logged by `TANZU_AI_DEBUG_HTTP`; forms are not logged.
+    fn debug_text(&self) -> Option<String> {
+        match self {
+            Self::Json(payload) => Some(payload.to_string()),
+            Self::Multipart(_) => None,
+        }
+    }
+
+    fn is_stream(&self) -> bool {
+        matches!(self, Self::Json(payload) if payload.get("stream").and_then(Value::as_bool) == Some(true))
+    }
+}
+
+/// The wire format's headers followed by a session's affinity headers.
+fn chat_headers<'a>(
+    format: WireFormat,
+    affinity: &'a [(&'static str, String)],
+) -> Vec<(&'a str, &'a str)> {
+    format
+        .headers()
+        .iter()
+        .copied()
+        .chain(affinity.iter().map(|(name, value)| (*name, value.as_str())))
+        .collect()
+}
+
+/// Model name used to label metrics for a request payload.
+fn model_label(payload: &Value) -> &str {
+    payload
+        .get("model")
+        .and_then(Value::as_str)
+        .unwrap_or("unknown")
+}
+
+/// The `ApiClient` over `http`, addressing paths under `endpoint_base`.
+///
+/// `ApiClient::with_client` comes from `patches/0002`; goose's other constructors build
+/// a client without the platform's TLS settings.
+fn api_client(http: &reqwest::Client, endpoint_base: &str, auth: ManagedBearerAuth) -> ApiClient {
+    ApiClient::with_client(
+        http.clone(),
+        endpoint_base.to_string(),
+        AuthMethod::Custom(Box::new(auth)),
+    )
+}
+
+/// Errors from the `ApiClient` are `anyhow` errors wrapping the `reqwest` error, if any.
+fn request_error(error: anyhow::Error) -> ProviderError {
+    match error.downcast::<reqwest::Error>() {
+        Ok(error) => error.into(),
+        Err(error) => ProviderError::RequestFailed(error.to_string()),
+    }
+}
+
+fn total_timeout_error(total: Duration) -> ProviderError {
+    ProviderError::RequestFailed(format!(
+        "Tanzu AI Services did not respond within {}s (TANZU_AI_TIMEOUT_SECS)",
+        total.as_secs()
+    ))
+}
+
+/// Cap on `Retry-After` delays, from `TANZU_AI_MAX_RETRY_AFTER_SECS`.
+fn max_retry_after() -> Duration {
+    crate::config::Config::global()
+        .get_param::<u64>("TANZU_AI_MAX_RETRY_AFTER_SECS")
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MAX_RETRY_AFTER)
+}
+
+/// Cap the server's `Retry-After` on a rate limit error, which the retry policy waits
+/// for instead of backing off.
+fn cap_retry_after(error: ProviderError, max_retry_after: Duration) -> ProviderError {
+    match error {
+        ProviderError::RateLimitExceeded {
+            details,
+            retry_delay,
+        } => ProviderError::RateLimitExceeded {
+            details,
+            retry_delay: retry_delay.map(|delay| delay.min(max_retry_after)),
+        },
+        other => other,
+    }
+}
+
+/// Parse a `Retry-After` value in delay-seconds or HTTP-date form.
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means retry now
+    Some(
+        (date.with_timezone(&chrono::Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+impl ProviderRetry for Transport {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tanzu::fault::FaultProfile;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_read_pem_inline_and_file() {
+        let pem = "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+        assert_eq!(read_pem(pem).unwrap(), pem.as_bytes());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("client.pem");
+        std::fs::write(&path, pem).unwrap();
+        assert_eq!(read_pem(path.to_str().unwrap()).unwrap(), pem.as_bytes());
+
+        assert!(read_pem("/nonexistent/client.pem").is_err());
+    }
+
+    #[test]
+    fn test_client_cert_requires_key() {
+        let tls = TlsSettings {
+            client_cert: Some("-----BEGIN CERTIFICATE-----".to_string()),
+            ..Default::default()
+        };
+        let err = build_http_client(&ClientSettings {
+            tls,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("must be set together"));
+    }
+
+    #[test]
+    fn test_invalid_ca_cert_rejected() {
+        let tls = TlsSettings {
+            ca_cert: Some(
+                "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----".to_string(),
+            ),
+            ..Default::default()
+        };
+        assert!(build_http_client(&ClientSettings {
+            tls,
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_empty_ca_bundle_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.pem");
+        std::fs::write(&path, "no certificates here\n").unwrap();
+
+        let tls = TlsSettings {
+            ca_bundle: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let err = build_http_client(&ClientSettings {
+            tls,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("TANZU_AI_CA_BUNDLE"));
+    }
+
+    #[test]
+    fn test_insecure_skip_verify_builds_client() {
+        let tls = TlsSettings {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        assert!(build_http_client(&ClientSettings {
+            tls,
+            ..Default::default()
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = chrono::DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_retry_after_is_capped() {
+        let limited = |secs| ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_delay: Some(Duration::from_secs(secs)),
+        };
+        let delay = |error| match error {
+            ProviderError::RateLimitExceeded { retry_delay, .. } => retry_delay,
+            _ => None,
+        };
+        let cap = Duration::from_secs(60);
+        assert_eq!(
+            delay(cap_retry_after(limited(20), cap)),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(delay(cap_retry_after(limited(600), cap)), Some(cap));
+
+        let server = ProviderError::ServerError("busy".to_string());
+        assert!(matches!(
+            cap_retry_after(server, cap),
+            ProviderError::ServerError(_)
+        ));
+    }
+
+    #[test]
+    fn test_no_proxy_matching() {
+        let no_proxy = NoProxy::parse("localhost, .apps.internal,*.sys.example.com,10.0.0.5");
+
+        assert!(no_proxy.matches("localhost"));
+        assert!(no_proxy.matches("genai-proxy.apps.internal"));
+        assert!(no_proxy.matches("apps.internal"));
+        assert!(no_proxy.matches("GenAI.sys.example.com."));
+        assert!(no_proxy.matches("10.0.0.5"));
+        assert!(!no_proxy.matches("genai.example.com"));
+        assert!(!no_proxy.matches("notapps.internal"));
+
+        assert!(NoProxy::parse("*").matches("anything.example.com"));
+        assert!(!NoProxy::default().matches("localhost"));
+    }
+
+    #[test]
+    fn test_invalid_proxy_url_rejected() {
+        let proxy = ProxySettings {
+            https_proxy: Some("not a url".to_string()),
+            no_proxy: None,
+        };
+        let err = build_http_client(&ClientSettings {
+            proxy,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("proxy"));
+
+        let proxy = ProxySettings {
+            https_proxy: Some("socks5://egress.internal:1080".to_string()),
+            no_proxy: Some(".apps.internal".to_string()),
+        };
+        assert!(build_http_client(&ClientSettings {
+            proxy,
+            ..Default::default()
+        })
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_ends_stalled_stream() {
+        let chunks = futures::stream::iter([Ok::<_, reqwest::Error>("data: {}")])
+            .chain(futures::stream::pending());
+        let items: Vec<_> = with_idle_timeout(chunks, Duration::from_millis(50))
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(*items[0].as_ref().unwrap(), "data: {}");
+        let err = items[1].as_ref().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(err
+            .to_string()
+            .contains("TANZU_AI_STREAM_IDLE_TIMEOUT_SECS"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_retried_when_first_token_is_late() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // The first connection sends headers and then nothing; the second replies
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut stalled = Vec::new();
+            for attempt in 0.. {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 8192];
+                let _ = socket.read(&mut request).await;
+                if attempt == 0 {
+                    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                                Transfer-Encoding: chunked\r\n\r\n";
+                    socket.write_all(head.as_bytes()).await.unwrap();
+                    stalled.push(socket);
+                    continue;
+                }
+                let chunk = serde_json::json!({
+                    "id": "1",
+                    "object": "chat.completion.chunk",
+                    "created": 0,
+                    "model": "llama3.2:1b",
+                    "choices": [{"index": 0, "delta": {"role": "assistant", "content": "hi"}}]
+                });
+                let body = format!("data: {}\n\ndata: [DONE]\n\n", chunk);
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let tokens = TokenManager::new("key".to_string(), || async { Ok("key".to_string()) });
+        let transport = Transport::new(reqwest::Client::new(), &endpoint, Arc::new(tokens))
+            .with_timeouts(TimeoutSettings {
+                first_token: Some(Duration::from_millis(200)),
+                ..Default::default()
+            });
+        let payload = serde_json::json!({"model": "llama3.2:1b", "stream": true});
+        let started = Instant::now();
+        let stream = transport
+            .chat_stream(None, WireFormat::OpenAi, &payload)
+            .await
+            .unwrap();
+        let text: String = stream
+            .filter_map(|item| async move { item.unwrap().0 })
+            .map(|message| message.as_concat_text())
+            .collect()
+            .await;
+        assert_eq!(text, "hi");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_late_first_token_retried_within_retry_limit() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        std::env::set_var("GOOSE_PROVIDER_SKIP_BACKOFF", "true");
+        // Every connection sends headers and then nothing
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            let mut stalled = Vec::new();
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let mut request = vec![0; 8192];
+                let _ = socket.read(&mut request).await;
+                let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                            Transfer-Encoding: chunked\r\n\r\n";
+                socket.write_all(head.as_bytes()).await.unwrap();
+                stalled.push(socket);
+            }
+        });
+
+        let tokens = TokenManager::new("key".to_string(), || async { Ok("key".to_string()) });
+        let transport = Transport::new(reqwest::Client::new(), &endpoint, Arc::new(tokens))
+            .with_timeouts(TimeoutSettings {
+                first_token: Some(Duration::from_millis(50)),
+                ..Default::default()
+            });
+        let payload = serde_json::json!({"model": "llama3.2:1b", "stream": true});
+        let error = match transport
+            .chat_stream(None, WireFormat::OpenAi, &payload)
+            .await
+        {
+            Ok(_) => panic!("stream should fail"),
+            Err(e) => e,
+        };
+        assert!(error
+            .to_string()
+            .contains("TANZU_AI_FIRST_TOKEN_TIMEOUT_SECS"));
+        assert_eq!(
+            connections.load(Ordering::SeqCst),
+            transport.retry_config().max_retries + 1
+        );
+    }
+
+    #[test]
+    fn test_shared_client_cached_per_settings() {
+        let settings = ClientSettings {
+            pool: PoolSettings {
+                max_idle_per_host: 7,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        shared_http_client(&settings).unwrap();
+        shared_http_client(&settings).unwrap();
+        assert!(CLIENTS.lock().unwrap().contains_key(&settings));
+
+        let invalid = ClientSettings {
+            proxy: ProxySettings {
+                https_proxy: Some("not a url".to_string()),
+                no_proxy: None,
+            },
+            ..Default::default()
+        };
+        assert!(shared_http_client(&invalid).is_err());
+        assert!(!CLIENTS.lock().unwrap().contains_key(&invalid));
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_request_retried_with_rotated_key() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("Authorization", "Bearer old-key"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("Authorization", "Bearer rotated-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tokens = TokenManager::new("old-key".to_string(), || async {
+            Ok("rotated-key".to_string())
+        });
+        let transport =
+            Transport::new(reqwest::Client::new(), &mock_server.uri(), Arc::new(tokens));
+        transport
+            .post("openai/v1/chat/completions", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(transport.api_key(), "rotated-key");
+
+        // Without a new key the error surfaces after a single attempt
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let tokens = TokenManager::new("old-key".to_string(), || async {
+            Ok("old-key".to_string())
+        });
+        let transport =
+            Transport::new(reqwest::Client::new(), &mock_server.uri(), Arc::new(tokens));
+        let result = transport
+            .post("openai/v1/chat/completions", &serde_json::json!({}))
+            .await;
+        assert!(matches!(result, Err(ProviderError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_no_retries_once_budget_exhausted() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        for _ in 0..10 {
+            limits::budget_for(&mock_server.uri()).record(true);
+        }
+
+        let tokens = TokenManager::new("key".to_string(), || async { Ok("key".to_string()) });
+        let transport =
+            Transport::new(reqwest::Client::new(), &mock_server.uri(), Arc::new(tokens));
+        let result = transport
+            .post("openai/v1/chat/completions", &serde_json::json!({}))
+            .await;
+        assert!(matches!(result, Err(ProviderError::ServerError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_injected_faults_send_no_request() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        // Without retries left an injected 502 is returned at once
+        for _ in 0..10 {
+            limits::budget_for(&mock_server.uri()).record(true);
+        }
+
+        let tokens = TokenManager::new("key".to_string(), || async { Ok("key".to_string()) });
+        let mut transport =
+            Transport::new(reqwest::Client::new(), &mock_server.uri(), Arc::new(tokens));
+        transport.faults = Some(Faults {
+            profile: FaultProfile::Flaky,
+            rate: 1.0,
+        });
+        let err = transport
+            .post("openai/v1/chat/completions", &serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Injected fault"));
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_secondary_when_primary_unreachable() {
+        let secondary_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(header("Authorization", "Bearer secondary-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(2)
+            .mount(&secondary_server)
+            .await;
+        // Nothing listens on a port released right after binding it
+        let primary = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/plan", listener.local_addr().unwrap())
+        };
+        let secondary = format!("{}/plan", secondary_server.uri());
+
+        let tokens = |key: &'static str| {
+            Arc::new(TokenManager::new(key.to_string(), move || async move {
+                Ok(key.to_string())
+            }))
+        };
+        let failover = Failover::new(&primary, &secondary, 1, Duration::ZERO);
+        let transport = Transport::new(reqwest::Client::new(), &primary, tokens("primary-key"))
+            .with_failover(
+                Transport::new(reqwest::Client::new(), &secondary, tokens("secondary-key")),
+                failover,
+            );
+
+        // Failed over on the first connection failure, then a failed probe falls back again
+        for _ in 0..2 {
+            transport
+                .post("openai/v1/chat/completions", &serde_json::json!({}))
+                .await
+                .unwrap();
+        }
+        assert!(transport.failover.as_ref().unwrap().0.failed_over());
+    }
+}
@@ -0,0 +1,156 @@
+//! Tool result truncation for small context windows.
+//!
+//! A single file listing or log dump can exceed the window of a model on a small plan,
+//! and the request then fails with `ContextLengthExceeded`. When a prompt would not
+//! fit the model's advertised context length, the largest tool results are cut in the
+//! middle, keeping their beginning and end around a marker saying how much was left
+//! out, until the prompt fits. Set `TANZU_AI_TRUNCATE_TOOL_RESULTS=false` to send tool
+//! results as they are.
+
+use super::context::CHARS_PER_TOKEN;
+use crate::conversation::message::{Message, MessageContent};
+use rmcp::model::Content;
+
+/// Share of the context window the prompt may fill, leaving the rest for the reply.
+const PROMPT_SHARE_PERCENT: usize = 90;
+/// Tool results are never cut below this many bytes.
+const MIN_KEPT_BYTES: usize = 1024;
+
+/// Whether oversized tool results are truncated (`TANZU_AI_TRUNCATE_TOOL_RESULTS`).
+pub fn enabled() -> bool {
+    crate::config::Config::global()
+        .get_param("TANZU_AI_TRUNCATE_TOOL_RESULTS")
+        .unwrap_or(true)
+}
+
+/// Shrink the largest tool results until a prompt estimated at `estimated_tokens` fits
+/// `context_length`, or `None` when it already fits or there is nothing to cut.
+pub fn fit_tool_results(
+    messages: &[Message],
+    estimated_tokens: usize,
+    context_length: usize,
+) -> Option<Vec<Message>> {
+    let budget = context_length * PROMPT_SHARE_PERCENT / 100;
+    if estimated_tokens <= budget {
+        return None;
+    }
+    let mut excess = (estimated_tokens - budget) * CHARS_PER_TOKEN;
+
+    // (message, content, item, length) of every text in a tool result, largest first
+    let mut results: Vec<(usize, usize, usize, usize)> = Vec::new();
+    for (m, message) in messages.iter().enumerate() {
+        for (c, content) in message.content.iter().enumerate() {
+            if let MessageContent::ToolResponse(response) = content {
+                if let Ok(items) = &response.tool_result {
+                    for (i, item) in items.iter().enumerate() {
+                        if let Some(text) = item.as_text() {
+                            results.push((m, c, i, text.text.len()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    results.sort_by_key(|r| std::cmp::Reverse(r.3));
+
+    let mut fitted = messages.to_vec();
+    let mut truncated = 0;
+    for (m, c, i, len) in results {
+        if excess == 0 || len <= MIN_KEPT_BYTES {
+            break;
+        }
+        let cut = excess.min(len - MIN_KEPT_BYTES);
+        if let MessageContent::ToolResponse(response) = &mut fitted[m].content[c] {
+            if let Ok(items) = &mut response.tool_result {
+                let text = items[i]
+                    .as_text()
+                    .map(|t| t.text.clone())
+                    .unwrap_or_default();
+                items[i] = Content::text(cut_middle(&text, len - cut));
+            }
+        }
+        excess = excess.saturating_sub(cut);
+        truncated += 1;
+    }
+
+    if truncated == 0 {
+        return None;
+    }
+    tracing::info!(
+        "Truncated {} tool result(s) to fit a {} token context window",
+        truncated,
+        context_length
+    );
+    Some(fitted)
+}
+
+/// Keep about `keep` bytes of `text`, two thirds from the start and one third from the end.
+fn cut_middle(text: &str, keep: usize) -> String {
+    let head = floor_char_boundary(text, keep * 2 / 3);
+    let tail = ceil_char_boundary(text, text.len() - (keep - keep * 2 / 3));
+    format!(
+        "{}\n[... {} bytes of tool output omitted to fit the model's context window ...]\n{}",
+        &text[..head],
+        tail - head,
+        &text[tail..]
+    )
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_output(message: &Message) -> String {
+        match &message.content[0] {
+            MessageContent::ToolResponse(response) => response.tool_result.as_ref().unwrap()[0]
+                .as_text()
+                .unwrap()
+                .text
+                .clone(),
+            _ => panic!("expected a tool response"),
+        }
+    }
+
+    #[test]
+    fn test_largest_result_truncated_with_marker() {
+        let big = format!("BEGIN{}END", "é".repeat(20_000));
+        let messages = vec![
+            Message::user().with_text("list the files"),
+            Message::user().with_tool_response("call_1", Ok(vec![Content::text("small")])),
+            Message::user().with_tool_response("call_2", Ok(vec![Content::text(big.clone())])),
+        ];
+        // 40k bytes of output in a 4k window
+        let estimated = big.len() / CHARS_PER_TOKEN;
+
+        let fitted = fit_tool_results(&messages, estimated, 4096).unwrap();
+        assert_eq!(tool_output(&fitted[1]), "small");
+        let output = tool_output(&fitted[2]);
+        assert!(output.starts_with("BEGIN"));
+        assert!(output.ends_with("END"));
+        assert!(output.contains("bytes of tool output omitted"));
+        assert!(output.len() < 16_000);
+    }
+
+    #[test]
+    fn test_fitting_prompt_untouched() {
+        let messages =
+            vec![Message::user().with_tool_response("call_1", Ok(vec![Content::text("ok")]))];
+        assert!(fit_tool_results(&messages, 100, 4096).is_none());
+        // Nothing large enough to cut
+        assert!(fit_tool_results(&messages, 8000, 4096).is_none());
+    }
+}
@@ -0,0 +1,260 @@
+//! OAuth2 client-credentials authentication against UAA.
+//!
+//! Some foundations issue short-lived tokens from UAA instead of long-lived binding
+//! JWTs. With `TANZU_AI_CLIENT_ID`, `TANZU_AI_CLIENT_SECRET` and `TANZU_AI_TOKEN_URL`
+//! set, the client credentials are exchanged for a bearer token, which is cached and
+//! exchanged again shortly before it expires or when the endpoint answers 401.
+
+use crate::providers::errors::ProviderError;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+/// Exchange this long before the token actually expires.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// How requests to a binding are authenticated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// The binding's API key, sent as is
+    #[default]
+    ApiKey,
+    /// Bearer tokens obtained from UAA with OAuth2 client credentials
+    ClientCredentials(UaaClientConfig),
+}
+
+/// A UAA OAuth2 client.
+#[derive(Clone, PartialEq, Eq)]
+pub struct UaaClientConfig {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl fmt::Debug for UaaClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UaaClientConfig")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"[REDACTED]")
+            .finish()
+    }
+}
+
+impl UaaClientConfig {
+    /// Read the client from `TANZU_AI_CLIENT_ID`, `TANZU_AI_CLIENT_SECRET` and
+    /// `TANZU_AI_TOKEN_URL`.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let client_id: String = config.get_param("TANZU_AI_CLIENT_ID").ok()?;
+        let Ok(client_secret) = config.get_secret::<String>("TANZU_AI_CLIENT_SECRET") else {
+            tracing::warn!("TANZU_AI_CLIENT_ID is set without TANZU_AI_CLIENT_SECRET; ignoring");
+            return None;
+        };
+        let Ok(token_url) = config.get_param::<String>("TANZU_AI_TOKEN_URL") else {
+            tracing::warn!("TANZU_AI_CLIENT_ID is set without TANZU_AI_TOKEN_URL; ignoring");
+            return None;
+        };
+        Some(Self {
+            token_url,
+            client_id,
+            client_secret,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+struct CachedToken {
+    token: String,
+    expires_at: Option<SystemTime>,
+}
+
+/// Bearer tokens for one binding, exchanged at UAA on demand.
+pub struct UaaTokens {
+    client: UaaClientConfig,
+    http: reqwest::Client,
+    cached: RwLock<Option<CachedToken>>,
+    /// Serialises exchanges so concurrent requests share one token
+    exchange: tokio::sync::Mutex<()>,
+}
+
+impl UaaTokens {
+    pub fn new(client: UaaClientConfig, http: reqwest::Client) -> Self {
+        Self {
+            client,
+            http,
+            cached: RwLock::new(None),
+            exchange: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The cached token, possibly empty or stale; for redaction and logging.
+    pub fn current(&self) -> String {
+        self.cached
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|c| c.token.clone())
+            .unwrap_or_default()
+    }
+
+    /// A valid bearer token, exchanging the client credentials first when needed.
+    pub async fn token(&self) -> Result<String, ProviderError> {
+        if let Some(token) = self.fresh(SystemTime::now()) {
+            return Ok(token);
+        }
+        let _exchange = self.exchange.lock().await;
+        // Another request may have exchanged while this one waited
+        if let Some(token) = self.fresh(SystemTime::now()) {
+            return Ok(token);
+        }
+
+        let token = self.exchange().await?;
+        let value = token.token.clone();
+        *self.cached.write().unwrap() = Some(token);
+        Ok(value)
+    }
+
+    /// Drop the cached token so the next request exchanges again.
+    pub fn invalidate(&self) {
+        *self.cached.write().unwrap() = None;
+    }
+
+    fn fresh(&self, now: SystemTime) -> Option<String> {
+        let cached = self.cached.read().unwrap();
+        let cached = cached.as_ref()?;
+        match cached.expires_at {
+            Some(expires_at) if now + REFRESH_MARGIN >= expires_at => None,
+            _ => Some(cached.token.clone()),
+        }
+    }
+
+    async fn exchange(&self) -> Result<CachedToken, ProviderError> {
+        let failed = |reason: String| {
+            ProviderError::Authentication(format!(
+                "UAA token request to {} failed: {}",
+                self.client.token_url, reason
+            ))
+        };
+        let response = self
+            .http
+            .post(&self.client.token_url)
+            .basic_auth(&self.client.client_id, Some(&self.client.client_secret))
+            .header("Accept", "application/json")
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(failed(format!("{} {}", status, body.trim())));
+        }
+        let token: TokenResponse = response.json().await.map_err(|e| failed(e.to_string()))?;
+        tracing::debug!(
+            "Obtained UAA token for client {} (expires in {:?}s)",
+            self.client.client_id,
+            token.expires_in
+        );
+        Ok(CachedToken {
+            token: token.access_token,
+            expires_at: token
+                .expires_in
+                .map(|secs| SystemTime::now() + Duration::from_secs(secs)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer) -> UaaClientConfig {
+        UaaClientConfig {
+            token_url: format!("{}/oauth/token", server.uri()),
+            client_id: "goose".to_string(),
+            client_secret: "s3cret".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_cached_until_invalidated() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            // base64("goose:s3cret")
+            .and(header("Authorization", "Basic Z29vc2U6czNjcmV0"))
+            .and(body_string_contains("grant_type=client_credentials"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "uaa-token",
+                "token_type": "bearer",
+                "expires_in": 3600
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let tokens = UaaTokens::new(client(&mock_server), reqwest::Client::new());
+        assert_eq!(tokens.current(), "");
+        assert_eq!(tokens.token().await.unwrap(), "uaa-token");
+        assert_eq!(tokens.token().await.unwrap(), "uaa-token");
+        assert_eq!(tokens.current(), "uaa-token");
+
+        tokens.invalidate();
+        assert_eq!(tokens.token().await.unwrap(), "uaa-token");
+    }
+
+    #[tokio::test]
+    async fn test_token_exchanged_again_near_expiry() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "short-lived",
+                "expires_in": 30
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let tokens = UaaTokens::new(client(&mock_server), reqwest::Client::new());
+        tokens.token().await.unwrap();
+        // Within the refresh margin, so every request exchanges again
+        tokens.token().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejected_client_is_authentication_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("Bad credentials"))
+            .mount(&mock_server)
+            .await;
+
+        let tokens = UaaTokens::new(client(&mock_server), reqwest::Client::new());
+        let err = tokens.token().await.unwrap_err();
+        assert!(
+            matches!(err, ProviderError::Authentication(msg) if msg.contains("Bad credentials"))
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_secret() {
+        let config = UaaClientConfig {
+            token_url: "https://uaa.sys.example.com/oauth/token".to_string(),
+            client_id: "goose".to_string(),
+            client_secret: "s3cret".to_string(),
+        };
+        assert!(!format!("{:?}", config).contains("s3cret"));
+    }
+}
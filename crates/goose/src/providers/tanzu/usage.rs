@@ -0,0 +1,433 @@
+//! Per-session token accounting.
+//!
+//! The ledger accumulates prompt and completion tokens per Goose session and model so
+//! users can reconcile consumption against their plan's quota. When
+//! `TANZU_AI_USAGE_FILE` is set, the full report is rewritten to that path on a blocking
+//! thread after recorded requests. Only one write runs at a time, and changes made while
+//! it runs are written together afterwards. Streamed requests also add their timing, so the report can give
+//! each model's average time to first token and throughput.
+
+use super::timing::StreamTiming;
+use super::usage_export::{self, UsageExporter, UsageRecord};
+use crate::providers::base::Usage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Session key used for requests made outside a Goose session.
+const NO_SESSION: &str = "default";
+
+/// Tokens consumed by one model.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Prompt tokens served from the model's prefix cache
+    pub cached_prompt_tokens: u64,
+    /// Streams with timing recorded; the timing totals below cover only these
+    pub timed_streams: u64,
+    pub time_to_first_token_ms: u64,
+    pub streamed_output_tokens: u64,
+    /// Time from each stream's first to last content chunk
+    pub generation_ms: u64,
+}
+
+impl ModelUsage {
+    fn add(&mut self, other: &ModelUsage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cached_prompt_tokens += other.cached_prompt_tokens;
+        self.timed_streams += other.timed_streams;
+        self.time_to_first_token_ms += other.time_to_first_token_ms;
+        self.streamed_output_tokens += other.streamed_output_tokens;
+        self.generation_ms += other.generation_ms;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Average time to first token over the timed streams.
+    pub fn mean_time_to_first_token_ms(&self) -> Option<u64> {
+        (self.timed_streams > 0).then(|| self.time_to_first_token_ms / self.timed_streams)
+    }
+
+    /// Output tokens per second of generation over the timed streams.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        (self.generation_ms > 0)
+            .then(|| self.streamed_output_tokens as f64 * 1000.0 / self.generation_ms as f64)
+    }
+}
+
+/// Token usage recorded by a Tanzu AI provider, by session and model.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TanzuUsageReport {
+    /// Session id → model → usage
+    pub sessions: BTreeMap<String, BTreeMap<String, ModelUsage>>,
+    /// Model → revision last served, for models whose revision is advertised
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub revisions: BTreeMap<String, String>,
+}
+
+impl TanzuUsageReport {
+    /// Usage summed across sessions for each model.
+    pub fn by_model(&self) -> BTreeMap<String, ModelUsage> {
+        let mut totals: BTreeMap<String, ModelUsage> = BTreeMap::new();
+        for models in self.sessions.values() {
+            for (model, usage) in models {
+                totals.entry(model.clone()).or_default().add(usage);
+            }
+        }
+        totals
+    }
+
+    /// Usage of one session, summed across models.
+    pub fn session_total(&self, session_id: &str) -> ModelUsage {
+        let mut total = ModelUsage::default();
+        for usage in self
+            .sessions
+            .get(session_id)
+            .into_iter()
+            .flat_map(|m| m.values())
+        {
+            total.add(usage);
+        }
+        total
+    }
+
+    /// Usage summed across every session and model.
+    pub fn total(&self) -> ModelUsage {
+        let mut total = ModelUsage::default();
+        for usage in self.by_model().values() {
+            total.add(usage);
+        }
+        total
+    }
+}
+
+/// Accumulates token usage for one provider instance.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    report: Mutex<TanzuUsageReport>,
+    file: Option<Arc<UsageFile>>,
+    /// Webhook export (`TANZU_AI_USAGE_WEBHOOK`), kept running while the ledger lives
+    exporter: Option<Arc<UsageExporter>>,
+}
+
+impl UsageLedger {
+    pub fn new(file: Option<PathBuf>) -> Self {
+        Self {
+            report: Mutex::new(TanzuUsageReport::default()),
+            file: file.map(|path| {
+                Arc::new(UsageFile {
+                    path,
+                    pending: Mutex::default(),
+                })
+            }),
+            exporter: None,
+        }
+    }
+
+    /// Ledger writing to `TANZU_AI_USAGE_FILE` and exporting to `TANZU_AI_USAGE_WEBHOOK`
+    /// when they are configured.
+    pub fn from_config() -> Self {
+        let file: Option<String> = crate::config::Config::global()
+            .get_param("TANZU_AI_USAGE_FILE")
+            .ok();
+        Self {
+            exporter: usage_export::start(),
+            ..Self::new(file.map(PathBuf::from))
+        }
+    }
+
+    /// Add one request's usage to the ledger.
+    pub fn record(&self, session_id: Option<&str>, model: &str, usage: &Usage) {
+        if let Some(exporter) = &self.exporter {
+            exporter.send(UsageRecord::new(session_id, model, usage));
+        }
+        self.accumulate(
+            session_id,
+            model,
+            ModelUsage {
+                requests: 1,
+                prompt_tokens: usage.input_tokens.unwrap_or(0).max(0) as u64,
+                completion_tokens: usage.output_tokens.unwrap_or(0).max(0) as u64,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Add prompt tokens a recorded request was served from the prefix cache.
+    pub fn record_cache_hits(&self, session_id: Option<&str>, model: &str, tokens: u64) {
+        let entry = ModelUsage {
+            cached_prompt_tokens: tokens,
+            ..Default::default()
+        };
+        self.accumulate(session_id, model, entry);
+    }
+
+    /// Add the timing of a streamed request.
+    pub fn record_stream_timing(
+        &self,
+        session_id: Option<&str>,
+        model: &str,
+        timing: &StreamTiming,
+    ) {
+        let entry = ModelUsage {
+            timed_streams: 1,
+            time_to_first_token_ms: timing.time_to_first_token.as_millis() as u64,
+            streamed_output_tokens: timing.output_tokens,
+            generation_ms: timing.generation.as_millis() as u64,
+            ..Default::default()
+        };
+        self.accumulate(session_id, model, entry);
+    }
+
+    /// Note the revision a model was served at.
+    pub fn record_revision(&self, model: &str, revision: &str) {
+        self.update(|report| {
+            let previous = report
+                .revisions
+                .insert(model.to_string(), revision.to_string());
+            previous.as_deref() != Some(revision)
+        });
+    }
+
+    /// Carry over the usage of a resumed session, unless it already has usage here.
+    pub fn restore_session(&self, session_id: &str, model: &str, usage: ModelUsage) {
+        self.update(|report| {
+            if usage == ModelUsage::default() || report.sessions.contains_key(session_id) {
+                return false;
+            }
+            report
+                .sessions
+                .entry(session_id.to_string())
+                .or_default()
+                .insert(model.to_string(), usage);
+            true
+        });
+    }
+
+    fn accumulate(&self, session_id: Option<&str>, model: &str, entry: ModelUsage) {
+        self.update(|report| {
+            report
+                .sessions
+                .entry(session_id.unwrap_or(NO_SESSION).to_string())
+                .or_default()
+                .entry(model.to_string())
+                .or_default()
+                .add(&entry);
+            true
+        });
+    }
+
+    /// Apply `change` to the report, rewriting the usage file if it reports a change.
+    fn update(&self, change: impl FnOnce(&mut TanzuUsageReport) -> bool) {
+        let mut report = self.report.lock().unwrap();
+        if change(&mut report) {
+            if let Some(file) = &self.file {
+                // Queued under the report lock, so snapshots reach the file in order
+                file.save(report.clone());
+            }
+        }
+    }
+
+    pub fn report(&self) -> TanzuUsageReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Send usage still queued for the webhook.
+    pub async fn flush(&self) {
+        if let Some(exporter) = &self.exporter {
+            exporter.flush().await;
+        }
+    }
+}
+
+/// The usage file and the latest report waiting to be written to it.
+#[derive(Debug)]
+struct UsageFile {
+    path: PathBuf,
+    pending: Mutex<Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    report: Option<TanzuUsageReport>,
+    /// Whether a writer is running; it picks up reports queued meanwhile
+    writing: bool,
+}
+
+impl UsageFile {
+    /// Queue `report` to be written, starting a writer unless one is running.
+    ///
+    /// Outside a Tokio runtime the report is written before returning.
+    fn save(self: &Arc<Self>, report: TanzuUsageReport) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            pending.report = Some(report);
+            if std::mem::replace(&mut pending.writing, true) {
+                return;
+            }
+        }
+        let file = self.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || file.write_pending());
+            }
+            Err(_) => file.write_pending(),
+        }
+    }
+
+    /// Write queued reports until none is left.
+    fn write_pending(&self) {
+        loop {
+            let report = {
+                let mut pending = self.pending.lock().unwrap();
+                match pending.report.take() {
+                    Some(report) => report,
+                    None => {
+                        pending.writing = false;
+                        return;
+                    }
+                }
+            };
+            if let Err(e) = write_report(&self.path, &report) {
+                tracing::warn!(
+                    "Failed to write Tanzu AI usage file {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Write the report next to its destination and rename it into place.
+fn write_report(path: &Path, report: &TanzuUsageReport) -> anyhow::Result<()> {
+    let json = serde_json::to_vec_pretty(report)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ledger_accumulates_by_session_and_model() {
+        let ledger = UsageLedger::new(None);
+        ledger.record(
+            Some("s1"),
+            "llama3.2:1b",
+            &Usage::new(Some(10), Some(5), Some(15)),
+        );
+        ledger.record(
+            Some("s1"),
+            "llama3.2:1b",
+            &Usage::new(Some(20), Some(2), Some(22)),
+        );
+        ledger.record(Some("s2"), "qwen3-30b", &Usage::new(Some(7), None, None));
+        ledger.record(None, "llama3.2:1b", &Usage::new(Some(1), Some(1), Some(2)));
+
+        let report = ledger.report();
+        assert_eq!(
+            report.sessions["s1"]["llama3.2:1b"],
+            ModelUsage {
+                requests: 2,
+                prompt_tokens: 30,
+                completion_tokens: 7,
+                ..Default::default()
+            }
+        );
+        assert_eq!(report.sessions[NO_SESSION]["llama3.2:1b"].requests, 1);
+        assert_eq!(report.by_model()["llama3.2:1b"].total_tokens(), 39);
+        assert_eq!(report.total().requests, 4);
+        assert_eq!(report.total().total_tokens(), 46);
+
+        ledger.record_cache_hits(Some("s1"), "llama3.2:1b", 24);
+        let usage = ledger.report().sessions["s1"]["llama3.2:1b"];
+        assert_eq!((usage.requests, usage.cached_prompt_tokens), (2, 24));
+
+        let timing = StreamTiming {
+            time_to_first_token: Duration::from_millis(300),
+            generation: Duration::from_millis(500),
+            output_tokens: 40,
+        };
+        ledger.record_stream_timing(Some("s2"), "qwen3-30b", &timing);
+        ledger.record_stream_timing(
+            Some("s2"),
+            "qwen3-30b",
+            &StreamTiming {
+                time_to_first_token: Duration::from_millis(100),
+                ..timing
+            },
+        );
+        let usage = ledger.report().by_model()["qwen3-30b"];
+        assert_eq!(usage.mean_time_to_first_token_ms(), Some(200));
+        assert_eq!(usage.tokens_per_second(), Some(80.0));
+        assert_eq!(ModelUsage::default().tokens_per_second(), None);
+    }
+
+    #[test]
+    fn test_ledger_writes_usage_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        let ledger = UsageLedger::new(Some(path.clone()));
+
+        ledger.record(
+            Some("s1"),
+            "llama3.2:1b",
+            &Usage::new(Some(3), Some(4), Some(7)),
+        );
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["sessions"]["s1"]["llama3.2:1b"]["prompt_tokens"], 3);
+        assert!(written.get("revisions").is_none());
+        assert_eq!(
+            written["sessions"]["s1"]["llama3.2:1b"]["completion_tokens"],
+            4
+        );
+
+        ledger.record_revision("llama3.2:1b", "sha256:9f86d081");
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["revisions"]["llama3.2:1b"], "sha256:9f86d081");
+    }
+
+    #[tokio::test]
+    async fn test_ledger_writes_usage_file_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        let ledger = UsageLedger::new(Some(path.clone()));
+
+        for _ in 0..50 {
+            ledger.record(
+                Some("s1"),
+                "qwen3-30b",
+                &Usage::new(Some(1), Some(1), Some(2)),
+            );
+        }
+
+        // Writes are coalesced, but the last one carries every request
+        let requests = || -> Option<u64> {
+            let written: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(&path).ok()?).ok()?;
+            written["sessions"]["s1"]["qwen3-30b"]["requests"].as_u64()
+        };
+        for _ in 0..200 {
+            if requests() == Some(50) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(requests(), Some(50));
+    }
+}
@@ -0,0 +1,588 @@
+//! Per-request usage posted to a FinOps webhook.
+//!
+//! With `TANZU_AI_USAGE_WEBHOOK` set, every request's token usage is queued and posted
+//! to that URL in batches, once `TANZU_AI_USAGE_WEBHOOK_BATCH_SIZE` (100) records are
+//! waiting or every `TANZU_AI_USAGE_WEBHOOK_INTERVAL_SECS` (10), whichever comes first.
+//! `TANZU_AI_USAGE_WEBHOOK_TOKEN` is sent as a bearer token when set. A batch is
+//!
+//! ```json
+//! {"source": {"org": "...", "space": "...", "app": "..."},
+//!  "records": [{"timestamp": "...", "session_id": "...", "model": "...",
+//!               "prompt_tokens": 812, "completion_tokens": 64}]}
+//! ```
+//!
+//! with the org, space and app of the cost attribution headers. A batch that still
+//! fails after three attempts is appended to a spill file (`usage-spill.jsonl` in the
+//! discovery cache directory, or `TANZU_AI_USAGE_SPILL_FILE`) and sent ahead of the
+//! next batch. The spill file keeps the newest 10,000 records. Processes sharing it take
+//! turns through a lock file next to it, and a flush takes the spilled records out of
+//! the file before posting them, so records spilled meanwhile are kept.
+//!
+//! One exporter runs per process and lives as long as a provider's usage ledger holds it.
+//! Records not yet sent when the last one is dropped are spilled, as are records queued
+//! after the runtime the exporter was started on has shut down; a later exporter sends
+//! them. Embedding code can [`UsageExporter::flush`] before shutting down instead.
+//!
+//! Under `TANZU_AI_STRICT_EGRESS` the provider refuses to start with a webhook
+//! configured; see [`super::egress`].
+
+use super::attribution::Attribution;
+use super::disk_cache::{default_dir, Lock};
+use super::transport::{shared_http_client, ClientSettings};
+use crate::providers::base::Usage;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const ATTEMPTS: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const POST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Most records kept in the spill file; older ones are dropped first.
+const MAX_SPILLED: usize = 10_000;
+
+static EXPORTER: Mutex<Weak<UsageExporter>> = Mutex::new(Weak::new());
+
+/// One request's usage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// RFC 3339
+    pub timestamp: String,
+    pub session_id: Option<String>,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Where and how batches are sent.
+#[derive(Debug, Clone)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub token: Option<String>,
+    pub batch_size: usize,
+    pub interval: Duration,
+    pub spill_file: Option<PathBuf>,
+    pub backoff: Duration,
+}
+
+impl WebhookSettings {
+    /// The configured webhook, if `TANZU_AI_USAGE_WEBHOOK` is set.
+    pub fn from_config() -> Option<Self> {
+        let config = crate::config::Config::global();
+        let url = config.get_param::<String>("TANZU_AI_USAGE_WEBHOOK").ok()?;
+        Some(Self {
+            url,
+            token: config
+                .get_secret::<String>("TANZU_AI_USAGE_WEBHOOK_TOKEN")
+                .ok(),
+            batch_size: config
+                .get_param::<usize>("TANZU_AI_USAGE_WEBHOOK_BATCH_SIZE")
+                .unwrap_or(DEFAULT_BATCH_SIZE)
+                .max(1),
+            interval: config
+                .get_param::<u64>("TANZU_AI_USAGE_WEBHOOK_INTERVAL_SECS")
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map_or(DEFAULT_INTERVAL, Duration::from_secs),
+            spill_file: config
+                .get_param::<String>("TANZU_AI_USAGE_SPILL_FILE")
+                .map(PathBuf::from)
+                .ok()
+                .or_else(|| default_dir().map(|dir| dir.join("usage-spill.jsonl"))),
+            backoff: INITIAL_BACKOFF,
+        })
+    }
+}
+
+/// Queues records for a background task that batches and posts them.
+#[derive(Debug)]
+pub struct UsageExporter {
+    shared: Arc<Shared>,
+    settings: WebhookSettings,
+    source: Attribution,
+    http: reqwest::Client,
+    task: JoinHandle<()>,
+}
+
+/// Records waiting to be sent, shared with the background task.
+#[derive(Debug, Default)]
+struct Shared {
+    queued: Mutex<Vec<UsageRecord>>,
+    /// Taken from the queue by a flush that has not finished
+    sending: Mutex<Vec<UsageRecord>>,
+    /// Held for the length of a flush, so flushes don't overwrite each other's `sending`
+    flushing: tokio::sync::Mutex<()>,
+    full: Notify,
+}
+
+impl UsageExporter {
+    /// Start posting to the webhook of `settings` from a task on the current runtime.
+    pub fn spawn(settings: WebhookSettings, http: reqwest::Client) -> Self {
+        let shared = Arc::new(Shared::default());
+        let source = Attribution::from_config();
+        let task = tokio::spawn(run(
+            shared.clone(),
+            settings.clone(),
+            source.clone(),
+            http.clone(),
+        ));
+        Self {
+            shared,
+            settings,
+            source,
+            http,
+            task,
+        }
+    }
+
+    pub fn send(&self, record: UsageRecord) {
+        let full = {
+            let mut queued = self.shared.queued.lock().unwrap();
+            queued.push(record);
+            queued.len() >= self.settings.batch_size
+        };
+        if self.is_stopped() {
+            tracing::warn!("Tanzu AI usage exporter stopped with its runtime; spilling records");
+            self.spill_unsent();
+        } else if full {
+            self.shared.full.notify_one();
+        }
+    }
+
+    /// Post everything queued now, spilling it if the webhook can't be reached.
+    pub async fn flush(&self) {
+        flush_queued(&self.shared, &self.settings, &self.source, &self.http).await;
+    }
+
+    /// Whether the background task has ended, which only happens when its runtime shuts
+    /// down.
+    fn is_stopped(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Move records that were not sent, including a batch whose post was cut short, to
+    /// the spill file.
+    ///
+    /// This doesn't wait for `flushing`, as a flush cut short never releases it; the spill
+    /// file's lock keeps it from racing a flush on the file.
+    fn spill_unsent(&self) {
+        let mut records = std::mem::take(&mut *self.shared.sending.lock().unwrap());
+        records.append(&mut self.shared.queued.lock().unwrap());
+        if !records.is_empty() {
+            spill(self.settings.spill_file.as_deref(), records);
+        }
+    }
+}
+
+impl Drop for UsageExporter {
+    fn drop(&mut self) {
+        // A batch whose post is cut short here is spilled and may reach the webhook twice,
+        // which beats losing it
+        self.task.abort();
+        self.spill_unsent();
+    }
+}
+
+/// The process's exporter, started on the current runtime when none is running.
+///
+/// `None` unless `TANZU_AI_USAGE_WEBHOOK` is set.
+pub fn start() -> Option<Arc<UsageExporter>> {
+    let settings = WebhookSettings::from_config()?;
+    let mut running = EXPORTER.lock().unwrap();
+    if let Some(exporter) = running.upgrade().filter(|e| !e.is_stopped()) {
+        return Some(exporter);
+    }
+    let http = match shared_http_client(&ClientSettings::from_config()) {
+        Ok(http) => http,
+        Err(e) => {
+            tracing::warn!("Not exporting Tanzu AI usage: {}", e);
+            return None;
+        }
+    };
+    tracing::info!("Exporting Tanzu AI usage to {}", settings.url);
+    let exporter = Arc::new(UsageExporter::spawn(settings, http));
+    *running = Arc::downgrade(&exporter);
+    Some(exporter)
+}
+
+impl UsageRecord {
+    /// The record of one request's usage, timestamped now.
+    pub fn new(session_id: Option<&str>, model: &str, usage: &Usage) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            session_id: session_id.map(String::from),
+            model: model.to_string(),
+            prompt_tokens: usage.input_tokens.unwrap_or(0).max(0) as u64,
+            completion_tokens: usage.output_tokens.unwrap_or(0).max(0) as u64,
+        }
+    }
+}
+
+async fn run(
+    shared: Arc<Shared>,
+    settings: WebhookSettings,
+    source: Attribution,
+    http: reqwest::Client,
+) {
+    let mut ticker = tokio::time::interval(settings.interval);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = shared.full.notified() => {}
+            _ = ticker.tick() => {}
+        }
+        flush_queued(&shared, &settings, &source, &http).await;
+    }
+}
+
+/// Send anything spilled earlier and the queued records, keeping them visible to
+/// [`UsageExporter::spill_unsent`] until they are posted or spilled again.
+async fn flush_queued(
+    shared: &Shared,
+    settings: &WebhookSettings,
+    source: &Attribution,
+    http: &reqwest::Client,
+) {
+    let _flushing = shared.flushing.lock().await;
+    let records = {
+        let mut queued = shared.queued.lock().unwrap();
+        if queued.is_empty() {
+            return;
+        }
+        let mut records = settings
+            .spill_file
+            .as_deref()
+            .map(take_spill)
+            .unwrap_or_default();
+        if !records.is_empty() {
+            tracing::info!("Resending {} spilled Tanzu AI usage records", records.len());
+        }
+        records.append(&mut queued);
+        shared.sending.lock().unwrap().clone_from(&records);
+        records
+    };
+    flush(records, settings, source, http).await;
+    shared.sending.lock().unwrap().clear();
+}
+
+/// Post `records`, spilling them on failure.
+async fn flush(
+    records: Vec<UsageRecord>,
+    settings: &WebhookSettings,
+    source: &Attribution,
+    http: &reqwest::Client,
+) {
+    if let Err(e) = post(&records, settings, source, http).await {
+        tracing::warn!(
+            "Could not send {} Tanzu AI usage records to {}: {}",
+            records.len(),
+            settings.url,
+            e
+        );
+        spill(settings.spill_file.as_deref(), records);
+    }
+}
+
+async fn post(
+    records: &[UsageRecord],
+    settings: &WebhookSettings,
+    source: &Attribution,
+    http: &reqwest::Client,
+) -> anyhow::Result<()> {
+    let body = json!({
+        "source": {"org": source.org, "space": source.space, "app": source.app},
+        "records": records,
+    });
+    let mut delay = settings.backoff;
+    let mut attempt = 1;
+    loop {
+        let mut request = http.post(&settings.url).json(&body).timeout(POST_TIMEOUT);
+        if let Some(token) = &settings.token {
+            request = request.bearer_auth(token);
+        }
+        let result = match request.send().await {
+            Ok(response) => response.error_for_status().map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => return Ok(()),
+            // Client errors will not go away on their own
+            Err(e) if attempt >= ATTEMPTS || e.status().is_some_and(|s| s.is_client_error()) => {
+                return Err(e.into())
+            }
+            Err(e) => {
+                tracing::debug!("Usage webhook attempt {} failed: {}", attempt, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Add `records` to the spill file, logging them as dropped when that fails.
+fn spill(path: Option<&Path>, mut records: Vec<UsageRecord>) {
+    let Some(path) = path else {
+        tracing::warn!(
+            "Dropping {} Tanzu AI usage records, no spill file is configured",
+            records.len()
+        );
+        return;
+    };
+    let result = spill_lock(path).and_then(|_lock| {
+        let mut spilled = read_spill(path);
+        spilled.append(&mut records);
+        write_spill(path, &spilled)
+    });
+    if let Err(e) = result {
+        tracing::warn!(
+            "Dropping Tanzu AI usage records, could not spill them to {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Empty the spill file, returning the records it held.
+fn take_spill(path: &Path) -> Vec<UsageRecord> {
+    let _lock = match spill_lock(path) {
+        Ok(lock) => lock,
+        Err(e) => {
+            tracing::debug!("Not resending spilled Tanzu AI usage records: {}", e);
+            return Vec::new();
+        }
+    };
+    let records = read_spill(path);
+    if !records.is_empty() {
+        let _ = std::fs::remove_file(path);
+    }
+    records
+}
+
+/// The lock held by any process reading and changing the spill file.
+fn spill_lock(path: &Path) -> std::io::Result<Lock> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    Lock::acquire(&path.with_extension("lock"))
+}
+
+fn read_spill(path: &Path) -> Vec<UsageRecord> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Replace the spill file with the newest [`MAX_SPILLED`] of `records`. The caller holds
+/// [`spill_lock`].
+fn write_spill(path: &Path, records: &[UsageRecord]) -> std::io::Result<()> {
+    let dropped = records.len().saturating_sub(MAX_SPILLED);
+    if dropped > 0 {
+        tracing::warn!(
+            "Tanzu AI usage spill file is full, dropping the {} oldest records",
+            dropped
+        );
+    }
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let mut file = std::fs::File::create(&tmp)?;
+    for record in &records[dropped..] {
+        serde_json::to_writer(&mut file, record)?;
+        file.write_all(b"\n")?;
+    }
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn usage_record(model: &str) -> UsageRecord {
+        UsageRecord {
+            timestamp: "2026-10-17T09:00:00+00:00".to_string(),
+            session_id: Some("s1".to_string()),
+            model: model.to_string(),
+            prompt_tokens: 812,
+            completion_tokens: 64,
+        }
+    }
+
+    fn settings(url: String, spill_file: PathBuf) -> WebhookSettings {
+        WebhookSettings {
+            url,
+            token: Some("finops-token".to_string()),
+            batch_size: 2,
+            interval: Duration::from_secs(60),
+            spill_file: Some(spill_file),
+            backoff: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spills_failed_batches_and_resends_them() {
+        let mock_server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let spill = dir.path().join("usage-spill.jsonl");
+        let settings = settings(format!("{}/usage", mock_server.uri()), spill.clone());
+        let source = Attribution::default();
+        let http = reqwest::Client::new();
+
+        Mock::given(method("POST"))
+            .and(path("/usage"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(ATTEMPTS as u64)
+            .mount(&mock_server)
+            .await;
+        flush(vec![usage_record("llama3.2:1b")], &settings, &source, &http).await;
+        assert_eq!(read_spill(&spill), vec![usage_record("llama3.2:1b")]);
+
+        Mock::given(method("POST"))
+            .and(path("/usage"))
+            .and(header("Authorization", "Bearer finops-token"))
+            .respond_with(ResponseTemplate::new(202))
+            .mount(&mock_server)
+            .await;
+        let shared = Shared::default();
+        shared.queued.lock().unwrap().push(usage_record("qwen3:8b"));
+        flush_queued(&shared, &settings, &source, &http).await;
+        assert!(!spill.exists());
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), ATTEMPTS as usize + 1);
+        let body: serde_json::Value = requests.last().unwrap().body_json().unwrap();
+        let models: Vec<&str> = body["records"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["model"].as_str().unwrap())
+            .collect();
+        assert_eq!(models, vec!["llama3.2:1b", "qwen3:8b"]);
+    }
+
+    #[test]
+    fn test_concurrent_spills_keep_every_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill_file = dir.path().join("spill.jsonl");
+
+        // Two writers spill while a third takes the file as a flush would
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|model| {
+                let spill_file = spill_file.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        spill(Some(&spill_file), vec![usage_record(model)]);
+                    }
+                })
+            })
+            .collect();
+        let taker = {
+            let spill_file = spill_file.clone();
+            std::thread::spawn(move || {
+                let mut taken = Vec::new();
+                for _ in 0..10 {
+                    taken.extend(take_spill(&spill_file));
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                taken
+            })
+        };
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let mut records = taker.join().unwrap();
+        records.extend(read_spill(&spill_file));
+
+        assert_eq!(records.len(), 40);
+        assert_eq!(records.iter().filter(|r| r.model == "a").count(), 20);
+        assert!(!dir.path().join("spill.lock").exists());
+    }
+
+    #[tokio::test]
+    async fn test_exporter_posts_full_batches() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/usage"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let settings = settings(
+            format!("{}/usage", mock_server.uri()),
+            dir.path().join("spill.jsonl"),
+        );
+
+        let exporter = UsageExporter::spawn(settings, reqwest::Client::new());
+        for model in ["a", "b"] {
+            exporter.send(usage_record(model));
+        }
+        for _ in 0..100 {
+            if mock_server.received_requests().await.unwrap().len() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // A partial batch waits for the interval unless flushed
+        exporter.send(usage_record("c"));
+        exporter.flush().await;
+        let sizes: Vec<usize> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| {
+                r.body_json::<serde_json::Value>().unwrap()["records"]
+                    .as_array()
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_exporter_spills_unsent_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill = dir.path().join("spill.jsonl");
+        let mut settings = settings("http://127.0.0.1:9/usage".to_string(), spill.clone());
+        settings.batch_size = 10;
+
+        let exporter = UsageExporter::spawn(settings, reqwest::Client::new());
+        exporter.send(usage_record("a"));
+        drop(exporter);
+
+        assert_eq!(read_spill(&spill), vec![usage_record("a")]);
+    }
+
+    #[test]
+    fn test_records_spilled_once_runtime_is_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill = dir.path().join("spill.jsonl");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let exporter = runtime.block_on(async {
+            UsageExporter::spawn(
+                settings("http://127.0.0.1:9/usage".to_string(), spill.clone()),
+                reqwest::Client::new(),
+            )
+        });
+        drop(runtime);
+
+        assert!(exporter.is_stopped());
+        exporter.send(usage_record("a"));
+        assert_eq!(read_spill(&spill), vec![usage_record("a")]);
+    }
+}
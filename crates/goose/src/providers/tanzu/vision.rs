@@ -0,0 +1,51 @@
+//! Image input checks for Tanzu-hosted models.
+//!
+//! Images are encoded as OpenAI `image_url` parts by the request formatter. Plans often
+//! mix text-only and multimodal models, so conversations with images are rejected up
+//! front when the selected model is known not to advertise VISION.
+
+use crate::conversation::message::{Message, MessageContent};
+use crate::providers::errors::ProviderError;
+
+/// Capability name the config URL uses for image input.
+pub const VISION_CAPABILITY: &str = "VISION";
+
+/// Whether any message carries an image, directly or in a tool result.
+pub fn contains_images(messages: &[Message]) -> bool {
+    messages
+        .iter()
+        .flat_map(|m| &m.content)
+        .any(|content| match content {
+            MessageContent::Image(_) => true,
+            MessageContent::ToolResponse(response) => response
+                .tool_result
+                .as_ref()
+                .is_ok_and(|contents| contents.iter().any(|c| c.as_image().is_some())),
+            _ => false,
+        })
+}
+
+/// Error for an image sent to a model without the VISION capability.
+pub fn unsupported(model: &str) -> ProviderError {
+    ProviderError::RequestFailed(format!(
+        "{} does not support image input (no VISION capability advertised by the Tanzu AI \
+         Services plan). Choose a vision-capable model or remove the image.",
+        model
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_images() {
+        let text = vec![Message::user().with_text("describe this")];
+        assert!(!contains_images(&text));
+
+        let image = vec![Message::user()
+            .with_text("describe this")
+            .with_image("iVBORw0KGgo=", "image/png")];
+        assert!(contains_images(&image));
+    }
+}
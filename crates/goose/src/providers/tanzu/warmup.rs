@@ -0,0 +1,118 @@
+//! Waiting out model cold starts.
+//!
+//! Models scaled to zero answer 503 with a `model-warming` hint while their GPUs spin
+//! up, which can take minutes. Instead of failing after the usual three retries, such
+//! requests are retried with a longer backoff until `TANZU_AI_MAX_WARMUP_SECS` (300 by
+//! default) has passed. Streaming requests report the wait as thinking content so the
+//! session shows what is happening; other requests log it.
+
+use crate::conversation::message::Message;
+use crate::providers::errors::ProviderError;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_WARMUP: Duration = Duration::from_secs(300);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Progress of waiting for one request's model to warm up.
+#[derive(Debug)]
+pub struct Warmup {
+    started: Instant,
+    max_wait: Duration,
+    attempt: u32,
+    /// Tests retry immediately, as with Goose's other retries
+    skip_backoff: bool,
+}
+
+impl Warmup {
+    pub fn from_config() -> Self {
+        let max_wait = crate::config::Config::global()
+            .get_param::<u64>("TANZU_AI_MAX_WARMUP_SECS")
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_MAX_WARMUP);
+        let skip_backoff = std::env::var("GOOSE_PROVIDER_SKIP_BACKOFF")
+            .is_ok_and(|value| value.parse::<bool>().unwrap_or(false));
+        Self::new(max_wait, skip_backoff)
+    }
+
+    pub fn new(max_wait: Duration, skip_backoff: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            max_wait,
+            attempt: 0,
+            skip_backoff,
+        }
+    }
+
+    /// Delay before the next attempt, or `None` once the maximum wait is used up.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.next_delay_at(Instant::now())
+    }
+
+    fn next_delay_at(&mut self, now: Instant) -> Option<Duration> {
+        let remaining = self
+            .max_wait
+            .checked_sub(now.saturating_duration_since(self.started))
+            .filter(|remaining| !remaining.is_zero())?;
+        self.attempt += 1;
+        if self.skip_backoff {
+            return Some(Duration::ZERO);
+        }
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(1 << (self.attempt - 1).min(16))
+            .min(MAX_BACKOFF);
+        Some(backoff.min(remaining))
+    }
+
+    /// A progress note for the user while waiting `delay` for `model`.
+    pub fn progress(&self, model: &str, delay: Duration) -> String {
+        format!(
+            "{} is warming up on Tanzu AI Services; retrying in {}s ({}s waited, up to {}s)",
+            model,
+            delay.as_secs(),
+            self.started.elapsed().as_secs(),
+            self.max_wait.as_secs()
+        )
+    }
+
+    /// The progress note as a streamed message chunk.
+    pub fn progress_message(&self, model: &str, delay: Duration) -> Message {
+        Message::assistant().with_thinking(self.progress(model, delay), "")
+    }
+
+    /// The error once the model did not warm up in time.
+    pub fn timed_out(&self, error: &ProviderError) -> ProviderError {
+        ProviderError::ServerError(format!(
+            "The model did not finish warming up within {}s (TANZU_AI_MAX_WARMUP_SECS): {}",
+            self.max_wait.as_secs(),
+            error
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_until_max_wait() {
+        let mut warmup = Warmup::new(Duration::from_secs(60), false);
+        let start = warmup.started;
+        assert_eq!(warmup.next_delay_at(start), Some(Duration::from_secs(5)));
+        assert_eq!(warmup.next_delay_at(start), Some(Duration::from_secs(10)));
+        assert_eq!(warmup.next_delay_at(start), Some(Duration::from_secs(20)));
+        assert_eq!(warmup.next_delay_at(start), Some(Duration::from_secs(30)));
+
+        // The last delay is cut to what is left of the maximum wait
+        let late = start + Duration::from_secs(50);
+        assert_eq!(warmup.next_delay_at(late), Some(Duration::from_secs(10)));
+        assert_eq!(warmup.next_delay_at(start + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_timed_out_error() {
+        let warmup = Warmup::new(Duration::from_secs(120), true);
+        let err = warmup.timed_out(&ProviderError::ServerError("model-warming".into()));
+        assert!(matches!(err, ProviderError::ServerError(msg) if msg.contains("120s")));
+    }
+}
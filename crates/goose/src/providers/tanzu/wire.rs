@@ -0,0 +1,266 @@
+//! Request/response adapters for a binding's `wire_format`.
+//!
+//! Most GenAI plans expose OpenAI-compatible paths, but the broker also advertises
+//! `anthropic` and `cohere` wire formats for models proxied to those APIs. Each format
+//! knows its chat path, extra headers, and how to convert Goose messages to and from
+//! the upstream JSON. Models that only support legacy completions are sent to the
+//! OpenAI completions path instead; see [`super::completion`].
+
+use super::completion;
+use super::reasoning;
+use crate::conversation::message::Message;
+use crate::model::ModelConfig;
+use crate::providers::base::Usage;
+use crate::providers::formats::{anthropic, openai};
+use crate::providers::utils::ImageFormat;
+use anyhow::Result;
+use rmcp::model::Tool;
+use serde_json::{json, Value};
+
+/// API shape spoken by a binding's endpoint.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    #[default]
+    OpenAi,
+    Anthropic,
+    Cohere,
+    /// OpenAI legacy completions, for COMPLETION-only models on OpenAI-compatible bindings
+    Completion,
+}
+
+impl WireFormat {
+    /// Parse the binding's `wire_format`; unknown values fall back to OpenAI.
+    pub fn parse(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("openai") => Self::OpenAi,
+            Some("anthropic") => Self::Anthropic,
+            Some("cohere") => Self::Cohere,
+            Some(other) => {
+                tracing::warn!(
+                    "Unsupported Tanzu AI wire_format '{}', assuming OpenAI-compatible",
+                    other
+                );
+                Self::OpenAi
+            }
+        }
+    }
+
+    /// Path of the chat endpoint relative to the binding's endpoint base.
+    pub fn chat_path(self) -> &'static str {
+        match self {
+            Self::OpenAi => "openai/v1/chat/completions",
+            Self::Anthropic => "anthropic/v1/messages",
+            Self::Cohere => "cohere/v2/chat",
+            Self::Completion => "openai/v1/completions",
+        }
+    }
+
+    /// Headers the upstream API requires in addition to bearer auth.
+    pub fn headers(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Anthropic => &[("anthropic-version", "2023-06-01")],
+            Self::OpenAi | Self::Cohere | Self::Completion => &[],
+        }
+    }
+
+    /// Whether SSE responses can be decoded; other formats are buffered into one chunk.
+    pub fn supports_streaming(self) -> bool {
+        self == Self::OpenAi
+    }
+
+    /// Build the chat request body.
+    pub fn create_request(
+        self,
+        model_config: &ModelConfig,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+        for_streaming: bool,
+    ) -> Result<Value> {
+        match self {
+            Self::OpenAi => openai::create_request(
+                model_config,
+                system,
+                messages,
+                tools,
+                &ImageFormat::OpenAi,
+                for_streaming,
+            ),
+            Self::Anthropic => anthropic::create_request(model_config, system, messages, tools),
+            // Cohere v2 chat accepts OpenAI-style messages and tools
+            Self::Cohere => {
+                let mut payload = openai::create_request(
+                    model_config,
+                    system,
+                    messages,
+                    tools,
+                    &ImageFormat::OpenAi,
+                    false,
+                )?;
+                if let Some(payload) = payload.as_object_mut() {
+                    payload.remove("stream");
+                    payload.remove("stream_options");
+                }
+                Ok(payload)
+            }
+            // Tools are emulated in the prompt for completion models
+            Self::Completion => Ok(completion::create_request(model_config, system, messages)),
+        }
+    }
+
+    /// Convert a chat response into a Goose message and its token usage.
+    pub fn parse_response(self, response: &Value) -> Result<(Message, Usage)> {
+        match self {
+            Self::OpenAi => {
+                let mut message = openai::response_to_message(response)?;
+                if let Some(text) = reasoning::response_reasoning(response) {
+                    if reasoning::show_reasoning() {
+                        reasoning::prepend_thinking(&mut message, text);
+                    }
+                }
+                Ok((message, self.usage(response)))
+            }
+            Self::Anthropic => Ok((
+                anthropic::response_to_message(response)?,
+                self.usage(response),
+            )),
+            Self::Cohere => {
+                let converted = cohere_to_openai(response);
+                Ok((
+                    openai::response_to_message(&converted)?,
+                    self.usage(response),
+                ))
+            }
+            Self::Completion => Ok((
+                completion::response_to_message(response)?,
+                self.usage(response),
+            )),
+        }
+    }
+
+    /// Token usage reported in a chat response.
+    pub fn usage(self, response: &Value) -> Usage {
+        match self {
+            Self::OpenAi => response
+                .get("usage")
+                .map(openai::get_usage)
+                .unwrap_or_default(),
+            Self::Anthropic => anthropic::get_usage(response).unwrap_or_default(),
+            Self::Cohere => {
+                let tokens = response
+                    .pointer("/usage/tokens")
+                    .or_else(|| response.pointer("/usage/billed_units"));
+                let count = |key: &str| {
+                    tokens
+                        .and_then(|t| t.get(key))
+                        .and_then(Value::as_f64)
+                        .map(|n| n as i32)
+                };
+                let (input, output) = (count("input_tokens"), count("output_tokens"));
+                let total = input.zip(output).map(|(i, o)| i + o);
+                Usage::new(input, output, total)
+            }
+            Self::Completion => completion::usage(response),
+        }
+    }
+}
+
+/// Reshape a Cohere v2 chat response as an OpenAI chat completion.
+fn cohere_to_openai(response: &Value) -> Value {
+    let message = response.get("message").cloned().unwrap_or_default();
+    let text: String = message
+        .get("content")
+        .and_then(Value::as_array)
+        .map(|blocks| {
+            blocks
+                .iter()
+                .filter(|b| b.get("type").and_then(Value::as_str) == Some("text"))
+                .filter_map(|b| b.get("text").and_then(Value::as_str))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut converted = json!({"role": "assistant", "content": text});
+    if let Some(tool_calls) = message.get("tool_calls").filter(|c| !c.is_null()) {
+        converted["tool_calls"] = tool_calls.clone();
+    }
+    json!({
+        "id": response.get("id").cloned().unwrap_or_default(),
+        "choices": [{"index": 0, "message": converted}],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wire_format() {
+        assert_eq!(WireFormat::parse(None), WireFormat::OpenAi);
+        assert_eq!(WireFormat::parse(Some("openai")), WireFormat::OpenAi);
+        assert_eq!(WireFormat::parse(Some("Anthropic")), WireFormat::Anthropic);
+        assert_eq!(WireFormat::parse(Some("cohere")), WireFormat::Cohere);
+        assert_eq!(WireFormat::parse(Some("bedrock")), WireFormat::OpenAi);
+    }
+
+    #[test]
+    fn test_cohere_response_with_tool_call() {
+        let response = json!({
+            "id": "c0ffee",
+            "finish_reason": "TOOL_CALL",
+            "message": {
+                "role": "assistant",
+                "tool_plan": "I will list the files.",
+                "tool_calls": [{
+                    "id": "shell_1",
+                    "type": "function",
+                    "function": {"name": "developer__shell", "arguments": "{\"command\":\"ls\"}"}
+                }]
+            },
+            "usage": {"tokens": {"input_tokens": 42, "output_tokens": 7}}
+        });
+
+        let converted = cohere_to_openai(&response);
+        assert_eq!(
+            converted["choices"][0]["message"]["tool_calls"][0]["id"],
+            "shell_1"
+        );
+
+        let usage = WireFormat::Cohere.usage(&response);
+        assert_eq!(usage.input_tokens, Some(42));
+        assert_eq!(usage.output_tokens, Some(7));
+        assert_eq!(usage.total_tokens, Some(49));
+    }
+
+    #[test]
+    fn test_cohere_response_text() {
+        let response = json!({
+            "message": {
+                "role": "assistant",
+                "content": [{"type": "text", "text": "Hello "}, {"type": "text", "text": "Tanzu"}]
+            }
+        });
+        let converted = cohere_to_openai(&response);
+        assert_eq!(converted["choices"][0]["message"]["content"], "Hello Tanzu");
+        assert!(converted["choices"][0]["message"]
+            .get("tool_calls")
+            .is_none());
+    }
+
+    #[test]
+    fn test_cohere_request_is_not_streamed() {
+        let model = ModelConfig::new_or_fail("command-r-plus");
+        let payload = WireFormat::Cohere
+            .create_request(
+                &model,
+                "system",
+                &[Message::user().with_text("hi")],
+                &[],
+                true,
+            )
+            .unwrap();
+        assert!(payload.get("stream").is_none());
+        assert!(payload.get("stream_options").is_none());
+        assert_eq!(payload["model"], "command-r-plus");
+    }
+}
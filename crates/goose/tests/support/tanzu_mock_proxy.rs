@@ -0,0 +1,347 @@
+//! A fake Tanzu AI Services GenAI proxy for integration tests.
+//!
+//! [`MockProxy`] serves one plan the way the proxy does: the config endpoint with its
+//! advertised models, the OpenAI model list, and chat completions answered as JSON or SSE
+//! depending on the request's `stream` flag. Every endpoint checks the plan's bearer
+//! token and answers 401 otherwise, and rate limits or errors can be queued in front of
+//! the scripted replies. The binding formats a plan is delivered in (`VCAP_SERVICES`,
+//! `cf service-key` output) point at the mock, so the provider can be built from them
+//! the same way it is on Cloud Foundry.
+//!
+//! ```ignore
+//! let proxy = MockProxy::start().await;
+//! proxy.reply_with("Hello from Tanzu").await;
+//! let client = TanzuClient::new(&proxy.endpoint_base(), proxy.api_key());
+//! ```
+
+use serde_json::{json, Value};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+pub const DEFAULT_API_KEY: &str = "mock-jwt-token";
+pub const DEFAULT_PLAN: &str = "mock-plan";
+pub const DEFAULT_MODEL: &str = "openai/gpt-oss-120b";
+
+/// Priority of queued failures, answered before the scripted replies.
+const FAILURE_PRIORITY: u8 = 1;
+/// Priority of the 401 for requests without the plan's token.
+const AUTH_PRIORITY: u8 = 2;
+
+/// A model the plan advertises on its config endpoint.
+#[derive(Debug, Clone)]
+pub struct MockModel {
+    pub name: String,
+    pub capabilities: Vec<String>,
+    pub context_length: Option<usize>,
+}
+
+impl MockModel {
+    pub fn new(name: &str, capabilities: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            context_length: None,
+        }
+    }
+
+    pub fn with_context_length(mut self, context_length: usize) -> Self {
+        self.context_length = Some(context_length);
+        self
+    }
+
+    fn to_json(&self) -> Value {
+        let mut model = json!({"name": self.name, "capabilities": self.capabilities});
+        if let Some(context_length) = self.context_length {
+            model["contextLength"] = json!(context_length);
+        }
+        model
+    }
+}
+
+/// Settings for a [`MockProxy`].
+#[derive(Debug, Clone)]
+pub struct MockProxyBuilder {
+    plan: String,
+    api_key: String,
+    models: Vec<MockModel>,
+}
+
+impl Default for MockProxyBuilder {
+    fn default() -> Self {
+        Self {
+            plan: DEFAULT_PLAN.to_string(),
+            api_key: DEFAULT_API_KEY.to_string(),
+            models: vec![MockModel::new(DEFAULT_MODEL, &["CHAT", "TOOLS"])],
+        }
+    }
+}
+
+impl MockProxyBuilder {
+    /// Path segment of the plan's endpoint base.
+    pub fn plan(mut self, plan: &str) -> Self {
+        self.plan = plan.to_string();
+        self
+    }
+
+    /// The bearer token the plan accepts.
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = api_key.to_string();
+        self
+    }
+
+    /// The advertised models, replacing the default gpt-oss model.
+    pub fn models(mut self, models: Vec<MockModel>) -> Self {
+        self.models = models;
+        self
+    }
+
+    pub async fn start(self) -> MockProxy {
+        let proxy = MockProxy {
+            server: MockServer::start().await,
+            plan: self.plan,
+            api_key: self.api_key,
+        };
+        proxy.mount_auth().await;
+        proxy.mount_discovery(&self.models).await;
+        proxy
+    }
+}
+
+/// A running fake GenAI proxy serving one plan.
+pub struct MockProxy {
+    server: MockServer,
+    plan: String,
+    api_key: String,
+}
+
+impl MockProxy {
+    /// A proxy with the default plan, token and model.
+    pub async fn start() -> Self {
+        MockProxyBuilder::default().start().await
+    }
+
+    pub fn builder() -> MockProxyBuilder {
+        MockProxyBuilder::default()
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    /// The plan's endpoint base, as in a binding's `endpoint.api_base`.
+    pub fn endpoint_base(&self) -> String {
+        format!("{}/{}", self.server.uri(), self.plan)
+    }
+
+    pub fn config_url(&self) -> String {
+        format!("{}/config/v1/endpoint", self.endpoint_base())
+    }
+
+    fn chat_path(&self) -> String {
+        format!("/{}/openai/v1/chat/completions", self.plan)
+    }
+
+    /// Binding credentials in the current multi-model format.
+    pub fn credentials(&self) -> Value {
+        json!({
+            "endpoint": {
+                "api_base": self.endpoint_base(),
+                "api_key": self.api_key,
+                "config_url": self.config_url(),
+                "name": self.plan
+            }
+        })
+    }
+
+    /// Binding credentials in the deprecated single-model format, without a config URL.
+    pub fn legacy_credentials(&self, model_name: &str) -> Value {
+        json!({
+            "api_base": format!("{}/openai", self.endpoint_base()),
+            "api_key": self.api_key,
+            "model_name": model_name,
+            "model_capabilities": ["chat", "tools"],
+            "wire_format": "openai"
+        })
+    }
+
+    /// A `VCAP_SERVICES` document with one `genai` binding named `binding_name`.
+    pub fn vcap_services(&self, binding_name: &str) -> String {
+        json!({
+            "genai": [{
+                "name": binding_name,
+                "label": "genai",
+                "plan": self.plan,
+                "tags": ["genai", "llm"],
+                "credentials": self.credentials()
+            }]
+        })
+        .to_string()
+    }
+
+    /// The output of `cf service-key` for the plan.
+    pub fn service_key(&self) -> String {
+        json!({"credentials": self.credentials()}).to_string()
+    }
+
+    /// Answer chat requests with `text`, streamed when the request asks for it.
+    pub async fn reply_with(&self, text: &str) {
+        let message = json!({"role": "assistant", "content": text});
+        self.mount_chat(message).await;
+    }
+
+    /// Answer chat requests with a call to tool `name`.
+    pub async fn reply_with_tool_call(&self, name: &str, arguments: Value) {
+        let message = json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": "call_mock_1",
+                "type": "function",
+                "function": {"name": name, "arguments": arguments.to_string()}
+            }]
+        });
+        self.mount_chat(message).await;
+    }
+
+    /// Answer the next `times` chat requests with 429 and a `Retry-After` of `retry_after_secs`.
+    pub async fn rate_limit(&self, times: u64, retry_after_secs: u64) {
+        let response = ResponseTemplate::new(429)
+            .insert_header("Retry-After", retry_after_secs.to_string().as_str())
+            .set_body_json(json!({
+                "error": {"message": "Rate limit exceeded", "type": "rate_limit_error"}
+            }));
+        self.fail_next(times, response).await;
+    }
+
+    /// Answer the next `times` chat requests with `status` and an OpenAI-style error body.
+    pub async fn fail_with(&self, times: u64, status: u16, message: &str) {
+        let response = ResponseTemplate::new(status)
+            .set_body_json(json!({"error": {"message": message, "type": "server_error"}}));
+        self.fail_next(times, response).await;
+    }
+
+    async fn fail_next(&self, times: u64, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path(self.chat_path()))
+            .and(header("Authorization", self.bearer().as_str()))
+            .respond_with(response)
+            .up_to_n_times(times)
+            .with_priority(FAILURE_PRIORITY)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Bodies of the chat requests received so far.
+    pub async fn chat_requests(&self) -> Vec<Value> {
+        let chat_path = self.chat_path();
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|request| request.method.as_str() == "POST" && request.url.path() == chat_path)
+            .filter_map(|request| serde_json::from_slice(&request.body).ok())
+            .collect()
+    }
+
+    fn bearer(&self) -> String {
+        format!("Bearer {}", self.api_key)
+    }
+
+    async fn mount_auth(&self) {
+        let expected = self.bearer();
+        Mock::given(move |request: &Request| {
+            request
+                .headers
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                != Some(expected.as_str())
+        })
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "error": {"message": "Invalid or expired JWT token", "type": "authentication_error"}
+        })))
+        .with_priority(AUTH_PRIORITY)
+        .mount(&self.server)
+        .await;
+    }
+
+    async fn mount_discovery(&self, models: &[MockModel]) {
+        Mock::given(method("GET"))
+            .and(path(format!("/{}/config/v1/endpoint", self.plan)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "name": self.plan,
+                "advertisedModels": models.iter().map(MockModel::to_json).collect::<Vec<_>>()
+            })))
+            .mount(&self.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/{}/openai/v1/models", self.plan)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": models
+                    .iter()
+                    .map(|m| json!({"id": m.name, "object": "model"}))
+                    .collect::<Vec<_>>()
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    async fn mount_chat(&self, message: Value) {
+        Mock::given(method("POST"))
+            .and(path(self.chat_path()))
+            .respond_with(move |request: &Request| chat_response(request, &message))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// A chat completion carrying `message`, as SSE when the request streams.
+fn chat_response(request: &Request, message: &Value) -> ResponseTemplate {
+    let body: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+    let model = body.get("model").cloned().unwrap_or(json!(DEFAULT_MODEL));
+    let finish_reason = if message.get("tool_calls").is_some() {
+        "tool_calls"
+    } else {
+        "stop"
+    };
+    let usage = json!({"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15});
+
+    if body.get("stream").and_then(Value::as_bool) != Some(true) {
+        return ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion",
+            "model": model,
+            "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+            "usage": usage
+        }));
+    }
+
+    let mut delta = message.clone();
+    if let Some(tool_calls) = delta.get_mut("tool_calls").and_then(Value::as_array_mut) {
+        for (index, call) in tool_calls.iter_mut().enumerate() {
+            call["index"] = json!(index);
+        }
+    }
+    let chunks = [
+        json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "model": model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": null}]
+        }),
+        json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "model": model,
+            "choices": [{"index": 0, "delta": {}, "finish_reason": finish_reason}],
+            "usage": usage
+        }),
+    ];
+    let mut sse: String = chunks
+        .iter()
+        .map(|chunk| format!("data: {}\n\n", chunk))
+        .collect();
+    sse.push_str("data: [DONE]\n\n");
+    ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream")
+}
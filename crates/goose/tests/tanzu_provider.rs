@@ -1,3 +1,5 @@
+mod support;
+
 #[cfg(test)]
 mod tanzu_provider_tests {
     use goose::model::ModelConfig;
@@ -31,7 +33,55 @@ mod tanzu_provider_tests {
         assert_eq!(meta.name, "tanzu_ai");
         assert_eq!(meta.display_name, "Tanzu AI Services");
         assert!(meta.allows_unlisted_models);
-        assert_eq!(meta.config_keys.len(), 4);
+        assert_eq!(meta.config_keys.len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_from_env_uses_configured_endpoint() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/plan/openai/v1/chat/completions"))
+            .and(header("Authorization", "Bearer env-jwt-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-env",
+                "object": "chat.completion",
+                "model": "openai/gpt-oss-120b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "configured"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 2, "completion_tokens": 1, "total_tokens": 3}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        std::env::set_var("TANZU_AI_ENDPOINT", format!("{}/plan", mock_server.uri()));
+        std::env::set_var("TANZU_AI_API_KEY", "env-jwt-token");
+        std::env::set_var("TANZU_AI_PREFLIGHT", "false");
+
+        let provider =
+            TanzuAIServicesProvider::from_env(ModelConfig::new_or_fail("openai/gpt-oss-120b"))
+                .await;
+
+        std::env::remove_var("TANZU_AI_ENDPOINT");
+        std::env::remove_var("TANZU_AI_API_KEY");
+        std::env::remove_var("TANZU_AI_PREFLIGHT");
+
+        let provider = provider.unwrap();
+        let model_config = provider.get_model_config();
+        let (message, _usage) = provider
+            .complete_with_model(
+                Some("test-session"),
+                &model_config,
+                "system",
+                &[goose::conversation::message::Message::user().with_text("hi")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "configured");
     }
 
     // --- Non-Streaming Completion Tests ---
@@ -446,4 +496,104 @@ mod tanzu_provider_tests {
             .collect();
         assert_eq!(tool_requests.len(), 1);
     }
+
+    // --- Mock Proxy Tests ---
+
+    use super::support::tanzu_mock_proxy::{MockModel, MockProxy};
+    use goose::providers::errors::ProviderError;
+    use goose::providers::tanzu::TanzuClient;
+
+    #[tokio::test]
+    async fn test_mock_proxy_completion_and_stream() {
+        let proxy = MockProxy::start().await;
+        proxy.reply_with("Hello from the mock proxy").await;
+        let client = TanzuClient::new(&proxy.endpoint_base(), proxy.api_key()).unwrap();
+        let model_config = ModelConfig::new_or_fail("openai/gpt-oss-120b");
+        let messages = [goose::conversation::message::Message::user().with_text("hi")];
+
+        let (message, usage) = client
+            .complete(&model_config, "system", &messages, &[])
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "Hello from the mock proxy");
+        assert_eq!(usage.usage.total_tokens, Some(15));
+
+        use futures::StreamExt;
+        let mut stream = client
+            .stream(&model_config, "system", &messages, &[])
+            .await
+            .unwrap();
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            if let Some(message) = chunk.unwrap().0 {
+                text.push_str(&message.as_concat_text());
+            }
+        }
+        assert_eq!(text, "Hello from the mock proxy");
+
+        let requests = proxy.chat_requests().await;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_mock_proxy_rejects_wrong_key() {
+        let proxy = MockProxy::start().await;
+        proxy.reply_with("unreachable").await;
+        let client = TanzuClient::new(&proxy.endpoint_base(), "stale-token").unwrap();
+
+        let result = client
+            .complete(
+                &ModelConfig::new_or_fail("openai/gpt-oss-120b"),
+                "system",
+                &[goose::conversation::message::Message::user().with_text("hi")],
+                &[],
+            )
+            .await;
+        assert!(matches!(result, Err(ProviderError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mock_proxy_rate_limit_then_tool_call() {
+        let proxy = MockProxy::builder().plan("rate-limited-plan").start().await;
+        proxy.rate_limit(1, 0).await;
+        proxy
+            .reply_with_tool_call("get_weather", json!({"location": "Boston"}))
+            .await;
+        let client = TanzuClient::new(&proxy.endpoint_base(), proxy.api_key()).unwrap();
+
+        let (message, _) = client
+            .complete(
+                &ModelConfig::new_or_fail("openai/gpt-oss-120b"),
+                "system",
+                &[goose::conversation::message::Message::user().with_text("weather?")],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert!(message.content.iter().any(|c| matches!(
+            c,
+            goose::conversation::message::MessageContent::ToolRequest(_)
+        )));
+        assert_eq!(proxy.chat_requests().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mock_proxy_advertises_models() {
+        let proxy = MockProxy::builder()
+            .models(vec![
+                MockModel::new("llama3.2:1b", &["CHAT", "TOOLS"]).with_context_length(8192),
+                MockModel::new("nomic-embed-text", &["EMBEDDING"]),
+            ])
+            .start()
+            .await;
+        let client = TanzuClient::new(&proxy.endpoint_base(), proxy.api_key()).unwrap();
+
+        let models = client.models().await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(
+            client.verify().await.unwrap(),
+            vec!["llama3.2:1b", "nomic-embed-text"]
+        );
+    }
 }